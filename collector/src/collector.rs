@@ -11,6 +11,7 @@ use crate::modules::processes::ProcessDataSource;
 use crate::modules::screen::ScreenDataSource;
 use crate::modules::shell_history::ShellHistoryDataSource;
 use crate::modules::weather::WeatherDataSource;
+use crate::modules::wifi::WifiDataSource;
 use crate::modules::window_activity::WindowActivityDataSource;
 use async_trait::async_trait;
 use config;
@@ -26,7 +27,7 @@ use tokio::time::Duration;
 use config::{
     BrowserHistoryConfig, CameraConfig, ClipboardConfig, HyprlandConfig, KeyboardConfig,
     MicrophoneConfig, MouseConfig, ProcessesConfig, ScreenConfig, ShellHistoryConfig,
-    WeatherConfig, WindowActivityConfig,
+    WeatherConfig, WifiConfig, WindowActivityConfig,
 };
 use lifelog_core::*;
 use lifelog_types::CollectorState;
@@ -639,6 +640,32 @@ impl Collector {
             }
         }
 
+        if config.wifi.as_ref().map(|w| w.enabled).unwrap_or(false) {
+            let config_clone = Arc::clone(&self.config);
+            match WifiDataSource::new(config_clone.wifi.clone().unwrap()) {
+                Ok(wifi_source) => match wifi_source.start() {
+                    Ok(ds_handle) => {
+                        let running_src = RunningSource::<WifiConfig> {
+                            instance: Arc::new(Mutex::new(Box::new(wifi_source))),
+                            handle: ds_handle,
+                        };
+                        self.sources
+                            .insert("wifi".to_string(), Box::new(running_src));
+                    }
+                    Err(e) => {
+                        let err = LifelogError::SourceSetup("wifi".to_string(), e.to_string());
+                        tracing::error!("{}", err);
+                        setup_errors.push(err);
+                    }
+                },
+                Err(e) => {
+                    let err = LifelogError::SourceSetup("wifi".to_string(), e.to_string());
+                    tracing::error!("{}", err);
+                    setup_errors.push(err);
+                }
+            }
+        }
+
         if config.hyprland.as_ref().map(|h| h.enabled).unwrap_or(false) {
             let config_clone = Arc::clone(&self.config);
             match HyprlandDataSource::new(config_clone.hyprland.clone().unwrap()) {
@@ -966,6 +993,31 @@ impl Collector {
             }
         }
 
+        if let Some(running_src_trait) = self.sources.get("wifi") {
+            if let Some(running_wifi_src) =
+                (running_src_trait as &dyn Any).downcast_ref::<RunningSource<WifiConfig>>()
+            {
+                let guard = running_wifi_src.instance.lock().await;
+                if let Some(wifi_ds) = guard.as_any().downcast_ref::<WifiDataSource>() {
+                    let buf_size = match wifi_ds.buffer.get_uncommitted_size().await {
+                        Ok(s) => s as usize,
+                        Err(e) => {
+                            tracing::error!("Failed to get buffer size: {}", e);
+                            0
+                        }
+                    };
+
+                    let fs = format!("WiFi source buffer length: {}", buf_size);
+                    buffer_states.push(fs.to_string());
+                    total += buf_size;
+
+                    let is_running = wifi_ds.is_running();
+                    let fs = format!("WiFi source running state: {}", is_running);
+                    source_states.push(fs.to_string());
+                }
+            }
+        }
+
         if let Some(running_src_trait) = self.sources.get("hyprland") {
             if let Some(running_hypr_src) =
                 (running_src_trait as &dyn Any).downcast_ref::<RunningSource<HyprlandConfig>>()
@@ -17,4 +17,5 @@ pub mod shell_history;
 pub mod text_upload;
 pub mod wayland_input_logger;
 pub mod weather;
+pub mod wifi;
 pub mod window_activity;
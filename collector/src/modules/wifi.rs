@@ -0,0 +1,287 @@
+use crate::data_source::{BufferedSource, DataSource, DataSourceHandle};
+use async_trait::async_trait;
+use config::WifiConfig;
+use lifelog_core::{LifelogError, Utc, Uuid};
+use lifelog_types::{to_pb_ts, WifiFrame};
+use prost::Message;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::process::Command;
+use tokio::time::{sleep, Duration};
+use utils::buffer::DiskBuffer;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// One access point from a scan.
+#[derive(Debug, Clone, PartialEq)]
+struct ScanEntry {
+    in_use: bool,
+    ssid: String,
+    signal: u32,
+    bssid: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct WifiDataSource {
+    config: WifiConfig,
+    pub buffer: Arc<DiskBuffer>,
+}
+
+impl WifiDataSource {
+    pub fn new(config: WifiConfig) -> Result<Self, LifelogError> {
+        let buffer_path = std::path::Path::new(&config.output_dir).join("buffer");
+        let buffer = DiskBuffer::new(&buffer_path).map_err(|e| {
+            LifelogError::Io(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                e.to_string(),
+            ))
+        })?;
+
+        Ok(WifiDataSource {
+            config,
+            buffer: Arc::new(buffer),
+        })
+    }
+
+    /// Splits one `nmcli -t` line on unescaped `:` and unescapes `\:` and `\\`.
+    fn split_terse(line: &str) -> Vec<String> {
+        let mut fields = vec![String::new()];
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    if let (Some(next), Some(field)) = (chars.next(), fields.last_mut()) {
+                        field.push(next);
+                    }
+                }
+                ':' => fields.push(String::new()),
+                _ => {
+                    if let Some(field) = fields.last_mut() {
+                        field.push(c);
+                    }
+                }
+            }
+        }
+        fields
+    }
+
+    /// Parses `nmcli -t` output listing either `IN-USE,SSID,SIGNAL,BSSID` or
+    /// `SSID,SIGNAL,BSSID`.
+    fn parse_scan(output: &str) -> Vec<ScanEntry> {
+        output
+            .lines()
+            .filter_map(|line| {
+                let fields = Self::split_terse(line.trim_end());
+                let (in_use, rest) = match fields.len() {
+                    4 => (fields[0].trim() == "*", &fields[1..]),
+                    3 => (false, &fields[..]),
+                    _ => return None,
+                };
+                Some(ScanEntry {
+                    in_use,
+                    ssid: rest[0].clone(),
+                    signal: rest[1].trim().parse().ok()?,
+                    bssid: rest[2].clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// The connected access point when the scan marks one, otherwise the strongest.
+    fn pick(entries: Vec<ScanEntry>) -> Option<ScanEntry> {
+        let connected = entries.iter().position(|e| e.in_use);
+        match connected {
+            Some(i) => entries.into_iter().nth(i),
+            None => entries.into_iter().max_by_key(|e| e.signal),
+        }
+    }
+
+    /// Address of the interface that routes outward. Connecting a UDP socket sends nothing.
+    fn local_ip() -> Option<String> {
+        let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+        socket.connect("192.0.2.1:80").ok()?;
+        Some(socket.local_addr().ok()?.ip().to_string())
+    }
+
+    async fn scan(&self) -> Result<Option<ScanEntry>, LifelogError> {
+        let mut parts = self.config.scan_command.split_whitespace();
+        let Some(program) = parts.next() else {
+            return Err(LifelogError::SourceSetup(
+                "wifi".to_string(),
+                "scan_command is empty".to_string(),
+            ));
+        };
+        let output = Command::new(program).args(parts).output().await?;
+        if !output.status.success() {
+            tracing::warn!(
+                status = %output.status,
+                stderr = %String::from_utf8_lossy(&output.stderr).trim(),
+                "WiFi scan command failed"
+            );
+            return Ok(None);
+        }
+        Ok(Self::pick(Self::parse_scan(&String::from_utf8_lossy(
+            &output.stdout,
+        ))))
+    }
+}
+
+#[async_trait]
+impl DataSource for WifiDataSource {
+    type Config = WifiConfig;
+
+    fn new(config: WifiConfig) -> Result<Self, LifelogError> {
+        WifiDataSource::new(config)
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn get_buffered_source(&self) -> Option<Arc<dyn BufferedSource>> {
+        Some(Arc::new(WifiBufferedSource {
+            stream_id: "wifi".to_string(),
+            buffer: self.buffer.clone(),
+        }))
+    }
+
+    fn start(&self) -> Result<DataSourceHandle, LifelogError> {
+        if RUNNING.load(Ordering::SeqCst) {
+            return Err(LifelogError::AlreadyRunning);
+        }
+
+        tracing::info!("WifiDataSource: Starting data source task");
+        RUNNING.store(true, Ordering::SeqCst);
+
+        let source_clone = self.clone();
+
+        let join_handle = tokio::spawn(async move {
+            let task_result = source_clone.run().await;
+            tracing::info!(result = ?task_result, "WifiDataSource background task finished");
+            task_result
+        });
+
+        Ok(DataSourceHandle { join: join_handle })
+    }
+
+    async fn stop(&mut self) -> Result<(), LifelogError> {
+        RUNNING.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn run(&self) -> Result<(), LifelogError> {
+        while RUNNING.load(Ordering::SeqCst) {
+            match self.scan().await {
+                Ok(entry) => {
+                    let ip = Self::local_ip().unwrap_or_default();
+                    // Disconnected with no address either: nothing to locate by.
+                    if entry.is_some() || !ip.is_empty() {
+                        let entry = entry.unwrap_or(ScanEntry {
+                            in_use: false,
+                            ssid: String::new(),
+                            signal: 0,
+                            bssid: String::new(),
+                        });
+                        let timestamp = to_pb_ts(Utc::now());
+                        let frame = WifiFrame {
+                            uuid: Uuid::new_v4().to_string(),
+                            timestamp,
+                            ssid: entry.ssid,
+                            bssid: entry.bssid,
+                            signal: entry.signal,
+                            ip,
+                            t_device: timestamp,
+                            t_canonical: timestamp,
+                            t_end: timestamp,
+                            ..Default::default()
+                        };
+
+                        let mut buf = Vec::new();
+                        if let Err(e) = frame.encode(&mut buf) {
+                            tracing::error!("Failed to encode WifiFrame: {}", e);
+                        } else if let Err(e) = self.buffer.append(&buf).await {
+                            tracing::error!("Failed to append WifiFrame to buffer: {}", e);
+                        } else {
+                            tracing::debug!("Stored wifi frame in WAL");
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("WiFi scan failed: {}", e);
+                }
+            }
+            sleep(Duration::from_secs_f64(self.config.interval)).await;
+        }
+        Ok(())
+    }
+
+    fn is_running(&self) -> bool {
+        RUNNING.load(Ordering::SeqCst)
+    }
+
+    fn get_config(&self) -> Self::Config {
+        self.config.clone()
+    }
+}
+
+pub struct WifiBufferedSource {
+    stream_id: String,
+    buffer: Arc<DiskBuffer>,
+}
+
+#[async_trait]
+impl BufferedSource for WifiBufferedSource {
+    fn stream_id(&self) -> String {
+        self.stream_id.clone()
+    }
+
+    async fn peek_upload_batch(
+        &self,
+        max_items: usize,
+    ) -> Result<(u64, Vec<Vec<u8>>), LifelogError> {
+        let (next_offset, raws) = self.buffer.peek_chunk(max_items).await.map_err(|e| {
+            LifelogError::Io(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                e.to_string(),
+            ))
+        })?;
+
+        Ok((next_offset, raws))
+    }
+
+    async fn commit_upload(&self, offset: u64) -> Result<(), LifelogError> {
+        self.buffer.commit_offset(offset).await.map_err(|e| {
+            LifelogError::Io(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                e.to_string(),
+            ))
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_terse_scan_with_in_use_column() {
+        let out =
+            "  :Cafe:80:11\\:22\\:33\\:44\\:55\\:66\n*:Home\\:Net:64:AA\\:BB\\:CC\\:DD\\:EE\\:FF\n";
+        let entries = WifiDataSource::parse_scan(out);
+        assert_eq!(entries.len(), 2);
+        let picked = WifiDataSource::pick(entries).expect("entry");
+        assert_eq!(picked.ssid, "Home:Net");
+        assert_eq!(picked.bssid, "AA:BB:CC:DD:EE:FF");
+        assert_eq!(picked.signal, 64);
+    }
+
+    #[test]
+    fn parse_terse_scan_without_in_use_picks_strongest() {
+        let out = "Cafe:40:11\\:22\\:33\\:44\\:55\\:66\nHome:72:AA\\:BB\\:CC\\:DD\\:EE\\:FF\nbroken line\n";
+        let picked = WifiDataSource::pick(WifiDataSource::parse_scan(out)).expect("entry");
+        assert_eq!(picked.ssid, "Home");
+        assert_eq!(picked.signal, 72);
+    }
+}
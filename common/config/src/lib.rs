@@ -67,6 +67,12 @@ pub fn load_server_deploy_config() -> ServerDeployConfig {
                 .and_then(|n| usize::try_from(n).ok())
         });
 
+    let sqlite_path = env::var("LIFELOG_SQLITE_PATH")
+        .ok()
+        .or_else(|| toml_str("sqlitePath", "sqlite_path"))
+        .filter(|p| !p.trim().is_empty())
+        .map(replace_home_dir_in_path);
+
     ServerDeployConfig {
         postgres_url,
         tls: TlsConfig {
@@ -75,6 +81,7 @@ pub fn load_server_deploy_config() -> ServerDeployConfig {
         },
         allow_plaintext,
         postgres_max_connections,
+        sqlite_path,
     }
 }
 
@@ -98,6 +105,240 @@ pub fn load_network_policy_from_unified() -> NetworkPolicy {
     NetworkPolicy { allowed_hosts }
 }
 
+/// Loads named places from `[[server.places]]` in the unified config.
+///
+/// ```toml
+/// [[server.places]]
+/// name = "home"
+/// ssids = ["MyWifi"]
+/// ipPrefixes = ["192.168.1."]
+/// latitude = 52.52
+/// longitude = 13.40
+/// radiusM = 150
+/// ```
+pub fn load_places_from_unified() -> Vec<NamedPlace> {
+    let path = env::var("LIFELOG_CONFIG_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| default_lifelog_config_path());
+    let Some(root) = load_toml_from_path(&path) else {
+        return Vec::new();
+    };
+    root.get("server")
+        .and_then(|s| s.get("places"))
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.iter().filter_map(parse_named_place).collect())
+        .unwrap_or_default()
+}
+
+/// Loads full-text search language settings from `[server.search]` in the unified config.
+///
+/// ```toml
+/// [server.search]
+/// languages = ["en", "de", "es"]
+/// defaultLanguage = "en"
+/// ```
+///
+/// Missing settings fall back to English only.
+pub fn load_search_config_from_unified() -> SearchConfig {
+    let path = env::var("LIFELOG_CONFIG_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| default_lifelog_config_path());
+    let Some(search) = load_toml_from_path(&path)
+        .and_then(|root| root.get("server").and_then(|s| s.get("search")).cloned())
+    else {
+        return SearchConfig::default();
+    };
+    let defaults = SearchConfig::default();
+    let languages: Vec<String> = search
+        .get("languages")
+        .and_then(|v| v.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|v| v.as_str().map(|s| s.trim().to_lowercase()))
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+        .unwrap_or(defaults.languages);
+    let default_language = search
+        .get("defaultLanguage")
+        .and_then(|v| v.as_str())
+        .map(|s| s.trim().to_lowercase())
+        .unwrap_or(defaults.default_language);
+    SearchConfig {
+        languages,
+        default_language,
+    }
+}
+
+/// Loads CAS encryption keys from `[server.casKeys]` in the unified config, overridden by
+/// `LIFELOG_CAS_KEY` and `LIFELOG_CAS_RETIRED_KEYS` (comma-separated).
+///
+/// ```toml
+/// [server.casKeys]
+/// active = "file:/etc/lifelog/cas-2026.key"
+/// retired = ["passphrase:@/etc/lifelog/cas-2025.pass"]
+/// ```
+pub fn load_cas_keys_from_unified() -> CasKeyConfig {
+    let path = env::var("LIFELOG_CONFIG_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| default_lifelog_config_path());
+    let keys = load_toml_from_path(&path)
+        .and_then(|root| root.get("server").and_then(|s| s.get("casKeys")).cloned());
+    let active = env::var("LIFELOG_CAS_KEY").ok().or_else(|| {
+        keys.as_ref()
+            .and_then(|k| k.get("active"))
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+    });
+    let retired: Vec<String> = match env::var("LIFELOG_CAS_RETIRED_KEYS") {
+        Ok(list) => list.split(',').map(|s| s.trim().to_string()).collect(),
+        Err(_) => keys
+            .as_ref()
+            .and_then(|k| k.get("retired"))
+            .and_then(|v| v.as_array())
+            .map(|arr| {
+                arr.iter()
+                    .filter_map(|v| v.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+    CasKeyConfig {
+        active: active.filter(|s| !s.trim().is_empty()),
+        retired: retired.into_iter().filter(|s| !s.is_empty()).collect(),
+    }
+}
+
+/// Loads `[server.payloadEncryption]` from the unified config; `LIFELOG_PAYLOAD_KEY` overrides
+/// the key.
+///
+/// ```toml
+/// [server.payloadEncryption]
+/// key = "file:/etc/lifelog/payload.key"
+/// indexedFields = ["text", "window_title"]
+/// ```
+pub fn load_payload_encryption_from_unified() -> PayloadEncryptionConfig {
+    let path = env::var("LIFELOG_CONFIG_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| default_lifelog_config_path());
+    let section = load_toml_from_path(&path).and_then(|root| {
+        root.get("server")
+            .and_then(|s| s.get("payloadEncryption"))
+            .cloned()
+    });
+    let defaults = PayloadEncryptionConfig::default();
+    let key = env::var("LIFELOG_PAYLOAD_KEY").ok().or_else(|| {
+        section
+            .as_ref()
+            .and_then(|s| s.get("key"))
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+    });
+    let indexed_fields = section
+        .as_ref()
+        .and_then(|s| s.get("indexedFields"))
+        .and_then(|v| v.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|v| v.as_str().map(|s| s.trim().to_string()))
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+        .unwrap_or(defaults.indexed_fields);
+    PayloadEncryptionConfig {
+        key: key.filter(|s| !s.trim().is_empty()),
+        indexed_fields,
+    }
+}
+
+/// Loads `[[server.retentionTiers]]` from the unified config. Entries without a modality or
+/// without any action are skipped.
+///
+/// ```toml
+/// [[server.retentionTiers]]
+/// modality = "Screen"
+/// afterDays = 7
+/// keepOneEverySecs = 60
+///
+/// [[server.retentionTiers]]
+/// modality = "Audio"
+/// afterDays = 30
+/// dropBlob = true
+/// ```
+pub fn load_retention_tiers_from_unified() -> Vec<RetentionTier> {
+    let path = env::var("LIFELOG_CONFIG_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| default_lifelog_config_path());
+    let Some(tiers) = load_toml_from_path(&path).and_then(|root| {
+        root.get("server")
+            .and_then(|s| s.get("retentionTiers"))
+            .and_then(|v| v.as_array())
+            .cloned()
+    }) else {
+        return Vec::new();
+    };
+    tiers.iter().filter_map(parse_retention_tier).collect()
+}
+
+fn parse_retention_tier(value: &toml::Value) -> Option<RetentionTier> {
+    let int = |key: &str| value.get(key).and_then(|v| v.as_integer());
+    let modality = value.get("modality")?.as_str()?.trim().to_string();
+    if modality.is_empty() {
+        return None;
+    }
+    let tier = RetentionTier {
+        modality,
+        after_days: int("afterDays").and_then(|n| u32::try_from(n).ok())?,
+        keep_one_every_secs: int("keepOneEverySecs")
+            .and_then(|n| u32::try_from(n).ok())
+            .filter(|&n| n > 0),
+        drop_blob: value
+            .get("dropBlob")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false),
+        reencode_quality: int("reencodeQuality").and_then(|n| u8::try_from(n.clamp(1, 100)).ok()),
+    };
+    if tier.keep_one_every_secs.is_none() && !tier.drop_blob && tier.reencode_quality.is_none() {
+        tracing::warn!(modality = %tier.modality, "Retention tier has no action; ignored");
+        return None;
+    }
+    Some(tier)
+}
+
+fn parse_named_place(value: &toml::Value) -> Option<NamedPlace> {
+    // Keys are already normalized to camelCase by `load_toml_from_path`.
+    let strings = |key: &str| -> Vec<String> {
+        value
+            .get(key)
+            .and_then(|v| v.as_array())
+            .map(|arr| {
+                arr.iter()
+                    .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let float = |key: &str| -> Option<f64> {
+        value
+            .get(key)
+            .and_then(|v| v.as_float().or_else(|| v.as_integer().map(|n| n as f64)))
+    };
+
+    let name = value.get("name")?.as_str()?.trim().to_string();
+    if name.is_empty() {
+        return None;
+    }
+    Some(NamedPlace {
+        name,
+        ssids: strings("ssids"),
+        bssids: strings("bssids"),
+        ip_prefixes: strings("ipPrefixes"),
+        latitude: float("latitude"),
+        longitude: float("longitude"),
+        radius_m: float("radiusM"),
+    })
+}
+
 // Re-export all config types from lifelog_types
 pub use lifelog_types::{
     AmbientConfig, AudioConfig, BrowserHistoryConfig, CameraConfig, ClipboardConfig,
@@ -451,7 +692,7 @@ pub fn create_default_config() -> CollectorConfig {
             enabled: false,
             interval: 300.0,
             output_dir: lifelog_dir.join("wifi").display().to_string(),
-            scan_command: "nmcli -t -f SSID,SIGNAL,BSSID device wifi list".to_string(),
+            scan_command: "nmcli -t -f IN-USE,SSID,SIGNAL,BSSID device wifi list".to_string(),
         }),
         clipboard: Some(ClipboardConfig {
             enabled: false,
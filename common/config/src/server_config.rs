@@ -5,6 +5,87 @@ pub struct NetworkPolicy {
     pub allowed_hosts: Vec<String>,
 }
 
+/// A user-defined place that location signals are matched against.
+///
+/// Any combination of WiFi networks, IP prefixes and a GPS geofence may be given; the first
+/// matching signal wins.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NamedPlace {
+    pub name: String,
+    pub ssids: Vec<String>,
+    pub bssids: Vec<String>,
+    pub ip_prefixes: Vec<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub radius_m: Option<f64>,
+}
+
+/// Languages full-text search is stemmed for.
+///
+/// `languages` are ISO 639-1 codes. Text detected as one of them is indexed with that
+/// language's stemmer, other detected languages use the unstemmed `simple` configuration, and
+/// text too short to detect reliably is treated as `default_language`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchConfig {
+    pub languages: Vec<String>,
+    pub default_language: String,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            languages: vec!["en".to_string()],
+            default_language: "en".to_string(),
+        }
+    }
+}
+
+/// Keys CAS blobs are encrypted with.
+///
+/// Each key is a spec: `file:<path>` (32 raw bytes), `passphrase:<text>` or
+/// `passphrase:@<path>`. `active` encrypts new blobs and is what a key rotation moves existing
+/// blobs to; `retired` keys only decrypt blobs not yet rotated. No `active` key means blobs are
+/// stored unencrypted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CasKeyConfig {
+    pub active: Option<String>,
+    pub retired: Vec<String>,
+}
+
+/// Field-level encryption of sensitive frame payloads.
+///
+/// `key` is a spec in the same forms as [`CasKeyConfig`]; without one, payloads are stored as
+/// they are. `indexed_fields` are the sealed payload fields that get blind-index tokens, so
+/// `Eq` and `Contains` queries still match them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayloadEncryptionConfig {
+    pub key: Option<String>,
+    pub indexed_fields: Vec<String>,
+}
+
+impl Default for PayloadEncryptionConfig {
+    fn default() -> Self {
+        Self {
+            key: None,
+            indexed_fields: vec!["text".to_string()],
+        }
+    }
+}
+
+/// A downsampling step applied to a modality's frames once they are `after_days` old.
+///
+/// `keep_one_every_secs` thins each stream to the first frame per interval; `drop_blob` removes
+/// the raw blob but keeps the frame and everything derived from it; `reencode_quality` re-encodes
+/// images as JPEG at that quality. Tiers only touch raw frames, never derived ones.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionTier {
+    pub modality: String,
+    pub after_days: u32,
+    pub keep_one_every_secs: Option<u32>,
+    pub drop_blob: bool,
+    pub reencode_quality: Option<u8>,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct TlsConfig {
     pub cert_path: Option<String>,
@@ -30,6 +111,8 @@ pub struct ServerDeployConfig {
     pub tls: TlsConfig,
     pub allow_plaintext: bool,
     pub postgres_max_connections: Option<usize>,
+    /// Single-file SQLite database used instead of Postgres when set.
+    pub sqlite_path: Option<String>,
 }
 
 pub fn resolve_file_ref(value: &str) -> String {
@@ -2,8 +2,8 @@ use lifelog_core::*;
 
 use lifelog_core::{DataOrigin, DataOriginType, LifelogImage, Transform, TransformError};
 use lifelog_types::DataModality;
-pub use lifelog_types::OcrFrame;
-use rusty_tesseract::{Args, Image};
+pub use lifelog_types::{OcrBox, OcrFrame};
+use rusty_tesseract::{Args, Data, Image};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -71,11 +71,11 @@ impl Transform for OcrTransform {
             ..Default::default()
         };
 
-        let data_output = match rusty_tesseract::image_to_string(&img, &args) {
-            Ok(text) => text,
+        let (text, words, lines) = match rusty_tesseract::image_to_data(&img, &args) {
+            Ok(output) => layout(&output.data),
             Err(e) => {
                 tracing::warn!(error = ?e, "Tesseract processing error");
-                String::new()
+                Default::default()
             }
         };
 
@@ -85,12 +85,14 @@ impl Transform for OcrTransform {
         });
 
         Ok(OcrFrame {
-            text: data_output,
+            text,
             uuid: input.uuid.to_string(),
             timestamp: ts,
             t_device: ts,
             t_canonical: ts,
             t_end: ts,
+            words,
+            lines,
             ..Default::default()
         })
     }
@@ -111,6 +113,85 @@ impl Transform for OcrTransform {
     }
 }
 
+/// Rebuilds the recognised text from Tesseract's TSV rows along with word and line boxes.
+///
+/// Words on a line are joined by spaces, lines by newlines and paragraphs by a blank line.
+/// Rows without text (page, block and paragraph rows, or empty word detections) are skipped.
+fn layout(data: &[Data]) -> (String, Vec<OcrBox>, Vec<OcrBox>) {
+    let mut text = String::new();
+    let mut words = Vec::new();
+    let mut lines: Vec<OcrBox> = Vec::new();
+    let mut line_words = 0usize;
+    let mut current_line = None;
+    let mut current_par = None;
+
+    let close_line = |lines: &mut Vec<OcrBox>, n: usize| {
+        if let Some(line) = lines.last_mut() {
+            line.confidence = if n == 0 {
+                0.0
+            } else {
+                line.confidence / n as f32
+            };
+        }
+    };
+
+    for row in data {
+        let word = row.text.trim();
+        if row.level != 5 || word.is_empty() {
+            continue;
+        }
+        let par = (row.page_num, row.block_num, row.par_num);
+        let line = (par, row.line_num);
+        if current_line != Some(line) {
+            close_line(&mut lines, line_words);
+            if current_par.is_some() {
+                text.push_str(if current_par == Some(par) {
+                    "\n"
+                } else {
+                    "\n\n"
+                });
+            }
+            lines.push(OcrBox {
+                left: row.left,
+                top: row.top,
+                ..Default::default()
+            });
+            line_words = 0;
+            current_line = Some(line);
+            current_par = Some(par);
+        } else {
+            text.push(' ');
+        }
+        text.push_str(word);
+
+        let confidence = row.conf.max(0.0);
+        if let Some(l) = lines.last_mut() {
+            let right = (l.left + l.width).max(row.left + row.width);
+            let bottom = (l.top + l.height).max(row.top + row.height);
+            l.left = l.left.min(row.left);
+            l.top = l.top.min(row.top);
+            l.width = right - l.left;
+            l.height = bottom - l.top;
+            l.confidence += confidence;
+            if !l.text.is_empty() {
+                l.text.push(' ');
+            }
+            l.text.push_str(word);
+        }
+        line_words += 1;
+        words.push(OcrBox {
+            text: word.to_string(),
+            left: row.left,
+            top: row.top,
+            width: row.width,
+            height: row.height,
+            confidence,
+        });
+    }
+    close_line(&mut lines, line_words);
+    (text, words, lines)
+}
+
 #[cfg(test)]
 #[allow(clippy::expect_used)]
 mod tests {
@@ -143,4 +224,44 @@ mod tests {
         assert_eq!(out.uuid, uuid.to_string());
         assert_eq!(out.timestamp().timestamp(), timestamp.timestamp());
     }
+
+    fn word(par: i32, line: i32, left: i32, top: i32, conf: f32, text: &str) -> Data {
+        Data {
+            level: 5,
+            page_num: 1,
+            block_num: 1,
+            par_num: par,
+            line_num: line,
+            word_num: 1,
+            left,
+            top,
+            width: 40,
+            height: 10,
+            conf,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn layout_rebuilds_text_and_line_boxes() {
+        let rows = vec![
+            Data {
+                level: 4,
+                ..word(1, 1, 10, 20, -1.0, "")
+            },
+            word(1, 1, 10, 20, 90.0, "Quarterly"),
+            word(1, 1, 60, 22, 80.0, "report"),
+            word(1, 1, 110, 20, -1.0, " "),
+            word(1, 2, 10, 40, 70.0, "draft"),
+            word(2, 1, 10, 80, 60.0, "Notes"),
+        ];
+        let (text, words, lines) = layout(&rows);
+        assert_eq!(text, "Quarterly report\ndraft\n\nNotes");
+        assert_eq!(words.len(), 4);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].text, "Quarterly report");
+        assert_eq!((lines[0].left, lines[0].top), (10, 20));
+        assert_eq!((lines[0].width, lines[0].height), (90, 12));
+        assert!((lines[0].confidence - 85.0).abs() < 1e-3);
+    }
 }
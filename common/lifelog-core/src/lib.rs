@@ -282,7 +282,9 @@ impl PrivacyTier {
             "Keystroke" | "Keystrokes" | "Audio" | "Clipboard" | "Microphone" => {
                 PrivacyTier::Sensitive
             }
-            "Screen" | "Browser" | "Ocr" | "WindowActivity" | "Camera" => PrivacyTier::Moderate,
+            "Screen" | "Browser" | "Ocr" | "WindowActivity" | "Camera" | "Wifi" | "Geo" => {
+                PrivacyTier::Moderate
+            }
             _ => PrivacyTier::Low,
         }
     }
@@ -419,6 +419,36 @@ mod helpers {
             "hyprland"
         }
     }
+
+    // WifiFrame
+    impl DataType for WifiFrame {
+        fn uuid(&self) -> CoreUuid {
+            parse_uuid(&self.uuid)
+        }
+        fn timestamp(&self) -> DateTime<Utc> {
+            to_dt(self.timestamp)
+        }
+    }
+    impl Modality for WifiFrame {
+        fn get_table_name() -> &'static str {
+            "wifi"
+        }
+    }
+
+    // GeoFrame
+    impl DataType for GeoFrame {
+        fn uuid(&self) -> CoreUuid {
+            parse_uuid(&self.uuid)
+        }
+        fn timestamp(&self) -> DateTime<Utc> {
+            to_dt(self.timestamp)
+        }
+    }
+    impl Modality for GeoFrame {
+        fn get_table_name() -> &'static str {
+            "geo"
+        }
+    }
 }
 
 #[cfg(feature = "full")]
@@ -0,0 +1,148 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use super::{CasBackend, CasError};
+
+/// One file per blob under a two-level fanout: `<root>/ab/cdef…` for hash `abcdef…`.
+#[derive(Debug)]
+pub struct DirBackend {
+    root: PathBuf,
+    /// Held by `remove`, `replace` and `touch`, so a blob being collected is neither written back
+    /// nor reused.
+    removal: Mutex<()>,
+}
+
+impl DirBackend {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            removal: Mutex::new(()),
+        }
+    }
+
+    fn lock_removal(&self) -> Result<MutexGuard<'_, ()>, CasError> {
+        self.removal
+            .lock()
+            .map_err(|_| CasError::Io(io::Error::other("CAS removal lock poisoned")))
+    }
+
+    fn path_for_hash(&self, hash: &str) -> PathBuf {
+        let (a, rest) = hash.split_at(2);
+        self.root.join(a).join(rest)
+    }
+
+    /// Writes `bytes` to a temporary file next to `path` and renames it into place.
+    fn persist(&self, path: &Path, bytes: &[u8]) -> Result<(), CasError> {
+        let parent = path.parent().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "No parent directory for CAS path")
+        })?;
+        fs::create_dir_all(parent)?;
+
+        let mut tmp = tempfile::NamedTempFile::new_in(parent)?;
+        io::Write::write_all(&mut tmp, bytes)?;
+        tmp.persist(path).map_err(|e| CasError::Io(e.error))?;
+        Ok(())
+    }
+}
+
+impl CasBackend for DirBackend {
+    fn write(&self, hash: &str, bytes: &[u8]) -> Result<(), CasError> {
+        let final_path = self.path_for_hash(hash);
+        if final_path.exists() {
+            return Ok(());
+        }
+        match self.persist(&final_path, bytes) {
+            Err(_e) if final_path.exists() => Ok(()),
+            result => result,
+        }
+    }
+
+    fn replace(&self, hash: &str, bytes: &[u8]) -> Result<(), CasError> {
+        let path = self.path_for_hash(hash);
+        let _removal = self.lock_removal()?;
+        if !path.exists() {
+            return Err(
+                io::Error::new(io::ErrorKind::NotFound, format!("blob {hash} is absent")).into(),
+            );
+        }
+        self.persist(&path, bytes)
+    }
+
+    fn read(&self, hash: &str) -> Result<Vec<u8>, CasError> {
+        Ok(fs::read(self.path_for_hash(hash))?)
+    }
+
+    fn contains(&self, hash: &str) -> Result<bool, CasError> {
+        Ok(self.path_for_hash(hash).exists())
+    }
+
+    fn remove(&self, hash: &str) -> Result<(), CasError> {
+        let p = self.path_for_hash(hash);
+        let _removal = self.lock_removal()?;
+        if p.exists() {
+            fs::remove_file(p)?;
+        }
+        Ok(())
+    }
+
+    fn touch(&self, hash: &str) -> Result<bool, CasError> {
+        let _removal = self.lock_removal()?;
+        match fs::File::options()
+            .write(true)
+            .open(self.path_for_hash(hash))
+        {
+            Ok(file) => {
+                file.set_modified(SystemTime::now())?;
+                Ok(true)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn remove_if_older(&self, hash: &str, cutoff: SystemTime) -> Result<bool, CasError> {
+        let p = self.path_for_hash(hash);
+        let _removal = self.lock_removal()?;
+        let modified = match fs::metadata(&p) {
+            Ok(meta) => meta.modified()?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        if modified >= cutoff {
+            return Ok(false);
+        }
+        fs::remove_file(p)?;
+        Ok(true)
+    }
+
+    fn list_shard(&self, shard: u8) -> Result<Vec<(String, SystemTime)>, CasError> {
+        let prefix = format!("{shard:02x}");
+        let dir = self.root.join(&prefix);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut blobs = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(rest) = name.to_str() else {
+                continue;
+            };
+            if rest.len() != 62 || !rest.chars().all(|c| c.is_ascii_hexdigit()) {
+                continue;
+            }
+            let meta = entry.metadata()?;
+            if !meta.is_file() {
+                continue;
+            }
+            blobs.push((format!("{prefix}{rest}"), meta.modified()?));
+        }
+        blobs.sort();
+        Ok(blobs)
+    }
+}
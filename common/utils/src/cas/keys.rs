@@ -0,0 +1,213 @@
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::CasError;
+
+/// Identifies a key-encryption key in envelope headers.
+pub type KeyId = [u8; 8];
+
+/// PBKDF2-HMAC-SHA256 rounds for passphrase-derived keys.
+pub const PASSPHRASE_ITERATIONS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+
+/// A key-encryption key: wraps the per-blob data keys, and decrypts blobs written in the
+/// single-key `ECAS` format.
+pub(super) struct Kek {
+    pub(super) id: KeyId,
+    pub(super) cipher: XChaCha20Poly1305,
+}
+
+impl Kek {
+    fn new(key: &[u8; 32]) -> Self {
+        Self {
+            id: key_id(key),
+            cipher: XChaCha20Poly1305::new(key.into()),
+        }
+    }
+}
+
+/// Key-encryption keys the CAS can use: the active one encrypts new blobs, retired ones still
+/// decrypt blobs written before a rotation to the active key finished.
+pub struct CasKeyring {
+    active: Kek,
+    retired: Vec<Kek>,
+}
+
+impl std::fmt::Debug for CasKeyring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CasKeyring")
+            .field("active", &self.active_id())
+            .field("retired", &self.retired_ids())
+            .finish()
+    }
+}
+
+impl CasKeyring {
+    pub fn new(active: &[u8; 32], retired: &[[u8; 32]]) -> Self {
+        let active = Kek::new(active);
+        let mut keyring = Self {
+            retired: Vec::new(),
+            active,
+        };
+        for key in retired {
+            let kek = Kek::new(key);
+            if keyring.find(&kek.id).is_none() {
+                keyring.retired.push(kek);
+            }
+        }
+        keyring
+    }
+
+    /// Loads the keys named by `active` and `retired` specs (see [`load_key`]). Passphrases are
+    /// stretched with the salt at `salt_path`, which is created on first use.
+    pub fn load(active: &str, retired: &[String], salt_path: &Path) -> Result<Self, CasError> {
+        let active = load_key(active, salt_path)?;
+        let retired = retired
+            .iter()
+            .map(|spec| load_key(spec, salt_path))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(&active, &retired))
+    }
+
+    /// Hex ID of the key new blobs are encrypted with.
+    pub fn active_id(&self) -> String {
+        hex_id(&self.active.id)
+    }
+
+    pub fn retired_ids(&self) -> Vec<String> {
+        self.retired.iter().map(|k| hex_id(&k.id)).collect()
+    }
+
+    pub(super) fn active(&self) -> &Kek {
+        &self.active
+    }
+
+    pub(super) fn find(&self, id: &KeyId) -> Option<&Kek> {
+        std::iter::once(&self.active)
+            .chain(&self.retired)
+            .find(|k| k.id == *id)
+    }
+
+    /// Active key first.
+    pub(super) fn all(&self) -> impl Iterator<Item = &Kek> {
+        std::iter::once(&self.active).chain(&self.retired)
+    }
+}
+
+/// Fingerprint of a key-encryption key, stored in the header of every blob it wraps.
+pub fn key_id(key: &[u8; 32]) -> KeyId {
+    let mut hasher = Sha256::new();
+    hasher.update(b"lifelog-cas-kek\0");
+    hasher.update(key);
+    let digest = hasher.finalize();
+    let mut id = [0u8; 8];
+    id.copy_from_slice(&digest[..8]);
+    id
+}
+
+pub fn hex_id(id: &KeyId) -> String {
+    id.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Loads a key from a spec: `file:<path>` (or a bare path) reads 32 raw bytes from a key file,
+/// `passphrase:<text>` derives the key from a passphrase, and `passphrase:@<path>` reads the
+/// passphrase from a file.
+pub fn load_key(spec: &str, salt_path: &Path) -> Result<[u8; 32], CasError> {
+    if let Some(passphrase) = spec.strip_prefix("passphrase:") {
+        let passphrase = match passphrase.strip_prefix('@') {
+            Some(path) => fs::read_to_string(path)?.trim().to_string(),
+            None => passphrase.to_string(),
+        };
+        if passphrase.is_empty() {
+            return Err(CasError::InvalidKey("empty passphrase".to_string()));
+        }
+        let salt = load_or_create_salt(salt_path)?;
+        return Ok(derive_key(&passphrase, &salt, PASSPHRASE_ITERATIONS));
+    }
+    let path = spec.strip_prefix("file:").unwrap_or(spec);
+    let bytes = fs::read(path)?;
+    bytes
+        .get(..32)
+        .and_then(|k| k.try_into().ok())
+        .ok_or_else(|| CasError::InvalidKey(format!("{path} holds fewer than 32 bytes")))
+}
+
+/// PBKDF2-HMAC-SHA256 with a 32-byte output.
+pub fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    pbkdf2::pbkdf2_hmac_array::<Sha256, 32>(passphrase.as_bytes(), salt, iterations)
+}
+
+fn load_or_create_salt(path: &Path) -> Result<Vec<u8>, CasError> {
+    match fs::read(path) {
+        Ok(salt) if salt.len() >= SALT_LEN => return Ok(salt),
+        Ok(_) => {
+            return Err(CasError::InvalidKey(format!(
+                "{} is shorter than {SALT_LEN} bytes",
+                path.display()
+            )))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut salt = vec![0u8; SALT_LEN];
+    rand::rngs::OsRng.fill_bytes(&mut salt);
+    let parent = path.parent().unwrap_or(Path::new("."));
+    let mut tmp = tempfile::NamedTempFile::new_in(parent)?;
+    io::Write::write_all(&mut tmp, &salt)?;
+    tmp.as_file().sync_all()?;
+    // Another process may have created the salt first; whichever landed is the one to use.
+    if let Err(e) = tmp.persist_noclobber(path) {
+        if !path.exists() {
+            return Err(e.error.into());
+        }
+    }
+    Ok(fs::read(path)?)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pbkdf2_matches_rfc_7914_vector() {
+        let key = derive_key("passwd", b"salt", 1);
+        assert_eq!(hex_id(&key[..8].try_into().unwrap()), "55ac046e56e3089f");
+        assert_eq!(key[24..], [0xe6, 0x8b, 0x9d, 0x57, 0xc2, 0x0d, 0xac, 0xbc]);
+    }
+
+    #[test]
+    fn key_specs_load_files_and_passphrases() {
+        let dir = tempfile::tempdir().unwrap();
+        let salt = dir.path().join("cas").join("kek.salt");
+        let key_file = dir.path().join("cas.key");
+        fs::write(&key_file, [3u8; 32]).unwrap();
+        assert_eq!(
+            load_key(&format!("file:{}", key_file.display()), &salt).unwrap(),
+            [3u8; 32]
+        );
+        fs::write(&key_file, [3u8; 16]).unwrap();
+        assert!(matches!(
+            load_key(&key_file.display().to_string(), &salt),
+            Err(CasError::InvalidKey(_))
+        ));
+
+        let pass_file = dir.path().join("pass");
+        fs::write(&pass_file, "correct horse\n").unwrap();
+        let from_file = load_key(&format!("passphrase:@{}", pass_file.display()), &salt).unwrap();
+        let inline = load_key("passphrase:correct horse", &salt).unwrap();
+        assert_eq!(from_file, inline);
+        assert_eq!(fs::read(&salt).unwrap().len(), SALT_LEN);
+
+        let keyring = CasKeyring::new(&inline, &[[3u8; 32], inline]);
+        assert_eq!(keyring.active_id(), hex_id(&key_id(&inline)));
+        assert_eq!(keyring.retired_ids(), vec![hex_id(&key_id(&[3u8; 32]))]);
+    }
+}
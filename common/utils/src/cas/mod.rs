@@ -0,0 +1,740 @@
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+mod dir;
+mod keys;
+mod pack;
+
+pub use dir::DirBackend;
+pub use keys::{derive_key, hex_id, key_id, load_key, CasKeyring, KeyId, PASSPHRASE_ITERATIONS};
+pub use pack::{PackBackend, PackOptions};
+
+/// Blobs encrypted directly with a single key (no key ID); still read, never written.
+const ENCRYPTION_MAGIC: &[u8; 4] = b"ECAS";
+/// Envelope-encrypted blobs: `ECK1 | key id | data key nonce | wrapped data key | nonce |
+/// ciphertext`. The data key is random per blob and wrapped by the key-encryption key named by
+/// the key ID, so rotating keys only rewrites the wrapped key.
+const ENVELOPE_MAGIC: &[u8; 4] = b"ECK1";
+const NONCE_LEN: usize = 24;
+const KEY_ID_LEN: usize = 8;
+/// A 32-byte data key plus its authentication tag.
+const WRAPPED_KEY_LEN: usize = 48;
+const ENVELOPE_HEADER_LEN: usize =
+    ENVELOPE_MAGIC.len() + KEY_ID_LEN + NONCE_LEN + WRAPPED_KEY_LEN + NONCE_LEN;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CasError {
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid hash: {0}")]
+    InvalidHash(String),
+    #[error("encryption error")]
+    Encryption,
+    #[error("decryption error")]
+    Decryption,
+    #[error("encrypted blob but no key configured")]
+    MissingKey,
+    #[error("blob is encrypted with unknown key {0}")]
+    UnknownKey(String),
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+    #[error("content does not match hash {0}")]
+    HashMismatch(String),
+    #[error("corrupt pack: {0}")]
+    CorruptPack(String),
+}
+
+/// Where blob bytes live. Backends store exactly the bytes they are given (ciphertext when the
+/// store is encrypted) under an already validated, lowercase hex hash.
+pub trait CasBackend: Send + Sync + std::fmt::Debug {
+    /// Stores `bytes` under `hash`; a no-op when the hash is already present.
+    fn write(&self, hash: &str, bytes: &[u8]) -> Result<(), CasError>;
+    /// Swaps the stored bytes of a blob for a new encoding of the same content, e.g. after
+    /// re-encrypting it. Fails with an `io::ErrorKind::NotFound` error when the hash is absent,
+    /// including when it is removed concurrently, so a collected blob is never written back.
+    fn replace(&self, hash: &str, bytes: &[u8]) -> Result<(), CasError>;
+    /// Fails with an `io::ErrorKind::NotFound` error when the hash is absent.
+    fn read(&self, hash: &str) -> Result<Vec<u8>, CasError>;
+    fn contains(&self, hash: &str) -> Result<bool, CasError>;
+    /// Removing an absent hash is not an error.
+    fn remove(&self, hash: &str) -> Result<(), CasError>;
+    /// Resets a blob's write time to now, so the grace period before an unreferenced blob is
+    /// collected starts over. Returns false when the hash is absent.
+    fn touch(&self, hash: &str) -> Result<bool, CasError>;
+    /// Removes a blob unless it was written or touched at or after `cutoff`. Checked atomically
+    /// against [`Self::touch`]. Returns whether the blob was removed.
+    fn remove_if_older(&self, hash: &str, cutoff: SystemTime) -> Result<bool, CasError>;
+    /// Hashes starting with the byte `shard`, sorted, with the time each blob was written.
+    fn list_shard(&self, shard: u8) -> Result<Vec<(String, SystemTime)>, CasError>;
+    /// Reclaims space left behind by removed blobs.
+    fn compact(&self) -> Result<CompactionSummary, CasError> {
+        Ok(CompactionSummary::default())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionSummary {
+    pub packs_rewritten: u64,
+    pub bytes_reclaimed: u64,
+}
+
+/// What [`FsCas::rekey`] did to a blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rekeyed {
+    /// Already wrapped by the active key.
+    Current,
+    /// Data key re-wrapped with the active key; the ciphertext was kept.
+    Rewrapped,
+    /// Plaintext or single-key blob encrypted afresh under the active key.
+    Reencrypted,
+}
+
+/// Storage layout selected by a `cas_path` setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CasLayout {
+    /// One file per blob under a two-level fanout (`ab/cdef…`).
+    Files,
+    /// Blobs appended to indexed pack files.
+    Packs,
+}
+
+/// Splits a `cas_path` setting into its layout and directory: `pack:<dir>` selects pack files,
+/// `fs:<dir>` or a bare path one file per blob.
+pub fn parse_cas_path(spec: &str) -> (CasLayout, &Path) {
+    if let Some(dir) = spec.strip_prefix("pack:") {
+        (CasLayout::Packs, Path::new(dir))
+    } else {
+        (
+            CasLayout::Files,
+            Path::new(spec.strip_prefix("fs:").unwrap_or(spec)),
+        )
+    }
+}
+
+/// Content-addressed blob store: hashes and optionally encrypts blobs in front of a
+/// [`CasBackend`]. Clones share the backend and the keyring, so swapping keys with
+/// [`FsCas::set_keyring`] affects every handle.
+#[derive(Clone)]
+pub struct FsCas {
+    backend: Arc<dyn CasBackend>,
+    keyring: Arc<RwLock<Option<Arc<CasKeyring>>>>,
+}
+
+impl std::fmt::Debug for FsCas {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FsCas")
+            .field("backend", &self.backend)
+            .field("active_key", &self.active_key_id())
+            .finish()
+    }
+}
+
+impl FsCas {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self::with_backend(Arc::new(DirBackend::new(root)))
+    }
+
+    pub fn with_key(root: impl Into<PathBuf>, key: &[u8; 32]) -> Self {
+        Self::new(root).encrypted(key)
+    }
+
+    pub fn with_key_file(
+        root: impl Into<PathBuf>,
+        key_file: &std::path::Path,
+    ) -> Result<Self, CasError> {
+        let key_bytes = fs::read(key_file)?;
+        if key_bytes.len() < 32 {
+            return Err(CasError::Encryption);
+        }
+        let key: [u8; 32] = key_bytes[..32]
+            .try_into()
+            .map_err(|_| CasError::Encryption)?;
+        Ok(Self::with_key(root, &key))
+    }
+
+    pub fn with_backend(backend: Arc<dyn CasBackend>) -> Self {
+        Self {
+            backend,
+            keyring: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Opens the store a `cas_path` setting describes (see [`parse_cas_path`]).
+    pub fn open(spec: &str) -> Result<Self, CasError> {
+        let backend: Arc<dyn CasBackend> = match parse_cas_path(spec) {
+            (CasLayout::Files, dir) => Arc::new(DirBackend::new(dir)),
+            (CasLayout::Packs, dir) => Arc::new(PackBackend::open(dir, PackOptions::default())?),
+        };
+        Ok(Self::with_backend(backend))
+    }
+
+    /// Encrypts blobs written from now on with `key`.
+    pub fn encrypted(self, key: &[u8; 32]) -> Self {
+        self.with_keyring(CasKeyring::new(key, &[]))
+    }
+
+    pub fn with_keyring(self, keyring: CasKeyring) -> Self {
+        self.set_keyring(Some(keyring));
+        self
+    }
+
+    /// Replaces the keys of this store and every clone of it. Blobs written afterwards are
+    /// encrypted with the new active key; `None` stops encrypting new blobs.
+    pub fn set_keyring(&self, keyring: Option<CasKeyring>) {
+        let mut current = self
+            .keyring
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        *current = keyring.map(Arc::new);
+    }
+
+    pub fn keyring(&self) -> Option<Arc<CasKeyring>> {
+        self.keyring
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Hex ID of the key new blobs are encrypted with, if any.
+    pub fn active_key_id(&self) -> Option<String> {
+        self.keyring().map(|k| k.active_id())
+    }
+
+    pub fn put(&self, bytes: &[u8]) -> Result<String, CasError> {
+        let hash = sha256_hex(bytes);
+        // A reused blob may have been unreferenced for a while; restart its grace period so it
+        // is not collected before the frame that now refers to it is stored.
+        if self.backend.touch(&hash)? {
+            return Ok(hash);
+        }
+        let on_disk = match self.keyring() {
+            Some(keyring) => seal(&keyring, bytes)?,
+            None => bytes.to_vec(),
+        };
+        self.backend.write(&hash, &on_disk)?;
+        Ok(hash)
+    }
+
+    pub fn get(&self, hash: &str) -> Result<Vec<u8>, CasError> {
+        let raw = self.backend.read(&normalize_hash(hash)?)?;
+        self.maybe_decrypt(&raw)
+    }
+
+    pub fn contains(&self, hash: &str) -> Result<bool, CasError> {
+        self.backend.contains(&normalize_hash(hash)?)
+    }
+
+    pub fn remove(&self, hash: &str) -> Result<(), CasError> {
+        self.backend.remove(&normalize_hash(hash)?)
+    }
+
+    /// Removes a blob unless [`Self::put`] wrote or reused it at or after `cutoff`. Returns
+    /// whether it was removed.
+    pub fn remove_if_older(&self, hash: &str, cutoff: SystemTime) -> Result<bool, CasError> {
+        self.backend.remove_if_older(&normalize_hash(hash)?, cutoff)
+    }
+
+    /// Re-reads a blob and checks that its content still hashes to `hash`, decrypting first when
+    /// it is encrypted. Returns the plaintext size.
+    pub fn verify(&self, hash: &str) -> Result<u64, CasError> {
+        let bytes = self.get(hash)?;
+        if sha256_hex(&bytes) != hash.to_ascii_lowercase() {
+            return Err(CasError::HashMismatch(hash.to_string()));
+        }
+        Ok(bytes.len() as u64)
+    }
+
+    /// Brings a blob under the active key: envelope blobs wrapped by another key get their data
+    /// key re-wrapped, single-key and plaintext blobs are encrypted afresh after checking their
+    /// hash. Fails with [`CasError::MissingKey`] when no key is configured.
+    pub fn rekey(&self, hash: &str) -> Result<Rekeyed, CasError> {
+        let hash = normalize_hash(hash)?;
+        let keyring = self.keyring().ok_or(CasError::MissingKey)?;
+        let raw = self.backend.read(&hash)?;
+        let (rekeyed, on_disk) = if raw.starts_with(ENVELOPE_MAGIC) {
+            if raw.get(4..4 + KEY_ID_LEN) == Some(keyring.active().id.as_slice()) {
+                return Ok(Rekeyed::Current);
+            }
+            (Rekeyed::Rewrapped, rewrap(&keyring, &raw)?)
+        } else {
+            let plaintext = self.maybe_decrypt(&raw)?;
+            if sha256_hex(&plaintext) != hash {
+                return Err(CasError::HashMismatch(hash));
+            }
+            (Rekeyed::Reencrypted, seal(&keyring, &plaintext)?)
+        };
+        self.backend.replace(&hash, &on_disk)?;
+        Ok(rekeyed)
+    }
+
+    /// Blobs whose hash starts with the byte `shard`, with the time they were written.
+    /// Temporary files of in-progress writes are skipped.
+    pub fn list_shard(&self, shard: u8) -> Result<Vec<(String, SystemTime)>, CasError> {
+        self.backend.list_shard(shard)
+    }
+
+    /// Reclaims space left by removed blobs; blocking, and a no-op for the one-file-per-blob
+    /// layout.
+    pub fn compact(&self) -> Result<CompactionSummary, CasError> {
+        self.backend.compact()
+    }
+
+    fn maybe_decrypt(&self, data: &[u8]) -> Result<Vec<u8>, CasError> {
+        if !data.starts_with(ENVELOPE_MAGIC) && !data.starts_with(ENCRYPTION_MAGIC) {
+            return Ok(data.to_vec());
+        }
+        let keyring = self.keyring().ok_or(CasError::MissingKey)?;
+        if data.starts_with(ENVELOPE_MAGIC) {
+            return open_envelope(&keyring, data);
+        }
+
+        let header_len = ENCRYPTION_MAGIC.len() + NONCE_LEN;
+        if data.len() < header_len {
+            return Err(CasError::Decryption);
+        }
+        let nonce = chacha20poly1305::XNonce::from_slice(&data[ENCRYPTION_MAGIC.len()..header_len]);
+        let ciphertext = &data[header_len..];
+        // Single-key blobs carry no key ID, so every known key is tried.
+        let plaintext = keyring
+            .all()
+            .find_map(|kek| kek.cipher.decrypt(nonce, ciphertext).ok());
+        plaintext.ok_or(CasError::Decryption)
+    }
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+/// Additional data binding a wrapped data key to the key ID in front of it.
+fn wrap_aad(key_id: &[u8]) -> Vec<u8> {
+    [ENVELOPE_MAGIC.as_slice(), key_id].concat()
+}
+
+/// The part of an envelope naming `kek` and holding `data_key` wrapped by it.
+fn wrapped_key_header(kek: &keys::Kek, data_key: &[u8; 32]) -> Result<Vec<u8>, CasError> {
+    let key_nonce: [u8; NONCE_LEN] = random_bytes();
+    let wrapped = kek
+        .cipher
+        .encrypt(
+            &key_nonce.into(),
+            Payload {
+                msg: data_key,
+                aad: &wrap_aad(&kek.id),
+            },
+        )
+        .map_err(|_| CasError::Encryption)?;
+    let mut header = Vec::with_capacity(ENVELOPE_HEADER_LEN);
+    header.extend_from_slice(ENVELOPE_MAGIC);
+    header.extend_from_slice(&kek.id);
+    header.extend_from_slice(&key_nonce);
+    header.extend_from_slice(&wrapped);
+    Ok(header)
+}
+
+/// Encrypts `plaintext` under a fresh data key wrapped by the active key.
+fn seal(keyring: &CasKeyring, plaintext: &[u8]) -> Result<Vec<u8>, CasError> {
+    let data_key: [u8; 32] = random_bytes();
+    let nonce: [u8; NONCE_LEN] = random_bytes();
+    let ciphertext = XChaCha20Poly1305::new(&data_key.into())
+        .encrypt(&nonce.into(), plaintext)
+        .map_err(|_| CasError::Encryption)?;
+    let mut out = wrapped_key_header(keyring.active(), &data_key)?;
+    out.reserve(NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// The data key of an envelope, unwrapped with the key its header names.
+fn unwrap_data_key(keyring: &CasKeyring, data: &[u8]) -> Result<[u8; 32], CasError> {
+    if data.len() < ENVELOPE_HEADER_LEN {
+        return Err(CasError::Decryption);
+    }
+    let id_end = ENVELOPE_MAGIC.len() + KEY_ID_LEN;
+    let id: KeyId = data[ENVELOPE_MAGIC.len()..id_end]
+        .try_into()
+        .map_err(|_| CasError::Decryption)?;
+    let kek = keyring
+        .find(&id)
+        .ok_or_else(|| CasError::UnknownKey(hex_id(&id)))?;
+    let key_nonce = chacha20poly1305::XNonce::from_slice(&data[id_end..id_end + NONCE_LEN]);
+    let wrapped = &data[id_end + NONCE_LEN..id_end + NONCE_LEN + WRAPPED_KEY_LEN];
+    kek.cipher
+        .decrypt(
+            key_nonce,
+            Payload {
+                msg: wrapped,
+                aad: &wrap_aad(&id),
+            },
+        )
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or(CasError::Decryption)
+}
+
+fn open_envelope(keyring: &CasKeyring, data: &[u8]) -> Result<Vec<u8>, CasError> {
+    let data_key = unwrap_data_key(keyring, data)?;
+    let nonce_start = ENVELOPE_HEADER_LEN - NONCE_LEN;
+    let nonce = chacha20poly1305::XNonce::from_slice(&data[nonce_start..ENVELOPE_HEADER_LEN]);
+    XChaCha20Poly1305::new(&data_key.into())
+        .decrypt(nonce, &data[ENVELOPE_HEADER_LEN..])
+        .map_err(|_| CasError::Decryption)
+}
+
+/// The same envelope with its data key wrapped by the active key instead.
+fn rewrap(keyring: &CasKeyring, data: &[u8]) -> Result<Vec<u8>, CasError> {
+    let data_key = unwrap_data_key(keyring, data)?;
+    let mut out = wrapped_key_header(keyring.active(), &data_key)?;
+    out.extend_from_slice(&data[ENVELOPE_HEADER_LEN - NONCE_LEN..]);
+    Ok(out)
+}
+
+/// Checks that `hash` is 64 hex digits and lowercases it.
+fn normalize_hash(hash: &str) -> Result<String, CasError> {
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(CasError::InvalidHash(hash.to_string()));
+    }
+    Ok(hash.to_ascii_lowercase())
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cas_dedupes_identical_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let cas = FsCas::new(dir.path());
+
+        let blob = b"hello world";
+        let h1 = cas.put(blob).unwrap();
+        let h2 = cas.put(blob).unwrap();
+        assert_eq!(h1, h2);
+        assert!(cas.contains(&h1).unwrap());
+        assert_eq!(cas.get(&h1).unwrap(), blob);
+
+        // Physical layout should be stable.
+        let p = dir.path().join(&h1[0..2]).join(&h1[2..]);
+        assert!(p.exists());
+    }
+
+    mod proptests {
+        use super::*;
+        use proptest::prelude::*;
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(256))]
+
+            #[test]
+            fn prop_cas_round_trip(data in prop::collection::vec(0u8..=255, 1..=4096)) {
+                let dir = tempfile::tempdir().unwrap();
+                let cas = FsCas::new(dir.path());
+                let hash = cas.put(&data).unwrap();
+                let retrieved = cas.get(&hash).unwrap();
+                prop_assert_eq!(&data, &retrieved);
+                prop_assert!(cas.contains(&hash).unwrap());
+            }
+
+            #[test]
+            fn prop_cas_dedup_any_content(
+                data in prop::collection::vec(0u8..=255, 1..=2048),
+                repeats in 2u32..=5,
+            ) {
+                let dir = tempfile::tempdir().unwrap();
+                let cas = FsCas::new(dir.path());
+                let mut hashes = Vec::new();
+                for _ in 0..repeats {
+                    hashes.push(cas.put(&data).unwrap());
+                }
+                // All puts return the same hash
+                let first = &hashes[0];
+                for h in &hashes {
+                    prop_assert_eq!(h, first);
+                }
+                // Only one file on disk
+                prop_assert_eq!(cas.get(first).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = [42u8; 32];
+        let cas = FsCas::with_key(dir.path(), &key);
+
+        let blob = b"secret data";
+        let hash = cas.put(blob).unwrap();
+        let retrieved = cas.get(&hash).unwrap();
+        assert_eq!(retrieved, blob);
+    }
+
+    #[test]
+    fn ciphertext_differs_from_plaintext() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = [42u8; 32];
+        let cas = FsCas::with_key(dir.path(), &key);
+
+        let blob = b"secret data that should be encrypted";
+        let hash = cas.put(blob).unwrap();
+
+        let on_disk_path = dir.path().join(&hash[0..2]).join(&hash[2..]);
+        let raw = std::fs::read(on_disk_path).unwrap();
+        assert_ne!(raw, blob);
+        assert!(raw.starts_with(b"ECK1"));
+        assert_eq!(&raw[4..12], &key_id(&key));
+    }
+
+    #[test]
+    fn single_key_blobs_are_read_and_reencrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = [42u8; 32];
+        let blob = b"written before envelopes";
+        let hash = sha256_hex(blob);
+        let nonce = [1u8; NONCE_LEN];
+        let ciphertext = XChaCha20Poly1305::new(&key.into())
+            .encrypt(&nonce.into(), blob.as_slice())
+            .unwrap();
+        let path = dir.path().join(&hash[0..2]).join(&hash[2..]);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, [b"ECAS".as_slice(), &nonce, &ciphertext].concat()).unwrap();
+
+        let cas = FsCas::with_key(dir.path(), &key);
+        assert_eq!(cas.get(&hash).unwrap(), blob);
+        assert_eq!(cas.rekey(&hash).unwrap(), Rekeyed::Reencrypted);
+        assert!(std::fs::read(&path).unwrap().starts_with(b"ECK1"));
+        assert_eq!(cas.get(&hash).unwrap(), blob);
+    }
+
+    #[test]
+    fn rotation_rewraps_without_touching_ciphertext() {
+        let dir = tempfile::tempdir().unwrap();
+        let (old_key, new_key) = ([1u8; 32], [2u8; 32]);
+        let cas = FsCas::with_key(dir.path(), &old_key);
+        let hash = cas.put(b"rotate me").unwrap();
+        let plain = FsCas::new(dir.path()).put(b"never encrypted").unwrap();
+        let path = dir.path().join(&hash[0..2]).join(&hash[2..]);
+        let before = std::fs::read(&path).unwrap();
+
+        let reader = cas.clone();
+        cas.set_keyring(Some(CasKeyring::new(&new_key, &[old_key])));
+        assert_eq!(reader.active_key_id(), Some(hex_id(&key_id(&new_key))));
+        assert_eq!(reader.get(&hash).unwrap(), b"rotate me");
+        assert_eq!(cas.rekey(&hash).unwrap(), Rekeyed::Rewrapped);
+        assert_eq!(cas.rekey(&hash).unwrap(), Rekeyed::Current);
+        assert_eq!(cas.rekey(&plain).unwrap(), Rekeyed::Reencrypted);
+
+        let after = std::fs::read(&path).unwrap();
+        assert_eq!(&after[4..12], &key_id(&new_key));
+        assert_eq!(
+            after[ENVELOPE_HEADER_LEN - NONCE_LEN..],
+            before[ENVELOPE_HEADER_LEN - NONCE_LEN..]
+        );
+
+        cas.set_keyring(Some(CasKeyring::new(&new_key, &[])));
+        assert_eq!(cas.get(&hash).unwrap(), b"rotate me");
+        assert_eq!(cas.get(&plain).unwrap(), b"never encrypted");
+        let stale = FsCas::with_key(dir.path(), &old_key);
+        assert!(matches!(stale.get(&hash), Err(CasError::UnknownKey(_))));
+        cas.set_keyring(None);
+        assert!(matches!(cas.rekey(&hash), Err(CasError::MissingKey)));
+    }
+
+    #[test]
+    fn reusing_a_blob_restarts_its_grace_period() {
+        let before = SystemTime::now() - std::time::Duration::from_secs(5);
+        let after = SystemTime::now() + std::time::Duration::from_secs(5);
+        for layout in ["fs", "pack"] {
+            let dir = tempfile::tempdir().unwrap();
+            let root = dir.path().join("cas");
+            let cas = FsCas::open(&format!("{layout}:{}", root.display())).unwrap();
+            let reused = cas.put(b"same screenshot").unwrap();
+            let dropped = cas.put(b"old screenshot").unwrap();
+            if layout == "fs" {
+                // Age both blobs past the cutoff, as if written long ago.
+                for hash in [&reused, &dropped] {
+                    std::fs::File::options()
+                        .write(true)
+                        .open(root.join(&hash[0..2]).join(&hash[2..]))
+                        .unwrap()
+                        .set_modified(SystemTime::UNIX_EPOCH)
+                        .unwrap();
+                }
+                assert!(cas.remove_if_older(&dropped, before).unwrap());
+            }
+
+            assert_eq!(cas.put(b"same screenshot").unwrap(), reused);
+            assert!(!cas.remove_if_older(&reused, before).unwrap());
+            assert!(cas.contains(&reused).unwrap());
+
+            assert!(cas.remove_if_older(&reused, after).unwrap());
+            assert!(!cas.contains(&reused).unwrap());
+            assert!(!cas.remove_if_older(&reused, after).unwrap());
+        }
+    }
+
+    #[test]
+    fn replacing_a_removed_blob_does_not_bring_it_back() {
+        for layout in ["fs", "pack"] {
+            let dir = tempfile::tempdir().unwrap();
+            let root = dir.path().join("cas");
+            let cas = FsCas::open(&format!("{layout}:{}", root.display())).unwrap();
+            let hash = cas.put(b"collected").unwrap();
+            cas.remove(&hash).unwrap();
+            assert!(matches!(
+                cas.backend.replace(&hash, b"rewritten"),
+                Err(CasError::Io(e)) if e.kind() == io::ErrorKind::NotFound
+            ));
+            assert!(!cas.contains(&hash).unwrap());
+        }
+    }
+
+    #[test]
+    fn backward_compat_reads_unencrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain_cas = FsCas::new(dir.path());
+        let blob = b"old plaintext blob";
+        let hash = plain_cas.put(blob).unwrap();
+
+        let key = [42u8; 32];
+        let encrypted_cas = FsCas::with_key(dir.path(), &key);
+        let retrieved = encrypted_cas.get(&hash).unwrap();
+        assert_eq!(retrieved, blob);
+    }
+
+    #[test]
+    fn encrypted_blob_without_key_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = [42u8; 32];
+        let cas_enc = FsCas::with_key(dir.path(), &key);
+
+        let blob = b"secret";
+        let hash = cas_enc.put(blob).unwrap();
+
+        let cas_plain = FsCas::new(dir.path());
+        let err = cas_plain.get(&hash).unwrap_err();
+        assert!(matches!(err, CasError::MissingKey));
+    }
+
+    #[test]
+    fn with_key_file_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("test.key");
+        std::fs::write(&key_path, [7u8; 32]).unwrap();
+
+        let cas = FsCas::with_key_file(dir.path().join("store"), &key_path).unwrap();
+        let hash = cas.put(b"via key file").unwrap();
+        assert_eq!(cas.get(&hash).unwrap(), b"via key file");
+    }
+
+    #[test]
+    fn verify_and_list_shard_find_corruption() {
+        let dir = tempfile::tempdir().unwrap();
+        let cas = FsCas::with_key(dir.path(), &[9u8; 32]);
+        let good = cas.put(b"intact").unwrap();
+        let bad = cas.put(b"will be damaged").unwrap();
+        assert_eq!(cas.verify(&good).unwrap(), 6);
+
+        let bad_path = dir.path().join(&bad[0..2]).join(&bad[2..]);
+        let mut raw = std::fs::read(&bad_path).unwrap();
+        let last = raw.len() - 1;
+        raw[last] ^= 0xff;
+        std::fs::write(&bad_path, raw).unwrap();
+        assert!(matches!(cas.verify(&bad), Err(CasError::Decryption)));
+
+        let plain = FsCas::new(dir.path().join("plain"));
+        let hash = plain.put(b"plain blob").unwrap();
+        let path = dir.path().join("plain").join(&hash[0..2]).join(&hash[2..]);
+        std::fs::write(&path, b"other bytes").unwrap();
+        assert!(matches!(
+            plain.verify(&hash),
+            Err(CasError::HashMismatch(_))
+        ));
+
+        let shard = u8::from_str_radix(&good[0..2], 16).unwrap();
+        std::fs::write(dir.path().join(&good[0..2]).join(".tmpXYZ"), b"partial").unwrap();
+        let listed: Vec<String> = cas
+            .list_shard(shard)
+            .unwrap()
+            .into_iter()
+            .map(|(h, _)| h)
+            .collect();
+        assert!(listed.contains(&good));
+        assert!(listed.iter().all(|h| h.len() == 64));
+        assert!(cas.list_shard(shard.wrapping_add(1)).is_ok());
+    }
+
+    #[test]
+    fn open_selects_layout_from_cas_path() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            parse_cas_path("fs:/srv/cas"),
+            (CasLayout::Files, Path::new("/srv/cas"))
+        );
+        assert_eq!(
+            parse_cas_path("/srv/cas"),
+            (CasLayout::Files, Path::new("/srv/cas"))
+        );
+
+        let spec = format!("pack:{}", dir.path().join("packs").display());
+        let cas = FsCas::open(&spec).unwrap().encrypted(&[5u8; 32]);
+        let hash = cas.put(b"packed and encrypted").unwrap();
+        assert_eq!(cas.verify(&hash).unwrap(), 20);
+        assert!(dir.path().join("packs").join("pack-00000001.pack").exists());
+        assert!(!dir.path().join("packs").join(&hash[0..2]).exists());
+
+        let reopened = FsCas::open(&spec).unwrap().encrypted(&[5u8; 32]);
+        assert_eq!(
+            reopened.get(&hash.to_uppercase()).unwrap(),
+            b"packed and encrypted"
+        );
+        reopened.set_keyring(Some(CasKeyring::new(&[6u8; 32], &[[5u8; 32]])));
+        assert_eq!(reopened.rekey(&hash).unwrap(), Rekeyed::Rewrapped);
+        let rotated = FsCas::open(&spec).unwrap().encrypted(&[6u8; 32]);
+        assert_eq!(rotated.verify(&hash).unwrap(), 20);
+        reopened.remove(&hash).unwrap();
+        assert!(!reopened.contains(&hash).unwrap());
+    }
+
+    #[test]
+    fn test_cas_concurrent_puts() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let dir = tempfile::tempdir().unwrap();
+        let cas = Arc::new(FsCas::new(dir.path()));
+        let data = b"concurrent-data".to_vec();
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let cas = Arc::clone(&cas);
+                let data = data.clone();
+                thread::spawn(move || cas.put(&data).unwrap())
+            })
+            .collect();
+
+        let hashes: Vec<String> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        // All threads produce the same hash
+        for h in &hashes {
+            assert_eq!(h, &hashes[0]);
+        }
+        assert_eq!(cas.get(&hashes[0]).unwrap(), data);
+    }
+}
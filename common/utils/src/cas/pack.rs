@@ -0,0 +1,1025 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use super::{CasBackend, CasError, CompactionSummary};
+
+const PACK_MAGIC: &[u8; 4] = b"LPAK";
+const INDEX_MAGIC: &[u8; 4] = b"LIDX";
+const FORMAT_VERSION: u32 = 1;
+/// Magic, format version and the pack's random id.
+const PACK_HEADER_LEN: u64 = 16;
+/// Tag, hash, data length and write time (unix seconds).
+const RECORD_HEADER_LEN: usize = 1 + 32 + 8 + 8;
+/// Truncated SHA-256 of a record's header and data, or of an index body.
+const CHECKSUM_LEN: usize = 8;
+/// Magic, format version, pack id, pack length and entry count.
+const INDEX_HEADER_LEN: usize = 4 + 4 + 8 + 8 + 8;
+const INDEX_ENTRY_LEN: usize = 1 + 32 + 8 + 8 + 8;
+
+const TAG_BLOB: u8 = 1;
+const TAG_TOMBSTONE: u8 = 2;
+
+type Key = [u8; 32];
+/// A live blob copied by compaction: its key, old location and new offset.
+type Moved = (Key, Location, u64);
+
+#[derive(Debug, Clone)]
+pub struct PackOptions {
+    /// The pack being appended to is sealed once the next record would take it past this size.
+    pub max_pack_bytes: u64,
+    /// Sealed packs where at least this share of the bytes belongs to removed or superseded
+    /// blobs are rewritten by compaction.
+    pub compact_dead_ratio: f64,
+}
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        Self {
+            max_pack_bytes: 256 * 1024 * 1024,
+            compact_dead_ratio: 0.3,
+        }
+    }
+}
+
+/// Blobs appended to a few large pack files instead of one file each.
+///
+/// `pack-NNNNNNNN.pack` files hold checksummed records: a blob, or a tombstone recording its
+/// removal. Records are only ever appended and synced before a write returns; a torn record at
+/// the end of the newest pack is truncated when the store is opened. Sealed packs get a
+/// `.idx` file listing their records so opening does not have to read them, and the index of
+/// every blob's location is kept in memory. Compaction rewrites sealed packs that are mostly
+/// dead under the same sequence number, then swaps them in with a rename.
+pub struct PackBackend {
+    root: PathBuf,
+    options: PackOptions,
+    state: RwLock<PackState>,
+    /// Serialises compactions; sealed packs are only ever rewritten while it is held.
+    compaction: Mutex<()>,
+}
+
+impl std::fmt::Debug for PackBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PackBackend")
+            .field("root", &self.root)
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+#[derive(Debug, Default)]
+struct PackState {
+    index: HashMap<Key, Location>,
+    packs: BTreeMap<u32, PackInfo>,
+    /// Packs that could not be read when the store was opened.
+    unreadable: BTreeSet<u32>,
+    active: Option<ActivePack>,
+    next_seq: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Location {
+    seq: u32,
+    offset: u64,
+    len: u64,
+    mtime: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PackInfo {
+    id: u64,
+    len: u64,
+    live_bytes: u64,
+    tombstone_bytes: u64,
+}
+
+impl PackInfo {
+    fn dead_bytes(&self) -> u64 {
+        self.len
+            .saturating_sub(PACK_HEADER_LEN + self.live_bytes + self.tombstone_bytes)
+    }
+}
+
+#[derive(Debug)]
+struct ActivePack {
+    seq: u32,
+    file: File,
+    /// Records appended so far, written out as the index when the pack is sealed.
+    entries: Vec<Entry>,
+}
+
+/// A record as listed in a pack or its index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    tag: u8,
+    key: Key,
+    offset: u64,
+    len: u64,
+    mtime: u64,
+}
+
+struct LoadedPack {
+    id: u64,
+    len: u64,
+    entries: Vec<Entry>,
+    sealed: bool,
+}
+
+impl PackBackend {
+    /// Opens the packs under `root`, creating the directory if needed, and rebuilds the index.
+    pub fn open(root: impl Into<PathBuf>, options: PackOptions) -> Result<Self, CasError> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        let mut seqs = Vec::new();
+        for entry in fs::read_dir(&root)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if name.ends_with(".tmp") {
+                // Left behind by a seal or compaction that never finished.
+                fs::remove_file(entry.path())?;
+            } else if let Some(seq) = name
+                .strip_prefix("pack-")
+                .and_then(|n| n.strip_suffix(".pack"))
+                .and_then(|n| n.parse::<u32>().ok())
+            {
+                seqs.push(seq);
+            }
+        }
+        seqs.sort_unstable();
+
+        let mut backend = Self {
+            root,
+            options,
+            state: RwLock::new(PackState::default()),
+            compaction: Mutex::new(()),
+        };
+        let mut state = PackState {
+            next_seq: seqs.last().map_or(1, |s| s + 1),
+            ..PackState::default()
+        };
+        let newest = seqs.last().copied();
+        for seq in seqs {
+            let loaded = match backend.load_pack(seq, Some(seq) == newest) {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    tracing::warn!(
+                        pack = %backend.pack_path(seq).display(),
+                        error = %e,
+                        "Skipping unreadable CAS pack"
+                    );
+                    state.unreadable.insert(seq);
+                    continue;
+                }
+            };
+            let mut tombstone_bytes = 0;
+            for entry in &loaded.entries {
+                if entry.tag == TAG_BLOB {
+                    state.index.insert(entry.key, entry.location(seq));
+                } else {
+                    state.index.remove(&entry.key);
+                    tombstone_bytes += record_len(0);
+                }
+            }
+            state.packs.insert(
+                seq,
+                PackInfo {
+                    id: loaded.id,
+                    len: loaded.len,
+                    live_bytes: 0,
+                    tombstone_bytes,
+                },
+            );
+            if !loaded.sealed {
+                state.active = Some(ActivePack {
+                    seq,
+                    file: OpenOptions::new()
+                        .append(true)
+                        .open(backend.pack_path(seq))?,
+                    entries: loaded.entries,
+                });
+            }
+        }
+        for loc in state.index.values() {
+            if let Some(info) = state.packs.get_mut(&loc.seq) {
+                info.live_bytes += record_len(loc.len);
+            }
+        }
+        backend.state = RwLock::new(state);
+        Ok(backend)
+    }
+
+    fn pack_path(&self, seq: u32) -> PathBuf {
+        self.root.join(format!("pack-{seq:08}.pack"))
+    }
+
+    fn index_path(&self, seq: u32) -> PathBuf {
+        self.root.join(format!("pack-{seq:08}.idx"))
+    }
+
+    fn read_state(&self) -> Result<RwLockReadGuard<'_, PackState>, CasError> {
+        self.state.read().map_err(|_| poisoned())
+    }
+
+    fn write_state(&self) -> Result<RwLockWriteGuard<'_, PackState>, CasError> {
+        self.state.write().map_err(|_| poisoned())
+    }
+
+    /// Reads a pack's records from its index, or by scanning it when the index is missing or
+    /// stale. The newest pack is left unsealed unless full; a torn record at its end is cut off.
+    fn load_pack(&self, seq: u32, newest: bool) -> Result<LoadedPack, CasError> {
+        let path = self.pack_path(seq);
+        let mut len = fs::metadata(&path)?.len();
+        let id = read_pack_id(&path)?;
+        if let Some(entries) = self.load_index(seq, id, len) {
+            return Ok(LoadedPack {
+                id,
+                len,
+                entries,
+                sealed: true,
+            });
+        }
+
+        let (entries, valid_len) = scan_pack(&path, len, newest)?;
+        if valid_len < len {
+            if newest {
+                tracing::warn!(
+                    pack = %path.display(),
+                    offset = valid_len,
+                    "Truncating incomplete write at the end of CAS pack"
+                );
+                let file = OpenOptions::new().write(true).open(&path)?;
+                file.set_len(valid_len)?;
+                file.sync_all()?;
+                len = valid_len;
+            } else {
+                tracing::warn!(
+                    pack = %path.display(),
+                    offset = valid_len,
+                    "CAS pack is unreadable past offset; later blobs in it are lost"
+                );
+            }
+        }
+        let sealed = !newest || len >= self.options.max_pack_bytes;
+        if sealed {
+            self.write_index(seq, id, len, &entries)?;
+        }
+        Ok(LoadedPack {
+            id,
+            len,
+            entries,
+            sealed,
+        })
+    }
+
+    /// The records listed in a pack's index, if it exists and describes this exact pack.
+    fn load_index(&self, seq: u32, id: u64, len: u64) -> Option<Vec<Entry>> {
+        let bytes = fs::read(self.index_path(seq)).ok()?;
+        if bytes.len() < INDEX_HEADER_LEN + CHECKSUM_LEN {
+            return None;
+        }
+        let (body, sum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+        if checksum(&[body]) != sum
+            || &body[0..4] != INDEX_MAGIC
+            || le_u32(&body[4..8]) != FORMAT_VERSION
+            || le_u64(&body[8..16]) != id
+            || le_u64(&body[16..24]) != len
+        {
+            return None;
+        }
+        let count = usize::try_from(le_u64(&body[24..32])).ok()?;
+        if body.len() != INDEX_HEADER_LEN + count.checked_mul(INDEX_ENTRY_LEN)? {
+            return None;
+        }
+        body[INDEX_HEADER_LEN..]
+            .chunks_exact(INDEX_ENTRY_LEN)
+            .map(|e| {
+                Some(Entry {
+                    tag: e[0],
+                    key: e[1..33].try_into().ok()?,
+                    offset: le_u64(&e[33..41]),
+                    len: le_u64(&e[41..49]),
+                    mtime: le_u64(&e[49..57]),
+                })
+            })
+            .collect()
+    }
+
+    fn write_index(&self, seq: u32, id: u64, len: u64, entries: &[Entry]) -> io::Result<()> {
+        let mut body = Vec::with_capacity(INDEX_HEADER_LEN + entries.len() * INDEX_ENTRY_LEN);
+        body.extend_from_slice(INDEX_MAGIC);
+        body.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        body.extend_from_slice(&id.to_le_bytes());
+        body.extend_from_slice(&len.to_le_bytes());
+        body.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+        for entry in entries {
+            body.push(entry.tag);
+            body.extend_from_slice(&entry.key);
+            body.extend_from_slice(&entry.offset.to_le_bytes());
+            body.extend_from_slice(&entry.len.to_le_bytes());
+            body.extend_from_slice(&entry.mtime.to_le_bytes());
+        }
+        let sum = checksum(&[&body]);
+        body.extend_from_slice(&sum);
+        write_atomically(&self.root, &self.index_path(seq), &body)
+    }
+
+    /// Writes the index of the pack being appended to, so it is never appended to again.
+    fn seal(&self, state: &mut PackState) -> Result<(), CasError> {
+        if let Some(active) = state.active.take() {
+            if let Some(info) = state.packs.get(&active.seq) {
+                self.write_index(active.seq, info.id, info.len, &active.entries)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn start_pack(&self, state: &mut PackState) -> Result<(), CasError> {
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        let id = rand::rngs::OsRng.next_u64();
+        let path = self.pack_path(seq);
+        write_atomically(&self.root, &path, &pack_header(id))?;
+        let file = OpenOptions::new().append(true).open(&path)?;
+        state.packs.insert(
+            seq,
+            PackInfo {
+                id,
+                len: PACK_HEADER_LEN,
+                live_bytes: 0,
+                tombstone_bytes: 0,
+            },
+        );
+        state.active = Some(ActivePack {
+            seq,
+            file,
+            entries: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Records the removal of the blob at `loc` and drops it from the index.
+    fn tombstone(&self, state: &mut PackState, key: Key, loc: Location) -> Result<(), CasError> {
+        self.append(state, TAG_TOMBSTONE, key, &[])?;
+        state.index.remove(&key);
+        if let Some(info) = state.packs.get_mut(&loc.seq) {
+            info.live_bytes = info.live_bytes.saturating_sub(record_len(loc.len));
+        }
+        Ok(())
+    }
+
+    /// Appends a record to the active pack, sealing it and starting another when it is full,
+    /// and syncs it to disk.
+    fn append(
+        &self,
+        state: &mut PackState,
+        tag: u8,
+        key: Key,
+        data: &[u8],
+    ) -> Result<Location, CasError> {
+        let size = record_len(data.len() as u64);
+        let full = match &state.active {
+            Some(active) => state.packs.get(&active.seq).is_none_or(|info| {
+                info.len > PACK_HEADER_LEN && info.len + size > self.options.max_pack_bytes
+            }),
+            None => true,
+        };
+        if full {
+            self.seal(state)?;
+            self.start_pack(state)?;
+        }
+
+        let PackState { active, packs, .. } = state;
+        let active = active
+            .as_mut()
+            .ok_or_else(|| io::Error::other("no pack open for writing"))?;
+        let info = packs
+            .get_mut(&active.seq)
+            .ok_or_else(|| io::Error::other("pack open for writing is not tracked"))?;
+        let entry = Entry {
+            tag,
+            key,
+            offset: info.len,
+            len: data.len() as u64,
+            mtime: now_secs(),
+        };
+        let result = active
+            .file
+            .write_all(&encode_record(&entry, data))
+            .and_then(|()| active.file.sync_data());
+        if let Err(e) = result {
+            // Cut off whatever part of the record reached the file so the next append starts
+            // on a record boundary.
+            let _ = active.file.set_len(info.len);
+            return Err(e.into());
+        }
+        info.len += size;
+        if tag == TAG_TOMBSTONE {
+            info.tombstone_bytes += size;
+        } else {
+            info.live_bytes += size;
+        }
+        active.entries.push(entry);
+        Ok(entry.location(active.seq))
+    }
+
+    /// Reads the blob record for `key` at `offset` and checks its checksum.
+    fn read_record(&self, seq: u32, offset: u64, key: &Key) -> Result<Vec<u8>, CasError> {
+        let mut file = File::open(self.pack_path(seq))?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        file.read_exact(&mut header)?;
+        let entry = decode_header(&header, offset)
+            .filter(|e| e.tag == TAG_BLOB && e.key == *key)
+            .ok_or_else(|| {
+                CasError::CorruptPack(format!("pack {seq} offset {offset}: unexpected record"))
+            })?;
+        let len = usize::try_from(entry.len)
+            .map_err(|_| CasError::CorruptPack(format!("pack {seq} offset {offset}: too long")))?;
+        let mut data = vec![0u8; len];
+        file.read_exact(&mut data)?;
+        let mut sum = [0u8; CHECKSUM_LEN];
+        file.read_exact(&mut sum)?;
+        if checksum(&[&header, &data]) != sum {
+            return Err(CasError::CorruptPack(format!(
+                "pack {seq} offset {offset}: checksum mismatch"
+            )));
+        }
+        Ok(data)
+    }
+
+    /// Rewrites sealed pack `seq` with only its live blobs, under the same sequence number so
+    /// it keeps its place in replay order. Tombstones are kept unless no older pack is left for
+    /// them to apply to. Returns the bytes reclaimed.
+    fn compact_pack(&self, seq: u32) -> Result<u64, CasError> {
+        let (info, mut live, oldest) = {
+            let state = self.read_state()?;
+            let Some(info) = state.packs.get(&seq).copied() else {
+                return Ok(0);
+            };
+            let live: Vec<(Key, Location)> = state
+                .index
+                .iter()
+                .filter(|(_, loc)| loc.seq == seq)
+                .map(|(key, loc)| (*key, *loc))
+                .collect();
+            let oldest = state.packs.range(..seq).next().is_none()
+                && state.unreadable.range(..seq).next().is_none();
+            (info, live, oldest)
+        };
+        live.sort_by_key(|(_, loc)| loc.offset);
+
+        let mut tombstones: Vec<Entry> = Vec::new();
+        if !oldest {
+            let entries = match self.load_index(seq, info.id, info.len) {
+                Some(entries) => entries,
+                None => scan_pack(&self.pack_path(seq), info.len, false)?.0,
+            };
+            let mut seen = BTreeSet::new();
+            tombstones = entries
+                .into_iter()
+                .filter(|e| e.tag == TAG_TOMBSTONE && seen.insert(e.key))
+                .collect();
+        }
+
+        let path = self.pack_path(seq);
+        if live.is_empty() && tombstones.is_empty() {
+            let mut state = self.write_state()?;
+            if state.index.values().any(|loc| loc.seq == seq) {
+                return Ok(0);
+            }
+            state.packs.remove(&seq);
+            fs::remove_file(&path)?;
+            match fs::remove_file(self.index_path(seq)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+            sync_dir(&self.root)?;
+            return Ok(info.len);
+        }
+
+        let id = rand::rngs::OsRng.next_u64();
+        let tmp = tmp_path(&path);
+        let written = self.write_compacted(&tmp, id, seq, &tombstones, &live);
+        let (entries, moved) = match written {
+            Ok(written) => written,
+            Err(e) => {
+                let _ = fs::remove_file(&tmp);
+                return Err(e);
+            }
+        };
+        let len = entries
+            .last()
+            .map_or(PACK_HEADER_LEN, |e| e.offset + record_len(e.len));
+
+        let mut state = self.write_state()?;
+        fs::rename(&tmp, &path)?;
+        sync_dir(&self.root)?;
+        let mut live_bytes = 0;
+        for (key, old, offset) in moved {
+            if state.index.get(&key) == Some(&old) {
+                state.index.insert(key, Location { offset, ..old });
+                live_bytes += record_len(old.len);
+            }
+        }
+        state.packs.insert(
+            seq,
+            PackInfo {
+                id,
+                len,
+                live_bytes,
+                tombstone_bytes: tombstones.len() as u64 * record_len(0),
+            },
+        );
+        drop(state);
+        // A crash before the new index lands just means the pack is scanned on the next open.
+        self.write_index(seq, id, len, &entries)?;
+        Ok(info.len.saturating_sub(len))
+    }
+
+    /// Writes the compacted copy of pack `seq` to `tmp`: tombstones first, then live blobs in
+    /// their original order. Returns its records and where each live blob moved to.
+    fn write_compacted(
+        &self,
+        tmp: &Path,
+        id: u64,
+        seq: u32,
+        tombstones: &[Entry],
+        live: &[(Key, Location)],
+    ) -> Result<(Vec<Entry>, Vec<Moved>), CasError> {
+        let mut out = BufWriter::new(File::create(tmp)?);
+        out.write_all(&pack_header(id))?;
+        let mut offset = PACK_HEADER_LEN;
+        let mut entries = Vec::with_capacity(tombstones.len() + live.len());
+        let mut moved = Vec::with_capacity(live.len());
+        for tombstone in tombstones {
+            let entry = Entry {
+                offset,
+                ..*tombstone
+            };
+            out.write_all(&encode_record(&entry, &[]))?;
+            offset += record_len(0);
+            entries.push(entry);
+        }
+        for (key, loc) in live {
+            let data = self.read_record(seq, loc.offset, key)?;
+            let entry = Entry {
+                tag: TAG_BLOB,
+                key: *key,
+                offset,
+                len: loc.len,
+                mtime: loc.mtime,
+            };
+            out.write_all(&encode_record(&entry, &data))?;
+            offset += record_len(loc.len);
+            entries.push(entry);
+            moved.push((*key, *loc, entry.offset));
+        }
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Ok((entries, moved))
+    }
+}
+
+impl CasBackend for PackBackend {
+    fn write(&self, hash: &str, bytes: &[u8]) -> Result<(), CasError> {
+        let key = parse_key(hash)?;
+        let mut state = self.write_state()?;
+        if state.index.contains_key(&key) {
+            return Ok(());
+        }
+        let loc = self.append(&mut state, TAG_BLOB, key, bytes)?;
+        state.index.insert(key, loc);
+        Ok(())
+    }
+
+    fn replace(&self, hash: &str, bytes: &[u8]) -> Result<(), CasError> {
+        let key = parse_key(hash)?;
+        let mut state = self.write_state()?;
+        let Some(old) = state.index.get(&key).copied() else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("blob {hash} is not in any pack"),
+            )
+            .into());
+        };
+        // The newer record wins when the packs are replayed; the old one is left for compaction.
+        let loc = self.append(&mut state, TAG_BLOB, key, bytes)?;
+        state.index.insert(key, loc);
+        if let Some(info) = state.packs.get_mut(&old.seq) {
+            info.live_bytes = info.live_bytes.saturating_sub(record_len(old.len));
+        }
+        Ok(())
+    }
+
+    fn read(&self, hash: &str) -> Result<Vec<u8>, CasError> {
+        let key = parse_key(hash)?;
+        // Held across the read so compaction cannot swap the pack out underneath it.
+        let state = self.read_state()?;
+        let loc = state.index.get(&key).copied().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("blob {hash} is not in any pack"),
+            )
+        })?;
+        self.read_record(loc.seq, loc.offset, &key)
+    }
+
+    fn contains(&self, hash: &str) -> Result<bool, CasError> {
+        let key = parse_key(hash)?;
+        Ok(self.read_state()?.index.contains_key(&key))
+    }
+
+    fn remove(&self, hash: &str) -> Result<(), CasError> {
+        let key = parse_key(hash)?;
+        let mut state = self.write_state()?;
+        let Some(loc) = state.index.get(&key).copied() else {
+            return Ok(());
+        };
+        self.tombstone(&mut state, key, loc)
+    }
+
+    /// Only the in-memory write time moves; after a restart the blob's record time applies
+    /// again. A reused blob is referenced by then.
+    fn touch(&self, hash: &str) -> Result<bool, CasError> {
+        let key = parse_key(hash)?;
+        let mut state = self.write_state()?;
+        Ok(match state.index.get_mut(&key) {
+            Some(loc) => {
+                loc.mtime = now_secs();
+                true
+            }
+            None => false,
+        })
+    }
+
+    fn remove_if_older(&self, hash: &str, cutoff: SystemTime) -> Result<bool, CasError> {
+        let key = parse_key(hash)?;
+        let mut state = self.write_state()?;
+        let Some(loc) = state.index.get(&key).copied() else {
+            return Ok(false);
+        };
+        if UNIX_EPOCH + Duration::from_secs(loc.mtime) >= cutoff {
+            return Ok(false);
+        }
+        self.tombstone(&mut state, key, loc)?;
+        Ok(true)
+    }
+
+    fn list_shard(&self, shard: u8) -> Result<Vec<(String, SystemTime)>, CasError> {
+        let state = self.read_state()?;
+        let mut blobs: Vec<(String, SystemTime)> = state
+            .index
+            .iter()
+            .filter(|(key, _)| key[0] == shard)
+            .map(|(key, loc)| (hex(key), UNIX_EPOCH + Duration::from_secs(loc.mtime)))
+            .collect();
+        blobs.sort();
+        Ok(blobs)
+    }
+
+    fn compact(&self) -> Result<CompactionSummary, CasError> {
+        let _compacting = self.compaction.lock().map_err(|_| poisoned())?;
+        let candidates: Vec<u32> = {
+            let state = self.read_state()?;
+            let active = state.active.as_ref().map(|a| a.seq);
+            state
+                .packs
+                .iter()
+                .filter(|(seq, info)| {
+                    let payload = info.len.saturating_sub(PACK_HEADER_LEN);
+                    Some(**seq) != active
+                        && info.dead_bytes() > 0
+                        && info.dead_bytes() as f64
+                            >= payload as f64 * self.options.compact_dead_ratio
+                })
+                .map(|(seq, _)| *seq)
+                .collect()
+        };
+
+        let mut summary = CompactionSummary::default();
+        for seq in candidates {
+            match self.compact_pack(seq) {
+                Ok(reclaimed) => {
+                    summary.packs_rewritten += 1;
+                    summary.bytes_reclaimed += reclaimed;
+                }
+                Err(e) => tracing::warn!(
+                    pack = %self.pack_path(seq).display(),
+                    error = %e,
+                    "CAS pack compaction failed"
+                ),
+            }
+        }
+        Ok(summary)
+    }
+}
+
+impl Entry {
+    fn location(&self, seq: u32) -> Location {
+        Location {
+            seq,
+            offset: self.offset,
+            len: self.len,
+            mtime: self.mtime,
+        }
+    }
+}
+
+/// Reads the records of a pack front to back. With `stop_at_bad` the scan ends at the first
+/// record failing its checksum (a torn append); otherwise such records are skipped. Returns the
+/// records and the offset the scan stopped at.
+fn scan_pack(path: &Path, file_len: u64, stop_at_bad: bool) -> Result<(Vec<Entry>, u64), CasError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    reader.seek(SeekFrom::Start(PACK_HEADER_LEN))?;
+    let mut offset = PACK_HEADER_LEN;
+    let mut entries = Vec::new();
+    while offset + (RECORD_HEADER_LEN + CHECKSUM_LEN) as u64 <= file_len {
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        let Some(entry) = decode_header(&header, offset) else {
+            break;
+        };
+        let Some(end) = offset
+            .checked_add(record_len(entry.len))
+            .filter(|end| *end <= file_len)
+        else {
+            break;
+        };
+        let mut data = vec![0u8; (entry.len) as usize];
+        reader.read_exact(&mut data)?;
+        let mut sum = [0u8; CHECKSUM_LEN];
+        reader.read_exact(&mut sum)?;
+        if checksum(&[&header, &data]) != sum {
+            if stop_at_bad {
+                break;
+            }
+            tracing::warn!(
+                pack = %path.display(),
+                offset,
+                "Skipping CAS pack record with a bad checksum"
+            );
+        } else {
+            entries.push(entry);
+        }
+        offset = end;
+    }
+    Ok((entries, offset))
+}
+
+fn read_pack_id(path: &Path) -> Result<u64, CasError> {
+    let mut header = [0u8; PACK_HEADER_LEN as usize];
+    File::open(path)?
+        .read_exact(&mut header)
+        .map_err(|_| CasError::CorruptPack(format!("{}: truncated header", path.display())))?;
+    if &header[0..4] != PACK_MAGIC || le_u32(&header[4..8]) != FORMAT_VERSION {
+        return Err(CasError::CorruptPack(format!(
+            "{}: not a version {FORMAT_VERSION} pack",
+            path.display()
+        )));
+    }
+    Ok(le_u64(&header[8..16]))
+}
+
+fn pack_header(id: u64) -> Vec<u8> {
+    let mut header = Vec::with_capacity(PACK_HEADER_LEN as usize);
+    header.extend_from_slice(PACK_MAGIC);
+    header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header.extend_from_slice(&id.to_le_bytes());
+    header
+}
+
+fn encode_record(entry: &Entry, data: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN + data.len() + CHECKSUM_LEN);
+    record.push(entry.tag);
+    record.extend_from_slice(&entry.key);
+    record.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    record.extend_from_slice(&entry.mtime.to_le_bytes());
+    record.extend_from_slice(data);
+    let sum = checksum(&[&record]);
+    record.extend_from_slice(&sum);
+    record
+}
+
+fn decode_header(header: &[u8; RECORD_HEADER_LEN], offset: u64) -> Option<Entry> {
+    let entry = Entry {
+        tag: header[0],
+        key: header[1..33].try_into().ok()?,
+        offset,
+        len: le_u64(&header[33..41]),
+        mtime: le_u64(&header[41..49]),
+    };
+    match entry.tag {
+        TAG_BLOB => Some(entry),
+        TAG_TOMBSTONE if entry.len == 0 => Some(entry),
+        _ => None,
+    }
+}
+
+fn record_len(data_len: u64) -> u64 {
+    (RECORD_HEADER_LEN + CHECKSUM_LEN) as u64 + data_len
+}
+
+fn checksum(parts: &[&[u8]]) -> [u8; CHECKSUM_LEN] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    let digest = hasher.finalize();
+    let mut sum = [0u8; CHECKSUM_LEN];
+    sum.copy_from_slice(&digest[..CHECKSUM_LEN]);
+    sum
+}
+
+fn parse_key(hash: &str) -> Result<Key, CasError> {
+    let invalid = || CasError::InvalidHash(hash.to_string());
+    if hash.len() != 64 {
+        return Err(invalid());
+    }
+    let mut key = [0u8; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hash.get(i * 2..i * 2 + 2).ok_or_else(invalid)?, 16)
+            .map_err(|_| invalid())?;
+    }
+    Ok(key)
+}
+
+fn hex(key: &Key) -> String {
+    key.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().unwrap_or_default())
+}
+
+fn le_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes.try_into().unwrap_or_default())
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+/// Replaces `path` with `bytes` via a synced temporary file and a rename.
+fn write_atomically(dir: &Path, path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let tmp = tmp_path(path);
+    let mut file = File::create(&tmp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    sync_dir(dir)
+}
+
+/// Makes renames and removals in `dir` durable.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    if cfg!(unix) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+fn poisoned() -> CasError {
+    CasError::Io(io::Error::other("CAS pack index lock poisoned"))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::super::sha256_hex;
+    use super::*;
+
+    fn put(backend: &PackBackend, data: &[u8]) -> String {
+        let hash = sha256_hex(data);
+        backend.write(&hash, data).unwrap();
+        hash
+    }
+
+    fn small_packs() -> PackOptions {
+        PackOptions {
+            max_pack_bytes: 200,
+            compact_dead_ratio: 0.3,
+        }
+    }
+
+    #[test]
+    fn blobs_and_removals_survive_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = PackBackend::open(dir.path(), small_packs()).unwrap();
+        let kept = put(&backend, &[1u8; 100]);
+        let removed = put(&backend, &[2u8; 100]);
+        let also_kept = put(&backend, b"small");
+        backend.remove(&removed).unwrap();
+        assert!(!backend.contains(&removed).unwrap());
+        drop(backend);
+
+        let backend = PackBackend::open(dir.path(), small_packs()).unwrap();
+        assert_eq!(backend.read(&kept).unwrap(), vec![1u8; 100]);
+        assert_eq!(backend.read(&also_kept).unwrap(), b"small");
+        assert!(!backend.contains(&removed).unwrap());
+        let err = backend.read(&removed).unwrap_err();
+        assert!(matches!(err, CasError::Io(e) if e.kind() == io::ErrorKind::NotFound));
+
+        let shard = u8::from_str_radix(&kept[0..2], 16).unwrap();
+        let listed: Vec<String> = backend
+            .list_shard(shard)
+            .unwrap()
+            .into_iter()
+            .map(|(h, _)| h)
+            .collect();
+        assert!(listed.contains(&kept));
+        assert!(listed.iter().all(|h| h.starts_with(&kept[0..2])));
+        // Each 100-byte blob outgrows a 200-byte pack, so earlier packs were sealed and indexed.
+        assert!(dir.path().join("pack-00000001.idx").exists());
+    }
+
+    #[test]
+    fn torn_append_is_truncated_on_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = PackBackend::open(dir.path(), PackOptions::default()).unwrap();
+        let first = put(&backend, b"first blob");
+        drop(backend);
+
+        let pack = dir.path().join("pack-00000001.pack");
+        let intact_len = fs::metadata(&pack).unwrap().len();
+        let torn = encode_record(
+            &Entry {
+                tag: TAG_BLOB,
+                key: parse_key(&sha256_hex(b"second blob")).unwrap(),
+                offset: intact_len,
+                len: 11,
+                mtime: 0,
+            },
+            b"second blob",
+        );
+        let mut file = OpenOptions::new().append(true).open(&pack).unwrap();
+        file.write_all(&torn[..torn.len() - 5]).unwrap();
+        drop(file);
+
+        let backend = PackBackend::open(dir.path(), PackOptions::default()).unwrap();
+        assert_eq!(fs::metadata(&pack).unwrap().len(), intact_len);
+        assert_eq!(backend.read(&first).unwrap(), b"first blob");
+        let second = put(&backend, b"second blob");
+        drop(backend);
+
+        let backend = PackBackend::open(dir.path(), PackOptions::default()).unwrap();
+        assert_eq!(backend.read(&second).unwrap(), b"second blob");
+
+        let mut raw = fs::read(&pack).unwrap();
+        let last = raw.len() - 1;
+        raw[last] ^= 0xff;
+        fs::write(&pack, raw).unwrap();
+        let backend = PackBackend::open(dir.path(), PackOptions::default()).unwrap();
+        assert!(!backend.contains(&second).unwrap());
+        assert!(backend.contains(&first).unwrap());
+    }
+
+    #[test]
+    fn compaction_reclaims_dead_blobs_and_keeps_removals() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = PackBackend::open(dir.path(), small_packs()).unwrap();
+        let mut hashes = Vec::new();
+        for i in 0..6u8 {
+            hashes.push(put(&backend, &[i; 120]));
+        }
+        // A blob removed from a later pack and written again must stay live after compaction.
+        backend.remove(&hashes[0]).unwrap();
+        backend.remove(&hashes[3]).unwrap();
+        backend.remove(&hashes[4]).unwrap();
+        put(&backend, &[4u8; 120]);
+        let packs_before = fs::read_dir(dir.path()).unwrap().count();
+
+        let summary = backend.compact().unwrap();
+        assert!(summary.packs_rewritten >= 2, "{summary:?}");
+        assert!(summary.bytes_reclaimed >= 240, "{summary:?}");
+        assert!(fs::read_dir(dir.path()).unwrap().count() < packs_before);
+        assert_eq!(backend.compact().unwrap().packs_rewritten, 0);
+
+        for backend in [
+            backend,
+            PackBackend::open(dir.path(), small_packs()).unwrap(),
+        ] {
+            for (i, hash) in hashes.iter().enumerate() {
+                let live = ![0, 3].contains(&i);
+                assert_eq!(backend.contains(hash).unwrap(), live, "blob {i}");
+                if live {
+                    assert_eq!(backend.read(hash).unwrap(), vec![i as u8; 120]);
+                }
+            }
+        }
+    }
+}
@@ -527,6 +527,8 @@ fn extract_payload_timestamp(data: &lifelog::LifelogData) -> Option<i64> {
         Payload::Cameraframe(f) => extract_ts!(f),
         Payload::Weatherframe(f) => extract_ts!(f),
         Payload::Hyprlandframe(f) => extract_ts!(f),
+        Payload::Wififrame(f) => extract_ts!(f),
+        Payload::Geoframe(f) => extract_ts!(f),
         Payload::Embeddingframe(f) => extract_ts!(f),
     }
 }
@@ -548,6 +550,8 @@ fn extract_payload_uuid(data: &lifelog::LifelogData) -> Option<String> {
         Payload::Cameraframe(f) => Some(f.uuid.clone()),
         Payload::Weatherframe(f) => Some(f.uuid.clone()),
         Payload::Hyprlandframe(f) => Some(f.uuid.clone()),
+        Payload::Wififrame(f) => Some(f.uuid.clone()),
+        Payload::Geoframe(f) => Some(f.uuid.clone()),
         Payload::Embeddingframe(f) => Some(f.uuid.clone()),
     }
 }
@@ -1037,6 +1041,18 @@ async fn get_frame_data_async(
                             timestamp: f.timestamp.map(|ts| ts.seconds),
                             ..Default::default()
                         },
+                        lifelog::lifelog_data::Payload::Wififrame(f) => FrameDataWrapper {
+                            uuid: f.uuid,
+                            modality: "Wifi".into(),
+                            timestamp: f.timestamp.map(|ts| ts.seconds),
+                            ..Default::default()
+                        },
+                        lifelog::lifelog_data::Payload::Geoframe(f) => FrameDataWrapper {
+                            uuid: f.uuid,
+                            modality: "Geo".into(),
+                            timestamp: f.timestamp.map(|ts| ts.seconds),
+                            ..Default::default()
+                        },
                         lifelog::lifelog_data::Payload::Mouseframe(f) => FrameDataWrapper {
                             uuid: f.uuid,
                             modality: "Mouse".into(),
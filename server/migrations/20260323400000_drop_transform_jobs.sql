@@ -1 +1,13 @@
-DROP TABLE IF EXISTS transform_jobs;
+-- transform_jobs was the queue of the pipeline that per-transform watermarks replaced. Drop it
+-- only when nothing is left in it, so a deployment still holding jobs keeps them to inspect.
+DO $$
+BEGIN
+    IF to_regclass('transform_jobs') IS NULL THEN
+        RETURN;
+    END IF;
+    IF EXISTS (SELECT 1 FROM transform_jobs) THEN
+        RAISE NOTICE 'transform_jobs still has rows; leaving it in place';
+    ELSE
+        DROP TABLE transform_jobs;
+    END IF;
+END $$;
@@ -1,3 +1,30 @@
+-- Transform outputs written twice for the same source before this index existed would stop it
+-- from building. Keep the earliest output of each group and move frames derived from the others
+-- onto it. Moved frames can duplicate each other in turn, so repeat until no group is left.
+DO $$
+BEGIN
+    CREATE TEMP TABLE transform_duplicates (id UUID PRIMARY KEY, keep UUID NOT NULL)
+        ON COMMIT DROP;
+    LOOP
+        TRUNCATE transform_duplicates;
+        INSERT INTO transform_duplicates (id, keep)
+        SELECT id, keep FROM (
+            SELECT id, first_value(id) OVER (
+                PARTITION BY source_frame_id, stream_id, modality ORDER BY t_ingest, id
+            ) AS keep
+            FROM frames
+            WHERE source_frame_id IS NOT NULL
+        ) ranked
+        WHERE id <> keep;
+        EXIT WHEN NOT FOUND;
+
+        UPDATE frames f SET source_frame_id = d.keep
+        FROM transform_duplicates d
+        WHERE f.source_frame_id = d.id;
+        DELETE FROM frames f USING transform_duplicates d WHERE f.id = d.id;
+    END LOOP;
+END $$;
+
 CREATE UNIQUE INDEX IF NOT EXISTS idx_frames_transform_dedup
     ON frames (source_frame_id, stream_id, modality)
     WHERE source_frame_id IS NOT NULL;
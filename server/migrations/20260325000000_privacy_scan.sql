@@ -0,0 +1,41 @@
+CREATE TABLE IF NOT EXISTS privacy_audit (
+    id BIGSERIAL PRIMARY KEY,
+    frame_id UUID NOT NULL,
+    transform_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    finding_kinds TEXT[] NOT NULL DEFAULT '{}',
+    finding_count INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_privacy_audit_frame ON privacy_audit (frame_id);
+CREATE INDEX IF NOT EXISTS idx_privacy_audit_created ON privacy_audit (created_at);
+
+-- Frames flagged by the secret scanner stay out of full-text search until redacted.
+DROP INDEX IF EXISTS idx_frames_search;
+ALTER TABLE frames DROP COLUMN IF EXISTS search_doc;
+
+CREATE OR REPLACE FUNCTION smart_search_doc(modality TEXT, payload JSONB)
+RETURNS TSVECTOR
+LANGUAGE sql
+IMMUTABLE PARALLEL SAFE
+AS $$
+    SELECT CASE
+        WHEN modality IN ('Processes', 'Hyprland', 'Mouse', 'VectorEmbedding') THEN
+            ''::tsvector
+        WHEN payload ? 'pii_findings' AND COALESCE(payload->>'pii_redacted', 'false') <> 'true' THEN
+            ''::tsvector
+        ELSE
+            COALESCE(
+                setweight(to_tsvector('english', COALESCE(payload->>'text', '') || ' ' || COALESCE(payload->>'content', '') || ' ' || COALESCE(payload->>'transcript', '')), 'A') ||
+                setweight(to_tsvector('english', COALESCE(payload->>'title', '') || ' ' || COALESCE(payload->>'url', '') || ' ' || COALESCE(payload->>'command', '') || ' ' || COALESCE(payload->>'application', '')), 'B') ||
+                setweight(to_tsvector('english', COALESCE(payload->>'window_title', '')), 'C'),
+                ''::tsvector
+            )
+    END
+$$;
+
+ALTER TABLE frames ADD COLUMN search_doc TSVECTOR
+    GENERATED ALWAYS AS (smart_search_doc(modality, payload)) STORED;
+
+CREATE INDEX idx_frames_search ON frames USING GIN (search_doc);
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS transform_dead_letters (
+    transform_id TEXT NOT NULL,
+    frame_id UUID NOT NULL,
+    origin TEXT NOT NULL,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    first_failed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_failed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    next_retry_at TIMESTAMPTZ,
+    PRIMARY KEY (transform_id, frame_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_transform_dead_letters_due
+    ON transform_dead_letters (transform_id, next_retry_at)
+    WHERE next_retry_at IS NOT NULL;
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS transform_cache (
+    transform_id TEXT NOT NULL,
+    fingerprint TEXT NOT NULL,
+    input_hash TEXT NOT NULL,
+    output JSONB NOT NULL,
+    hits BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_hit_at TIMESTAMPTZ,
+    PRIMARY KEY (transform_id, fingerprint, input_hash)
+);
+
+CREATE TABLE IF NOT EXISTS transform_cache_stats (
+    transform_id TEXT PRIMARY KEY,
+    hits BIGINT NOT NULL DEFAULT 0,
+    misses BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,25 @@
+CREATE TABLE IF NOT EXISTS entities (
+    id BIGSERIAL PRIMARY KEY,
+    kind TEXT NOT NULL,
+    -- Normalized lookup key; `display` keeps the first spelling seen.
+    value TEXT NOT NULL,
+    display TEXT NOT NULL,
+    first_seen TIMESTAMPTZ NOT NULL,
+    last_seen TIMESTAMPTZ NOT NULL,
+    UNIQUE (kind, value)
+);
+
+CREATE TABLE IF NOT EXISTS entity_mentions (
+    entity_id BIGINT NOT NULL REFERENCES entities (id) ON DELETE CASCADE,
+    frame_id UUID NOT NULL,
+    transform_id TEXT NOT NULL,
+    field TEXT NOT NULL,
+    -- Character offsets into the frame's `payload->>field`.
+    start_offset INTEGER NOT NULL,
+    end_offset INTEGER NOT NULL,
+    t_canonical TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (entity_id, frame_id, field, start_offset)
+);
+
+CREATE INDEX IF NOT EXISTS idx_entity_mentions_frame ON entity_mentions (frame_id);
+CREATE INDEX IF NOT EXISTS idx_entity_mentions_entity_t ON entity_mentions (entity_id, t_canonical DESC);
@@ -0,0 +1,65 @@
+-- Per-frame text search language. Holds the ISO 639-1 code of an enabled language, 'simple' for
+-- text in other languages, or NULL for frames without text and frames written before detection
+-- existed, which keep the previous English stemming.
+ALTER TABLE frames ADD COLUMN IF NOT EXISTS language TEXT;
+
+-- Keep in sync with SUPPORTED in src/search_language.rs.
+CREATE OR REPLACE FUNCTION search_config(language TEXT)
+RETURNS REGCONFIG
+LANGUAGE sql
+IMMUTABLE PARALLEL SAFE
+AS $$
+    SELECT CASE COALESCE(language, 'en')
+        WHEN 'ar' THEN 'arabic'::regconfig
+        WHEN 'da' THEN 'danish'::regconfig
+        WHEN 'de' THEN 'german'::regconfig
+        WHEN 'el' THEN 'greek'::regconfig
+        WHEN 'en' THEN 'english'::regconfig
+        WHEN 'es' THEN 'spanish'::regconfig
+        WHEN 'fi' THEN 'finnish'::regconfig
+        WHEN 'fr' THEN 'french'::regconfig
+        WHEN 'hu' THEN 'hungarian'::regconfig
+        WHEN 'id' THEN 'indonesian'::regconfig
+        WHEN 'it' THEN 'italian'::regconfig
+        WHEN 'lt' THEN 'lithuanian'::regconfig
+        WHEN 'ne' THEN 'nepali'::regconfig
+        WHEN 'nl' THEN 'dutch'::regconfig
+        WHEN 'no' THEN 'norwegian'::regconfig
+        WHEN 'pt' THEN 'portuguese'::regconfig
+        WHEN 'ro' THEN 'romanian'::regconfig
+        WHEN 'ru' THEN 'russian'::regconfig
+        WHEN 'sv' THEN 'swedish'::regconfig
+        WHEN 'ta' THEN 'tamil'::regconfig
+        WHEN 'tr' THEN 'turkish'::regconfig
+        ELSE 'simple'::regconfig
+    END
+$$;
+
+DROP INDEX IF EXISTS idx_frames_search;
+ALTER TABLE frames DROP COLUMN IF EXISTS search_doc;
+DROP FUNCTION IF EXISTS smart_search_doc(TEXT, JSONB);
+
+CREATE OR REPLACE FUNCTION smart_search_doc(modality TEXT, payload JSONB, language TEXT)
+RETURNS TSVECTOR
+LANGUAGE sql
+IMMUTABLE PARALLEL SAFE
+AS $$
+    SELECT CASE
+        WHEN modality IN ('Processes', 'Hyprland', 'Mouse', 'VectorEmbedding') THEN
+            ''::tsvector
+        WHEN payload ? 'pii_findings' AND COALESCE(payload->>'pii_redacted', 'false') <> 'true' THEN
+            ''::tsvector
+        ELSE
+            COALESCE(
+                setweight(to_tsvector(search_config(language), COALESCE(payload->>'text', '') || ' ' || COALESCE(payload->>'content', '') || ' ' || COALESCE(payload->>'transcript', '')), 'A') ||
+                setweight(to_tsvector(search_config(language), COALESCE(payload->>'title', '') || ' ' || COALESCE(payload->>'url', '') || ' ' || COALESCE(payload->>'command', '') || ' ' || COALESCE(payload->>'application', '')), 'B') ||
+                setweight(to_tsvector(search_config(language), COALESCE(payload->>'window_title', '')), 'C'),
+                ''::tsvector
+            )
+    END
+$$;
+
+ALTER TABLE frames ADD COLUMN search_doc TSVECTOR
+    GENERATED ALWAYS AS (smart_search_doc(modality, payload, language)) STORED;
+
+CREATE INDEX idx_frames_search ON frames USING GIN (search_doc);
@@ -0,0 +1,13 @@
+-- Perceptual (difference) hash of Screen and Camera frames, lifted out of the payload so
+-- similarity searches can compare it with bit_count(a # b). Frames ingested before hashing
+-- existed have no hash.
+ALTER TABLE frames ADD COLUMN IF NOT EXISTS phash BIGINT GENERATED ALWAYS AS (
+    CASE WHEN payload->>'phash' ~ '^[0-9a-f]{16}$'
+        THEN ('x' || (payload->>'phash'))::bit(64)::bigint
+    END
+) STORED;
+
+-- Lookup of the previous image frame of a stream when checking a new one for duplicates.
+CREATE INDEX IF NOT EXISTS idx_frames_phash_stream
+    ON frames (collector_id, stream_id, modality, t_canonical)
+    WHERE phash IS NOT NULL;
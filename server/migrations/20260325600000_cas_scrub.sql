@@ -0,0 +1,27 @@
+-- Progress of the incremental CAS scrubber. It works through the 256 shard directories a few at
+-- a time; the counters cover the pass in progress and are reset when a new pass starts.
+CREATE TABLE IF NOT EXISTS cas_scrub_state (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    next_shard INTEGER NOT NULL DEFAULT 0,
+    pass_started_at TIMESTAMPTZ,
+    last_pass_completed_at TIMESTAMPTZ,
+    last_run_at TIMESTAMPTZ,
+    blobs_verified BIGINT NOT NULL DEFAULT 0,
+    bytes_verified BIGINT NOT NULL DEFAULT 0,
+    blobs_unverified BIGINT NOT NULL DEFAULT 0,
+    orphans_deleted BIGINT NOT NULL DEFAULT 0
+);
+
+-- Blobs that failed verification ('corrupt') or that frames reference but the CAS does not
+-- hold ('missing'). Rows are replaced each time their shard is scrubbed.
+CREATE TABLE IF NOT EXISTS cas_scrub_findings (
+    blob_hash TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    detail TEXT NOT NULL DEFAULT '',
+    frame_count BIGINT NOT NULL DEFAULT 0,
+    first_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Raw upload chunks are stored in the CAS too; the scrubber must not collect them.
+CREATE INDEX IF NOT EXISTS idx_upload_chunks_hash ON upload_chunks (hash);
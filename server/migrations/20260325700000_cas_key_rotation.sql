@@ -0,0 +1,21 @@
+-- Runs of the CAS key rotation: each one walks the 256 shards, moving every blob under
+-- target_key_id. next_shard reaches 256 when the run completes. Starting a rotation to another
+-- key supersedes the one in progress.
+CREATE TABLE IF NOT EXISTS cas_key_rotations (
+    id BIGSERIAL PRIMARY KEY,
+    target_key_id TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'running'
+        CHECK (status IN ('running', 'completed', 'superseded')),
+    next_shard INTEGER NOT NULL DEFAULT 0,
+    blobs_current BIGINT NOT NULL DEFAULT 0,
+    blobs_rewrapped BIGINT NOT NULL DEFAULT 0,
+    blobs_reencrypted BIGINT NOT NULL DEFAULT 0,
+    blobs_failed BIGINT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_run_at TIMESTAMPTZ,
+    completed_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_cas_key_rotations_running
+    ON cas_key_rotations ((TRUE)) WHERE status = 'running';
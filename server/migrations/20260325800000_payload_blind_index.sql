@@ -0,0 +1,4 @@
+-- Blind-index tokens of sealed payloads (see src/payload_crypto.rs). Eq and Contains queries
+-- match sealed frames with `payload->'blind_index' ?& ARRAY[...]`.
+CREATE INDEX IF NOT EXISTS idx_frames_blind_index
+    ON frames USING GIN ((payload->'blind_index'));
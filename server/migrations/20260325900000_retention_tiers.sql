@@ -0,0 +1,3 @@
+-- JPEG quality a retention tier last re-encoded the frame's blob at; NULL while it is the
+-- original capture.
+ALTER TABLE frames ADD COLUMN IF NOT EXISTS reencoded_quality SMALLINT;
@@ -0,0 +1,32 @@
+-- Frames and time ranges exempt from retention. A pin names either one frame or a time range.
+CREATE TABLE IF NOT EXISTS pins (
+    id BIGSERIAL PRIMARY KEY,
+    frame_id UUID,
+    time_range TSTZRANGE,
+    reason TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK ((frame_id IS NULL) <> (time_range IS NULL))
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_pins_frame ON pins (frame_id) WHERE frame_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_pins_time_range ON pins USING GIST (time_range);
+
+-- True when a frame must be kept: a pinned range overlaps it, it or a frame it was derived from
+-- is pinned, or a frame derived from it is pinned. Retention and thinning skip such frames.
+CREATE OR REPLACE FUNCTION frame_pinned(f_id UUID, f_source UUID, f_range TSTZRANGE)
+RETURNS BOOLEAN
+LANGUAGE sql STABLE AS $$
+    SELECT EXISTS (SELECT 1 FROM pins p WHERE p.time_range && f_range)
+        OR EXISTS (
+            WITH RECURSIVE lineage (id, source) AS (
+                SELECT f_id, f_source
+                UNION
+                SELECT f.id, f.source_frame_id FROM frames f JOIN lineage l ON f.id = l.source
+            )
+            SELECT 1 FROM pins p JOIN lineage l ON p.frame_id = l.id
+        )
+        OR EXISTS (
+            SELECT 1 FROM pins p JOIN frames d ON d.id = p.frame_id
+            WHERE d.source_frame_id = f_id
+        )
+$$;
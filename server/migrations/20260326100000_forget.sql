@@ -0,0 +1,17 @@
+-- Append-only log of confirmed forgets. Each row's hash covers its content and the previous
+-- row's hash, so editing or deleting a row breaks the chain from that point on.
+CREATE TABLE IF NOT EXISTS forget_audit (
+    id BIGSERIAL PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL,
+    selector TEXT NOT NULL,
+    reason TEXT NOT NULL DEFAULT '',
+    frames BIGINT NOT NULL,
+    blobs BIGINT NOT NULL,
+    frame_ids_sha256 TEXT NOT NULL,
+    prev_hash TEXT NOT NULL,
+    hash TEXT NOT NULL
+);
+
+-- Forget follows near-duplicates to the frame they duplicate.
+CREATE INDEX IF NOT EXISTS idx_frames_duplicate_of
+    ON frames ((payload->>'duplicate_of')) WHERE payload ? 'duplicate_of';
@@ -0,0 +1,203 @@
+-- Range-partitions frames by UTC month of t_canonical; see src/partitions.rs. Retention drops
+-- whole months and range scans skip months outside the range.
+
+-- A partitioned table only enforces unique constraints that include the partition key. Frame IDs
+-- and the transform-output dedup key are claimed here instead, by the triggers below.
+CREATE TABLE IF NOT EXISTS frame_keys (
+    id UUID PRIMARY KEY,
+    t_canonical TIMESTAMPTZ NOT NULL,
+    source_frame_id UUID,
+    stream_id TEXT NOT NULL,
+    modality TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_frame_keys_transform_dedup
+    ON frame_keys (source_frame_id, stream_id, modality)
+    WHERE source_frame_id IS NOT NULL;
+-- Cleared by range when a partition is dropped, which fires no delete triggers.
+CREATE INDEX IF NOT EXISTS idx_frame_keys_t_canonical ON frame_keys (t_canonical);
+
+-- Partitions the server manages, and when each was last vacuumed after its month closed.
+CREATE TABLE IF NOT EXISTS frame_partitions (
+    name TEXT PRIMARY KEY,
+    range_start TIMESTAMPTZ NOT NULL UNIQUE,
+    range_end TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    maintained_at TIMESTAMPTZ
+);
+
+-- Longest time_range of any frame. Range predicates bound t_canonical from below with it, so
+-- the planner can prune months that end before the range starts. It never shrinks.
+CREATE TABLE IF NOT EXISTS frames_max_span (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    span INTERVAL NOT NULL
+);
+
+CREATE OR REPLACE FUNCTION frames_max_span()
+RETURNS INTERVAL
+LANGUAGE sql STABLE AS $$
+    SELECT COALESCE((SELECT span FROM frames_max_span), INTERVAL '0')
+$$;
+
+ALTER TABLE frames RENAME TO frames_unpartitioned;
+ALTER INDEX frames_pkey RENAME TO frames_unpartitioned_pkey;
+
+CREATE TABLE frames (
+    id              UUID NOT NULL,
+    collector_id    TEXT NOT NULL,
+    stream_id       TEXT NOT NULL,
+    modality        TEXT NOT NULL,
+    time_range      TSTZRANGE NOT NULL,
+    t_device        TIMESTAMPTZ,
+    t_ingest        TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    t_canonical     TIMESTAMPTZ NOT NULL,
+    t_end           TIMESTAMPTZ,
+    time_quality    TEXT NOT NULL DEFAULT 'unknown',
+    blob_hash       TEXT,
+    blob_size       INTEGER,
+    indexed         BOOLEAN NOT NULL DEFAULT true,
+    source_frame_id UUID,
+    payload         JSONB NOT NULL DEFAULT '{}',
+    language        TEXT,
+    search_doc      TSVECTOR GENERATED ALWAYS AS (smart_search_doc(modality, payload, language)) STORED,
+    phash           BIGINT GENERATED ALWAYS AS (
+        CASE WHEN payload->>'phash' ~ '^[0-9a-f]{16}$'
+            THEN ('x' || (payload->>'phash'))::bit(64)::bigint
+        END
+    ) STORED,
+    reencoded_quality SMALLINT,
+    PRIMARY KEY (id, t_canonical)
+) PARTITION BY RANGE (t_canonical);
+
+-- Creates the partition for the UTC month holding `t` unless it exists, and returns its name.
+CREATE OR REPLACE FUNCTION create_frame_partition(t TIMESTAMPTZ)
+RETURNS TEXT
+LANGUAGE plpgsql AS $$
+DECLARE
+    month_start TIMESTAMP := date_trunc('month', t AT TIME ZONE 'UTC');
+    lower_bound TIMESTAMPTZ := month_start AT TIME ZONE 'UTC';
+    upper_bound TIMESTAMPTZ := (month_start + INTERVAL '1 month') AT TIME ZONE 'UTC';
+    partition_name TEXT := 'frames_' || to_char(month_start, 'YYYY_MM');
+BEGIN
+    IF to_regclass(partition_name) IS NOT NULL THEN
+        RETURN partition_name;
+    END IF;
+    -- Ingest and transforms may ask for the same month at once.
+    PERFORM pg_advisory_xact_lock(hashtext('create_frame_partition'));
+    EXECUTE format(
+        'CREATE TABLE IF NOT EXISTS %I PARTITION OF frames FOR VALUES FROM (%L) TO (%L)',
+        partition_name, lower_bound, upper_bound
+    );
+    INSERT INTO frame_partitions (name, range_start, range_end)
+    VALUES (partition_name, lower_bound, upper_bound)
+    ON CONFLICT (name) DO NOTHING;
+    RETURN partition_name;
+END
+$$;
+
+SELECT create_frame_partition(month)
+FROM (
+    SELECT DISTINCT date_trunc('month', t_canonical AT TIME ZONE 'UTC') AT TIME ZONE 'UTC' AS month
+    FROM frames_unpartitioned
+) months;
+SELECT create_frame_partition(NOW());
+
+INSERT INTO frames (
+    id, collector_id, stream_id, modality, time_range, t_device, t_ingest, t_canonical, t_end,
+    time_quality, blob_hash, blob_size, indexed, source_frame_id, payload, language,
+    reencoded_quality
+)
+SELECT
+    id, collector_id, stream_id, modality, time_range, t_device, t_ingest, t_canonical, t_end,
+    time_quality, blob_hash, blob_size, indexed, source_frame_id, payload, language,
+    reencoded_quality
+FROM frames_unpartitioned;
+
+INSERT INTO frame_keys (id, t_canonical, source_frame_id, stream_id, modality)
+SELECT id, t_canonical, source_frame_id, stream_id, modality FROM frames_unpartitioned;
+
+INSERT INTO frames_max_span (span)
+SELECT COALESCE(MAX(upper(time_range) - lower(time_range)), INTERVAL '0') FROM frames_unpartitioned;
+
+DROP TABLE frames_unpartitioned;
+
+-- Indexes on the parent are created on every partition, including ones added later.
+CREATE INDEX IF NOT EXISTS idx_frames_time_gist ON frames USING GIST (time_range);
+CREATE INDEX IF NOT EXISTS idx_frames_collector ON frames (collector_id, modality);
+CREATE INDEX IF NOT EXISTS idx_frames_modality_t ON frames (modality, t_canonical);
+CREATE INDEX IF NOT EXISTS idx_frames_search ON frames USING GIN (search_doc);
+CREATE INDEX IF NOT EXISTS idx_frames_payload ON frames USING GIN (payload jsonb_path_ops);
+CREATE INDEX IF NOT EXISTS idx_frames_blob ON frames (blob_hash) WHERE blob_hash IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_frames_source
+    ON frames (source_frame_id) WHERE source_frame_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_frames_phash_stream
+    ON frames (collector_id, stream_id, modality, t_canonical)
+    WHERE phash IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_frames_blind_index
+    ON frames USING GIN ((payload->'blind_index'));
+CREATE INDEX IF NOT EXISTS idx_frames_duplicate_of
+    ON frames ((payload->>'duplicate_of')) WHERE payload ? 'duplicate_of';
+
+-- Claims the frame's ID and dedup key. A frame whose ID or dedup key is taken is skipped, the
+-- way ON CONFLICT DO NOTHING skipped it when the table enforced them itself.
+CREATE OR REPLACE FUNCTION frame_keys_claim()
+RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    INSERT INTO frame_keys (id, t_canonical, source_frame_id, stream_id, modality)
+    VALUES (NEW.id, NEW.t_canonical, NEW.source_frame_id, NEW.stream_id, NEW.modality)
+    ON CONFLICT DO NOTHING;
+    IF NOT FOUND THEN
+        RETURN NULL;
+    END IF;
+    RETURN NEW;
+END
+$$;
+
+CREATE OR REPLACE FUNCTION frame_keys_update()
+RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    UPDATE frame_keys
+    SET id = NEW.id, t_canonical = NEW.t_canonical, source_frame_id = NEW.source_frame_id,
+        stream_id = NEW.stream_id, modality = NEW.modality
+    WHERE id = OLD.id;
+    RETURN NEW;
+END
+$$;
+
+CREATE OR REPLACE FUNCTION frame_keys_release()
+RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    DELETE FROM frame_keys WHERE id = OLD.id;
+    RETURN OLD;
+END
+$$;
+
+CREATE OR REPLACE FUNCTION frames_track_max_span()
+RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    UPDATE frames_max_span m SET span = n.span
+    FROM (SELECT MAX(upper(time_range) - lower(time_range)) AS span FROM changed_frames) n
+    WHERE n.span > m.span;
+    RETURN NULL;
+END
+$$;
+
+-- An UPDATE moving a frame to another month runs as a delete from the old partition and an
+-- insert into the new one: the key is updated, released, then claimed again with the new values.
+CREATE TRIGGER frame_keys_claim BEFORE INSERT ON frames
+    FOR EACH ROW EXECUTE FUNCTION frame_keys_claim();
+CREATE TRIGGER frame_keys_update
+    BEFORE UPDATE OF id, t_canonical, source_frame_id, stream_id, modality ON frames
+    FOR EACH ROW EXECUTE FUNCTION frame_keys_update();
+CREATE TRIGGER frame_keys_release BEFORE DELETE ON frames
+    FOR EACH ROW EXECUTE FUNCTION frame_keys_release();
+CREATE TRIGGER frames_max_span_insert AFTER INSERT ON frames
+    REFERENCING NEW TABLE AS changed_frames
+    FOR EACH STATEMENT EXECUTE FUNCTION frames_track_max_span();
+CREATE TRIGGER frames_max_span_update AFTER UPDATE ON frames
+    REFERENCING NEW TABLE AS changed_frames
+    FOR EACH STATEMENT EXECUTE FUNCTION frames_track_max_span();
@@ -0,0 +1,12 @@
+-- A rotation that reaches the last shard with blobs it could not move finishes as
+-- completed_with_errors, so it is not mistaken for one after which old keys can be dropped.
+-- source_key_ids records the retired keys configured when the rotation started: while a
+-- rotation is running or finished with errors, the server refuses to start without them.
+ALTER TABLE cas_key_rotations DROP CONSTRAINT IF EXISTS cas_key_rotations_status_check;
+ALTER TABLE cas_key_rotations ADD CONSTRAINT cas_key_rotations_status_check
+    CHECK (status IN ('running', 'completed', 'completed_with_errors', 'superseded'));
+
+ALTER TABLE cas_key_rotations ADD COLUMN IF NOT EXISTS source_key_ids TEXT[] NOT NULL DEFAULT '{}';
+
+UPDATE cas_key_rotations SET status = 'completed_with_errors'
+WHERE status = 'completed' AND blobs_failed > 0;
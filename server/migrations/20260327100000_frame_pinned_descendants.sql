@@ -0,0 +1,23 @@
+-- frame_pinned looked only one level down, so pinning an OCR frame's translation did not keep
+-- the screenshot the OCR came from. Follow derived frames to any depth, like the lineage upward.
+CREATE OR REPLACE FUNCTION frame_pinned(f_id UUID, f_source UUID, f_range TSTZRANGE)
+RETURNS BOOLEAN
+LANGUAGE sql STABLE AS $$
+    SELECT EXISTS (SELECT 1 FROM pins p WHERE p.time_range && f_range)
+        OR EXISTS (
+            WITH RECURSIVE lineage (id, source) AS (
+                SELECT f_id, f_source
+                UNION
+                SELECT f.id, f.source_frame_id FROM frames f JOIN lineage l ON f.id = l.source
+            )
+            SELECT 1 FROM pins p JOIN lineage l ON p.frame_id = l.id
+        )
+        OR EXISTS (
+            WITH RECURSIVE descendants (id) AS (
+                SELECT d.id FROM frames d WHERE d.source_frame_id = f_id
+                UNION
+                SELECT d.id FROM frames d JOIN descendants s ON d.source_frame_id = s.id
+            )
+            SELECT 1 FROM pins p JOIN descendants s ON p.frame_id = s.id
+        )
+$$;
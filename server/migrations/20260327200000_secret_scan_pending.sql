@@ -0,0 +1,24 @@
+-- Frames are stored with 'pii_scan_pending' set while a secret scan still has to run on them,
+-- and stay out of full-text search until the scan clears it. Frames stored earlier have no
+-- marker and are unaffected.
+CREATE OR REPLACE FUNCTION smart_search_doc(modality TEXT, payload JSONB, language TEXT)
+RETURNS TSVECTOR
+LANGUAGE sql
+IMMUTABLE PARALLEL SAFE
+AS $$
+    SELECT CASE
+        WHEN modality IN ('Processes', 'Hyprland', 'Mouse', 'VectorEmbedding') THEN
+            ''::tsvector
+        WHEN payload ? 'pii_scan_pending' THEN
+            ''::tsvector
+        WHEN payload ? 'pii_findings' AND COALESCE(payload->>'pii_redacted', 'false') <> 'true' THEN
+            ''::tsvector
+        ELSE
+            COALESCE(
+                setweight(to_tsvector(search_config(language), COALESCE(payload->>'text', '') || ' ' || COALESCE(payload->>'content', '') || ' ' || COALESCE(payload->>'transcript', '')), 'A') ||
+                setweight(to_tsvector(search_config(language), COALESCE(payload->>'title', '') || ' ' || COALESCE(payload->>'url', '') || ' ' || COALESCE(payload->>'command', '') || ' ' || COALESCE(payload->>'application', '')), 'B') ||
+                setweight(to_tsvector(search_config(language), COALESCE(payload->>'window_title', '')), 'C'),
+                ''::tsvector
+            )
+    END
+$$;
@@ -0,0 +1,21 @@
+-- Every frame kept by a frame pin: the pinned frames, the frames they were derived from and the
+-- frames derived from them, to any depth. Retention copies this set once per run rather than
+-- walking the lineage of each row it deletes or degrades through frame_pinned.
+CREATE OR REPLACE FUNCTION pinned_frame_ids()
+RETURNS SETOF UUID
+LANGUAGE sql STABLE AS $$
+    WITH RECURSIVE
+    ancestors (id, source) AS (
+        SELECT f.id, f.source_frame_id FROM frames f JOIN pins p ON p.frame_id = f.id
+        UNION
+        SELECT f.id, f.source_frame_id FROM frames f JOIN ancestors a ON f.id = a.source
+    ),
+    descendants (id) AS (
+        SELECT p.frame_id FROM pins p WHERE p.frame_id IS NOT NULL
+        UNION
+        SELECT d.id FROM frames d JOIN descendants s ON d.source_frame_id = s.id
+    )
+    SELECT id FROM ancestors
+    UNION
+    SELECT id FROM descendants
+$$;
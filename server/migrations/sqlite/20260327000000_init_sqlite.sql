@@ -0,0 +1,90 @@
+-- Single-file storage for laptop-only deployments; see src/storage/sqlite.rs. Times are
+-- microseconds since the Unix epoch, UUIDs are text and payloads JSON text.
+CREATE TABLE IF NOT EXISTS frames (
+    seq INTEGER PRIMARY KEY,
+    id TEXT NOT NULL UNIQUE,
+    collector_id TEXT NOT NULL,
+    stream_id TEXT NOT NULL,
+    modality TEXT NOT NULL,
+    t_device INTEGER,
+    t_ingest INTEGER NOT NULL,
+    t_canonical INTEGER NOT NULL,
+    t_end INTEGER,
+    time_quality TEXT NOT NULL,
+    blob_hash TEXT,
+    blob_size INTEGER,
+    indexed INTEGER NOT NULL DEFAULT 0,
+    source_frame_id TEXT,
+    payload TEXT NOT NULL,
+    language TEXT,
+    phash INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_frames_modality_time
+    ON frames (modality, collector_id, t_canonical);
+CREATE INDEX IF NOT EXISTS idx_frames_stream_time
+    ON frames (collector_id, stream_id, modality, t_canonical);
+CREATE INDEX IF NOT EXISTS idx_frames_time ON frames (t_canonical);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_frames_transform_output
+    ON frames (source_frame_id, stream_id, modality)
+    WHERE source_frame_id IS NOT NULL;
+
+-- Interval index standing in for Postgres' time_range column: [t_canonical, t_end] in seconds,
+-- keyed by frames.seq. R*Tree coordinates are 32-bit floats rounded outwards, so it only
+-- narrows candidates; queries recheck the exact microsecond columns.
+CREATE VIRTUAL TABLE IF NOT EXISTS frame_intervals USING rtree(seq, t_start, t_stop);
+
+-- Full-text index standing in for search_doc, keyed by frames.seq. Its columns carry the
+-- weights of search_doc's A, B and C parts; the text is written by the server.
+CREATE VIRTUAL TABLE IF NOT EXISTS frames_fts USING fts5(
+    primary_text,
+    secondary_text,
+    window_title,
+    tokenize = 'porter unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER IF NOT EXISTS frames_interval_insert AFTER INSERT ON frames BEGIN
+    INSERT INTO frame_intervals (seq, t_start, t_stop)
+    VALUES (NEW.seq, NEW.t_canonical / 1e6, COALESCE(NEW.t_end, NEW.t_canonical) / 1e6);
+END;
+
+CREATE TRIGGER IF NOT EXISTS frames_interval_update AFTER UPDATE OF t_canonical, t_end ON frames BEGIN
+    UPDATE frame_intervals
+    SET t_start = NEW.t_canonical / 1e6, t_stop = COALESCE(NEW.t_end, NEW.t_canonical) / 1e6
+    WHERE seq = NEW.seq;
+END;
+
+CREATE TRIGGER IF NOT EXISTS frames_delete AFTER DELETE ON frames BEGIN
+    DELETE FROM frame_intervals WHERE seq = OLD.seq;
+    DELETE FROM frames_fts WHERE rowid = OLD.seq;
+END;
+
+CREATE TABLE IF NOT EXISTS upload_chunks (
+    id TEXT PRIMARY KEY,
+    collector_id TEXT NOT NULL,
+    stream_id TEXT NOT NULL,
+    session_id INTEGER NOT NULL,
+    "offset" INTEGER NOT NULL,
+    length INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    indexed INTEGER NOT NULL DEFAULT 0,
+    frame_uuid TEXT,
+    created_at INTEGER NOT NULL,
+    UNIQUE (collector_id, stream_id, session_id, "offset")
+);
+
+CREATE TABLE IF NOT EXISTS catalog (
+    origin TEXT PRIMARY KEY,
+    collector_id TEXT NOT NULL,
+    modality TEXT NOT NULL,
+    stream_id TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS transform_watermarks (
+    transform_id TEXT NOT NULL,
+    origin TEXT NOT NULL,
+    cursor_value TEXT NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (transform_id, origin)
+);
@@ -0,0 +1,73 @@
+-- Transform worker state, mirroring the Postgres transform_cache, transform_dead_letters,
+-- entities and privacy_audit tables. Times are microseconds since the Unix epoch.
+CREATE TABLE IF NOT EXISTS transform_cache (
+    transform_id TEXT NOT NULL,
+    fingerprint TEXT NOT NULL,
+    input_hash TEXT NOT NULL,
+    output TEXT NOT NULL,
+    hits INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL,
+    last_hit_at INTEGER,
+    PRIMARY KEY (transform_id, fingerprint, input_hash)
+);
+
+CREATE TABLE IF NOT EXISTS transform_cache_stats (
+    transform_id TEXT PRIMARY KEY,
+    hits INTEGER NOT NULL DEFAULT 0,
+    misses INTEGER NOT NULL DEFAULT 0,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS transform_dead_letters (
+    transform_id TEXT NOT NULL,
+    frame_id TEXT NOT NULL,
+    origin TEXT NOT NULL,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    first_failed_at INTEGER NOT NULL,
+    last_failed_at INTEGER NOT NULL,
+    next_retry_at INTEGER,
+    PRIMARY KEY (transform_id, frame_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_transform_dead_letters_due
+    ON transform_dead_letters (transform_id, next_retry_at)
+    WHERE next_retry_at IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS entities (
+    id INTEGER PRIMARY KEY,
+    kind TEXT NOT NULL,
+    value TEXT NOT NULL,
+    display TEXT NOT NULL,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    UNIQUE (kind, value)
+);
+
+CREATE TABLE IF NOT EXISTS entity_mentions (
+    entity_id INTEGER NOT NULL REFERENCES entities (id),
+    frame_id TEXT NOT NULL,
+    transform_id TEXT NOT NULL,
+    field TEXT NOT NULL,
+    start_offset INTEGER NOT NULL,
+    end_offset INTEGER NOT NULL,
+    t_canonical INTEGER NOT NULL,
+    PRIMARY KEY (entity_id, frame_id, field, start_offset)
+);
+
+CREATE INDEX IF NOT EXISTS idx_entity_mentions_frame ON entity_mentions (frame_id);
+CREATE INDEX IF NOT EXISTS idx_entity_mentions_entity_t
+    ON entity_mentions (entity_id, t_canonical DESC);
+
+CREATE TABLE IF NOT EXISTS privacy_audit (
+    id INTEGER PRIMARY KEY,
+    frame_id TEXT NOT NULL,
+    transform_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    -- JSON array of finding kinds.
+    finding_kinds TEXT NOT NULL DEFAULT '[]',
+    finding_count INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_privacy_audit_frame ON privacy_audit (frame_id);
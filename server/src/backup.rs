@@ -0,0 +1,781 @@
+//! Consistent, incremental backups of the lifelog.
+//!
+//! A backup repository is a directory holding a content-addressed object store (`objects/`, the
+//! same layout as the server CAS, optionally encrypted) and one manifest per snapshot
+//! (`snapshots/<id>.json`). Table rows are dumped as JSONL segments inside a single
+//! `REPEATABLE READ` transaction, frames segmented by UTC day so unchanged days hash to the same
+//! object and are stored once. Blobs referenced by frames and upload chunks are copied by hash,
+//! so each snapshot only adds what changed since the last one.
+
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::GenericClient;
+use lifelog_core::LifelogError;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::IsolationLevel;
+use utils::cas::{sha256_hex, CasError, CasKeyring, FsCas};
+
+use crate::postgres::PostgresPool;
+
+/// Tables in the backup, in restore order (parents before the rows referencing them). Upload
+/// chunks are kept so collectors resume where they left off instead of re-sending their buffers.
+/// Caches, dead letters and CAS maintenance state are rebuilt by the server and skipped.
+pub const TABLES: &[&str] = &[
+    "catalog",
+    "frames",
+    "upload_chunks",
+    "transform_watermarks",
+    "entities",
+    "entity_mentions",
+    "privacy_audit",
+    "pins",
+    "forget_audit",
+];
+
+const REPO_FORMAT_VERSION: u32 = 1;
+
+/// Column naming the CAS blob a row references, for the tables that have one.
+fn blob_column(table: &str) -> Option<&'static str> {
+    match table {
+        "frames" => Some("blob_hash"),
+        "upload_chunks" => Some("hash"),
+        _ => None,
+    }
+}
+/// Rows per JSONL segment; frames additionally start a new segment on each UTC day.
+const SEGMENT_ROWS: usize = 5_000;
+
+/// `repo.json`: fixed at creation so later snapshots cannot mix keys.
+#[derive(Debug, Serialize, Deserialize)]
+struct RepoConfig {
+    version: u32,
+    key_id: Option<String>,
+}
+
+/// One snapshot manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    /// The snapshot this one was taken on top of, if any.
+    pub parent: Option<String>,
+    /// Last migration applied to the source database.
+    pub schema_version: Option<String>,
+    pub tables: Vec<TableDump>,
+    /// Distinct blobs referenced by the frames and upload chunks in this snapshot.
+    pub blobs: u64,
+    /// Referenced blobs that could not be read from the CAS when the snapshot was taken.
+    #[serde(default)]
+    pub missing_blobs: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableDump {
+    pub table: String,
+    pub rows: u64,
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Segment {
+    pub hash: String,
+    pub rows: u64,
+}
+
+/// A backup repository on disk.
+#[derive(Debug)]
+pub struct BackupRepo {
+    root: PathBuf,
+    objects: FsCas,
+}
+
+impl BackupRepo {
+    /// Opens the repository at `root`, creating it on first use. `key` is a key spec as accepted
+    /// by [`utils::cas::load_key`]; a repository created with a key must always be opened with
+    /// the same key, and one created without a key stays unencrypted.
+    pub fn open(root: impl Into<PathBuf>, key: Option<&str>) -> Result<Self, CasError> {
+        let root = root.into();
+        std::fs::create_dir_all(root.join("snapshots"))?;
+        let keyring = match key {
+            Some(spec) => {
+                let key = utils::cas::load_key(spec, &root.join("kek.salt"))?;
+                Some(CasKeyring::new(&key, &[]))
+            }
+            None => None,
+        };
+        let key_id = keyring.as_ref().map(|k| k.active_id());
+
+        let config_path = root.join("repo.json");
+        match std::fs::read(&config_path) {
+            Ok(bytes) => {
+                let config: RepoConfig = serde_json::from_slice(&bytes)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                if config.version != REPO_FORMAT_VERSION {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unsupported backup repository version {}", config.version),
+                    )
+                    .into());
+                }
+                match (&config.key_id, &key_id) {
+                    (Some(_), None) => return Err(CasError::MissingKey),
+                    (Some(want), Some(got)) if want != got => {
+                        return Err(CasError::UnknownKey(got.clone()))
+                    }
+                    (None, Some(_)) => {
+                        return Err(CasError::InvalidKey(
+                            "repository was created without encryption".to_string(),
+                        ))
+                    }
+                    _ => {}
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let config = RepoConfig {
+                    version: REPO_FORMAT_VERSION,
+                    key_id,
+                };
+                let bytes = serde_json::to_vec_pretty(&config)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                std::fs::write(&config_path, bytes)?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        let objects = FsCas::new(root.join("objects"));
+        let objects = match keyring {
+            Some(keyring) => objects.with_keyring(keyring),
+            None => objects,
+        };
+        Ok(Self { root, objects })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Snapshot IDs, oldest first.
+    pub fn snapshots(&self) -> Result<Vec<String>, CasError> {
+        let mut ids = Vec::new();
+        for entry in std::fs::read_dir(self.root.join("snapshots"))? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if let Some(id) = name.strip_suffix(".json") {
+                ids.push(id.to_string());
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Loads a snapshot manifest; the latest one when `id` is `None`.
+    pub fn load_snapshot(&self, id: Option<&str>) -> Result<Snapshot, CasError> {
+        let id = match id {
+            Some(id) => id.to_string(),
+            None => self.snapshots()?.pop().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "backup repository has no snapshots",
+                )
+            })?,
+        };
+        let bytes = std::fs::read(self.snapshot_path(&id))?;
+        serde_json::from_slice(&bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e).into())
+    }
+
+    fn save_snapshot(&self, snapshot: &Snapshot) -> Result<(), CasError> {
+        let bytes = serde_json::to_vec_pretty(snapshot)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        // Written last and renamed into place, so a snapshot never names missing objects.
+        let path = self.snapshot_path(&snapshot.id);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, bytes)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    fn snapshot_path(&self, id: &str) -> PathBuf {
+        self.root.join("snapshots").join(format!("{id}.json"))
+    }
+
+    /// Reads a segment and checks it against its hash and row count.
+    fn read_segment(&self, segment: &Segment) -> Result<Vec<String>, CasError> {
+        let bytes = self.objects.get(&segment.hash)?;
+        if sha256_hex(&bytes) != segment.hash {
+            return Err(CasError::HashMismatch(segment.hash.clone()));
+        }
+        let text =
+            String::from_utf8(bytes).map_err(|_| CasError::HashMismatch(segment.hash.clone()))?;
+        let lines: Vec<String> = text.lines().map(str::to_string).collect();
+        if lines.len() as u64 != segment.rows {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "segment {} holds {} rows, manifest says {}",
+                    segment.hash,
+                    lines.len(),
+                    segment.rows
+                ),
+            )
+            .into());
+        }
+        Ok(lines)
+    }
+}
+
+fn repo_err(e: CasError) -> LifelogError {
+    LifelogError::Database(format!("backup repository: {e}"))
+}
+
+fn db_err(what: &str) -> impl Fn(tokio_postgres::Error) -> LifelogError + '_ {
+    move |e| LifelogError::Database(format!("{what}: {e}"))
+}
+
+/// What a backup run wrote.
+#[derive(Debug, Clone)]
+pub struct BackupSummary {
+    pub snapshot: Snapshot,
+    /// Segments not already present from an earlier snapshot.
+    pub segments_written: u64,
+    pub blobs_copied: u64,
+}
+
+/// Columns restored for `table`: everything except generated columns, which Postgres recomputes.
+async fn table_columns(
+    client: &impl GenericClient,
+    table: &str,
+) -> Result<(Vec<String>, Vec<String>), LifelogError> {
+    let rows = client
+        .query(
+            "SELECT column_name::TEXT, is_generated = 'ALWAYS' AS generated
+             FROM information_schema.columns
+             WHERE table_schema = current_schema() AND table_name = $1
+             ORDER BY ordinal_position",
+            &[&table],
+        )
+        .await
+        .map_err(db_err("backup column lookup failed"))?;
+    let mut stored = Vec::new();
+    let mut generated = Vec::new();
+    for row in rows {
+        let name: String = row.get(0);
+        if row.get::<_, bool>(1) {
+            generated.push(name);
+        } else {
+            stored.push(name);
+        }
+    }
+    if stored.is_empty() {
+        return Err(LifelogError::Database(format!(
+            "table {table} does not exist"
+        )));
+    }
+    Ok((stored, generated))
+}
+
+/// Takes a snapshot of every table in [`TABLES`] and the blobs its rows reference.
+pub async fn backup(
+    pool: &PostgresPool,
+    cas: &FsCas,
+    repo: &BackupRepo,
+    now: DateTime<Utc>,
+) -> Result<BackupSummary, LifelogError> {
+    let parent = repo.snapshots().map_err(repo_err)?.pop();
+    let mut client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("postgres pool get failed: {e}")))?;
+    let tx = client
+        .build_transaction()
+        .isolation_level(IsolationLevel::RepeatableRead)
+        .read_only(true)
+        .start()
+        .await
+        .map_err(db_err("backup transaction begin failed"))?;
+    let schema_version: Option<String> = tx
+        .query_one("SELECT MAX(version) FROM schema_migrations", &[])
+        .await
+        .map_err(db_err("schema version lookup failed"))?
+        .get(0);
+
+    let mut tables = Vec::with_capacity(TABLES.len());
+    let mut segments_written = 0u64;
+    let mut blob_hashes = BTreeSet::new();
+    for &table in TABLES {
+        let (_, generated) = table_columns(&tx, table).await?;
+        // Frames are grouped by day so old days keep producing byte-identical segments.
+        let (group, order) = if table == "frames" {
+            (
+                "to_char(t.t_canonical AT TIME ZONE 'UTC', 'YYYY-MM-DD')",
+                "1, t.id",
+            )
+        } else {
+            ("''", "1, 2")
+        };
+        // Forgotten upload chunks keep their row with an empty hash.
+        let blob = blob_column(table).map_or("NULL::TEXT".to_string(), |column| {
+            format!("NULLIF(t.{column}, '')")
+        });
+        tx.execute(
+            &format!(
+                "DECLARE backup_rows NO SCROLL CURSOR FOR
+                 SELECT {group}, (to_jsonb(t) - $1::TEXT[])::TEXT, {blob}
+                 FROM {table} t ORDER BY {order}"
+            ),
+            &[&generated],
+        )
+        .await
+        .map_err(db_err("backup cursor failed"))?;
+
+        let mut dump = TableDump {
+            table: table.to_string(),
+            rows: 0,
+            segments: Vec::new(),
+        };
+        let mut current_group: Option<String> = None;
+        let mut lines: Vec<String> = Vec::new();
+        loop {
+            let rows = tx
+                .query(&format!("FETCH {SEGMENT_ROWS} FROM backup_rows"), &[])
+                .await
+                .map_err(db_err("backup fetch failed"))?;
+            let done = rows.len() < SEGMENT_ROWS;
+            for row in rows {
+                let group: String = row.get(0);
+                if current_group.as_deref() != Some(group.as_str()) || lines.len() >= SEGMENT_ROWS {
+                    segments_written +=
+                        flush_segment(repo, &mut dump, &mut lines).map_err(repo_err)?;
+                    current_group = Some(group);
+                }
+                lines.push(row.get(1));
+                if let Some(hash) = row.get::<_, Option<String>>(2) {
+                    blob_hashes.insert(hash);
+                }
+            }
+            if done {
+                break;
+            }
+        }
+        segments_written += flush_segment(repo, &mut dump, &mut lines).map_err(repo_err)?;
+        tx.execute("CLOSE backup_rows", &[])
+            .await
+            .map_err(db_err("backup cursor close failed"))?;
+        tables.push(dump);
+    }
+    tx.commit()
+        .await
+        .map_err(db_err("backup transaction commit failed"))?;
+    drop(client);
+
+    let mut blobs_copied = 0u64;
+    let mut missing_blobs = Vec::new();
+    for hash in &blob_hashes {
+        if repo.objects.contains(hash).map_err(repo_err)? {
+            continue;
+        }
+        match cas.get(hash) {
+            Ok(bytes) if sha256_hex(&bytes) == *hash => {
+                repo.objects.put(&bytes).map_err(repo_err)?;
+                blobs_copied += 1;
+            }
+            Ok(_) => {
+                tracing::warn!(hash = %hash, "Blob content does not match its hash; not backed up");
+                missing_blobs.push(hash.clone());
+            }
+            Err(e) => {
+                tracing::warn!(hash = %hash, error = %e, "Blob unreadable; not backed up");
+                missing_blobs.push(hash.clone());
+            }
+        }
+    }
+
+    let snapshot = Snapshot {
+        id: now.format("%Y%m%dT%H%M%S%.3fZ").to_string(),
+        created_at: now,
+        parent,
+        schema_version,
+        tables,
+        blobs: blob_hashes.len() as u64,
+        missing_blobs,
+    };
+    repo.save_snapshot(&snapshot).map_err(repo_err)?;
+    Ok(BackupSummary {
+        snapshot,
+        segments_written,
+        blobs_copied,
+    })
+}
+
+/// Stores the buffered rows as one segment; returns 1 when the segment is new to the repository.
+fn flush_segment(
+    repo: &BackupRepo,
+    dump: &mut TableDump,
+    lines: &mut Vec<String>,
+) -> Result<u64, CasError> {
+    if lines.is_empty() {
+        return Ok(0);
+    }
+    let mut bytes = lines.join("\n").into_bytes();
+    bytes.push(b'\n');
+    let existed = repo.objects.contains(&sha256_hex(&bytes))?;
+    let hash = repo.objects.put(&bytes)?;
+    dump.rows += lines.len() as u64;
+    dump.segments.push(Segment {
+        hash,
+        rows: lines.len() as u64,
+    });
+    lines.clear();
+    Ok(u64::from(!existed))
+}
+
+/// Outcome of checking or restoring a snapshot.
+#[derive(Debug, Clone, Default)]
+pub struct RestoreSummary {
+    pub snapshot: String,
+    /// `(table, rows)` in restore order.
+    pub rows: Vec<(String, u64)>,
+    pub blobs_verified: u64,
+    /// Blobs missing from the snapshot (recorded at backup time or unreadable now).
+    pub blobs_missing: Vec<String>,
+}
+
+/// Blob hashes referenced by the rows of a segment of `table`.
+fn segment_blobs(
+    table: &str,
+    lines: &[String],
+    out: &mut BTreeSet<String>,
+) -> Result<(), CasError> {
+    let Some(column) = blob_column(table) else {
+        return Ok(());
+    };
+    for line in lines {
+        let row: serde_json::Value = serde_json::from_str(line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(hash) = row.get(column).and_then(|h| h.as_str()) {
+            if !hash.is_empty() {
+                out.insert(hash.to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads every segment and blob of a snapshot and checks hashes and row counts, without
+/// touching any database.
+pub fn verify(repo: &BackupRepo, snapshot: &Snapshot) -> Result<RestoreSummary, CasError> {
+    let mut summary = RestoreSummary {
+        snapshot: snapshot.id.clone(),
+        ..Default::default()
+    };
+    let mut blobs = BTreeSet::new();
+    for dump in &snapshot.tables {
+        for segment in &dump.segments {
+            let lines = repo.read_segment(segment)?;
+            segment_blobs(&dump.table, &lines, &mut blobs)?;
+        }
+        summary.rows.push((dump.table.clone(), dump.rows));
+    }
+    for hash in &blobs {
+        if snapshot.missing_blobs.contains(hash) {
+            summary.blobs_missing.push(hash.clone());
+            continue;
+        }
+        repo.objects.verify(hash)?;
+        summary.blobs_verified += 1;
+    }
+    Ok(summary)
+}
+
+/// Restores a snapshot into an empty, migrated database and its CAS, then checks the restored
+/// row counts and blobs against the manifest. Refuses to run when any backed-up table already
+/// holds rows.
+pub async fn restore(
+    pool: &PostgresPool,
+    cas: &FsCas,
+    repo: &BackupRepo,
+    snapshot: &Snapshot,
+) -> Result<RestoreSummary, LifelogError> {
+    let mut client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("postgres pool get failed: {e}")))?;
+    if let Some(version) = &snapshot.schema_version {
+        let known = client
+            .query_opt(
+                "SELECT 1 FROM schema_migrations WHERE version = $1",
+                &[version],
+            )
+            .await
+            .map_err(db_err("schema version lookup failed"))?;
+        if known.is_none() {
+            return Err(LifelogError::Validation {
+                field: "snapshot".to_string(),
+                reason: format!(
+                    "snapshot {} was taken at schema {version}, which this server does not know",
+                    snapshot.id
+                ),
+            });
+        }
+    }
+    for &table in TABLES {
+        let occupied: bool = client
+            .query_one(&format!("SELECT EXISTS (SELECT 1 FROM {table})"), &[])
+            .await
+            .map_err(db_err("restore emptiness check failed"))?
+            .get(0);
+        if occupied {
+            return Err(LifelogError::Validation {
+                field: "database".to_string(),
+                reason: format!("table {table} is not empty; restore needs a fresh database"),
+            });
+        }
+    }
+
+    let mut summary = RestoreSummary {
+        snapshot: snapshot.id.clone(),
+        ..Default::default()
+    };
+    let mut blobs = BTreeSet::new();
+    let tx = client
+        .transaction()
+        .await
+        .map_err(db_err("restore transaction begin failed"))?;
+    for dump in &snapshot.tables {
+        if !TABLES.contains(&dump.table.as_str()) {
+            tracing::warn!(table = %dump.table, "Skipping unknown table in snapshot");
+            continue;
+        }
+        let table = dump.table.as_str();
+        let (columns, _) = table_columns(&tx, table).await?;
+        // Quoted, since `upload_chunks` has an `offset` column.
+        let columns = columns
+            .iter()
+            .map(|c| crate::partitions::quote_ident(c))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let insert = format!(
+            "INSERT INTO {table} ({columns})
+             SELECT {columns} FROM jsonb_populate_recordset(NULL::{table}, $1::TEXT::JSONB)"
+        );
+        for segment in &dump.segments {
+            let lines = repo.read_segment(segment).map_err(repo_err)?;
+            let array = format!("[{}]", lines.join(","));
+            segment_blobs(table, &lines, &mut blobs).map_err(repo_err)?;
+            if table == "frames" {
+                // A failed insert would abort the transaction, so the segment's months are
+                // partitioned up front.
+                tx.execute(
+                    "SELECT create_frame_partition(t_canonical)
+                     FROM (
+                         SELECT DISTINCT date_trunc('month', t_canonical AT TIME ZONE 'UTC')
+                             AT TIME ZONE 'UTC' AS t_canonical
+                         FROM jsonb_populate_recordset(NULL::frames, $1::TEXT::JSONB)
+                     ) months",
+                    &[&array],
+                )
+                .await
+                .map_err(db_err("restore frame partitions failed"))?;
+            }
+            tx.execute(&insert, &[&array])
+                .await
+                .map_err(|e| LifelogError::Database(format!("restore {table} failed: {e}")))?;
+        }
+
+        // Serial columns must continue after the restored IDs.
+        let serials = tx
+            .query(
+                "SELECT column_name::TEXT FROM information_schema.columns
+                 WHERE table_schema = current_schema() AND table_name = $1
+                   AND column_default LIKE 'nextval(%'",
+                &[&table],
+            )
+            .await
+            .map_err(db_err("restore sequence lookup failed"))?;
+        for row in serials {
+            let column: String = row.get(0);
+            tx.execute(
+                &format!(
+                    "SELECT setval(pg_get_serial_sequence($1, $2),
+                                   COALESCE((SELECT MAX({column}) FROM {table}), 0) + 1, false)"
+                ),
+                &[&table, &column],
+            )
+            .await
+            .map_err(db_err("restore sequence reset failed"))?;
+        }
+    }
+
+    // Blobs go in before the rows are committed, so restored frames never point at nothing.
+    for hash in &blobs {
+        if snapshot.missing_blobs.contains(hash) {
+            summary.blobs_missing.push(hash.clone());
+            continue;
+        }
+        let bytes = repo.objects.get(hash).map_err(repo_err)?;
+        let stored = cas
+            .put(&bytes)
+            .map_err(|e| LifelogError::Database(format!("restore blob {hash}: {e}")))?;
+        if stored != *hash {
+            return Err(repo_err(CasError::HashMismatch(hash.clone())));
+        }
+    }
+    tx.commit()
+        .await
+        .map_err(db_err("restore transaction commit failed"))?;
+
+    for dump in &snapshot.tables {
+        if !TABLES.contains(&dump.table.as_str()) {
+            continue;
+        }
+        let rows: i64 = client
+            .query_one(&format!("SELECT COUNT(*) FROM {}", dump.table), &[])
+            .await
+            .map_err(db_err("restore verification failed"))?
+            .get(0);
+        if rows as u64 != dump.rows {
+            return Err(LifelogError::Database(format!(
+                "restored {rows} rows into {}, snapshot holds {}",
+                dump.table, dump.rows
+            )));
+        }
+        summary.rows.push((dump.table.clone(), dump.rows));
+    }
+    for hash in &blobs {
+        if snapshot.missing_blobs.contains(hash) {
+            continue;
+        }
+        cas.verify(hash)
+            .map_err(|e| LifelogError::Database(format!("restored blob {hash}: {e}")))?;
+        summary.blobs_verified += 1;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames_dump() -> TableDump {
+        TableDump {
+            table: "frames".to_string(),
+            rows: 0,
+            segments: Vec::new(),
+        }
+    }
+
+    fn segment(repo: &BackupRepo, lines: &[&str]) -> Option<Segment> {
+        let mut dump = frames_dump();
+        let mut buf: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+        flush_segment(repo, &mut dump, &mut buf).ok()?;
+        dump.segments.pop()
+    }
+
+    #[test]
+    #[allow(clippy::panic)]
+    fn identical_segments_are_stored_once() {
+        let Ok(dir) = tempfile::tempdir() else {
+            panic!("tempdir");
+        };
+        let Ok(repo) = BackupRepo::open(dir.path(), None) else {
+            panic!("open repo");
+        };
+        let mut dump = frames_dump();
+        let mut lines = vec![r#"{"id": "a"}"#.to_string()];
+        assert_eq!(flush_segment(&repo, &mut dump, &mut lines).ok(), Some(1));
+        let mut lines = vec![r#"{"id": "a"}"#.to_string()];
+        assert_eq!(flush_segment(&repo, &mut dump, &mut lines).ok(), Some(0));
+        assert_eq!(dump.rows, 2);
+        assert_eq!(dump.segments[0].hash, dump.segments[1].hash);
+    }
+
+    #[test]
+    #[allow(clippy::panic)]
+    fn repository_key_is_fixed_at_creation() {
+        let Ok(dir) = tempfile::tempdir() else {
+            panic!("tempdir");
+        };
+        let key_path = dir.path().join("backup.key");
+        std::fs::write(&key_path, [7u8; 32]).unwrap_or_default();
+        let spec = format!("file:{}", key_path.display());
+        let root = dir.path().join("repo");
+
+        let Ok(repo) = BackupRepo::open(&root, Some(&spec)) else {
+            panic!("open encrypted repo");
+        };
+        let Some(seg) = segment(&repo, &[r#"{"id": "a", "blob_hash": null}"#]) else {
+            panic!("segment");
+        };
+
+        assert!(matches!(
+            BackupRepo::open(&root, None),
+            Err(CasError::MissingKey)
+        ));
+        assert!(matches!(
+            BackupRepo::open(&root, Some("passphrase:other")),
+            Err(CasError::UnknownKey(_))
+        ));
+        let Ok(reopened) = BackupRepo::open(&root, Some(&spec)) else {
+            panic!("reopen");
+        };
+        assert_eq!(reopened.read_segment(&seg).map(|l| l.len()).ok(), Some(1));
+    }
+
+    #[test]
+    #[allow(clippy::panic)]
+    fn verify_reports_row_count_mismatch_and_missing_blobs() {
+        let Ok(dir) = tempfile::tempdir() else {
+            panic!("tempdir");
+        };
+        let Ok(repo) = BackupRepo::open(dir.path(), None) else {
+            panic!("open repo");
+        };
+        let blob = repo.objects.put(b"screen").unwrap_or_default();
+        let line = format!(r#"{{"id": "a", "blob_hash": "{blob}"}}"#);
+        let gone = "ab".repeat(32);
+        let missing = format!(r#"{{"id": "b", "blob_hash": "{gone}"}}"#);
+        let Some(seg) = segment(&repo, &[&line, &missing]) else {
+            panic!("segment");
+        };
+        let mut snapshot = Snapshot {
+            id: "s1".to_string(),
+            created_at: Utc::now(),
+            parent: None,
+            schema_version: None,
+            tables: vec![TableDump {
+                table: "frames".to_string(),
+                rows: 2,
+                segments: vec![seg],
+            }],
+            blobs: 2,
+            missing_blobs: vec![gone.clone()],
+        };
+        let Ok(summary) = verify(&repo, &snapshot) else {
+            panic!("verify");
+        };
+        assert_eq!(summary.rows, vec![("frames".to_string(), 2)]);
+        assert_eq!(summary.blobs_verified, 1);
+        assert_eq!(summary.blobs_missing, vec![gone]);
+
+        snapshot.tables[0].segments[0].rows = 3;
+        assert!(verify(&repo, &snapshot).is_err());
+    }
+
+    #[test]
+    fn upload_chunk_blobs_are_referenced_unless_forgotten() {
+        let chunk = "cd".repeat(32);
+        let lines = vec![
+            format!(r#"{{"id": "c:s:1:0", "hash": "{chunk}"}}"#),
+            r#"{"id": "c:s:1:9", "hash": ""}"#.to_string(),
+        ];
+        let mut blobs = BTreeSet::new();
+        assert!(segment_blobs("upload_chunks", &lines, &mut blobs).is_ok());
+        assert_eq!(blobs.into_iter().collect::<Vec<_>>(), vec![chunk]);
+
+        let mut blobs = BTreeSet::new();
+        assert!(segment_blobs("pins", &lines, &mut blobs).is_ok());
+        assert!(blobs.is_empty());
+    }
+}
@@ -0,0 +1,561 @@
+//! Purging frames on request, together with everything derived from them.
+//!
+//! Forget works on a closure of frames: the selected ones, every frame derived from them through
+//! `source_frame_id` (transitively), near-duplicates whose `duplicate_of` names one of them, and
+//! the summaries, episodes and meetings whose payload lists one of them as an input. Those
+//! aggregates are deleted rather than patched, since their text can repeat what was forgotten.
+//! Deleting the closure also removes the transform cache entries for its content, its entity
+//! mentions and dead letters, and the CAS blobs no remaining frame or upload chunk references.
+//! Each confirmed forget appends a row to `forget_audit`, a hash chain checked by `verify_chain`.
+
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::GenericClient;
+use lifelog_core::uuid::Uuid;
+use lifelog_core::{DataOrigin, LifelogError};
+use serde::Serialize;
+use utils::cas::{sha256_hex, FsCas};
+
+use crate::postgres::PostgresPool;
+use crate::transform::cache;
+
+/// Most frames one request may select. Larger purges must be split, which keeps previews honest.
+pub const MAX_FRAMES: usize = 100_000;
+/// Frames loaded at a time to hash their content for the transform cache.
+const CACHE_HASH_BATCH: usize = 200;
+
+/// What a forget request selected, as recorded in the audit log. A query is kept only by its
+/// hash, since its text can name what was forgotten.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ForgetSelector {
+    pub query_sha256: Option<String>,
+    pub time_ranges: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+    pub origins: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ForgetReport {
+    pub matched_frames: u64,
+    /// Frames in the closure beyond the matched ones.
+    pub derived_frames: u64,
+    pub frames_by_modality: BTreeMap<String, u64>,
+    pub pinned_frames: u64,
+    /// Blobs referenced only by frames in the closure.
+    pub blobs: u64,
+    pub blob_bytes: u64,
+    pub forgotten: bool,
+    pub cache_entries: u64,
+    pub entity_mentions: u64,
+    pub audit_id: Option<i64>,
+}
+
+impl ForgetReport {
+    pub fn total_frames(&self) -> u64 {
+        self.matched_frames + self.derived_frames
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub selector: String,
+    pub reason: String,
+    pub frames: i64,
+    pub blobs: i64,
+    pub frame_ids_sha256: String,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditRecord {
+    fn expected_hash(&self) -> String {
+        chain_hash(
+            &self.prev_hash,
+            self.created_at,
+            &self.selector,
+            &self.reason,
+            self.frames,
+            self.blobs,
+            &self.frame_ids_sha256,
+        )
+    }
+}
+
+fn chain_hash(
+    prev_hash: &str,
+    created_at: DateTime<Utc>,
+    selector: &str,
+    reason: &str,
+    frames: i64,
+    blobs: i64,
+    frame_ids_sha256: &str,
+) -> String {
+    let material = serde_json::json!([
+        prev_hash,
+        created_at.timestamp_micros(),
+        selector,
+        reason,
+        frames,
+        blobs,
+        frame_ids_sha256,
+    ]);
+    sha256_hex(material.to_string().as_bytes())
+}
+
+/// ID of the first record, in ID order, whose hash or link to its predecessor does not match.
+/// Removing the newest records leaves the chain intact, so keep a copy of the latest hash to
+/// detect that.
+pub fn verify_chain(records: &[AuditRecord]) -> Option<i64> {
+    let mut prev = "";
+    for record in records {
+        if record.prev_hash != prev || record.hash != record.expected_hash() {
+            return Some(record.id);
+        }
+        prev = &record.hash;
+    }
+    None
+}
+
+fn db_err(what: &'static str) -> impl Fn(tokio_postgres::Error) -> LifelogError {
+    move |e| LifelogError::Database(format!("{what}: {e}"))
+}
+
+/// Frames overlapping any of `ranges`, limited to `origins` when any are given. Unlike LLQL
+/// queries this includes near-duplicates, so nothing captured in the ranges is left behind.
+/// Returns at most `MAX_FRAMES + 1` IDs.
+pub async fn frames_in_ranges(
+    pool: &PostgresPool,
+    ranges: &[(DateTime<Utc>, DateTime<Utc>)],
+    origins: &[DataOrigin],
+) -> Result<Vec<Uuid>, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let starts: Vec<DateTime<Utc>> = ranges.iter().map(|r| r.0).collect();
+    let ends: Vec<DateTime<Utc>> = ranges.iter().map(|r| r.1).collect();
+    let modalities: Vec<String> = origins.iter().map(|o| o.modality_name.clone()).collect();
+    let collectors: Vec<String> = origins
+        .iter()
+        .map(|o| o.collector_id().unwrap_or_default().to_string())
+        .collect();
+    let limit = (MAX_FRAMES + 1) as i64;
+    let rows = client
+        .query(
+            "SELECT f.id FROM frames f
+             WHERE EXISTS (
+                 SELECT 1 FROM unnest($1::timestamptz[], $2::timestamptz[]) r (s, e)
+                 WHERE f.time_range && tstzrange(r.s, r.e, '[)')
+             )
+             AND (cardinality($3::text[]) = 0 OR EXISTS (
+                 SELECT 1 FROM unnest($3::text[], $4::text[]) o (modality, collector_id)
+                 WHERE f.modality = o.modality
+                   AND (o.collector_id = '' OR f.collector_id = o.collector_id)
+             ))
+             LIMIT $5",
+            &[&starts, &ends, &modalities, &collectors, &limit],
+        )
+        .await
+        .map_err(db_err("forget range query"))?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+/// Reports what forgetting `seeds` would delete, without deleting anything.
+pub async fn preview(pool: &PostgresPool, seeds: &[Uuid]) -> Result<ForgetReport, LifelogError> {
+    let mut client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let tx = client
+        .transaction()
+        .await
+        .map_err(db_err("forget transaction begin failed"))?;
+    let (report, _) = collect_closure(&tx, seeds).await?;
+    tx.rollback()
+        .await
+        .map_err(db_err("forget preview rollback failed"))?;
+    Ok(report)
+}
+
+/// Deletes `seeds` and their closure in one transaction, appends the audit record and then
+/// removes the orphaned blobs from the CAS. Pinned frames are forgotten too, with their pins.
+pub async fn forget(
+    pool: &PostgresPool,
+    cas: &FsCas,
+    seeds: &[Uuid],
+    selector: &ForgetSelector,
+    reason: &str,
+    now: DateTime<Utc>,
+) -> Result<ForgetReport, LifelogError> {
+    let mut client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let tx = client
+        .transaction()
+        .await
+        .map_err(db_err("forget transaction begin failed"))?;
+    let (mut report, orphan_hashes) = collect_closure(&tx, seeds).await?;
+    if report.total_frames() == 0 {
+        return Ok(report);
+    }
+
+    let mut ids: Vec<Uuid> = tx
+        .query("SELECT id FROM forget_ids", &[])
+        .await
+        .map_err(db_err("forget id list"))?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    ids.sort();
+
+    let input_hashes = cache_input_hashes(pool, cas, &ids).await?;
+    report.cache_entries = tx
+        .execute(
+            "DELETE FROM transform_cache WHERE input_hash = ANY($1)",
+            &[&input_hashes],
+        )
+        .await
+        .map_err(db_err("forget transform cache"))?;
+
+    let mentioned: Vec<i64> = tx
+        .query(
+            "DELETE FROM entity_mentions WHERE frame_id IN (SELECT id FROM forget_ids)
+             RETURNING entity_id",
+            &[],
+        )
+        .await
+        .map_err(db_err("forget entity mentions"))?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    report.entity_mentions = mentioned.len() as u64;
+    // An entity seen only in forgotten frames would still spell out what was in them.
+    tx.execute(
+        "DELETE FROM entities e WHERE e.id = ANY($1)
+         AND NOT EXISTS (SELECT 1 FROM entity_mentions m WHERE m.entity_id = e.id)",
+        &[&mentioned],
+    )
+    .await
+    .map_err(db_err("forget orphan entities"))?;
+
+    tx.execute(
+        "DELETE FROM transform_dead_letters WHERE frame_id IN (SELECT id FROM forget_ids)",
+        &[],
+    )
+    .await
+    .map_err(db_err("forget dead letters"))?;
+    // Chunk rows stay, without their blob, so a collector resuming the session does not upload
+    // the chunk again.
+    tx.execute(
+        "UPDATE upload_chunks SET hash = '', frame_uuid = NULL
+         WHERE frame_uuid IN (SELECT id::text FROM forget_ids)",
+        &[],
+    )
+    .await
+    .map_err(db_err("forget upload chunks"))?;
+    tx.execute(
+        "DELETE FROM pins WHERE frame_id IN (SELECT id FROM forget_ids)",
+        &[],
+    )
+    .await
+    .map_err(db_err("forget pins"))?;
+    tx.execute(
+        "DELETE FROM cas_scrub_findings WHERE blob_hash = ANY($1)",
+        &[&orphan_hashes],
+    )
+    .await
+    .map_err(db_err("forget scrub findings"))?;
+    tx.execute(
+        "DELETE FROM frames WHERE id IN (SELECT id FROM forget_ids)",
+        &[],
+    )
+    .await
+    .map_err(db_err("forget frames"))?;
+
+    let ids_text = ids
+        .iter()
+        .map(Uuid::to_string)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let selector_json = serde_json::to_string(selector)
+        .map_err(|e| LifelogError::Database(format!("forget selector: {e}")))?;
+    let created_at = DateTime::from_timestamp_micros(now.timestamp_micros()).unwrap_or(now);
+    report.audit_id = Some(
+        append_audit(
+            &tx,
+            created_at,
+            &selector_json,
+            reason,
+            ids.len() as i64,
+            orphan_hashes.len() as i64,
+            &sha256_hex(ids_text.as_bytes()),
+        )
+        .await?,
+    );
+
+    tx.commit()
+        .await
+        .map_err(db_err("forget transaction commit failed"))?;
+    report.forgotten = true;
+
+    for hash in &orphan_hashes {
+        if let Err(e) = cas.remove(hash) {
+            tracing::warn!(hash = %hash, error = %e, "forget: failed to remove CAS blob");
+        }
+    }
+    Ok(report)
+}
+
+/// Fills the `forget_ids` temp table with the closure of `seeds` and reports on it. Also returns
+/// the blobs that would be orphaned.
+async fn collect_closure<C: GenericClient>(
+    client: &C,
+    seeds: &[Uuid],
+) -> Result<(ForgetReport, Vec<String>), LifelogError> {
+    client
+        .batch_execute("CREATE TEMP TABLE forget_ids (id UUID PRIMARY KEY) ON COMMIT DROP")
+        .await
+        .map_err(db_err("forget temp table"))?;
+    let matched = client
+        .execute(
+            "INSERT INTO forget_ids SELECT id FROM frames WHERE id = ANY($1)",
+            &[&seeds],
+        )
+        .await
+        .map_err(db_err("forget seed frames"))?;
+    let mut total = matched;
+    loop {
+        let added = client
+            .execute(
+                "INSERT INTO forget_ids
+                 SELECT f.id FROM frames f WHERE f.source_frame_id IN (SELECT id FROM forget_ids)
+                 UNION
+                 SELECT f.id FROM frames f
+                 WHERE f.payload ? 'duplicate_of'
+                   AND f.payload->>'duplicate_of' IN (SELECT id::text FROM forget_ids)
+                 UNION
+                 SELECT f.id FROM frames f, LATERAL (SELECT ARRAY(SELECT id::text FROM forget_ids)) g(ids)
+                 WHERE f.modality IN ('Summary', 'Episode', 'Meeting')
+                   AND (f.payload->'cited_frame_ids' ?| g.ids
+                        OR f.payload->'child_summary_ids' ?| g.ids
+                        OR f.payload->'member_frame_ids' ?| g.ids
+                        OR f.payload->'transcript_frame_ids' ?| g.ids
+                        OR f.payload->'audio_frame_ids' ?| g.ids)
+                 ON CONFLICT DO NOTHING",
+                &[],
+            )
+            .await
+            .map_err(db_err("forget derived frames"))?;
+        if added == 0 {
+            break;
+        }
+        total += added;
+    }
+
+    let mut report = ForgetReport {
+        matched_frames: matched,
+        derived_frames: total - matched,
+        ..Default::default()
+    };
+    let rows = client
+        .query(
+            "SELECT f.modality, COUNT(*),
+                    COUNT(*) FILTER (WHERE frame_pinned(f.id, f.source_frame_id, f.time_range))
+             FROM frames f JOIN forget_ids d ON d.id = f.id
+             GROUP BY f.modality",
+            &[],
+        )
+        .await
+        .map_err(db_err("forget modality counts"))?;
+    for row in rows {
+        let count: i64 = row.get(1);
+        let pinned: i64 = row.get(2);
+        report.frames_by_modality.insert(row.get(0), count as u64);
+        report.pinned_frames += pinned as u64;
+    }
+    let mentions: i64 = client
+        .query_one(
+            "SELECT COUNT(*) FROM entity_mentions WHERE frame_id IN (SELECT id FROM forget_ids)",
+            &[],
+        )
+        .await
+        .map_err(db_err("forget entity mention count"))?
+        .get(0);
+    report.entity_mentions = mentions as u64;
+
+    let rows = client
+        .query(
+            "WITH doomed AS (
+                 SELECT f.blob_hash AS hash, f.blob_size::BIGINT AS size
+                 FROM frames f JOIN forget_ids d ON d.id = f.id
+                 WHERE f.blob_hash IS NOT NULL AND f.blob_hash <> ''
+                 UNION ALL
+                 SELECT c.hash, c.length::BIGINT
+                 FROM upload_chunks c JOIN forget_ids d ON c.frame_uuid = d.id::text
+                 WHERE c.hash <> ''
+             )
+             SELECT b.hash, COALESCE(MAX(b.size), 0)::BIGINT FROM doomed b
+             WHERE NOT EXISTS (
+                 SELECT 1 FROM frames f
+                 WHERE f.blob_hash = b.hash AND f.id NOT IN (SELECT id FROM forget_ids)
+             )
+             AND NOT EXISTS (
+                 SELECT 1 FROM upload_chunks c
+                 WHERE c.hash = b.hash
+                   AND (c.frame_uuid IS NULL OR c.frame_uuid NOT IN (SELECT id::text FROM forget_ids))
+             )
+             GROUP BY b.hash",
+            &[],
+        )
+        .await
+        .map_err(db_err("forget orphan blobs"))?;
+    let mut hashes = Vec::with_capacity(rows.len());
+    for row in rows {
+        let size: i64 = row.get(1);
+        report.blob_bytes += size.max(0) as u64;
+        hashes.push(row.get(0));
+    }
+    report.blobs = hashes.len() as u64;
+    Ok((report, hashes))
+}
+
+/// Transform cache keys for the content of `ids`. Identical content elsewhere shares the entry,
+/// which then only has to be recomputed.
+async fn cache_input_hashes(
+    pool: &PostgresPool,
+    cas: &FsCas,
+    ids: &[Uuid],
+) -> Result<Vec<String>, LifelogError> {
+    let mut hashes = HashSet::new();
+    for batch in ids.chunks(CACHE_HASH_BATCH) {
+        for data in crate::frames::get_by_ids(pool, cas, batch).await? {
+            hashes.extend(cache::input_hash(&data));
+        }
+    }
+    Ok(hashes.into_iter().collect())
+}
+
+async fn append_audit<C: GenericClient>(
+    client: &C,
+    created_at: DateTime<Utc>,
+    selector: &str,
+    reason: &str,
+    frames: i64,
+    blobs: i64,
+    frame_ids_sha256: &str,
+) -> Result<i64, LifelogError> {
+    // Serializes concurrent forgets so each record links to the one before it.
+    client
+        .batch_execute("LOCK TABLE forget_audit IN EXCLUSIVE MODE")
+        .await
+        .map_err(db_err("forget audit lock"))?;
+    let prev_hash: String = client
+        .query_opt(
+            "SELECT hash FROM forget_audit ORDER BY id DESC LIMIT 1",
+            &[],
+        )
+        .await
+        .map_err(db_err("forget audit head"))?
+        .map(|row| row.get(0))
+        .unwrap_or_default();
+    let hash = chain_hash(
+        &prev_hash,
+        created_at,
+        selector,
+        reason,
+        frames,
+        blobs,
+        frame_ids_sha256,
+    );
+    let row = client
+        .query_one(
+            "INSERT INTO forget_audit
+                 (created_at, selector, reason, frames, blobs, frame_ids_sha256, prev_hash, hash)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             RETURNING id",
+            &[
+                &created_at,
+                &selector,
+                &reason,
+                &frames,
+                &blobs,
+                &frame_ids_sha256,
+                &prev_hash,
+                &hash,
+            ],
+        )
+        .await
+        .map_err(db_err("forget audit insert"))?;
+    Ok(row.get(0))
+}
+
+/// The audit log, oldest first.
+pub async fn audit_log(pool: &PostgresPool) -> Result<Vec<AuditRecord>, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let rows = client
+        .query(
+            "SELECT id, created_at, selector, reason, frames, blobs, frame_ids_sha256, prev_hash, hash
+             FROM forget_audit ORDER BY id",
+            &[],
+        )
+        .await
+        .map_err(db_err("forget audit list"))?;
+    Ok(rows
+        .iter()
+        .map(|row| AuditRecord {
+            id: row.get("id"),
+            created_at: row.get("created_at"),
+            selector: row.get("selector"),
+            reason: row.get("reason"),
+            frames: row.get("frames"),
+            blobs: row.get("blobs"),
+            frame_ids_sha256: row.get("frame_ids_sha256"),
+            prev_hash: row.get("prev_hash"),
+            hash: row.get("hash"),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: i64, prev_hash: &str, reason: &str) -> AuditRecord {
+        let created_at = DateTime::from_timestamp(1_700_000_000 + id, 0).unwrap_or_default();
+        let mut record = AuditRecord {
+            id,
+            created_at,
+            selector: "{}".to_string(),
+            reason: reason.to_string(),
+            frames: 3,
+            blobs: 1,
+            frame_ids_sha256: sha256_hex(b"ids"),
+            prev_hash: prev_hash.to_string(),
+            hash: String::new(),
+        };
+        record.hash = record.expected_hash();
+        record
+    }
+
+    #[test]
+    fn verify_chain_detects_edits_and_removals() {
+        let first = record(1, "", "password manager");
+        let second = record(2, &first.hash, "private call");
+        let third = record(3, &second.hash, "");
+        let chain = vec![first.clone(), second.clone(), third.clone()];
+        assert_eq!(verify_chain(&chain), None);
+
+        let mut edited = chain.clone();
+        edited[1].frames = 1;
+        assert_eq!(verify_chain(&edited), Some(2));
+
+        let removed = vec![first, third];
+        assert_eq!(verify_chain(&removed), Some(3));
+    }
+}
@@ -8,8 +8,12 @@ use utils::cas::FsCas;
 
 fn row_to_frame_row(row: &tokio_postgres::Row) -> Result<FrameRow, LifelogError> {
     let t_canonical: DateTime<Utc> = row.get("t_canonical");
+    let id: uuid::Uuid = row.get("id");
+    let mut payload: serde_json::Value = row.get("payload");
+    crate::payload_crypto::open_payload(id, &mut payload)
+        .map_err(|e| LifelogError::Database(format!("frame {id} payload: {e}")))?;
     Ok(FrameRow {
-        id: row.get("id"),
+        id,
         collector_id: row.get("collector_id"),
         stream_id: row.get("stream_id"),
         modality: row.get("modality"),
@@ -22,7 +26,7 @@ fn row_to_frame_row(row: &tokio_postgres::Row) -> Result<FrameRow, LifelogError>
         blob_size: row.get("blob_size"),
         indexed: row.get("indexed"),
         source_frame_id: row.get("source_frame_id"),
-        payload: row.get("payload"),
+        payload,
     })
 }
 
@@ -119,10 +123,11 @@ pub async fn get_keys_after_filtered(
 
     let collector_id = extract_collector_id(origin);
     let modality = &origin.modality_name;
+    // Near-duplicate images are never handed to transforms; see `crate::phash`.
     let derived_filter = if exclude_derived {
-        " AND source_frame_id IS NULL"
+        " AND source_frame_id IS NULL AND NOT (payload ? 'duplicate_of')"
     } else {
-        ""
+        " AND NOT (payload ? 'duplicate_of')"
     };
 
     let rows = if let Some(cid) = collector_id {
@@ -176,14 +181,14 @@ pub async fn count_keys_after(
     let count: i64 = if let Some(cid) = collector_id {
         client
             .query_one(
-                "SELECT COUNT(*) FROM frames WHERE modality = $1 AND collector_id = $2 AND t_canonical > $3",
+                "SELECT COUNT(*) FROM frames WHERE modality = $1 AND collector_id = $2 AND t_canonical > $3 AND NOT (payload ? 'duplicate_of')",
                 &[&modality, &cid, &after],
             )
             .await
     } else {
         client
             .query_one(
-                "SELECT COUNT(*) FROM frames WHERE modality = $1 AND t_canonical > $2",
+                "SELECT COUNT(*) FROM frames WHERE modality = $1 AND t_canonical > $2 AND NOT (payload ? 'duplicate_of')",
                 &[&modality, &after],
             )
             .await
@@ -217,6 +222,25 @@ pub async fn get_origins(pool: &PostgresPool) -> Result<Vec<DataOrigin>, Lifelog
     Ok(origins)
 }
 
+/// Whether frames derived from `source` are sealed: it has a sensitive modality or is itself
+/// sealed. Always false while payload encryption is off.
+async fn source_is_sensitive(
+    client: &deadpool_postgres::Object,
+    source: Option<uuid::Uuid>,
+) -> Result<bool, LifelogError> {
+    let Some(source) = source.filter(|_| crate::payload_crypto::configured().is_some()) else {
+        return Ok(false);
+    };
+    let row = client
+        .query_opt(
+            "SELECT modality = ANY($2) OR payload ? 'sealed' FROM frames WHERE id = $1",
+            &[&source, &crate::payload_crypto::SENSITIVE_MODALITIES],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("transform source lookup: {e}")))?;
+    Ok(row.is_some_and(|r| r.get::<_, bool>(0)))
+}
+
 pub async fn insert_transform_output(
     pool: &PostgresPool,
     row: &FrameRow,
@@ -229,19 +253,21 @@ pub async fn insert_transform_output(
     let sql = "INSERT INTO frames (
             id, collector_id, stream_id, modality, time_range,
             t_device, t_ingest, t_canonical, t_end, time_quality,
-            blob_hash, blob_size, indexed, source_frame_id, payload
+            blob_hash, blob_size, indexed, source_frame_id, payload, language
         ) VALUES (
             $1, $2, $3, $4, tstzrange($5, $6, '[]'),
             $7, $8, $9, $10, $11,
-            $12, $13, $14, $15, $16
+            $12, $13, $14, $15, $16, $17
         )
-        ON CONFLICT (source_frame_id, stream_id, modality)
-            WHERE source_frame_id IS NOT NULL
-        DO NOTHING";
-
-    let params = row.insert_params();
-    let rows_affected = client
-        .execute(sql, &params)
+        ON CONFLICT DO NOTHING";
+
+    let sensitive_source = source_is_sensitive(&client, row.source_frame_id).await?;
+    let row = row.sealed_derived(sensitive_source).map_err(|e| {
+        LifelogError::Database(format!("transform output seal (id={}): {e}", row.id))
+    })?;
+    let language = row.search_language();
+    let params = row.insert_params(&language);
+    let rows_affected = crate::partitions::execute_routed(&client, sql, &params, row.t_canonical)
         .await
         .map_err(|e| LifelogError::Database(format!("transform output insert: {e}")))?;
 
@@ -258,34 +284,67 @@ pub async fn insert_transform_output(
 }
 
 pub async fn upsert(pool: &PostgresPool, row: &FrameRow) -> Result<(), LifelogError> {
+    upsert_derived(pool, row, false).await
+}
+
+/// [`upsert`] for a frame built from others, sealed when `sensitive_source` says one of them
+/// was; see [`FrameRow::sealed_derived`].
+pub async fn upsert_derived(
+    pool: &PostgresPool,
+    row: &FrameRow,
+    sensitive_source: bool,
+) -> Result<(), LifelogError> {
     let client = pool
         .get()
         .await
         .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
 
-    let sql = "INSERT INTO frames (
-            id, collector_id, stream_id, modality, time_range,
-            t_device, t_ingest, t_canonical, t_end, time_quality,
-            blob_hash, blob_size, indexed, source_frame_id, payload
-        ) VALUES (
-            $1, $2, $3, $4, tstzrange($5, $6, '[]'),
-            $7, $8, $9, $10, $11,
-            $12, $13, $14, $15, $16
-        )
-        ON CONFLICT (id) DO UPDATE SET
-            payload = EXCLUDED.payload,
-            t_ingest = EXCLUDED.t_ingest,
-            t_canonical = EXCLUDED.t_canonical,
-            t_end = EXCLUDED.t_end,
-            time_quality = EXCLUDED.time_quality,
-            indexed = EXCLUDED.indexed,
-            time_range = EXCLUDED.time_range";
-
-    let params = row.insert_params();
-    client
-        .execute(sql, &params)
-        .await
-        .map_err(|e| LifelogError::Database(format!("frames upsert: {e}")))?;
+    // `frames` is partitioned, so its ID alone is no conflict target: update the frame if it is
+    // there and insert it otherwise. An insert racing another writer's waits for that writer's
+    // `frame_keys` claim and is then skipped, so the update is retried once the row is there.
+    let update_sql = "UPDATE frames SET
+            payload = $2,
+            t_ingest = $3,
+            t_canonical = $4,
+            t_end = $5,
+            time_quality = $6,
+            indexed = $7,
+            time_range = tstzrange($8, $9, '[]'),
+            language = $10
+        WHERE id = $1";
+
+    let row = row
+        .sealed_derived(sensitive_source)
+        .map_err(|e| LifelogError::Database(format!("frames upsert seal (id={}): {e}", row.id)))?;
+    let language = row.search_language();
+    let params = row.insert_params(&language);
+    let update_params = [
+        params[0], params[15], params[7], params[8], params[9], params[10], params[13], params[4],
+        params[5], params[16],
+    ];
+    let update = || async {
+        crate::partitions::execute_routed(&client, update_sql, &update_params, row.t_canonical)
+            .await
+            .map_err(|e| LifelogError::Database(format!("frames upsert: {e}")))
+    };
+    if update().await? > 0 {
+        return Ok(());
+    }
+    let inserted = crate::partitions::execute_routed(
+        &client,
+        FrameRow::insert_sql(),
+        &params,
+        row.t_canonical,
+    )
+    .await
+    .map_err(|e| LifelogError::Database(format!("frames upsert: {e}")))?;
+    if inserted == 0 && update().await? == 0 {
+        tracing::debug!(
+            id = %row.id,
+            modality = %row.modality,
+            "Frame upsert skipped; its transform-output key belongs to another frame"
+        );
+    }
 
     Ok(())
 }
@@ -11,13 +11,32 @@ fn dt_to_pb(dt: DateTime<Utc>) -> pbjson_types::Timestamp {
     }
 }
 
+/// Frames whose blob was dropped by a retention tier keep their metadata and come back empty.
 fn load_blob(cas: &FsCas, blob_hash: &Option<String>) -> Result<Vec<u8>, String> {
     match blob_hash {
         Some(h) => cas.get(h).map_err(|e| format!("CAS get failed: {e}")),
-        None => Err("expected blob_hash but found None".to_string()),
+        None => Ok(Vec::new()),
     }
 }
 
+/// Word or line boxes stored in an OCR payload; empty for frames OCR'd before boxes were kept.
+pub fn json_to_ocr_boxes(v: &JsonValue) -> Vec<lifelog_types::OcrBox> {
+    v.as_array()
+        .map(|arr| {
+            arr.iter()
+                .map(|b| lifelog_types::OcrBox {
+                    text: b["text"].as_str().unwrap_or("").to_string(),
+                    left: b["left"].as_i64().unwrap_or(0) as i32,
+                    top: b["top"].as_i64().unwrap_or(0) as i32,
+                    width: b["width"].as_i64().unwrap_or(0) as i32,
+                    height: b["height"].as_i64().unwrap_or(0) as i32,
+                    confidence: b["confidence"].as_f64().unwrap_or(0.0) as f32,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 fn json_to_hypr_monitors(p: &JsonValue) -> Vec<lifelog_types::HyprMonitor> {
     p["monitors"]
         .as_array()
@@ -259,6 +278,33 @@ pub fn to_lifelog_data(row: &FrameRow, cas: &FsCas) -> Result<lifelog_types::Lif
             time_quality: 0,
             record_type: 0,
         }),
+        "Wifi" => Payload::Wififrame(lifelog_types::WifiFrame {
+            uuid,
+            timestamp,
+            ssid: p["ssid"].as_str().unwrap_or("").to_string(),
+            bssid: p["bssid"].as_str().unwrap_or("").to_string(),
+            signal: p["signal"].as_u64().unwrap_or(0) as u32,
+            ip: p["ip"].as_str().unwrap_or("").to_string(),
+            t_device,
+            t_ingest,
+            t_canonical,
+            t_end,
+            time_quality: 0,
+            record_type: 0,
+        }),
+        "Geo" => Payload::Geoframe(lifelog_types::GeoFrame {
+            uuid,
+            timestamp,
+            latitude: p["latitude"].as_f64().unwrap_or(0.0),
+            longitude: p["longitude"].as_f64().unwrap_or(0.0),
+            accuracy_m: p["accuracy_m"].as_f64().unwrap_or(0.0),
+            t_device,
+            t_ingest,
+            t_canonical,
+            t_end,
+            time_quality: 0,
+            record_type: 0,
+        }),
         "Hyprland" => {
             let monitors = json_to_hypr_monitors(p);
             let workspaces = json_to_hypr_workspaces(&p["workspaces"]);
@@ -332,6 +378,8 @@ pub fn to_lifelog_data(row: &FrameRow, cas: &FsCas) -> Result<lifelog_types::Lif
             t_end,
             time_quality: 0,
             record_type: 0,
+            words: json_to_ocr_boxes(&p["words"]),
+            lines: json_to_ocr_boxes(&p["lines"]),
         }),
         "Transcription" => Payload::Transcriptionframe(lifelog_types::TranscriptionFrame {
             uuid,
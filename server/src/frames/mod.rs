@@ -8,8 +8,11 @@ pub use db::*;
 pub use deserialize::*;
 pub use serialize::*;
 
+use std::borrow::Cow;
+
 use chrono::{DateTime, Utc};
 use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
 #[derive(Debug, Clone)]
@@ -30,21 +33,65 @@ pub struct FrameRow {
     pub payload: JsonValue,
 }
 
+/// Stable id for a frame built from other frames, so rebuilding it from the same inputs
+/// overwrites it in place. `key` names what the frame covers, e.g. `episode:laptop:1700000000`.
+pub fn derived_frame_id(key: &str) -> Uuid {
+    let digest = Sha256::digest(key.as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    uuid::Builder::from_custom_bytes(bytes).into_uuid()
+}
+
 impl FrameRow {
     pub fn insert_sql() -> &'static str {
         "INSERT INTO frames (
             id, collector_id, stream_id, modality, time_range,
             t_device, t_ingest, t_canonical, t_end, time_quality,
-            blob_hash, blob_size, indexed, source_frame_id, payload
+            blob_hash, blob_size, indexed, source_frame_id, payload, language
         ) VALUES (
             $1, $2, $3, $4, tstzrange($5, $6, '[]'),
             $7, $8, $9, $10, $11,
-            $12, $13, $14, $15, $16
+            $12, $13, $14, $15, $16, $17
         )
-        ON CONFLICT (id) DO NOTHING"
+        ON CONFLICT DO NOTHING"
+    }
+
+    /// The row as it is stored: sensitive payload fields sealed when payload encryption is on;
+    /// see [`crate::payload_crypto`].
+    pub fn sealed(&self) -> Result<Cow<'_, FrameRow>, String> {
+        self.sealed_derived(false)
+    }
+
+    /// Like [`Self::sealed`], but a frame derived from a sensitive one is sealed whatever its
+    /// own modality, so a translation of a sealed transcript is not stored in the clear.
+    pub fn sealed_derived(&self, sensitive_source: bool) -> Result<Cow<'_, FrameRow>, String> {
+        self.sealed_with(crate::payload_crypto::configured(), sensitive_source)
+    }
+
+    pub(crate) fn sealed_with(
+        &self,
+        cipher: Option<&crate::payload_crypto::PayloadCipher>,
+        sensitive_source: bool,
+    ) -> Result<Cow<'_, FrameRow>, String> {
+        let Some(cipher) = cipher
+            .filter(|_| sensitive_source || crate::payload_crypto::is_sensitive(&self.modality))
+        else {
+            return Ok(Cow::Borrowed(self));
+        };
+        let mut row = self.clone();
+        cipher.seal(row.id, &mut row.payload)?;
+        Ok(Cow::Owned(row))
+    }
+
+    /// Language the frame's text is indexed as for full-text search; see [`crate::search_language`].
+    pub fn search_language(&self) -> Option<String> {
+        crate::search_language::payload_language(&self.payload)
     }
 
-    pub fn insert_params(&self) -> [&(dyn tokio_postgres::types::ToSql + Sync); 16] {
+    pub fn insert_params<'a>(
+        &'a self,
+        language: &'a Option<String>,
+    ) -> [&'a (dyn tokio_postgres::types::ToSql + Sync); 17] {
         let range_start = &self.t_canonical;
         let range_end = &self.t_end;
         [
@@ -64,6 +111,7 @@ impl FrameRow {
             &self.indexed,
             &self.source_frame_id,
             &self.payload,
+            language,
         ]
     }
 }
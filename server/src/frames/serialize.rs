@@ -16,6 +16,22 @@ fn parse_uuid(s: &str) -> Uuid {
     Uuid::parse_str(s).unwrap_or_default()
 }
 
+fn ocr_boxes_to_json(boxes: &[lifelog_types::OcrBox]) -> JsonValue {
+    boxes
+        .iter()
+        .map(|b| {
+            json!({
+                "text": b.text,
+                "left": b.left,
+                "top": b.top,
+                "width": b.width,
+                "height": b.height,
+                "confidence": b.confidence,
+            })
+        })
+        .collect()
+}
+
 pub fn from_screen(
     collector_id: &str,
     stream_id: &str,
@@ -49,6 +65,7 @@ pub fn from_screen(
             "width": frame.width,
             "height": frame.height,
             "mime_type": frame.mime_type,
+            "phash": crate::phash::dhash(&frame.image_bytes).map(crate::phash::to_hex),
         }),
     })
 }
@@ -343,6 +360,7 @@ pub fn from_camera(
             "height": frame.height,
             "mime_type": frame.mime_type,
             "device": frame.device,
+            "phash": crate::phash::dhash(&frame.image_bytes).map(crate::phash::to_hex),
         }),
     })
 }
@@ -376,6 +394,59 @@ pub fn from_weather(
     }
 }
 
+pub fn from_wifi(
+    collector_id: &str,
+    stream_id: &str,
+    frame: &lifelog_types::WifiFrame,
+) -> FrameRow {
+    let t_device = pb_to_dt(frame.t_device.clone().or(frame.timestamp.clone()));
+    FrameRow {
+        id: parse_uuid(&frame.uuid),
+        collector_id: collector_id.to_string(),
+        stream_id: stream_id.to_string(),
+        modality: "Wifi".to_string(),
+        t_device: Some(t_device),
+        t_ingest: Utc::now(),
+        t_canonical: t_device,
+        t_end: Some(t_device),
+        time_quality: "unknown".to_string(),
+        blob_hash: None,
+        blob_size: None,
+        indexed: true,
+        source_frame_id: None,
+        payload: json!({
+            "ssid": frame.ssid,
+            "bssid": frame.bssid,
+            "signal": frame.signal,
+            "ip": frame.ip,
+        }),
+    }
+}
+
+pub fn from_geo(collector_id: &str, stream_id: &str, frame: &lifelog_types::GeoFrame) -> FrameRow {
+    let t_device = pb_to_dt(frame.t_device.clone().or(frame.timestamp.clone()));
+    FrameRow {
+        id: parse_uuid(&frame.uuid),
+        collector_id: collector_id.to_string(),
+        stream_id: stream_id.to_string(),
+        modality: "Geo".to_string(),
+        t_device: Some(t_device),
+        t_ingest: Utc::now(),
+        t_canonical: t_device,
+        t_end: Some(t_device),
+        time_quality: "unknown".to_string(),
+        blob_hash: None,
+        blob_size: None,
+        indexed: true,
+        source_frame_id: None,
+        payload: json!({
+            "latitude": frame.latitude,
+            "longitude": frame.longitude,
+            "accuracy_m": frame.accuracy_m,
+        }),
+    }
+}
+
 pub fn from_hyprland(
     collector_id: &str,
     stream_id: &str,
@@ -486,7 +557,11 @@ pub fn from_ocr(
         blob_size: None,
         indexed: true,
         source_frame_id,
-        payload: json!({ "text": frame.text }),
+        payload: json!({
+            "text": frame.text,
+            "words": ocr_boxes_to_json(&frame.words),
+            "lines": ocr_boxes_to_json(&frame.lines),
+        }),
     }
 }
 
@@ -570,6 +645,8 @@ pub fn from_lifelog_data(
         Some(Payload::Cameraframe(f)) => from_camera(collector_id, stream_id, f, cas),
         Some(Payload::Weatherframe(f)) => Ok(from_weather(collector_id, stream_id, f)),
         Some(Payload::Hyprlandframe(f)) => Ok(from_hyprland(collector_id, stream_id, f)),
+        Some(Payload::Wififrame(f)) => Ok(from_wifi(collector_id, stream_id, f)),
+        Some(Payload::Geoframe(f)) => Ok(from_geo(collector_id, stream_id, f)),
         Some(Payload::Ocrframe(f)) => Ok(from_ocr(collector_id, stream_id, f, None)),
         Some(Payload::Transcriptionframe(f)) => Ok(from_transcription(collector_id, stream_id, f)),
         Some(Payload::Embeddingframe(f)) => Ok(from_embedding(collector_id, stream_id, f)),
@@ -346,6 +346,61 @@ fn roundtrip_weather() {
     }
 }
 
+#[test]
+fn roundtrip_wifi_and_geo() {
+    let (_dir, cas) = test_cas();
+    let wifi = lifelog_types::WifiFrame {
+        uuid: "550e8400-e29b-41d4-a716-44665544000e".to_string(),
+        timestamp: ts(11500),
+        ssid: "HomeNet".to_string(),
+        bssid: "aa:bb:cc:dd:ee:ff".to_string(),
+        signal: 72,
+        ip: "192.168.1.17".to_string(),
+        t_device: ts(11500),
+        ..Default::default()
+    };
+    let data = lifelog_types::LifelogData {
+        payload: Some(lifelog_types::lifelog_data::Payload::Wififrame(
+            wifi.clone(),
+        )),
+    };
+    let row = from_lifelog_data("c1", "wifi", &data, &cas).unwrap();
+    assert_eq!(row.modality, "Wifi");
+    match to_lifelog_data(&row, &cas).unwrap().payload.unwrap() {
+        lifelog_types::lifelog_data::Payload::Wififrame(f) => {
+            assert_eq!(
+                (f.ssid, f.bssid, f.signal, f.ip),
+                (wifi.ssid, wifi.bssid, 72, wifi.ip)
+            );
+        }
+        _ => panic!("wrong payload type"),
+    }
+
+    let geo = lifelog_types::GeoFrame {
+        uuid: "550e8400-e29b-41d4-a716-44665544000f".to_string(),
+        timestamp: ts(11600),
+        latitude: 52.52,
+        longitude: 13.405,
+        accuracy_m: 12.0,
+        t_device: ts(11600),
+        ..Default::default()
+    };
+    let data = lifelog_types::LifelogData {
+        payload: Some(lifelog_types::lifelog_data::Payload::Geoframe(geo)),
+    };
+    let row = from_lifelog_data("phone", "geo", &data, &cas).unwrap();
+    assert_eq!(row.modality, "Geo");
+    match to_lifelog_data(&row, &cas).unwrap().payload.unwrap() {
+        lifelog_types::lifelog_data::Payload::Geoframe(f) => {
+            assert_eq!(
+                (f.latitude, f.longitude, f.accuracy_m),
+                (52.52, 13.405, 12.0)
+            );
+        }
+        _ => panic!("wrong payload type"),
+    }
+}
+
 #[test]
 fn roundtrip_hyprland() {
     let (_dir, cas) = test_cas();
@@ -403,6 +458,14 @@ fn roundtrip_ocr() {
         timestamp: ts(13000),
         text: "extracted text from screen".to_string(),
         t_device: ts(13000),
+        words: vec![lifelog_types::OcrBox {
+            text: "extracted".to_string(),
+            left: 12,
+            top: 30,
+            width: 80,
+            height: 14,
+            confidence: 91.5,
+        }],
         ..Default::default()
     };
     let row = from_ocr("c1", "ocr", &frame, Some(source_id));
@@ -411,6 +474,8 @@ fn roundtrip_ocr() {
     match rt.payload.unwrap() {
         lifelog_types::lifelog_data::Payload::Ocrframe(f) => {
             assert_eq!(f.text, "extracted text from screen");
+            assert_eq!(f.words, frame.words);
+            assert!(f.lines.is_empty());
         }
         _ => panic!("wrong payload type"),
     }
@@ -488,6 +553,53 @@ fn no_payload_errors() {
 fn insert_sql_is_valid() {
     let sql = FrameRow::insert_sql();
     assert!(sql.contains("INSERT INTO frames"));
-    assert!(sql.contains("ON CONFLICT (id) DO NOTHING"));
+    assert!(sql.contains("ON CONFLICT DO NOTHING"));
     assert!(sql.contains("$16"));
 }
+
+#[test]
+fn frames_derived_from_sensitive_sources_are_sealed() {
+    let cipher = crate::payload_crypto::PayloadCipher::new(&[7u8; 32], vec!["text".to_string()]);
+    let translation = FrameRow {
+        id: Uuid::new_v4(),
+        collector_id: "c1".to_string(),
+        stream_id: "translation".to_string(),
+        modality: "Translation".to_string(),
+        t_device: None,
+        t_ingest: chrono::Utc::now(),
+        t_canonical: chrono::Utc::now(),
+        t_end: None,
+        time_quality: "good".to_string(),
+        blob_hash: None,
+        blob_size: None,
+        indexed: true,
+        source_frame_id: Some(Uuid::new_v4()),
+        payload: serde_json::json!({"text": "meet me at the usual place", "source_modality": "Transcription"}),
+    };
+
+    let clear = translation.sealed_with(Some(&cipher), false).unwrap();
+    assert_eq!(clear.payload["text"], "meet me at the usual place");
+
+    let sealed = translation.sealed_with(Some(&cipher), true).unwrap();
+    assert!(sealed.payload.get("text").is_none());
+    assert!(!sealed.payload.to_string().contains("usual place"));
+    assert!(sealed
+        .payload
+        .get(crate::payload_crypto::SEALED_FIELD)
+        .is_some());
+}
+
+#[test]
+fn derived_frame_ids_are_the_leading_digest_bytes() {
+    let key = "episode:laptop:1700000000";
+    let digest = utils::cas::sha256_hex(key.as_bytes());
+    assert_eq!(
+        derived_frame_id(key).simple().to_string()[..12],
+        digest[..12]
+    );
+    assert_eq!(derived_frame_id(key), derived_frame_id(key));
+    assert_ne!(
+        derived_frame_id(key),
+        derived_frame_id("episode:laptop:1700000001")
+    );
+}
@@ -1,5 +1,6 @@
 use crate::ingest::UnifiedIngestBackend;
 use crate::server::ServerHandle;
+use crate::transform::{entities, episode};
 use chrono::Utc;
 use futures_core::Stream;
 use lifelog_types::lifelog_server_service_server::LifelogServerService;
@@ -292,13 +293,18 @@ impl LifelogServerService for GRPCServerLifelogServerService {
                 }
 
                 let server = self.server.server.read().await;
-                let transforms = server.config.read().await.transforms.clone();
-                let pool = server.postgres_pool.clone();
+                // Without Postgres no transforms run, so none may hold back a frame's ACK.
+                let transforms = if server.postgres_pool.is_some() {
+                    server.config.read().await.transforms.clone()
+                } else {
+                    Vec::new()
+                };
                 let backend = UnifiedIngestBackend {
-                    pool,
+                    store: server.store.clone(),
                     cas: server.cas.clone(),
                     skew_estimates: server.skew_estimates.clone(),
                     transforms,
+                    dag: server.transform_dag.clone(),
                 };
                 ingester = Some(ChunkIngester::new(
                     backend,
@@ -352,38 +358,19 @@ impl LifelogServerService for GRPCServerLifelogServerService {
             .stream
             .as_ref()
             .ok_or_else(|| Status::invalid_argument("missing stream identity"))?;
-        let pool = {
+        let store = {
             let server = self.server.server.read().await;
-            server.postgres_pool.clone()
+            server.store.clone()
         };
 
-        let client = pool
-            .get()
-            .await
-            .map_err(|e| Status::internal(format!("Postgres pool error: {e}")))?;
-        let row = client
-            .query_opt(
-                "SELECT \"offset\", length
-                 FROM upload_chunks
-                 WHERE collector_id = $1 AND stream_id = $2 AND session_id = $3
-                 ORDER BY \"offset\" DESC
-                 LIMIT 1",
-                &[
-                    &stream_id.collector_id,
-                    &stream_id.stream_id,
-                    &(stream_id.session_id as i64),
-                ],
+        let offset = store
+            .upload_offset(
+                &stream_id.collector_id,
+                &stream_id.stream_id,
+                stream_id.session_id as i64,
             )
             .await
-            .map_err(|e| Status::internal(format!("Postgres query error: {e}")))?;
-
-        let offset = row
-            .map(|r| {
-                let offset: i64 = r.get(0);
-                let length: i32 = r.get(1);
-                offset as u64 + length as u64
-            })
-            .unwrap_or(0);
+            .map_err(|e| Status::internal(format!("Upload offset query error: {e}")))?;
 
         Ok(Response::new(GetUploadOffsetResponse { offset }))
     }
@@ -475,4 +462,658 @@ impl LifelogServerService for GRPCServerLifelogServerService {
 
         Ok(Response::new(PairCollectorResponse { collector_id }))
     }
+
+    async fn list_dead_letters(
+        &self,
+        request: Request<ListDeadLettersRequest>,
+    ) -> Result<Response<ListDeadLettersResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let req = request.into_inner();
+        let state = self.server.server.read().await.transform_state.clone();
+
+        let transform_id = Some(req.transform_id.trim()).filter(|s| !s.is_empty());
+        let limit = match req.limit {
+            0 => 100,
+            n => n.min(1000) as usize,
+        };
+        let entries = state
+            .list_dead_letters(transform_id, req.include_exhausted, limit)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to list dead letters: {e}")))?
+            .into_iter()
+            .map(|d| DeadLetterEntry {
+                transform_id: d.transform_id,
+                frame_id: d.frame_id.to_string(),
+                origin: d.origin,
+                error: d.error,
+                attempts: d.attempts.max(0) as u32,
+                first_failed_at: lifelog_types::to_pb_ts(d.first_failed_at),
+                last_failed_at: lifelog_types::to_pb_ts(d.last_failed_at),
+                exhausted: d.next_retry_at.is_none(),
+                next_retry_at: d.next_retry_at.and_then(lifelog_types::to_pb_ts),
+            })
+            .collect();
+
+        Ok(Response::new(ListDeadLettersResponse { entries }))
+    }
+
+    async fn retry_dead_letters(
+        &self,
+        request: Request<DeadLetterActionRequest>,
+    ) -> Result<Response<DeadLetterActionResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let (transform_id, frame_ids) = parse_dead_letter_action(request.into_inner())?;
+        let state = self.server.server.read().await.transform_state.clone();
+
+        let affected = state
+            .retry_dead_letters(&transform_id, &frame_ids, Utc::now())
+            .await
+            .map_err(|e| Status::internal(format!("Failed to retry dead letters: {e}")))?;
+        tracing::info!(%transform_id, affected, "Scheduled dead-lettered frames for retry");
+
+        Ok(Response::new(DeadLetterActionResponse { affected }))
+    }
+
+    async fn discard_dead_letters(
+        &self,
+        request: Request<DeadLetterActionRequest>,
+    ) -> Result<Response<DeadLetterActionResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let (transform_id, frame_ids) = parse_dead_letter_action(request.into_inner())?;
+        let state = self.server.server.read().await.transform_state.clone();
+
+        let affected = state
+            .discard_dead_letters(&transform_id, &frame_ids)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to discard dead letters: {e}")))?;
+        tracing::info!(%transform_id, affected, "Discarded dead-lettered frames");
+
+        Ok(Response::new(DeadLetterActionResponse { affected }))
+    }
+
+    async fn find_entity_mentions(
+        &self,
+        request: Request<FindEntityMentionsRequest>,
+    ) -> Result<Response<FindEntityMentionsResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let req = request.into_inner();
+        let query = req.query.trim();
+        if query.is_empty() {
+            return Err(Status::invalid_argument("query is required"));
+        }
+        let kind = match req.kind.trim() {
+            "" => None,
+            k => Some(
+                entities::EntityKind::parse(k)
+                    .ok_or_else(|| Status::invalid_argument(format!("unknown entity kind: {k}")))?,
+            ),
+        };
+        let limit = match req.limit {
+            0 => 50,
+            n => n.min(1000) as usize,
+        };
+        let pool = {
+            let server = self.server.server.read().await;
+            server
+                .postgres_pool
+                .clone()
+                .ok_or_else(postgres_unavailable)?
+        };
+
+        const MAX_MATCHED_ENTITIES: usize = 20;
+        let matched = entities::find_entities(&pool, query, kind, MAX_MATCHED_ENTITIES)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to search entities: {e}")))?;
+        let ids: Vec<i64> = matched.iter().map(|e| e.id).collect();
+        let hits = entities::recent_mentions(&pool, &ids, limit)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to load entity mentions: {e}")))?;
+
+        let mentions = hits
+            .into_iter()
+            .filter_map(|hit| {
+                let entity = matched.iter().find(|e| e.id == hit.entity_id)?;
+                Some(EntityMention {
+                    kind: entity.kind.clone(),
+                    value: entity.value.clone(),
+                    frame_id: hit.frame_id.to_string(),
+                    source_frame_id: hit
+                        .source_frame_id
+                        .map(|id| id.to_string())
+                        .unwrap_or_default(),
+                    collector_id: hit.collector_id,
+                    modality: hit.modality,
+                    field: hit.field,
+                    snippet: hit.snippet,
+                    timestamp: lifelog_types::to_pb_ts(hit.t_canonical),
+                })
+            })
+            .collect();
+        let entities = matched
+            .into_iter()
+            .map(|e| EntitySummary {
+                kind: e.kind,
+                value: e.value,
+                display: e.display,
+                mention_count: e.mention_count.max(0) as u64,
+                first_seen: lifelog_types::to_pb_ts(e.first_seen),
+                last_seen: lifelog_types::to_pb_ts(e.last_seen),
+            })
+            .collect();
+
+        Ok(Response::new(FindEntityMentionsResponse {
+            entities,
+            mentions,
+        }))
+    }
+
+    async fn list_episodes(
+        &self,
+        request: Request<ListEpisodesRequest>,
+    ) -> Result<Response<ListEpisodesResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let req = request.into_inner();
+        let window = req
+            .window
+            .ok_or_else(|| Status::invalid_argument("window is required"))?;
+        let start = lifelog_types::to_dt(window.start);
+        let end = lifelog_types::to_dt(window.end);
+        if start >= end {
+            return Err(Status::invalid_argument("window start must be < end"));
+        }
+        let collector_id = Some(req.collector_id.trim()).filter(|c| !c.is_empty());
+        let pool = {
+            let server = self.server.server.read().await;
+            server
+                .postgres_pool
+                .clone()
+                .ok_or_else(postgres_unavailable)?
+        };
+
+        let rows = episode::list(&pool, start, end, collector_id)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to list episodes: {e}")))?;
+        let strings = |v: &serde_json::Value| -> Vec<String> {
+            v.as_array()
+                .map(|a| {
+                    a.iter()
+                        .filter_map(|s| s.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let episodes = rows
+            .into_iter()
+            .map(|row| Episode {
+                id: row.id.to_string(),
+                collector_id: row.collector_id,
+                start: lifelog_types::to_pb_ts(row.start),
+                end: lifelog_types::to_pb_ts(row.end),
+                title: row.payload["title"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                dominant_app: row.payload["dominant_app"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                apps: strings(&row.payload["apps"]),
+                domains: strings(&row.payload["domains"]),
+                cwds: strings(&row.payload["cwds"]),
+                member_frame_ids: strings(&row.payload["member_frame_ids"]),
+                member_count: row.payload["member_count"].as_u64().unwrap_or_default(),
+            })
+            .collect();
+
+        Ok(Response::new(ListEpisodesResponse { episodes }))
+    }
+
+    async fn find_similar_frames(
+        &self,
+        request: Request<FindSimilarFramesRequest>,
+    ) -> Result<Response<FindSimilarFramesResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let req = request.into_inner();
+        let frame_id = uuid::Uuid::parse_str(req.frame_id.trim())
+            .map_err(|_| Status::invalid_argument("frame_id must be a UUID"))?;
+        let max_distance = match req.max_distance {
+            0 => crate::phash::DEFAULT_SIMILAR_DISTANCE,
+            n => n.min(64),
+        };
+        let limit = match req.limit {
+            0 => 50,
+            n => n.min(1000) as usize,
+        };
+        let window = match req.window_days {
+            0 => crate::phash::DEFAULT_SIMILAR_WINDOW,
+            n => chrono::Duration::days(i64::from(n.min(365))),
+        };
+        let pool = {
+            let server = self.server.server.read().await;
+            server
+                .postgres_pool
+                .clone()
+                .ok_or_else(postgres_unavailable)?
+        };
+
+        let similar = crate::phash::find_similar(
+            &pool,
+            frame_id,
+            max_distance,
+            limit,
+            req.include_duplicates,
+            window,
+            req.same_stream,
+        )
+        .await
+        .map_err(|e| Status::internal(format!("Failed to find similar frames: {e}")))?
+        .ok_or_else(|| Status::not_found("frame not found or has no perceptual hash"))?;
+
+        let frames = similar
+            .into_iter()
+            .map(|f| SimilarFrame {
+                frame_id: f.id.to_string(),
+                collector_id: f.collector_id,
+                modality: f.modality,
+                timestamp: lifelog_types::to_pb_ts(f.t_canonical),
+                distance: f.distance,
+            })
+            .collect();
+
+        Ok(Response::new(FindSimilarFramesResponse { frames }))
+    }
+
+    async fn find_ocr_hits(
+        &self,
+        request: Request<FindOcrHitsRequest>,
+    ) -> Result<Response<FindOcrHitsResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let req = request.into_inner();
+        let frame_id = uuid::Uuid::parse_str(req.frame_id.trim())
+            .map_err(|_| Status::invalid_argument("frame_id must be a UUID"))?;
+        let pool = {
+            let server = self.server.server.read().await;
+            server
+                .postgres_pool
+                .clone()
+                .ok_or_else(postgres_unavailable)?
+        };
+
+        let (layout, hits) = crate::ocr_highlight::find_hits(&pool, frame_id, &req.query)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to find OCR hits: {e}")))?
+            .ok_or_else(|| Status::not_found("no OCR frame for this frame"))?;
+
+        Ok(Response::new(FindOcrHitsResponse {
+            ocr_frame_id: layout.ocr_frame_id.to_string(),
+            image_frame_id: layout
+                .image_frame_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            hits,
+        }))
+    }
+
+    async fn get_highlighted_thumbnail(
+        &self,
+        request: Request<GetHighlightedThumbnailRequest>,
+    ) -> Result<Response<GetHighlightedThumbnailResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let req = request.into_inner();
+        let frame_id = uuid::Uuid::parse_str(req.frame_id.trim())
+            .map_err(|_| Status::invalid_argument("frame_id must be a UUID"))?;
+        let max_width = match req.max_width {
+            0 => crate::ocr_highlight::DEFAULT_THUMBNAIL_WIDTH,
+            n => n,
+        };
+        let (pool, cas) = {
+            let server = self.server.server.read().await;
+            (
+                server
+                    .postgres_pool
+                    .clone()
+                    .ok_or_else(postgres_unavailable)?,
+                server.cas.clone(),
+            )
+        };
+
+        let thumb = crate::ocr_highlight::highlighted_thumbnail(
+            &pool, &cas, frame_id, &req.query, max_width,
+        )
+        .await
+        .map_err(|e| Status::internal(format!("Failed to render thumbnail: {e}")))?
+        .ok_or_else(|| Status::not_found("frame not found or has no image"))?;
+
+        Ok(Response::new(GetHighlightedThumbnailResponse {
+            image: thumb.jpeg,
+            width: thumb.width,
+            height: thumb.height,
+            hits: thumb.hits,
+        }))
+    }
+
+    async fn get_cas_scrub_report(
+        &self,
+        request: Request<GetCasScrubReportRequest>,
+    ) -> Result<Response<GetCasScrubReportResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let req = request.into_inner();
+        let limit = match req.limit {
+            0 => 100,
+            n => n.min(1000) as usize,
+        };
+        let pool = {
+            let server = self.server.server.read().await;
+            server
+                .postgres_pool
+                .clone()
+                .ok_or_else(postgres_unavailable)?
+        };
+
+        let report = crate::scrub::report(&pool, limit)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to load scrub report: {e}")))?;
+
+        let findings = report
+            .findings
+            .into_iter()
+            .map(|f| CasScrubFinding {
+                blob_hash: f.blob_hash,
+                kind: f.kind,
+                detail: f.detail,
+                frame_count: f.frame_count.max(0) as u64,
+                frame_ids: f.frame_ids.iter().map(|id| id.to_string()).collect(),
+                first_seen: lifelog_types::to_pb_ts(f.first_seen_at),
+                last_seen: lifelog_types::to_pb_ts(f.last_seen_at),
+            })
+            .collect();
+
+        Ok(Response::new(GetCasScrubReportResponse {
+            shards_scanned: report.shards_scanned,
+            total_shards: crate::scrub::SHARDS,
+            pass_started: report.pass_started_at.and_then(lifelog_types::to_pb_ts),
+            last_pass_completed: report
+                .last_pass_completed_at
+                .and_then(lifelog_types::to_pb_ts),
+            last_run: report.last_run_at.and_then(lifelog_types::to_pb_ts),
+            blobs_verified: report.blobs_verified,
+            bytes_verified: report.bytes_verified,
+            blobs_unverified: report.blobs_unverified,
+            orphans_deleted: report.orphans_deleted,
+            corrupt_blobs: report.corrupt_blobs,
+            missing_blobs: report.missing_blobs,
+            findings,
+        }))
+    }
+
+    async fn rotate_cas_key(
+        &self,
+        request: Request<RotateCasKeyRequest>,
+    ) -> Result<Response<RotateCasKeyResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let rotation = self
+            .server
+            .start_key_rotation()
+            .await
+            .map_err(|e| match e {
+                lifelog_core::LifelogError::Validation { .. } => {
+                    Status::failed_precondition(format!("Failed to start key rotation: {e}"))
+                }
+                e => Status::internal(format!("Failed to start key rotation: {e}")),
+            })?;
+        Ok(Response::new(RotateCasKeyResponse {
+            rotation: Some(key_rotation_to_pb(rotation)),
+        }))
+    }
+
+    async fn get_cas_key_rotation(
+        &self,
+        request: Request<GetCasKeyRotationRequest>,
+    ) -> Result<Response<GetCasKeyRotationResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let (pool, cas) = {
+            let server = self.server.server.read().await;
+            (
+                server
+                    .postgres_pool
+                    .clone()
+                    .ok_or_else(postgres_unavailable)?,
+                server.cas.clone(),
+            )
+        };
+
+        let rotation = crate::key_rotation::latest(&pool)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to load key rotation: {e}")))?;
+        let keyring = cas.keyring();
+
+        Ok(Response::new(GetCasKeyRotationResponse {
+            rotation: rotation.map(key_rotation_to_pb),
+            active_key_id: keyring.as_ref().map(|k| k.active_id()).unwrap_or_default(),
+            retired_key_ids: keyring.map(|k| k.retired_ids()).unwrap_or_default(),
+        }))
+    }
+
+    async fn pin(&self, request: Request<PinRequest>) -> Result<Response<PinResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let req = request.into_inner();
+        let frame_ids = parse_frame_ids(&req.frame_ids).map_err(Status::invalid_argument)?;
+        let pool = {
+            let server = self.server.server.read().await;
+            server
+                .postgres_pool
+                .clone()
+                .ok_or_else(postgres_unavailable)?
+        };
+
+        let pins = match (frame_ids.is_empty(), req.start, req.end) {
+            (false, None, None) => crate::pins::pin_frames(&pool, &frame_ids, req.reason.trim())
+                .await
+                .map_err(|e| Status::internal(format!("Failed to pin frames: {e}")))?,
+            (true, Some(start), Some(end)) => {
+                let start = lifelog_types::to_dt(Some(start));
+                let end = lifelog_types::to_dt(Some(end));
+                if start >= end {
+                    return Err(Status::invalid_argument("start must be < end"));
+                }
+                vec![crate::pins::pin_range(&pool, start, end, req.reason.trim())
+                    .await
+                    .map_err(|e| Status::internal(format!("Failed to pin range: {e}")))?]
+            }
+            _ => {
+                return Err(Status::invalid_argument(
+                    "give either frame_ids or both start and end",
+                ))
+            }
+        };
+        if pins.is_empty() {
+            return Err(Status::not_found("none of the frames exist"));
+        }
+
+        Ok(Response::new(PinResponse {
+            pins: pins.into_iter().map(pin_to_pb).collect(),
+        }))
+    }
+
+    async fn unpin(
+        &self,
+        request: Request<UnpinRequest>,
+    ) -> Result<Response<UnpinResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let req = request.into_inner();
+        let frame_ids = parse_frame_ids(&req.frame_ids).map_err(Status::invalid_argument)?;
+        if req.pin_ids.is_empty() && frame_ids.is_empty() {
+            return Err(Status::invalid_argument("pin_ids or frame_ids is required"));
+        }
+        let pool = {
+            let server = self.server.server.read().await;
+            server
+                .postgres_pool
+                .clone()
+                .ok_or_else(postgres_unavailable)?
+        };
+
+        let removed = crate::pins::unpin(&pool, &req.pin_ids, &frame_ids)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to unpin: {e}")))?;
+        Ok(Response::new(UnpinResponse { removed }))
+    }
+
+    async fn list_pins(
+        &self,
+        request: Request<ListPinsRequest>,
+    ) -> Result<Response<ListPinsResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let pool = {
+            let server = self.server.server.read().await;
+            server
+                .postgres_pool
+                .clone()
+                .ok_or_else(postgres_unavailable)?
+        };
+
+        let pins = crate::pins::list(&pool)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to list pins: {e}")))?;
+        Ok(Response::new(ListPinsResponse {
+            pins: pins.into_iter().map(pin_to_pb).collect(),
+        }))
+    }
+
+    async fn forget(
+        &self,
+        request: Request<ForgetRequest>,
+    ) -> Result<Response<ForgetResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let req = request.into_inner();
+        tracing::info!(
+            confirm = req.confirm,
+            ranges = req.time_ranges.len(),
+            origins = ?req.origins,
+            "Received forget request"
+        );
+
+        let report = self.server.process_forget(req).await.map_err(|e| match e {
+            lifelog_core::LifelogError::Validation { ref field, .. }
+                if field == "expected_frames" =>
+            {
+                Status::failed_precondition(format!("Failed to forget: {e}"))
+            }
+            lifelog_core::LifelogError::Validation { .. } => {
+                Status::invalid_argument(format!("Failed to forget: {e}"))
+            }
+            e => Status::internal(format!("Failed to forget: {e}")),
+        })?;
+        Ok(Response::new(ForgetResponse {
+            matched_frames: report.matched_frames,
+            derived_frames: report.derived_frames,
+            frames_by_modality: report.frames_by_modality.into_iter().collect(),
+            pinned_frames: report.pinned_frames,
+            blobs: report.blobs,
+            blob_bytes: report.blob_bytes,
+            forgotten: report.forgotten,
+            cache_entries: report.cache_entries,
+            entity_mentions: report.entity_mentions,
+            audit_id: report.audit_id.unwrap_or_default(),
+        }))
+    }
+
+    async fn list_forget_audit(
+        &self,
+        request: Request<ListForgetAuditRequest>,
+    ) -> Result<Response<ListForgetAuditResponse>, Status> {
+        self.check_auth(request.metadata())?;
+        let pool = {
+            let server = self.server.server.read().await;
+            server
+                .postgres_pool
+                .clone()
+                .ok_or_else(postgres_unavailable)?
+        };
+
+        let records = crate::forget::audit_log(&pool)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to load forget audit: {e}")))?;
+        let first_broken_id = crate::forget::verify_chain(&records);
+        Ok(Response::new(ListForgetAuditResponse {
+            records: records
+                .into_iter()
+                .map(|r| ForgetAuditRecord {
+                    id: r.id,
+                    created: lifelog_types::to_pb_ts(r.created_at),
+                    selector: r.selector,
+                    reason: r.reason,
+                    frames: r.frames.max(0) as u64,
+                    blobs: r.blobs.max(0) as u64,
+                    frame_ids_sha256: r.frame_ids_sha256,
+                    prev_hash: r.prev_hash,
+                    hash: r.hash,
+                })
+                .collect(),
+            chain_intact: first_broken_id.is_none(),
+            first_broken_id: first_broken_id.unwrap_or_default(),
+        }))
+    }
+}
+
+/// Error for an RPC whose feature has no SQLite implementation.
+fn postgres_unavailable() -> Status {
+    Status::unimplemented("this feature requires the Postgres storage backend")
+}
+
+fn parse_frame_ids(ids: &[String]) -> Result<Vec<uuid::Uuid>, String> {
+    ids.iter()
+        .map(|id| {
+            uuid::Uuid::parse_str(id.trim()).map_err(|_| format!("frame id {id} is not a UUID"))
+        })
+        .collect()
+}
+
+fn pin_to_pb(pin: crate::pins::Pin) -> lifelog_types::Pin {
+    lifelog_types::Pin {
+        id: pin.id,
+        frame_id: pin.frame_id.map(|id| id.to_string()).unwrap_or_default(),
+        start: pin
+            .range
+            .and_then(|(start, _)| lifelog_types::to_pb_ts(start)),
+        end: pin.range.and_then(|(_, end)| lifelog_types::to_pb_ts(end)),
+        reason: pin.reason,
+        created: lifelog_types::to_pb_ts(pin.created_at),
+    }
+}
+
+fn key_rotation_to_pb(rotation: crate::key_rotation::KeyRotation) -> CasKeyRotation {
+    CasKeyRotation {
+        id: rotation.id,
+        target_key_id: rotation.target_key_id,
+        status: rotation.status,
+        shards_done: rotation.shards_done,
+        total_shards: crate::scrub::SHARDS,
+        blobs_current: rotation.blobs_current,
+        blobs_rewrapped: rotation.blobs_rewrapped,
+        blobs_reencrypted: rotation.blobs_reencrypted,
+        blobs_failed: rotation.blobs_failed,
+        last_error: rotation.last_error.unwrap_or_default(),
+        started: lifelog_types::to_pb_ts(rotation.started_at),
+        last_run: rotation.last_run_at.and_then(lifelog_types::to_pb_ts),
+        completed: rotation.completed_at.and_then(lifelog_types::to_pb_ts),
+    }
+}
+
+fn parse_dead_letter_action(
+    req: DeadLetterActionRequest,
+) -> Result<(String, Vec<uuid::Uuid>), Status> {
+    let transform_id = req.transform_id.trim().to_string();
+    if transform_id.is_empty() {
+        return Err(Status::invalid_argument("transform_id is required"));
+    }
+    let frame_ids = req
+        .frame_ids
+        .iter()
+        .map(|id| {
+            id.parse::<uuid::Uuid>()
+                .map_err(|_| Status::invalid_argument(format!("invalid frame id: {id}")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((transform_id, frame_ids))
 }
@@ -2,17 +2,22 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use lifelog_core::time_skew::SkewEstimate;
+use lifelog_core::{DataOrigin, DataOriginType};
 use prost::Message;
 use tokio::sync::RwLock;
 use utils::ingest::IngestBackend;
 
-use crate::postgres::PostgresPool;
+use crate::storage::{chunk_id, FrameStore, UploadChunk};
+use crate::transform::dag::TransformDag;
+use crate::transform::secrets::SCAN_PENDING_FIELD;
 
 pub struct UnifiedIngestBackend {
-    pub pool: PostgresPool,
+    pub store: Arc<dyn FrameStore>,
     pub cas: utils::cas::FsCas,
     pub skew_estimates: Arc<RwLock<std::collections::HashMap<String, SkewEstimate>>>,
     pub transforms: Vec<lifelog_types::TransformSpec>,
+    /// Decides which frames are held out of search until they are scanned for secrets.
+    pub dag: Arc<TransformDag>,
 }
 
 #[async_trait]
@@ -39,8 +44,18 @@ impl IngestBackend for UnifiedIngestBackend {
 
         let (frame_uuid, indexed) = match decode_result {
             Some(Ok(data)) => {
-                let mut row =
-                    crate::frames::from_lifelog_data(collector_id, stream_id, &data, &self.cas)?;
+                // Decoding images for their perceptual hash and writing blobs to the CAS both
+                // block, so build the row off the async workers.
+                let (cas, collector, stream) = (
+                    self.cas.clone(),
+                    collector_id.to_string(),
+                    stream_id.to_string(),
+                );
+                let mut row = tokio::task::spawn_blocking(move || {
+                    crate::frames::from_lifelog_data(&collector, &stream, &data, &cas)
+                })
+                .await
+                .map_err(|e| format!("spawn_blocking: {e}"))??;
 
                 let (t_canonical, time_quality) =
                     get_canonical_time(&self.skew_estimates, collector_id, row.t_canonical).await;
@@ -62,45 +77,25 @@ impl IngestBackend for UnifiedIngestBackend {
                     row.indexed = false;
                 }
 
-                let uuid_str = row.id.to_string();
-
-                let client = self
-                    .pool
-                    .get()
-                    .await
-                    .map_err(|e| format!("postgres pool get failed: {e}"))?;
-
-                client
-                    .execute(crate::frames::FrameRow::insert_sql(), &row.insert_params())
-                    .await
-                    .map_err(|e| {
-                        format!(
-                            "postgres insert frames failed for {} (id={}): {e:?}",
-                            row.modality, row.id
-                        )
-                    })?;
+                let origin = DataOrigin::new(
+                    DataOriginType::DeviceId(row.collector_id.clone()),
+                    row.modality.clone(),
+                );
+                if self.dag.search_gated(&origin) {
+                    if let serde_json::Value::Object(payload) = &mut row.payload {
+                        payload.insert(
+                            SCAN_PENDING_FIELD.to_string(),
+                            serde_json::Value::Bool(true),
+                        );
+                    }
+                }
 
-                let origin_key = format!("{}:{}", collector_id, row.modality);
-                let collector_id_owned = collector_id.to_string();
-                let stream_id_owned = stream_id.to_string();
-                client
-                    .execute(
-                        "INSERT INTO catalog (origin, collector_id, modality, stream_id)
-                         VALUES ($1, $2, $3, $4)
-                         ON CONFLICT (origin) DO NOTHING",
-                        &[
-                            &origin_key,
-                            &collector_id_owned,
-                            &row.modality,
-                            &stream_id_owned,
-                        ],
-                    )
+                self.store
+                    .insert_ingested(&mut row)
                     .await
-                    .map_err(|e| {
-                        format!("postgres catalog registration failed for origin={origin_key}: {e}")
-                    })?;
+                    .map_err(|e| e.to_string())?;
 
-                (Some(uuid_str), true)
+                (Some(row.id.to_string()), true)
             }
             Some(Err(e)) => {
                 tracing::warn!(
@@ -116,34 +111,19 @@ impl IngestBackend for UnifiedIngestBackend {
             }
         };
 
-        let client = self
-            .pool
-            .get()
+        self.store
+            .record_chunk(&UploadChunk {
+                collector_id: collector_id.to_string(),
+                stream_id: stream_id.to_string(),
+                session_id: session_id_i64,
+                offset: offset_i64,
+                length: length_i32,
+                hash: hash.to_string(),
+                frame_uuid,
+                indexed,
+            })
             .await
-            .map_err(|e| format!("postgres pool get failed: {e}"))?;
-
-        let id_str = format!("{}_{}_{}_{}", collector_id, stream_id, session_id, offset);
-        client
-            .execute(
-                "INSERT INTO upload_chunks (
-                    id, collector_id, stream_id, session_id, \"offset\", length, hash, frame_uuid, indexed
-                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
-                ON CONFLICT (id) DO UPDATE
-                SET indexed = (upload_chunks.indexed OR EXCLUDED.indexed)",
-                &[
-                    &id_str,
-                    &collector_id,
-                    &stream_id,
-                    &session_id_i64,
-                    &offset_i64,
-                    &length_i32,
-                    &hash,
-                    &frame_uuid,
-                    &indexed,
-                ],
-            )
-            .await
-            .map_err(|e| format!("postgres upsert upload_chunks failed: {e}"))?;
+            .map_err(|e| e.to_string())?;
 
         Ok(())
     }
@@ -155,19 +135,14 @@ impl IngestBackend for UnifiedIngestBackend {
         session_id: u64,
         offset: u64,
     ) -> bool {
-        let id = format!("{}_{}_{}_{}", collector_id, stream_id, session_id, offset);
-        let client = match self.pool.get().await {
-            Ok(c) => c,
-            Err(_) => return false,
+        let Ok(session_id) = i64::try_from(session_id) else {
+            return false;
         };
-        match client
-            .query_opt("SELECT indexed FROM upload_chunks WHERE id = $1", &[&id])
-            .await
-        {
-            Ok(Some(row)) => row.get::<_, bool>(0),
-            Ok(None) => false,
-            Err(_) => false,
-        }
+        let Ok(offset) = i64::try_from(offset) else {
+            return false;
+        };
+        let id = chunk_id(collector_id, stream_id, session_id, offset);
+        self.store.chunk_indexed(&id).await.unwrap_or(false)
     }
 }
 
@@ -235,6 +210,16 @@ fn decode_stream_payload(
                 .map(|f| make_data(Payload::Weatherframe(f)))
                 .map_err(|e| e.to_string()),
         ),
+        "wifi" => Some(
+            lifelog_types::WifiFrame::decode(payload)
+                .map(|f| make_data(Payload::Wififrame(f)))
+                .map_err(|e| e.to_string()),
+        ),
+        "geo" | "gps" => Some(
+            lifelog_types::GeoFrame::decode(payload)
+                .map(|f| make_data(Payload::Geoframe(f)))
+                .map_err(|e| e.to_string()),
+        ),
         "hyprland" => Some(
             lifelog_types::HyprlandFrame::decode(payload)
                 .map(|f| make_data(Payload::Hyprlandframe(f)))
@@ -0,0 +1,400 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use config::CasKeyConfig;
+use lifelog_core::LifelogError;
+use tokio_postgres::Row;
+use utils::cas::{CasError, CasKeyring, FsCas, Rekeyed};
+
+use crate::postgres::PostgresPool;
+use crate::scrub::SHARDS;
+
+/// Salt for passphrase-derived keys. It lives with the blobs so backups carry it along.
+pub fn salt_path(cas_path: &str) -> PathBuf {
+    utils::cas::parse_cas_path(cas_path).1.join("kek.salt")
+}
+
+/// Loads the configured CAS keys; `Ok(None)` when no active key is configured.
+pub fn load_keyring(keys: &CasKeyConfig, cas_path: &str) -> Result<Option<CasKeyring>, CasError> {
+    let Some(active) = &keys.active else {
+        if !keys.retired.is_empty() {
+            tracing::warn!("Retired CAS keys are ignored because no active key is configured");
+        }
+        return Ok(None);
+    };
+    CasKeyring::load(active, &keys.retired, &salt_path(cas_path)).map(Some)
+}
+
+/// A run of the key rotation and its progress.
+#[derive(Debug, Clone)]
+pub struct KeyRotation {
+    pub id: i64,
+    pub target_key_id: String,
+    /// Retired keys configured when the rotation started.
+    pub source_key_ids: Vec<String>,
+    /// `running`, `completed`, `completed_with_errors` or `superseded`.
+    pub status: String,
+    /// Shards done, out of [`SHARDS`].
+    pub shards_done: u32,
+    pub blobs_current: u64,
+    pub blobs_rewrapped: u64,
+    pub blobs_reencrypted: u64,
+    pub blobs_failed: u64,
+    pub last_error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+const ROTATION_COLUMNS: &str =
+    "id, target_key_id, source_key_ids, status, next_shard, blobs_current, \
+     blobs_rewrapped, blobs_reencrypted, blobs_failed, last_error, started_at, last_run_at, \
+     completed_at";
+
+impl KeyRotation {
+    fn from_row(row: &Row) -> Self {
+        let count = |name: &str| row.get::<_, i64>(name).max(0) as u64;
+        Self {
+            id: row.get("id"),
+            target_key_id: row.get("target_key_id"),
+            source_key_ids: row.get("source_key_ids"),
+            status: row.get("status"),
+            shards_done: row.get::<_, i32>("next_shard").clamp(0, SHARDS as i32) as u32,
+            blobs_current: count("blobs_current"),
+            blobs_rewrapped: count("blobs_rewrapped"),
+            blobs_reencrypted: count("blobs_reencrypted"),
+            blobs_failed: count("blobs_failed"),
+            last_error: row.get("last_error"),
+            started_at: row.get("started_at"),
+            last_run_at: row.get("last_run_at"),
+            completed_at: row.get("completed_at"),
+        }
+    }
+}
+
+/// Re-reads the configured keys into `cas`, so new blobs are encrypted with the active key
+/// right away, and starts rotating existing blobs to it.
+pub async fn rotate_to_configured_key(
+    pool: &PostgresPool,
+    cas: &FsCas,
+    cas_path: &str,
+    now: DateTime<Utc>,
+) -> Result<KeyRotation, LifelogError> {
+    let keys = config::load_cas_keys_from_unified();
+    let cas_path = cas_path.to_string();
+    // Deriving passphrase keys takes a moment of CPU.
+    let keyring = tokio::task::spawn_blocking(move || load_keyring(&keys, &cas_path))
+        .await?
+        .map_err(|e| LifelogError::Validation {
+            field: "casKeys".to_string(),
+            reason: e.to_string(),
+        })?;
+    let Some(keyring) = keyring else {
+        return Err(LifelogError::Validation {
+            field: "casKeys.active".to_string(),
+            reason: "no active CAS key is configured".to_string(),
+        });
+    };
+    let target = keyring.active_id();
+    tracing::info!(
+        key_id = %target,
+        retired = keyring.retired_ids().len(),
+        "Reloaded CAS keys"
+    );
+    check_retired_keys(pool, &keyring).await?;
+    let retired = keyring.retired_ids();
+    cas.set_keyring(Some(keyring));
+    start(pool, &target, &retired, now).await
+}
+
+/// Fails when a key the latest rotation was moving blobs away from is no longer configured while
+/// that rotation is still running or left blobs it could not move, since those blobs would become
+/// unreadable.
+pub async fn check_retired_keys(
+    pool: &PostgresPool,
+    keyring: &CasKeyring,
+) -> Result<(), LifelogError> {
+    let Some(rotation) = latest(pool).await? else {
+        return Ok(());
+    };
+    if rotation.status != "running" && rotation.status != "completed_with_errors" {
+        return Ok(());
+    }
+    let configured: Vec<String> = std::iter::once(keyring.active_id())
+        .chain(keyring.retired_ids())
+        .collect();
+    let missing: Vec<&str> = rotation
+        .source_key_ids
+        .iter()
+        .filter(|id| !configured.contains(id))
+        .map(String::as_str)
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(LifelogError::Validation {
+        field: "casKeys.retired".to_string(),
+        reason: format!(
+            "key {} is still needed: rotation #{} is {} with {} failed blobs; keep it retired \
+             until a rotation completes without failures",
+            missing.join(", "),
+            rotation.id,
+            rotation.status,
+            rotation.blobs_failed
+        ),
+    })
+}
+
+/// Starts rotating to `target_key_id` from the `source_key_ids`. A rotation already running to
+/// the same key is returned as is; one running to another key is superseded.
+pub async fn start(
+    pool: &PostgresPool,
+    target_key_id: &str,
+    source_key_ids: &[String],
+    now: DateTime<Utc>,
+) -> Result<KeyRotation, LifelogError> {
+    let mut client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let tx = client
+        .transaction()
+        .await
+        .map_err(|e| LifelogError::Database(format!("key rotation tx begin: {e}")))?;
+    let running = tx
+        .query_opt(
+            &format!(
+                "SELECT {ROTATION_COLUMNS} FROM cas_key_rotations
+                 WHERE status = 'running' FOR UPDATE"
+            ),
+            &[],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("key rotation query: {e}")))?
+        .map(|row| KeyRotation::from_row(&row));
+    if let Some(running) = running {
+        if running.target_key_id == target_key_id {
+            return Ok(running);
+        }
+        tx.execute(
+            "UPDATE cas_key_rotations SET status = 'superseded', completed_at = $2
+             WHERE id = $1",
+            &[&running.id, &now],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("key rotation supersede: {e}")))?;
+    }
+    let row = tx
+        .query_one(
+            &format!(
+                "INSERT INTO cas_key_rotations (target_key_id, source_key_ids, started_at)
+                 VALUES ($1, $2, $3)
+                 RETURNING {ROTATION_COLUMNS}"
+            ),
+            &[&target_key_id, &source_key_ids, &now],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("key rotation insert: {e}")))?;
+    tx.commit()
+        .await
+        .map_err(|e| LifelogError::Database(format!("key rotation tx commit: {e}")))?;
+    Ok(KeyRotation::from_row(&row))
+}
+
+/// The most recent rotation, running or not.
+pub async fn latest(pool: &PostgresPool) -> Result<Option<KeyRotation>, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let row = client
+        .query_opt(
+            &format!("SELECT {ROTATION_COLUMNS} FROM cas_key_rotations ORDER BY id DESC LIMIT 1"),
+            &[],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("key rotation query: {e}")))?;
+    Ok(row.map(|row| KeyRotation::from_row(&row)))
+}
+
+/// Blobs of one shard by what rotating them did.
+#[derive(Debug, Default)]
+struct ShardTally {
+    current: i64,
+    rewrapped: i64,
+    reencrypted: i64,
+    failed: i64,
+    last_error: Option<String>,
+}
+
+fn rotate_shard(cas: &FsCas, shard: u8) -> Result<ShardTally, CasError> {
+    let mut tally = ShardTally::default();
+    for (hash, _) in cas.list_shard(shard)? {
+        match cas.rekey(&hash) {
+            Ok(Rekeyed::Current) => tally.current += 1,
+            Ok(Rekeyed::Rewrapped) => tally.rewrapped += 1,
+            Ok(Rekeyed::Reencrypted) => tally.reencrypted += 1,
+            // Collected since the shard was listed.
+            Err(CasError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                tracing::warn!(hash = %hash, error = %e, "Failed to rotate CAS blob key");
+                tally.failed += 1;
+                tally.last_error = Some(format!("{hash}: {e}"));
+            }
+        }
+    }
+    Ok(tally)
+}
+
+/// Advances the running rotation by up to `shards_per_run` shards. Returns its progress, or
+/// `None` when no rotation is running. A rotation that reaches the last shard having failed to
+/// move some blobs ends as `completed_with_errors`; running it again retries them. A rotation whose target is no longer the active key is
+/// superseded instead, since its blobs could not be moved to a key the server does not hold.
+pub async fn rotate_once(
+    pool: &PostgresPool,
+    cas: &FsCas,
+    shards_per_run: u32,
+    now: DateTime<Utc>,
+) -> Result<Option<KeyRotation>, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let Some(mut rotation) = client
+        .query_opt(
+            &format!("SELECT {ROTATION_COLUMNS} FROM cas_key_rotations WHERE status = 'running'"),
+            &[],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("key rotation query: {e}")))?
+        .map(|row| KeyRotation::from_row(&row))
+    else {
+        return Ok(None);
+    };
+
+    let active = cas.active_key_id();
+    if active.as_deref() != Some(rotation.target_key_id.as_str()) {
+        let reason = match &active {
+            Some(key) => format!("active CAS key changed to {key}"),
+            None => "no active CAS key is configured".to_string(),
+        };
+        tracing::warn!(rotation = rotation.id, reason = %reason, "Superseding CAS key rotation");
+        let row = client
+            .query_one(
+                &format!(
+                    "UPDATE cas_key_rotations
+                     SET status = 'superseded', completed_at = $2, last_error = $3
+                     WHERE id = $1 RETURNING {ROTATION_COLUMNS}"
+                ),
+                &[&rotation.id, &now, &reason],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("key rotation supersede: {e}")))?;
+        return Ok(Some(KeyRotation::from_row(&row)));
+    }
+
+    for _ in 0..shards_per_run.clamp(1, SHARDS) {
+        let shard = rotation.shards_done;
+        if shard >= SHARDS {
+            break;
+        }
+        let shard_cas = cas.clone();
+        let tally = tokio::task::spawn_blocking(move || {
+            rotate_shard(&shard_cas, shard as u8).map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| LifelogError::Database(format!("key rotation task: {e}")))?
+        .map_err(|e| LifelogError::Database(format!("key rotation shard {shard:02x}: {e}")))?;
+
+        let next_shard = (shard + 1) as i32;
+        let row = client
+            .query_one(
+                &format!(
+                    "UPDATE cas_key_rotations SET next_shard = $2, last_run_at = $3,
+                     blobs_current = blobs_current + $4, blobs_rewrapped = blobs_rewrapped + $5,
+                     blobs_reencrypted = blobs_reencrypted + $6, blobs_failed = blobs_failed + $7,
+                     last_error = COALESCE($8, last_error),
+                     status = CASE
+                         WHEN $2::INTEGER < $9::INTEGER THEN status
+                         WHEN blobs_failed + $7 > 0 THEN 'completed_with_errors'
+                         ELSE 'completed'
+                     END,
+                     completed_at = CASE WHEN $2::INTEGER >= $9::INTEGER THEN $3 ELSE completed_at END
+                     WHERE id = $1 RETURNING {ROTATION_COLUMNS}"
+                ),
+                &[
+                    &rotation.id,
+                    &next_shard,
+                    &now,
+                    &tally.current,
+                    &tally.rewrapped,
+                    &tally.reencrypted,
+                    &tally.failed,
+                    &tally.last_error,
+                    &(SHARDS as i32),
+                ],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("key rotation progress: {e}")))?;
+        rotation = KeyRotation::from_row(&row);
+    }
+    Ok(Some(rotation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::panic)]
+    fn rotate_shard_moves_blobs_to_the_active_key() {
+        let Ok(dir) = tempfile::tempdir() else {
+            panic!("tempdir");
+        };
+        let cas = FsCas::with_key(dir.path(), &[1u8; 32]);
+        let rotated = cas.put(b"old key").unwrap_or_default();
+        cas.set_keyring(None);
+        let plain = cas.put(b"plaintext").unwrap_or_default();
+        cas.set_keyring(Some(CasKeyring::new(&[2u8; 32], &[[1u8; 32]])));
+        let current = cas.put(b"new key").unwrap_or_default();
+
+        let shards: std::collections::BTreeSet<u8> = [&rotated, &plain, &current]
+            .iter()
+            .filter_map(|hash| u8::from_str_radix(&hash[0..2], 16).ok())
+            .collect();
+        let mut tally = ShardTally::default();
+        for shard in shards {
+            let Ok(t) = rotate_shard(&cas, shard) else {
+                panic!("shard {shard} failed");
+            };
+            tally.current += t.current;
+            tally.rewrapped += t.rewrapped;
+            tally.reencrypted += t.reencrypted;
+            tally.failed += t.failed;
+        }
+        assert_eq!(
+            (
+                tally.current,
+                tally.rewrapped,
+                tally.reencrypted,
+                tally.failed
+            ),
+            (1, 1, 1, 0)
+        );
+
+        let new_only = FsCas::with_key(dir.path(), &[2u8; 32]);
+        for hash in [&rotated, &plain, &current] {
+            assert!(new_only.verify(hash).is_ok(), "{hash}");
+        }
+    }
+
+    #[test]
+    fn keyring_is_optional_and_salted_next_to_the_blobs() {
+        let keys = CasKeyConfig::default();
+        assert!(matches!(load_keyring(&keys, "/nonexistent"), Ok(None)));
+        assert_eq!(
+            salt_path("pack:/srv/lifelog/cas"),
+            PathBuf::from("/srv/lifelog/cas/kek.salt")
+        );
+    }
+}
@@ -1,11 +1,22 @@
+pub mod phash;
 pub mod policy;
 pub mod server;
 
+pub mod backup;
+pub mod forget;
 pub mod frames;
 pub mod grpc_service;
 pub(crate) mod ingest;
+pub mod key_rotation;
+pub mod ocr_highlight;
+pub mod partitions;
+pub mod payload_crypto;
+pub mod pins;
 pub mod postgres;
 pub mod query;
 pub(crate) mod replay;
 pub(crate) mod retention;
+pub mod scrub;
+pub mod search_language;
+pub mod storage;
 pub mod transform;
@@ -7,7 +7,10 @@ use lifelog_server::server::Server as LifelogServer;
 use lifelog_server::server::ServerHandle as LifelogServerHandle;
 use lifelog_types::lifelog_server_service_client::LifelogServerServiceClient;
 use lifelog_types::lifelog_server_service_server::LifelogServerServiceServer;
-use lifelog_types::{PairCollectorRequest, FILE_DESCRIPTOR_SET};
+use lifelog_types::{
+    CasKeyRotation, GetCasKeyRotationRequest, PairCollectorRequest, RotateCasKeyRequest,
+    FILE_DESCRIPTOR_SET,
+};
 use rustls::client::danger::{ServerCertVerified, ServerCertVerifier};
 use rustls::Error as RustlsError;
 use serde::Serialize;
@@ -45,6 +48,43 @@ enum Commands {
         #[arg(short, long)]
         yes: bool,
     },
+    /// Re-read the configured CAS keys and rotate stored blobs to the active key
+    RotateKey {
+        /// Server URL of the running server
+        #[arg(long, default_value = "https://localhost:7182")]
+        server_url: String,
+        /// Only show the configured keys and rotation progress
+        #[arg(long)]
+        status: bool,
+    },
+    /// Write an incremental snapshot of the database and referenced blobs to a backup repository
+    Backup {
+        /// Backup repository directory, created on first use
+        #[arg(long)]
+        repo: PathBuf,
+        /// Key spec (`file:<path>` or `passphrase:<text>`) encrypting the repository; falls back
+        /// to LIFELOG_BACKUP_KEY
+        #[arg(long)]
+        key: Option<String>,
+    },
+    /// Restore a snapshot into a fresh database and CAS, checking it afterwards
+    Restore {
+        /// Backup repository directory
+        #[arg(long)]
+        repo: PathBuf,
+        /// Snapshot ID (defaults to the latest)
+        #[arg(long)]
+        snapshot: Option<String>,
+        /// Key spec the repository was created with; falls back to LIFELOG_BACKUP_KEY
+        #[arg(long)]
+        key: Option<String>,
+        /// Only read and check the snapshot; leave the database and CAS untouched
+        #[arg(long)]
+        verify_only: bool,
+        /// List the snapshots in the repository
+        #[arg(long)]
+        list: bool,
+    },
 }
 
 #[derive(Debug)]
@@ -549,6 +589,225 @@ async fn pair_collector(
     Ok(resp.into_inner().collector_id)
 }
 
+/// Connects to the local server with the admin token, trusting the server's own certificate.
+async fn admin_client(
+    server_url: &str,
+) -> Result<
+    LifelogServerServiceClient<
+        tonic::service::interceptor::InterceptedService<
+            Channel,
+            impl FnMut(Request<()>) -> Result<Request<()>, Status>,
+        >,
+    >,
+    lifelog_core::LifelogError,
+> {
+    let (server_url, server_host, _) = parse_server_url(server_url)?;
+    let ca_path = match std::env::var("LIFELOG_TLS_CA_CERT_PATH")
+        .or_else(|_| std::env::var("LIFELOG_TLS_CERT_PATH"))
+    {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => onboarding_paths()?.server_cert_path,
+    };
+    let ca_cert_pem =
+        fs::read_to_string(&ca_path).map_err(|e| lifelog_core::LifelogError::Validation {
+            field: "LIFELOG_TLS_CA_CERT_PATH".to_string(),
+            reason: format!("failed to read {}: {e}", ca_path.display()),
+        })?;
+    let token = std::env::var("LIFELOG_AUTH_TOKEN").map_err(|_| {
+        lifelog_core::LifelogError::Validation {
+            field: "LIFELOG_AUTH_TOKEN".to_string(),
+            reason: "must be set to the server's auth token".to_string(),
+        }
+    })?;
+
+    let tls = ClientTlsConfig::new()
+        .domain_name(server_host)
+        .ca_certificate(Certificate::from_pem(ca_cert_pem));
+    let channel: Channel = Endpoint::from_shared(server_url)?
+        .tls_config(tls)
+        .map_err(|e| lifelog_core::LifelogError::Validation {
+            field: "server_url".to_string(),
+            reason: format!("TLS config error: {}", e),
+        })?
+        .connect()
+        .await?;
+
+    let interceptor = move |mut req: Request<()>| -> Result<Request<()>, Status> {
+        let bearer = format!("Bearer {}", token);
+        let value = MetadataValue::try_from(bearer.as_str())
+            .map_err(|_| Status::unauthenticated("invalid auth token format"))?;
+        req.metadata_mut().insert("authorization", value);
+        Ok(req)
+    };
+    Ok(LifelogServerServiceClient::with_interceptor(
+        channel,
+        interceptor,
+    ))
+}
+
+fn print_key_rotation(rotation: &CasKeyRotation) {
+    let ts = |t: &Option<pbjson_types::Timestamp>| {
+        t.as_ref()
+            .and_then(|t| DateTime::from_timestamp(t.seconds, t.nanos.max(0) as u32))
+            .map_or_else(|| "-".to_string(), |t| t.to_rfc3339())
+    };
+    println!(
+        "Rotation #{} to key {}: {} ({}/{} shards)",
+        rotation.id,
+        rotation.target_key_id,
+        rotation.status,
+        rotation.shards_done,
+        rotation.total_shards
+    );
+    println!(
+        "  blobs: {} current, {} re-wrapped, {} re-encrypted, {} failed",
+        rotation.blobs_current,
+        rotation.blobs_rewrapped,
+        rotation.blobs_reencrypted,
+        rotation.blobs_failed
+    );
+    println!(
+        "  started {}, last run {}, completed {}",
+        ts(&rotation.started),
+        ts(&rotation.last_run),
+        ts(&rotation.completed)
+    );
+    if !rotation.last_error.is_empty() {
+        println!("  last error: {}", rotation.last_error);
+    }
+}
+
+async fn run_rotate_key(
+    server_url: String,
+    status: bool,
+) -> Result<(), lifelog_core::LifelogError> {
+    let mut client = admin_client(&server_url).await?;
+    if !status {
+        let resp = client
+            .rotate_cas_key(Request::new(RotateCasKeyRequest {}))
+            .await?
+            .into_inner();
+        if let Some(rotation) = resp.rotation.as_ref() {
+            print_key_rotation(rotation);
+        }
+        println!("Blobs are rotated in the background; check progress with `rotate-key --status`.");
+        return Ok(());
+    }
+
+    let resp = client
+        .get_cas_key_rotation(Request::new(GetCasKeyRotationRequest {}))
+        .await?
+        .into_inner();
+    if resp.active_key_id.is_empty() {
+        println!("CAS encryption is off");
+    } else {
+        println!("Active key: {}", resp.active_key_id);
+    }
+    if !resp.retired_key_ids.is_empty() {
+        println!("Retired keys: {}", resp.retired_key_ids.join(", "));
+    }
+    match resp.rotation.as_ref() {
+        Some(rotation) => print_key_rotation(rotation),
+        None => println!("No key rotation has run"),
+    }
+    Ok(())
+}
+
+fn open_backup_repo(
+    repo: &Path,
+    key: Option<String>,
+) -> Result<lifelog_server::backup::BackupRepo, utils::cas::CasError> {
+    let key = key.or_else(|| std::env::var("LIFELOG_BACKUP_KEY").ok());
+    lifelog_server::backup::BackupRepo::open(repo, key.as_deref())
+}
+
+fn backup_repo_error(repo: &Path, e: utils::cas::CasError) -> lifelog_core::LifelogError {
+    lifelog_core::LifelogError::Validation {
+        field: "repo".to_string(),
+        reason: format!("{}: {e}", repo.display()),
+    }
+}
+
+fn print_restore_summary(summary: &lifelog_server::backup::RestoreSummary) {
+    for (table, rows) in &summary.rows {
+        println!("  {table}: {rows} rows");
+    }
+    println!("  blobs: {} verified", summary.blobs_verified);
+    if !summary.blobs_missing.is_empty() {
+        println!(
+            "  {} blobs were missing when the snapshot was taken",
+            summary.blobs_missing.len()
+        );
+    }
+}
+
+async fn run_backup(repo: PathBuf, key: Option<String>) -> Result<(), lifelog_core::LifelogError> {
+    let backup_repo = open_backup_repo(&repo, key).map_err(|e| backup_repo_error(&repo, e))?;
+    let config = load_server_config();
+    let (storage, cas) = lifelog_server::server::open_storage(&config).await?;
+    let pool = storage
+        .postgres
+        .ok_or_else(|| lifelog_server::storage::postgres_required("backup"))?;
+    let summary = lifelog_server::backup::backup(&pool, &cas, &backup_repo, Utc::now()).await?;
+    let snapshot = &summary.snapshot;
+    println!("Snapshot {} written to {}", snapshot.id, repo.display());
+    for table in &snapshot.tables {
+        println!(
+            "  {}: {} rows in {} segments",
+            table.table,
+            table.rows,
+            table.segments.len()
+        );
+    }
+    println!(
+        "  {} new segments, {} of {} blobs copied",
+        summary.segments_written, summary.blobs_copied, snapshot.blobs
+    );
+    if !snapshot.missing_blobs.is_empty() {
+        println!(
+            "  {} referenced blobs could not be read and are not in the snapshot",
+            snapshot.missing_blobs.len()
+        );
+    }
+    Ok(())
+}
+
+async fn run_restore(
+    repo: PathBuf,
+    snapshot: Option<String>,
+    key: Option<String>,
+    verify_only: bool,
+    list: bool,
+) -> Result<(), lifelog_core::LifelogError> {
+    let backup_repo = open_backup_repo(&repo, key).map_err(|e| backup_repo_error(&repo, e))?;
+    let repo_err = |e| backup_repo_error(&repo, e);
+    if list {
+        for id in backup_repo.snapshots().map_err(repo_err)? {
+            println!("{id}");
+        }
+        return Ok(());
+    }
+    let snapshot = backup_repo
+        .load_snapshot(snapshot.as_deref())
+        .map_err(repo_err)?;
+    if verify_only {
+        let summary = lifelog_server::backup::verify(&backup_repo, &snapshot).map_err(repo_err)?;
+        println!("Snapshot {} is intact", summary.snapshot);
+        print_restore_summary(&summary);
+        return Ok(());
+    }
+
+    let config = load_server_config();
+    let (storage, cas) = lifelog_server::server::open_storage(&config).await?;
+    let pool = storage
+        .postgres
+        .ok_or_else(|| lifelog_server::storage::postgres_required("restore"))?;
+    let summary = lifelog_server::backup::restore(&pool, &cas, &backup_repo, &snapshot).await?;
+    println!("Restored snapshot {}", summary.snapshot);
+    print_restore_summary(&summary);
+    Ok(())
+}
+
 async fn run_init() -> Result<(), lifelog_core::LifelogError> {
     let paths = onboarding_paths()?;
     fs::create_dir_all(paths.config_dir.clone())?;
@@ -827,11 +1086,8 @@ fn check_disk_space(path: &str) {
     }
 }
 
-async fn run_server() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
-
+/// The `[server]` config with the `LIFELOG_*` environment overrides applied.
+fn load_server_config() -> config::ServerConfig {
     let mut config = config::load_server_config_from_unified().unwrap_or_else(|| {
         panic!(
             "Missing or invalid [server] in {}. No defaults are applied.",
@@ -855,8 +1111,22 @@ async fn run_server() -> Result<(), Box<dyn std::error::Error>> {
     if let Ok(cas) = std::env::var("LIFELOG_CAS_PATH") {
         config.cas_path = cas;
     }
+    config
+}
+
+async fn run_server() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+
+    let config = load_server_config();
     let server = LifelogServer::new(&config).await?;
-    check_disk_space(&config.cas_path);
+    check_disk_space(
+        &utils::cas::parse_cas_path(&config.cas_path)
+            .1
+            .display()
+            .to_string(),
+    );
 
     let addr = format!("{}:{}", config.host, config.port).parse()?;
 
@@ -890,16 +1160,10 @@ async fn run_server() -> Result<(), Box<dyn std::error::Error>> {
         loop {
             interval.tick().await;
             let server = health_handle.server.read().await;
-            let status = match server.postgres_pool.get().await {
-                Ok(client) => match client.execute("SELECT 1", &[]).await {
-                    Ok(_) => tonic_health::ServingStatus::Serving,
-                    Err(e) => {
-                        tracing::warn!(error = %e, "health check: postgres query failed");
-                        tonic_health::ServingStatus::NotServing
-                    }
-                },
+            let status = match server.store.ping().await {
+                Ok(()) => tonic_health::ServingStatus::Serving,
                 Err(e) => {
-                    tracing::warn!(error = %e, "health check: postgres pool unavailable");
+                    tracing::warn!(error = %e, "health check: database query failed");
                     tonic_health::ServingStatus::NotServing
                 }
             };
@@ -924,10 +1188,18 @@ async fn run_server() -> Result<(), Box<dyn std::error::Error>> {
             interval.tick().await;
             match retention_handle.run_retention_once().await {
                 Ok(summary) => {
-                    if summary.deleted_records > 0 || summary.deleted_blobs > 0 {
+                    if summary.deleted_records > 0
+                        || summary.deleted_blobs > 0
+                        || summary.thinned_records > 0
+                        || summary.dropped_blobs > 0
+                        || summary.reencoded_blobs > 0
+                    {
                         tracing::info!(
                             deleted_records = summary.deleted_records,
                             deleted_blobs = summary.deleted_blobs,
+                            thinned_records = summary.thinned_records,
+                            dropped_blobs = summary.dropped_blobs,
+                            reencoded_blobs = summary.reencoded_blobs,
                             "retention pruncompleted"
                         );
                     }
@@ -939,31 +1211,177 @@ async fn run_server() -> Result<(), Box<dyn std::error::Error>> {
         }
     });
 
+    let scrub_handle = server_handle.clone();
+    tokio::task::spawn(async move {
+        if !scrub_handle.has_postgres().await {
+            return;
+        }
+        let env_u64 = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        let defaults = lifelog_server::scrub::ScrubOptions::default();
+        let options = lifelog_server::scrub::ScrubOptions {
+            shards_per_run: env_u64("LIFELOG_SCRUB_SHARDS_PER_RUN")
+                .map_or(defaults.shards_per_run, |n| n.min(256) as u32),
+            orphan_grace: env_u64("LIFELOG_CAS_ORPHAN_GRACE_HOURS")
+                .map_or(defaults.orphan_grace, |h| chrono::Duration::hours(h as i64)),
+        };
+        let interval_secs = env_u64("LIFELOG_SCRUB_INTERVAL_SECS")
+            .unwrap_or(3600)
+            .max(60);
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            match scrub_handle.run_scrub_once(&options).await {
+                Ok(summary) => {
+                    tracing::debug!(
+                        shards = summary.shards_scanned,
+                        verified = summary.blobs_verified,
+                        orphans_deleted = summary.orphans_deleted,
+                        packs_compacted = summary.packs_compacted,
+                        bytes_reclaimed = summary.bytes_reclaimed,
+                        "CAS scrub completed"
+                    );
+                    if summary.corrupt_blobs > 0 || summary.missing_blobs > 0 {
+                        tracing::warn!(
+                            corrupt = summary.corrupt_blobs,
+                            missing = summary.missing_blobs,
+                            "CAS scrub found damaged or missing blobs"
+                        );
+                    }
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, "CAS scrub failed");
+                }
+            }
+        }
+    });
+
+    // Frames stored before payload encryption was turned on are sealed once, in the background.
+    let seal_handle = server_handle.clone();
+    tokio::task::spawn(async move {
+        if !seal_handle.has_postgres().await {
+            return;
+        }
+        let mut total = 0;
+        loop {
+            match seal_handle.seal_payload_backlog(500).await {
+                Ok(0) => break,
+                Ok(n) => total += n,
+                Err(e) => {
+                    tracing::error!(error = %e, "Sealing stored payloads failed");
+                    break;
+                }
+            }
+        }
+        if total > 0 {
+            tracing::info!(frames = total, "Sealed payloads of stored sensitive frames");
+        }
+    });
+
+    let rotation_handle = server_handle.clone();
+    tokio::task::spawn(async move {
+        if !rotation_handle.has_postgres().await {
+            return;
+        }
+        let env_u64 = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        let shards_per_run = env_u64("LIFELOG_KEY_ROTATION_SHARDS_PER_RUN")
+            .unwrap_or(8)
+            .clamp(1, 256) as u32;
+        let interval_secs = env_u64("LIFELOG_KEY_ROTATION_INTERVAL_SECS")
+            .unwrap_or(60)
+            .max(1);
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            match rotation_handle.run_key_rotation_once(shards_per_run).await {
+                Ok(Some(rotation)) => {
+                    tracing::debug!(
+                        key_id = %rotation.target_key_id,
+                        shards = rotation.shards_done,
+                        rewrapped = rotation.blobs_rewrapped,
+                        reencrypted = rotation.blobs_reencrypted,
+                        "CAS key rotation progressed"
+                    );
+                    match rotation.status.as_str() {
+                        "completed" => tracing::info!(
+                            key_id = %rotation.target_key_id,
+                            "CAS key rotation completed"
+                        ),
+                        "completed_with_errors" => tracing::warn!(
+                            key_id = %rotation.target_key_id,
+                            failed = rotation.blobs_failed,
+                            last_error = rotation.last_error.as_deref().unwrap_or(""),
+                            "CAS key rotation finished with blobs it could not move; keep the \
+                             retired keys and run rotate-key again"
+                        ),
+                        _ => {}
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::error!(error = %e, "CAS key rotation failed");
+                }
+            }
+        }
+    });
+
     let summary_handle = server_handle.clone();
     tokio::task::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(86400));
+        // Reported at startup on SQLite, along with the other Postgres-only maintenance.
+        if !summary_handle.has_postgres().await {
+            return;
+        }
+        let endpoint = std::env::var("LIFELOG_OLLAMA_ENDPOINT")
+            .unwrap_or_else(|_| "http://localhost:11434".to_string());
+        let mut params = std::collections::HashMap::from([
+            (
+                "model".to_string(),
+                std::env::var("LIFELOG_SUMMARY_MODEL")
+                    .unwrap_or_else(|_| "gemma3:4b-it-qat".to_string()),
+            ),
+            ("timeout_secs".to_string(), "120".to_string()),
+        ]);
+        if let Ok(provider) = std::env::var("LIFELOG_SUMMARY_PROVIDER") {
+            params.insert("provider".to_string(), provider);
+        }
+        let llm = lifelog_server::transform::llm_provider::LlmClient::from_params(
+            "summary",
+            endpoint,
+            &params,
+            lifelog_server::transform::egress::EgressGuard::new(
+                config::load_network_policy_from_unified().allowed_hosts,
+            ),
+        );
+
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
         interval.tick().await;
         loop {
             interval.tick().await;
             let server = summary_handle.server.read().await;
-            let endpoint = std::env::var("LIFELOG_OLLAMA_ENDPOINT")
-                .unwrap_or_else(|_| "http://localhost:11434".to_string());
-            let model = std::env::var("LIFELOG_SUMMARY_MODEL")
-                .unwrap_or_else(|_| "gemma3:4b-it-qat".to_string());
-            match lifelog_server::transform::summary::generate_daily_summary(
-                &server.postgres_pool,
-                &server.cas,
+            let Some(pool) = &server.postgres_pool else {
+                return;
+            };
+            match lifelog_server::transform::summary::generate_summaries(
+                pool,
                 &server.http_client,
-                &endpoint,
-                &model,
+                &llm,
+                chrono::Utc::now(),
             )
             .await
             {
-                Ok(()) => {
-                    tracing::info!("daily summary generated successfully");
+                Ok(summary) => {
+                    let usage = llm.usage();
+                    tracing::info!(
+                        written = summary.written,
+                        unchanged = summary.unchanged,
+                        llm_calls = usage.calls,
+                        prompt_tokens = usage.prompt_tokens,
+                        completion_tokens = usage.completion_tokens,
+                        "summaries updated"
+                    );
                 }
                 Err(e) => {
-                    tracing::error!(error = %e, "daily summary generation failed");
+                    tracing::error!(error = %e, "summary generation failed");
                 }
             }
         }
@@ -971,14 +1389,26 @@ async fn run_server() -> Result<(), Box<dyn std::error::Error>> {
 
     let meeting_handle = server_handle.clone();
     tokio::task::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+        if !meeting_handle.has_postgres().await {
+            return;
+        }
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(900));
         interval.tick().await;
         loop {
             interval.tick().await;
             let server = meeting_handle.server.read().await;
-            match lifelog_server::transform::meeting::detect_meetings(&server.postgres_pool).await {
-                Ok(()) => {
-                    tracing::debug!("meeting detection completed");
+            let Some(pool) = &server.postgres_pool else {
+                return;
+            };
+            match lifelog_server::transform::meeting::detect_meetings(pool, chrono::Utc::now())
+                .await
+            {
+                Ok(summary) => {
+                    tracing::debug!(
+                        sessions = summary.sessions_written,
+                        removed = summary.sessions_removed,
+                        "meeting detection completed"
+                    );
                 }
                 Err(e) => {
                     tracing::error!(error = %e, "meeting detection failed");
@@ -987,6 +1417,80 @@ async fn run_server() -> Result<(), Box<dyn std::error::Error>> {
         }
     });
 
+    let episode_handle = server_handle.clone();
+    tokio::task::spawn(async move {
+        if !episode_handle.has_postgres().await {
+            return;
+        }
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(900));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let server = episode_handle.server.read().await;
+            let Some(pool) = &server.postgres_pool else {
+                return;
+            };
+            match lifelog_server::transform::episode::segment_episodes(pool, chrono::Utc::now())
+                .await
+            {
+                Ok(summary) => {
+                    tracing::debug!(
+                        written = summary.episodes_written,
+                        removed = summary.episodes_removed,
+                        "episode segmentation completed"
+                    );
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, "episode segmentation failed");
+                }
+            }
+        }
+    });
+
+    let places = config::load_places_from_unified();
+    if !places.is_empty() {
+        let location_handle = server_handle.clone();
+        tokio::task::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(600));
+            interval.tick().await;
+            let mut warned_no_signals = false;
+            loop {
+                interval.tick().await;
+                let server = location_handle.server.read().await;
+                let Some(pool) = &server.postgres_pool else {
+                    return;
+                };
+                match lifelog_server::transform::location::infer_locations(
+                    pool,
+                    &places,
+                    chrono::Utc::now(),
+                )
+                .await
+                {
+                    Ok(summary) if summary.signal_frames == 0 && !warned_no_signals => {
+                        warned_no_signals = true;
+                        tracing::warn!(
+                            modalities = ?lifelog_server::transform::location::SIGNAL_MODALITIES,
+                            "places are configured but no location signal frames have arrived; \
+                             enable the collector's wifi source or upload geo fixes"
+                        );
+                    }
+                    Ok(summary) => {
+                        tracing::debug!(
+                            signal_frames = summary.signal_frames,
+                            visits = summary.visits_written,
+                            removed = summary.visits_removed,
+                            "location inference completed"
+                        );
+                    }
+                    Err(e) => {
+                        tracing::error!(error = %e, "location inference failed");
+                    }
+                }
+            }
+        });
+    }
+
     let deploy_config = config::load_server_deploy_config();
     let tls_config = deploy_config.tls;
     let mut builder = TonicServer::builder()
@@ -1094,5 +1598,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
         Commands::Init => run_init().await.map_err(Into::into),
         Commands::Join { server_url, yes } => run_join(server_url, yes).await.map_err(Into::into),
+        Commands::RotateKey { server_url, status } => {
+            run_rotate_key(server_url, status).await.map_err(Into::into)
+        }
+        Commands::Backup { repo, key } => run_backup(repo, key).await.map_err(Into::into),
+        Commands::Restore {
+            repo,
+            snapshot,
+            key,
+            verify_only,
+            list,
+        } => run_restore(repo, snapshot, key, verify_only, list)
+            .await
+            .map_err(Into::into),
     }
 }
@@ -0,0 +1,304 @@
+use image::{imageops::FilterType, ImageFormat, Rgb, RgbImage};
+use lifelog_types::OcrBox;
+use std::io::Cursor;
+use uuid::Uuid;
+
+use crate::postgres::PostgresPool;
+use lifelog_core::LifelogError;
+use utils::cas::FsCas;
+
+/// Default width of highlighted thumbnails, in pixels.
+pub const DEFAULT_THUMBNAIL_WIDTH: u32 = 480;
+/// Thumbnails are never scaled past this width.
+pub const MAX_THUMBNAIL_WIDTH: u32 = 1920;
+
+const HIGHLIGHT: [u8; 3] = [255, 214, 0];
+/// Share of the highlight colour blended into covered pixels.
+const HIGHLIGHT_ALPHA: f32 = 0.4;
+
+/// Terms a search query is matched against OCR words with: lowercased alphanumeric runs, with
+/// websearch operators (`or`, negated `-terms`, quotes) dropped.
+pub fn query_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for token in query.split_whitespace() {
+        if token.starts_with('-') || token.eq_ignore_ascii_case("or") {
+            continue;
+        }
+        for term in token
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|t| !t.is_empty())
+        {
+            let term = term.to_lowercase();
+            if !terms.contains(&term) {
+                terms.push(term);
+            }
+        }
+    }
+    terms
+}
+
+fn normalize(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Words that start with one of `terms`, so "deploy" highlights "Deployment,". Prefix matching
+/// stands in for the stemming full-text search applies to the same query.
+pub fn matching_boxes<'a>(words: &'a [OcrBox], terms: &[String]) -> Vec<&'a OcrBox> {
+    if terms.is_empty() {
+        return Vec::new();
+    }
+    words
+        .iter()
+        .filter(|w| {
+            let word = normalize(&w.text);
+            !word.is_empty() && terms.iter().any(|t| word.starts_with(t.as_str()))
+        })
+        .collect()
+}
+
+/// OCR output for a frame: the OCR frame itself and the screenshot it was read from.
+#[derive(Debug, Clone)]
+pub struct OcrLayout {
+    pub ocr_frame_id: Uuid,
+    pub image_frame_id: Option<Uuid>,
+    pub words: Vec<OcrBox>,
+}
+
+/// Loads the word boxes for `frame_id`, which may name either an OCR frame or the screenshot it
+/// was read from (the most recent OCR run wins). `Ok(None)` when no OCR frame matches.
+pub async fn load_layout(
+    pool: &PostgresPool,
+    frame_id: Uuid,
+) -> Result<Option<OcrLayout>, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let row = client
+        .query_opt(
+            "SELECT id, source_frame_id, payload->'words' AS words
+             FROM frames
+             WHERE modality = 'Ocr' AND (id = $1 OR source_frame_id = $1)
+             ORDER BY (id = $1) DESC, t_ingest DESC
+             LIMIT 1",
+            &[&frame_id],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("OCR layout query: {e}")))?;
+    Ok(row.map(|row| {
+        let words: Option<serde_json::Value> = row.get("words");
+        OcrLayout {
+            ocr_frame_id: row.get("id"),
+            image_frame_id: row.get("source_frame_id"),
+            words: words
+                .map(|w| crate::frames::json_to_ocr_boxes(&w))
+                .unwrap_or_default(),
+        }
+    }))
+}
+
+/// Boxes of the words on `frame_id` matching `query`. `Ok(None)` when the frame has no OCR.
+pub async fn find_hits(
+    pool: &PostgresPool,
+    frame_id: Uuid,
+    query: &str,
+) -> Result<Option<(OcrLayout, Vec<OcrBox>)>, LifelogError> {
+    let Some(layout) = load_layout(pool, frame_id).await? else {
+        return Ok(None);
+    };
+    let terms = query_terms(query);
+    let hits = matching_boxes(&layout.words, &terms)
+        .into_iter()
+        .cloned()
+        .collect();
+    Ok(Some((layout, hits)))
+}
+
+/// A rendered thumbnail and the hit boxes drawn on it, in thumbnail pixels.
+#[derive(Debug, Clone)]
+pub struct Thumbnail {
+    pub jpeg: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub hits: Vec<OcrBox>,
+}
+
+/// Renders the screenshot behind `frame_id` scaled to `max_width`, with words matching `query`
+/// highlighted. Screenshots without OCR are rendered without highlights. `Ok(None)` when neither
+/// the frame nor its OCR source has an image.
+pub async fn highlighted_thumbnail(
+    pool: &PostgresPool,
+    cas: &FsCas,
+    frame_id: Uuid,
+    query: &str,
+    max_width: u32,
+) -> Result<Option<Thumbnail>, LifelogError> {
+    let (image_frame_id, hits) = match find_hits(pool, frame_id, query).await? {
+        Some((layout, hits)) => (layout.image_frame_id.unwrap_or(frame_id), hits),
+        None => (frame_id, Vec::new()),
+    };
+
+    let blob_hash: Option<String> = {
+        let client = pool
+            .get()
+            .await
+            .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+        client
+            .query_opt(
+                "SELECT blob_hash FROM frames WHERE id = $1 AND modality IN ('Screen', 'Camera')",
+                &[&image_frame_id],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("thumbnail frame lookup: {e}")))?
+            .and_then(|row| row.get("blob_hash"))
+    };
+    let Some(blob_hash) = blob_hash else {
+        return Ok(None);
+    };
+
+    let cas = cas.clone();
+    let max_width = max_width.clamp(1, MAX_THUMBNAIL_WIDTH);
+    let thumb = tokio::task::spawn_blocking(move || {
+        let bytes = cas
+            .get(&blob_hash)
+            .map_err(|e| format!("CAS get failed: {e}"))?;
+        render(&bytes, &hits, max_width).map_err(|e| format!("thumbnail render: {e}"))
+    })
+    .await
+    .map_err(|e| LifelogError::Database(format!("thumbnail task: {e}")))?
+    .map_err(LifelogError::Database)?;
+    Ok(Some(thumb))
+}
+
+/// Scales an encoded image down to at most `max_width` and highlights `boxes`, given in pixels
+/// of the original image.
+pub fn render(
+    image_bytes: &[u8],
+    boxes: &[OcrBox],
+    max_width: u32,
+) -> Result<Thumbnail, image::ImageError> {
+    let image = image::load_from_memory(image_bytes)?;
+    let (orig_w, orig_h) = (image.width().max(1), image.height().max(1));
+    let mut thumb: RgbImage = if orig_w > max_width {
+        let height = ((orig_h as f32 * max_width as f32 / orig_w as f32).round() as u32).max(1);
+        image.resize_exact(max_width, height, FilterType::Triangle)
+    } else {
+        image
+    }
+    .to_rgb8();
+
+    let scale = thumb.width() as f32 / orig_w as f32;
+    let hits: Vec<OcrBox> = boxes
+        .iter()
+        .map(|b| OcrBox {
+            left: (b.left as f32 * scale).floor() as i32,
+            top: (b.top as f32 * scale).floor() as i32,
+            width: ((b.width as f32 * scale).ceil() as i32).max(1),
+            height: ((b.height as f32 * scale).ceil() as i32).max(1),
+            ..b.clone()
+        })
+        .collect();
+    for hit in &hits {
+        highlight(&mut thumb, hit);
+    }
+
+    let (width, height) = thumb.dimensions();
+    let mut jpeg = Cursor::new(Vec::new());
+    thumb.write_to(&mut jpeg, ImageFormat::Jpeg)?;
+    Ok(Thumbnail {
+        jpeg: jpeg.into_inner(),
+        width,
+        height,
+        hits,
+    })
+}
+
+/// Tints the pixels inside `b` and outlines it, clipped to the image.
+fn highlight(image: &mut RgbImage, b: &OcrBox) {
+    let (w, h) = (image.width() as i64, image.height() as i64);
+    let x0 = i64::from(b.left).clamp(0, w);
+    let y0 = i64::from(b.top).clamp(0, h);
+    let x1 = (i64::from(b.left) + i64::from(b.width)).clamp(0, w);
+    let y1 = (i64::from(b.top) + i64::from(b.height)).clamp(0, h);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let edge = x == x0 || x == x1 - 1 || y == y0 || y == y1 - 1;
+            let px = image.get_pixel_mut(x as u32, y as u32);
+            *px = if edge {
+                Rgb(HIGHLIGHT)
+            } else {
+                Rgb(std::array::from_fn(|i| {
+                    let blended = f32::from(px[i]) * (1.0 - HIGHLIGHT_ALPHA)
+                        + f32::from(HIGHLIGHT[i]) * HIGHLIGHT_ALPHA;
+                    blended.round() as u8
+                }))
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str, left: i32) -> OcrBox {
+        OcrBox {
+            text: text.to_string(),
+            left,
+            top: 10,
+            width: 40,
+            height: 12,
+            confidence: 90.0,
+        }
+    }
+
+    #[test]
+    fn matches_words_by_prefix_and_skips_operators() {
+        assert_eq!(
+            query_terms(r#""Deploy pipeline" or -staging prod-db"#),
+            vec!["deploy", "pipeline", "prod", "db"]
+        );
+        let words = vec![
+            word("Deployment,", 0),
+            word("staging", 50),
+            word("(pipeline)", 100),
+            word("redeploy", 150),
+            word("—", 200),
+        ];
+        let terms = query_terms("deploy pipeline");
+        let hits: Vec<&str> = matching_boxes(&words, &terms)
+            .iter()
+            .map(|b| b.text.as_str())
+            .collect();
+        assert_eq!(hits, vec!["Deployment,", "(pipeline)"]);
+        assert!(matching_boxes(&words, &query_terms("-deploy")).is_empty());
+    }
+
+    #[test]
+    #[allow(clippy::panic)]
+    fn render_scales_hits_and_tints_them() {
+        let image = RgbImage::from_pixel(800, 400, Rgb([0, 0, 0]));
+        let mut png = Cursor::new(Vec::new());
+        let _ = image.write_to(&mut png, ImageFormat::Png);
+
+        let Ok(thumb) = render(&png.into_inner(), &[word("hit", 200)], 400) else {
+            panic!("render failed");
+        };
+        assert_eq!((thumb.width, thumb.height), (400, 200));
+        assert_eq!(thumb.hits.len(), 1);
+        assert_eq!((thumb.hits[0].left, thumb.hits[0].top), (100, 5));
+        assert_eq!((thumb.hits[0].width, thumb.hits[0].height), (20, 6));
+
+        let Ok(decoded) = image::load_from_memory(&thumb.jpeg) else {
+            panic!("thumbnail is not a valid image");
+        };
+        let decoded = decoded.to_rgb8();
+        let inside = decoded.get_pixel(110, 8);
+        let outside = decoded.get_pixel(300, 150);
+        assert!(inside[0] > 60 && inside[2] < 40, "{inside:?}");
+        assert!(outside[0] < 20, "{outside:?}");
+    }
+}
@@ -0,0 +1,263 @@
+//! Monthly partitions of the `frames` table, ranged on `t_canonical` in UTC; see the
+//! `partitioned_frames` migration.
+//!
+//! The server keeps partitions for the current month and the next [`MONTHS_AHEAD`] in place and
+//! creates any other month the first time a frame lands in it. Once a month has closed its
+//! partition is vacuumed and analyzed, and retention drops it whole when every frame in it has
+//! expired.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Datelike, Months, TimeZone, Utc};
+use deadpool_postgres::{GenericClient, Object};
+use lifelog_core::LifelogError;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::ToSql;
+
+use crate::postgres::PostgresPool;
+use crate::retention::NOT_PINNED;
+
+/// Months after the current one whose partitions are created ahead of time.
+pub const MONTHS_AHEAD: u32 = 2;
+
+/// Start of the UTC month holding `t`.
+pub fn month_start(t: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(t.year(), t.month(), 1, 0, 0, 0)
+        .single()
+        .unwrap_or(t)
+}
+
+/// Name of the partition holding `t`, as `create_frame_partition` names it.
+pub fn partition_name(t: DateTime<Utc>) -> String {
+    format!("frames_{:04}_{:02}", t.year(), t.month())
+}
+
+/// Creates the partition of the month holding `t` unless it exists. Returns its name.
+pub async fn create<C: GenericClient>(
+    client: &C,
+    t: DateTime<Utc>,
+) -> Result<String, LifelogError> {
+    client
+        .query_one("SELECT create_frame_partition($1)", &[&t])
+        .await
+        .map(|row| row.get(0))
+        .map_err(|e| LifelogError::Database(format!("create frame partition for {t}: {e}")))
+}
+
+/// Creates the partitions of the current month and the [`MONTHS_AHEAD`] after it.
+pub async fn ensure_ahead(pool: &PostgresPool, now: DateTime<Utc>) -> Result<(), LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let start = month_start(now);
+    for ahead in 0..=MONTHS_AHEAD {
+        let month = start
+            .checked_add_months(Months::new(ahead))
+            .unwrap_or(start);
+        create(&client, month).await?;
+    }
+    Ok(())
+}
+
+/// Whether `e` rejected a row because no partition covers its `t_canonical`.
+fn is_missing_partition(e: &tokio_postgres::Error) -> bool {
+    e.as_db_error().is_some_and(|db| {
+        *db.code() == SqlState::CHECK_VIOLATION && db.message().starts_with("no partition of")
+    })
+}
+
+/// Runs a statement writing one frame with the given `t_canonical`, creating the partition of its
+/// month first if the statement finds none. Must not run inside a transaction, which the
+/// rejected attempt would abort.
+pub async fn execute_routed<C: GenericClient>(
+    client: &C,
+    sql: &str,
+    params: &[&(dyn ToSql + Sync)],
+    t_canonical: DateTime<Utc>,
+) -> Result<u64, LifelogError> {
+    match client.execute(sql, params).await {
+        Err(e) if is_missing_partition(&e) => {
+            let partition = create(client, t_canonical).await?;
+            tracing::info!(%partition, "Created frame partition on demand");
+            client
+                .execute(sql, params)
+                .await
+                .map_err(|e| LifelogError::Database(format!("{e:?}")))
+        }
+        result => result.map_err(|e| LifelogError::Database(format!("{e:?}"))),
+    }
+}
+
+/// Vacuums and analyzes partitions whose month has ended and that have not been since, flushing
+/// pending GIN entries and refreshing the planner's statistics. Returns how many were.
+pub async fn maintain_closed(pool: &PostgresPool, now: DateTime<Utc>) -> Result<u64, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let closed: Vec<String> = client
+        .query(
+            "SELECT name FROM frame_partitions
+             WHERE range_end <= $1 AND (maintained_at IS NULL OR maintained_at < range_end)
+             ORDER BY range_start",
+            &[&now],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("closed partition query: {e}")))?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+
+    for name in &closed {
+        client
+            .batch_execute(&format!("VACUUM (ANALYZE) {}", quote_ident(name)))
+            .await
+            .map_err(|e| LifelogError::Database(format!("vacuum {name}: {e}")))?;
+        client
+            .execute(
+                "UPDATE frame_partitions SET maintained_at = NOW() WHERE name = $1",
+                &[name],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("partition maintenance record: {e}")))?;
+        tracing::info!(partition = %name, "Vacuumed closed frame partition");
+    }
+    Ok(closed.len() as u64)
+}
+
+/// Detaches and drops every partition whose frames would all be deleted by retention: each one's
+/// modality has a cutoff, from `cutoff_for`, after the end of the month, and none is pinned.
+/// Empty partitions of past months are dropped too. The blobs of dropped frames are added to
+/// `candidate_hashes`. Returns how many frames were dropped. Pins are read from the snapshot
+/// `retention::snapshot_pins` took on `client`.
+pub(crate) async fn drop_expired(
+    client: &mut Object,
+    now: DateTime<Utc>,
+    cutoff_for: impl Fn(&str) -> Option<DateTime<Utc>>,
+    candidate_hashes: &mut HashSet<String>,
+) -> Result<u64, LifelogError> {
+    let db_err = |context: &'static str| {
+        move |e: tokio_postgres::Error| LifelogError::Database(format!("{context}: {e}"))
+    };
+    let partitions: Vec<(String, DateTime<Utc>, DateTime<Utc>)> = client
+        .query(
+            "SELECT name, range_start, range_end FROM frame_partitions
+             WHERE range_end <= $1 ORDER BY range_start",
+            &[&now],
+        )
+        .await
+        .map_err(db_err("partition query"))?
+        .iter()
+        .map(|row| (row.get(0), row.get(1), row.get(2)))
+        .collect();
+
+    let mut dropped = 0u64;
+    for (name, start, end) in partitions {
+        let table = quote_ident(&name);
+        let tx = client
+            .transaction()
+            .await
+            .map_err(db_err("partition drop begin"))?;
+        // Holds off writers, so the frames checked are the frames dropped.
+        tx.batch_execute(&format!("LOCK TABLE {table} IN EXCLUSIVE MODE"))
+            .await
+            .map_err(db_err("partition lock"))?;
+
+        let modalities: Vec<String> = tx
+            .query(&format!("SELECT DISTINCT modality FROM {table}"), &[])
+            .await
+            .map_err(db_err("partition modality query"))?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        if !modalities
+            .iter()
+            .all(|m| cutoff_for(m).is_some_and(|cutoff| end <= cutoff))
+        {
+            continue;
+        }
+        let pinned: bool = tx
+            .query_one(
+                &format!("SELECT EXISTS (SELECT 1 FROM {table} WHERE NOT {NOT_PINNED})"),
+                &[],
+            )
+            .await
+            .map_err(db_err("partition pin check"))?
+            .get(0);
+        if pinned {
+            continue;
+        }
+
+        let frames: i64 = tx
+            .query_one(&format!("SELECT COUNT(*) FROM {table}"), &[])
+            .await
+            .map_err(db_err("partition count"))?
+            .get(0);
+        let hashes = tx
+            .query(
+                &format!("SELECT DISTINCT blob_hash FROM {table} WHERE blob_hash IS NOT NULL"),
+                &[],
+            )
+            .await
+            .map_err(db_err("partition blob query"))?;
+        // Dropping a partition fires no delete triggers, so its keys are released here.
+        tx.execute(
+            "DELETE FROM frame_keys WHERE t_canonical >= $1 AND t_canonical < $2",
+            &[&start, &end],
+        )
+        .await
+        .map_err(db_err("partition key release"))?;
+        tx.batch_execute(&format!(
+            "ALTER TABLE frames DETACH PARTITION {table}; DROP TABLE {table};"
+        ))
+        .await
+        .map_err(db_err("partition drop"))?;
+        tx.execute("DELETE FROM frame_partitions WHERE name = $1", &[&name])
+            .await
+            .map_err(db_err("partition record delete"))?;
+        tx.commit().await.map_err(db_err("partition drop commit"))?;
+
+        candidate_hashes.extend(
+            hashes
+                .iter()
+                .map(|row| row.get::<_, String>(0))
+                .filter(|hash| !hash.is_empty()),
+        );
+        dropped = dropped.saturating_add(frames as u64);
+        tracing::info!(partition = %name, frames, "Dropped expired frame partition");
+    }
+    Ok(dropped)
+}
+
+pub(crate) fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+
+    use super::{month_start, partition_name};
+
+    fn at(y: i32, mo: u32, d: u32, h: u32) -> Option<DateTime<Utc>> {
+        Utc.with_ymd_and_hms(y, mo, d, h, 59, 59).single()
+    }
+
+    #[test]
+    fn names_partitions_by_utc_month() {
+        let end_of_march = at(2026, 3, 31, 23);
+        assert_eq!(
+            end_of_march.map(partition_name).as_deref(),
+            Some("frames_2026_03")
+        );
+        assert_eq!(
+            end_of_march.map(month_start),
+            Utc.with_ymd_and_hms(2026, 3, 1, 0, 0, 0).single()
+        );
+        assert_eq!(
+            at(2026, 12, 1, 0).map(partition_name).as_deref(),
+            Some("frames_2026_12")
+        );
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::BTreeSet;
+
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use config::PayloadEncryptionConfig;
+use hmac::{Hmac, Mac};
+use lifelog_core::{LifelogError, PrivacyTier};
+use once_cell::sync::OnceCell;
+use serde_json::{json, Map, Value as JsonValue};
+use sha2::Sha256;
+use utils::cas::CasError;
+use uuid::Uuid;
+
+use crate::postgres::PostgresPool;
+
+/// Payload key holding the encrypted fields of a sealed frame.
+pub const SEALED_FIELD: &str = "sealed";
+/// Payload key holding the blind-index tokens of a sealed frame.
+pub const BLIND_INDEX_FIELD: &str = "blind_index";
+
+/// Payload fields holding captured text. These are the ones moved into the envelope; metadata
+/// such as codecs, sizes and MIME types stays in the clear.
+const SEALED_FIELDS: &[&str] = &[
+    "text",
+    "content",
+    "transcript",
+    "application",
+    "window_title",
+    "title",
+    "url",
+    "command",
+    "participants",
+];
+
+/// Modalities whose frames are sealed: the sensitive privacy tier, plus transcripts, which
+/// repeat microphone audio as text.
+pub const SENSITIVE_MODALITIES: &[&str] = &[
+    "Keystroke",
+    "Keystrokes",
+    "Audio",
+    "Clipboard",
+    "Microphone",
+    "Transcription",
+];
+
+const ENVELOPE_VERSION: u64 = 1;
+const TOKEN_BYTES: usize = 16;
+const MAX_WORD_CHARS: usize = 64;
+/// Cap on tokens per frame, so a huge clipboard entry does not bloat the index.
+const MAX_TOKENS: usize = 2048;
+
+pub fn is_sensitive(modality: &str) -> bool {
+    PrivacyTier::for_modality(modality) == PrivacyTier::Sensitive || modality == "Transcription"
+}
+
+/// Seals payload fields and derives the blind-index tokens that let `Eq` and `Contains` match
+/// them without decrypting.
+///
+/// Tokens are keyed HMACs, so a database dump alone reveals neither the words nor the values. It
+/// does show which sealed frames share a word or value.
+pub struct PayloadCipher {
+    key_id: String,
+    cipher: XChaCha20Poly1305,
+    index_key: [u8; 32],
+    indexed_fields: Vec<String>,
+}
+
+impl PayloadCipher {
+    pub fn new(key: &[u8; 32], indexed_fields: Vec<String>) -> Self {
+        let key_id = subkey(key, b"lifelog-payload-key-id")[..8]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        Self {
+            key_id,
+            cipher: XChaCha20Poly1305::new((&subkey(key, b"lifelog-payload-enc")).into()),
+            index_key: subkey(key, b"lifelog-payload-index"),
+            indexed_fields,
+        }
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// Whether `field` gets blind-index tokens when sealed.
+    pub fn indexes(&self, field: &str) -> bool {
+        self.indexed_fields.iter().any(|f| f == field)
+    }
+
+    /// Moves the text fields of `payload` into an envelope bound to `frame_id`, and adds
+    /// blind-index tokens for the indexed ones. Returns whether anything was sealed.
+    pub fn seal(&self, frame_id: Uuid, payload: &mut JsonValue) -> Result<bool, String> {
+        let Some(obj) = payload.as_object_mut() else {
+            return Ok(false);
+        };
+        if obj.contains_key(SEALED_FIELD) {
+            return Ok(false);
+        }
+        let mut fields = Map::new();
+        for name in SEALED_FIELDS {
+            match obj.remove(*name) {
+                Some(JsonValue::Null) | None => {}
+                Some(value) => {
+                    fields.insert(name.to_string(), value);
+                }
+            }
+        }
+        if fields.is_empty() {
+            return Ok(false);
+        }
+
+        let mut tokens = BTreeSet::new();
+        for (name, value) in &fields {
+            if let (true, Some(text)) = (self.indexes(name), value.as_str()) {
+                tokens.insert(self.eq_token(name, text));
+                tokens.extend(self.word_tokens(text));
+            }
+        }
+
+        let plaintext = serde_json::to_vec(&fields).map_err(|e| e.to_string())?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let data = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &plaintext,
+                    aad: frame_id.as_bytes(),
+                },
+            )
+            .map_err(|_| "payload encryption failed".to_string())?;
+        let b64 = base64::engine::general_purpose::STANDARD;
+        obj.insert(
+            SEALED_FIELD.to_string(),
+            json!({
+                "v": ENVELOPE_VERSION,
+                "key_id": self.key_id,
+                "nonce": b64.encode(nonce),
+                "data": b64.encode(data),
+            }),
+        );
+        if !tokens.is_empty() {
+            obj.insert(
+                BLIND_INDEX_FIELD.to_string(),
+                tokens.into_iter().take(MAX_TOKENS).collect(),
+            );
+        }
+        Ok(true)
+    }
+
+    /// Restores the fields sealed by [`Self::seal`] and drops the blind-index tokens.
+    pub fn open(&self, frame_id: Uuid, payload: &mut JsonValue) -> Result<(), String> {
+        let Some(obj) = payload.as_object_mut() else {
+            return Ok(());
+        };
+        let Some(envelope) = obj.get(SEALED_FIELD) else {
+            return Ok(());
+        };
+        let key_id = envelope["key_id"].as_str().unwrap_or_default();
+        if key_id != self.key_id {
+            return Err(format!(
+                "payload is sealed with key {key_id}, but the configured key is {}",
+                self.key_id
+            ));
+        }
+        let b64 = base64::engine::general_purpose::STANDARD;
+        let decode = |name: &str| {
+            b64.decode(envelope[name].as_str().unwrap_or_default())
+                .map_err(|e| format!("sealed payload {name}: {e}"))
+        };
+        let nonce = decode("nonce")?;
+        if nonce.len() != 24 {
+            return Err("sealed payload nonce has the wrong length".to_string());
+        }
+        let plaintext = self
+            .cipher
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &decode("data")?,
+                    aad: frame_id.as_bytes(),
+                },
+            )
+            .map_err(|_| "sealed payload failed authentication".to_string())?;
+        let fields: Map<String, JsonValue> =
+            serde_json::from_slice(&plaintext).map_err(|e| format!("sealed payload: {e}"))?;
+        obj.remove(SEALED_FIELD);
+        obj.remove(BLIND_INDEX_FIELD);
+        obj.extend(fields);
+        Ok(())
+    }
+
+    /// Token matching a sealed `field` whose whole value is `value`.
+    pub fn eq_token(&self, field: &str, value: &str) -> String {
+        self.token(&[b"eq", field.as_bytes(), value.as_bytes()])
+    }
+
+    /// Tokens for the words of `text`: lowercased runs of letters and digits, two characters or
+    /// longer. A sealed frame matches a `Contains` query when it has every token of the query.
+    pub fn word_tokens(&self, text: &str) -> BTreeSet<String> {
+        if self.indexed_fields.is_empty() {
+            return BTreeSet::new();
+        }
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|w| w.chars().nth(1).is_some())
+            .map(|w| {
+                let word: String = w.chars().take(MAX_WORD_CHARS).collect();
+                self.token(&[b"word", word.to_lowercase().as_bytes()])
+            })
+            .collect()
+    }
+
+    fn token(&self, parts: &[&[u8]]) -> String {
+        let mut mac = hmac(&self.index_key);
+        for part in parts {
+            mac.update(&(part.len() as u64).to_be_bytes());
+            mac.update(part);
+        }
+        let digest = mac.finalize().into_bytes();
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&digest[..TOKEN_BYTES])
+    }
+}
+
+fn hmac(key: &[u8; 32]) -> Hmac<Sha256> {
+    // HMAC zero-pads keys shorter than its 64-byte block, so this is HMAC keyed with `key`.
+    let mut block = [0u8; 64];
+    block[..32].copy_from_slice(key);
+    <Hmac<Sha256> as KeyInit>::new(&block.into())
+}
+
+fn subkey(key: &[u8; 32], label: &[u8]) -> [u8; 32] {
+    let mut mac = hmac(key);
+    mac.update(label);
+    mac.finalize().into_bytes().into()
+}
+
+static CONFIGURED: OnceCell<Option<PayloadCipher>> = OnceCell::new();
+
+/// Loads the payload key from the unified config, once. Passphrase keys share the CAS salt.
+pub fn init(cas_path: &str) -> Result<Option<&'static PayloadCipher>, CasError> {
+    CONFIGURED
+        .get_or_try_init(|| load(&config::load_payload_encryption_from_unified(), cas_path))
+        .map(Option::as_ref)
+}
+
+fn load(
+    config: &PayloadEncryptionConfig,
+    cas_path: &str,
+) -> Result<Option<PayloadCipher>, CasError> {
+    let Some(spec) = &config.key else {
+        return Ok(None);
+    };
+    let key = utils::cas::load_key(spec, &crate::key_rotation::salt_path(cas_path))?;
+    Ok(Some(PayloadCipher::new(
+        &key,
+        config.indexed_fields.clone(),
+    )))
+}
+
+/// The payload cipher, if [`init`] found a key.
+pub fn configured() -> Option<&'static PayloadCipher> {
+    CONFIGURED.get().and_then(Option::as_ref)
+}
+
+/// Opens `payload` in place if it is sealed.
+pub fn open_payload(frame_id: Uuid, payload: &mut JsonValue) -> Result<(), String> {
+    if payload.get(SEALED_FIELD).is_none() {
+        return Ok(());
+    }
+    configured()
+        .ok_or_else(|| "payload is sealed but no payload key is configured".to_string())?
+        .open(frame_id, payload)
+}
+
+/// Applies a privacy-scan patch to a sealed payload and seals the result again, so redacted
+/// text never reaches Postgres unencrypted.
+pub fn patch_sealed(
+    frame_id: Uuid,
+    mut payload: JsonValue,
+    patch: Map<String, JsonValue>,
+    dropped: &[&str],
+) -> Result<JsonValue, String> {
+    let cipher = configured()
+        .ok_or_else(|| "payload is sealed but no payload key is configured".to_string())?;
+    cipher.open(frame_id, &mut payload)?;
+    if let Some(obj) = payload.as_object_mut() {
+        for field in dropped {
+            obj.remove(*field);
+        }
+        obj.extend(patch);
+    }
+    cipher.seal(frame_id, &mut payload)?;
+    Ok(payload)
+}
+
+/// Seals up to `batch` frames stored before payload encryption was turned on, and drops the
+/// entity mentions extracted from them. Returns how many were sealed; zero once none are left.
+pub async fn seal_backlog(pool: &PostgresPool, batch: i64) -> Result<u64, LifelogError> {
+    let Some(cipher) = configured() else {
+        return Ok(0);
+    };
+    let mut client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let tx = client
+        .transaction()
+        .await
+        .map_err(|e| LifelogError::Database(format!("payload seal tx begin: {e}")))?;
+    let rows = tx
+        .query(
+            "SELECT f.id, f.payload FROM frames f
+             WHERE NOT (f.payload ? 'sealed') AND f.payload ?| $2
+               AND (f.modality = ANY($1) OR EXISTS (
+                   SELECT 1 FROM frames s
+                   WHERE s.id = f.source_frame_id
+                     AND (s.modality = ANY($1) OR s.payload ? 'sealed')
+               ))
+             LIMIT $3 FOR UPDATE OF f SKIP LOCKED",
+            &[&SENSITIVE_MODALITIES, &SEALED_FIELDS, &batch],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("payload seal query: {e}")))?;
+
+    let mut sealed = 0;
+    for row in &rows {
+        let id: Uuid = row.get("id");
+        let mut payload: JsonValue = row.get("payload");
+        cipher
+            .seal(id, &mut payload)
+            .map_err(|e| LifelogError::Database(format!("payload seal {id}: {e}")))?;
+        // The language was detected from the text now sealed.
+        tx.execute(
+            "UPDATE frames SET payload = $2, language = NULL WHERE id = $1",
+            &[&id, &payload],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("payload seal update: {e}")))?;
+        // Entity mentions would point at the sealed text; their entities are pruned once
+        // nothing mentions them.
+        tx.execute("DELETE FROM entity_mentions WHERE frame_id = $1", &[&id])
+            .await
+            .map_err(|e| LifelogError::Database(format!("payload seal mentions: {e}")))?;
+        sealed += 1;
+    }
+    tx.commit()
+        .await
+        .map_err(|e| LifelogError::Database(format!("payload seal tx commit: {e}")))?;
+    Ok(sealed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher() -> PayloadCipher {
+        PayloadCipher::new(&[7u8; 32], vec!["text".to_string()])
+    }
+
+    #[test]
+    fn sealed_fields_round_trip_and_are_bound_to_the_frame() {
+        let cipher = cipher();
+        let id = Uuid::new_v4();
+        let original = json!({
+            "text": "hunter2 is my Password",
+            "mime_type": "text/plain",
+        });
+        let mut payload = original.clone();
+        assert_eq!(cipher.seal(id, &mut payload), Ok(true));
+        assert!(payload.get("text").is_none());
+        assert_eq!(payload["mime_type"], "text/plain");
+        assert!(!payload.to_string().contains("hunter2"));
+
+        let tokens = payload[BLIND_INDEX_FIELD]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let has = |t: &String| tokens.iter().any(|v| v.as_str() == Some(t.as_str()));
+        assert!(has(&cipher.eq_token("text", "hunter2 is my Password")));
+        assert!(cipher.word_tokens("PASSWORD hunter2").iter().all(has));
+        assert!(!cipher.word_tokens("hunter3").iter().any(has));
+
+        let mut moved = payload.clone();
+        assert!(cipher.open(Uuid::new_v4(), &mut moved).is_err());
+        let other_key = PayloadCipher::new(&[8u8; 32], vec![]);
+        assert!(other_key.open(id, &mut moved).is_err());
+
+        assert_eq!(cipher.open(id, &mut payload), Ok(()));
+        assert_eq!(payload, original);
+    }
+
+    #[test]
+    fn only_text_fields_are_sealed_and_only_indexed_fields_tokenized() {
+        let cipher = cipher();
+        let mut payload = json!({"codec": "opus", "sample_rate": 48000});
+        assert_eq!(cipher.seal(Uuid::new_v4(), &mut payload), Ok(false));
+
+        let mut payload = json!({"application": "Signal", "window_title": "Alice"});
+        assert_eq!(cipher.seal(Uuid::new_v4(), &mut payload), Ok(true));
+        assert!(payload.get(BLIND_INDEX_FIELD).is_none());
+    }
+
+    #[test]
+    fn sensitive_modalities_match_the_privacy_tier() {
+        for modality in SENSITIVE_MODALITIES {
+            assert!(is_sensitive(modality), "{modality}");
+        }
+        assert!(!is_sensitive("Screen"));
+        assert!(!is_sensitive("ShellHistory"));
+    }
+}
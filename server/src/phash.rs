@@ -0,0 +1,227 @@
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::frames::FrameRow;
+use crate::postgres::PostgresPool;
+use lifelog_core::LifelogError;
+
+/// Two hashes at most this many bits apart are treated as the same picture.
+pub const DUPLICATE_DISTANCE: u32 = 4;
+/// Default Hamming distance for "looks like this" searches.
+pub const DEFAULT_SIMILAR_DISTANCE: u32 = 10;
+/// Default distance in time, either way, within which "looks like this" searches look.
+pub const DEFAULT_SIMILAR_WINDOW: Duration = Duration::days(30);
+/// A frame is only compared with a predecessor captured at most this long before it.
+pub(crate) const MAX_DUPLICATE_GAP: Duration = Duration::minutes(10);
+
+/// Difference hash of an encoded image: 64 bits, one per horizontally adjacent pixel pair of a
+/// 9x8 grayscale thumbnail, set when brightness increases to the right. Returns `None` when the
+/// bytes cannot be decoded.
+pub fn dhash(image_bytes: &[u8]) -> Option<u64> {
+    let image = image::load_from_memory(image_bytes).ok()?;
+    let thumb = image.thumbnail_exact(9, 8).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = thumb.get_pixel(x, y)[0];
+            let right = thumb.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(left < right);
+        }
+    }
+    Some(hash)
+}
+
+pub fn hamming(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Hash as stored in frame payloads: 16 lowercase hex digits.
+pub fn to_hex(hash: u64) -> String {
+    format!("{hash:016x}")
+}
+
+pub fn from_hex(hex: &str) -> Option<u64> {
+    if hex.len() != 16 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u64::from_str_radix(hex, 16).ok()
+}
+
+/// Finds the frame a newly ingested image frame duplicates, if any.
+///
+/// The frame is compared with the previous frame of the same stream. When that one is itself a
+/// duplicate, the comparison is made against the frame it duplicates instead, so a slowly
+/// changing screen eventually breaks out of the run rather than drifting along it.
+pub async fn find_duplicate_of(
+    client: &tokio_postgres::Client,
+    row: &FrameRow,
+    hash: u64,
+) -> Result<Option<Uuid>, LifelogError> {
+    let since: DateTime<Utc> = row.t_canonical - MAX_DUPLICATE_GAP;
+    let prev = client
+        .query_opt(
+            "SELECT p.id, p.phash, r.id AS rep_id, r.phash AS rep_phash
+             FROM frames p
+             LEFT JOIN frames r ON r.id = (p.payload->>'duplicate_of')::uuid
+             WHERE p.collector_id = $1 AND p.stream_id = $2 AND p.modality = $3
+             AND p.t_canonical < $4 AND p.t_canonical >= $5 AND p.phash IS NOT NULL
+             ORDER BY p.t_canonical DESC
+             LIMIT 1",
+            &[
+                &row.collector_id,
+                &row.stream_id,
+                &row.modality,
+                &row.t_canonical,
+                &since,
+            ],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("duplicate lookup: {e}")))?;
+    let Some(prev) = prev else {
+        return Ok(None);
+    };
+
+    let rep_id: Option<Uuid> = prev.get("rep_id");
+    let rep_hash: Option<i64> = prev.get("rep_phash");
+    let (id, other) = match (rep_id, rep_hash) {
+        (Some(id), Some(h)) => (id, h),
+        _ => (prev.get("id"), prev.get("phash")),
+    };
+    Ok((hamming(hash, other as u64) <= DUPLICATE_DISTANCE).then_some(id))
+}
+
+/// An image frame found by [`find_similar`].
+#[derive(Debug, Clone)]
+pub struct SimilarFrame {
+    pub id: Uuid,
+    pub collector_id: String,
+    pub modality: String,
+    pub t_canonical: DateTime<Utc>,
+    pub distance: u32,
+}
+
+/// Image frames whose perceptual hash is within `max_distance` bits of `frame_id`'s, closest
+/// first. Only frames captured within `window` of the requested one are compared, and with
+/// `same_stream` only those of its collector and stream. Near-duplicates are left out unless
+/// `include_duplicates` is set, so a run of identical screenshots shows up once; a duplicate
+/// whose original is not among the matches is still returned. Returns `Ok(None)` when the frame
+/// has no hash.
+pub async fn find_similar(
+    pool: &PostgresPool,
+    frame_id: Uuid,
+    max_distance: u32,
+    limit: usize,
+    include_duplicates: bool,
+    window: Duration,
+    same_stream: bool,
+) -> Result<Option<Vec<SimilarFrame>>, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+
+    let frame = client
+        .query_opt(
+            "SELECT phash, t_canonical, collector_id, stream_id FROM frames WHERE id = $1",
+            &[&frame_id],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("phash lookup: {e}")))?;
+    let Some(frame) = frame else {
+        return Ok(None);
+    };
+    let Some(hash) = frame.get::<_, Option<i64>>("phash") else {
+        return Ok(None);
+    };
+    let t_canonical: DateTime<Utc> = frame.get("t_canonical");
+    let collector_id: String = frame.get("collector_id");
+    let stream_id: String = frame.get("stream_id");
+
+    let max_distance = i32::try_from(max_distance.min(64)).unwrap_or(64);
+    let rows = client
+        .query(
+            "WITH matches AS (
+                 SELECT id, collector_id, modality, t_canonical, payload, distance FROM (
+                     SELECT id, collector_id, modality, t_canonical, payload,
+                            bit_count((phash # $1)::bit(64))::int AS distance
+                     FROM frames
+                     WHERE phash IS NOT NULL
+                       AND t_canonical BETWEEN $5 AND $6
+                       AND (NOT $7 OR (collector_id = $8 AND stream_id = $9))
+                 ) f
+                 WHERE distance <= $3
+             )
+             SELECT id, collector_id, modality, t_canonical, distance FROM matches m
+             WHERE id <> $2
+               AND ($4 OR NOT (payload ? 'duplicate_of') OR NOT EXISTS (
+                   SELECT 1 FROM matches o WHERE o.id = (m.payload->>'duplicate_of')::uuid))
+             ORDER BY distance ASC, t_canonical DESC
+             LIMIT $10",
+            &[
+                &hash,
+                &frame_id,
+                &max_distance,
+                &include_duplicates,
+                &(t_canonical - window),
+                &(t_canonical + window),
+                &same_stream,
+                &collector_id,
+                &stream_id,
+                &(limit as i64),
+            ],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("similar frames query: {e}")))?;
+
+    Ok(Some(
+        rows.iter()
+            .map(|row| {
+                let distance: i32 = row.get("distance");
+                SimilarFrame {
+                    id: row.get("id"),
+                    collector_id: row.get("collector_id"),
+                    modality: row.get("modality"),
+                    t_canonical: row.get("t_canonical"),
+                    distance: distance.max(0) as u32,
+                }
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, ImageFormat, Luma};
+    use std::io::Cursor;
+
+    fn png(f: impl Fn(u32, u32) -> u8) -> Vec<u8> {
+        let img = ImageBuffer::from_fn(64, 48, |x, y| Luma([f(x, y)]));
+        let mut out = Cursor::new(Vec::new());
+        let _ = img.write_to(&mut out, ImageFormat::Png);
+        out.into_inner()
+    }
+
+    #[test]
+    fn near_identical_images_hash_close_and_different_images_far() {
+        let gradient = png(|x, _| (x * 4) as u8);
+        let gradient_noise = png(|x, y| (x * 4) as u8 + u8::from(x == 10 && y == 10));
+        let reversed = png(|x, _| 255 - (x * 4) as u8);
+
+        let a = dhash(&gradient).unwrap_or_default();
+        let b = dhash(&gradient_noise).unwrap_or_default();
+        let c = dhash(&reversed).unwrap_or_default();
+        assert!(hamming(a, b) <= DUPLICATE_DISTANCE);
+        assert!(hamming(a, c) > DEFAULT_SIMILAR_DISTANCE);
+        assert_eq!(dhash(b"not an image"), None);
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        let h = 0x00ab_cdef_0123_4567;
+        assert_eq!(to_hex(h), "00abcdef01234567");
+        assert_eq!(from_hex(&to_hex(h)), Some(h));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zzzzzzzzzzzzzzzz"), None);
+    }
+}
@@ -0,0 +1,134 @@
+//! Pinned frames and time ranges, which retention never deletes or degrades.
+//!
+//! Whether a frame is pinned is decided in SQL: queries use `frame_pinned(id, source_frame_id,
+//! time_range)` per row, and retention and thinning snapshot `pinned_frame_ids()` and the
+//! pinned ranges once per run. Both follow the lineage the same way.
+
+use chrono::{DateTime, Utc};
+use lifelog_core::uuid::Uuid;
+use lifelog_core::LifelogError;
+use tokio_postgres::Row;
+
+use crate::postgres::PostgresPool;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pin {
+    pub id: i64,
+    pub frame_id: Option<Uuid>,
+    /// `[start, end)` of a range pin.
+    pub range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+const PIN_COLUMNS: &str =
+    "id, frame_id, lower(time_range) AS range_start, upper(time_range) AS range_end, reason, created_at";
+
+impl Pin {
+    fn from_row(row: &Row) -> Self {
+        let start: Option<DateTime<Utc>> = row.get("range_start");
+        let end: Option<DateTime<Utc>> = row.get("range_end");
+        Self {
+            id: row.get("id"),
+            frame_id: row.get("frame_id"),
+            range: start.zip(end),
+            reason: row.get("reason"),
+            created_at: row.get("created_at"),
+        }
+    }
+}
+
+fn db_err(what: &'static str) -> impl Fn(tokio_postgres::Error) -> LifelogError {
+    move |e| LifelogError::Database(format!("{what}: {e}"))
+}
+
+/// Pins existing frames; pinning a frame again updates its reason. Unknown IDs are ignored.
+pub async fn pin_frames(
+    pool: &PostgresPool,
+    frame_ids: &[Uuid],
+    reason: &str,
+) -> Result<Vec<Pin>, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let rows = client
+        .query(
+            &format!(
+                "INSERT INTO pins (frame_id, reason)
+                 SELECT id, $2 FROM frames WHERE id = ANY($1)
+                 ON CONFLICT (frame_id) WHERE frame_id IS NOT NULL
+                 DO UPDATE SET reason = EXCLUDED.reason
+                 RETURNING {PIN_COLUMNS}"
+            ),
+            &[&frame_ids, &reason],
+        )
+        .await
+        .map_err(db_err("pin frames"))?;
+    Ok(rows.iter().map(Pin::from_row).collect())
+}
+
+/// Pins everything captured in `[start, end)`, including frames that arrive later.
+pub async fn pin_range(
+    pool: &PostgresPool,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    reason: &str,
+) -> Result<Pin, LifelogError> {
+    if end <= start {
+        return Err(LifelogError::Validation {
+            field: "end".to_string(),
+            reason: "must be after start".to_string(),
+        });
+    }
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let row = client
+        .query_one(
+            &format!(
+                "INSERT INTO pins (time_range, reason) VALUES (tstzrange($1, $2, '[)'), $3)
+                 RETURNING {PIN_COLUMNS}"
+            ),
+            &[&start, &end, &reason],
+        )
+        .await
+        .map_err(db_err("pin range"))?;
+    Ok(Pin::from_row(&row))
+}
+
+/// Removes pins by pin ID or by pinned frame ID; returns how many were removed.
+pub async fn unpin(
+    pool: &PostgresPool,
+    pin_ids: &[i64],
+    frame_ids: &[Uuid],
+) -> Result<u64, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    client
+        .execute(
+            "DELETE FROM pins WHERE id = ANY($1) OR frame_id = ANY($2)",
+            &[&pin_ids, &frame_ids],
+        )
+        .await
+        .map_err(db_err("unpin"))
+}
+
+/// All pins, newest first.
+pub async fn list(pool: &PostgresPool) -> Result<Vec<Pin>, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let rows = client
+        .query(
+            &format!("SELECT {PIN_COLUMNS} FROM pins ORDER BY created_at DESC, id DESC"),
+            &[],
+        )
+        .await
+        .map_err(db_err("list pins"))?;
+    Ok(rows.iter().map(Pin::from_row).collect())
+}
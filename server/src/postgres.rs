@@ -39,6 +39,95 @@ const MIGRATIONS: &[EmbeddedMigration] = &[
         version: "20260323300000_dynamic_search_doc.sql",
         sql: include_str!("../migrations/20260323300000_dynamic_search_doc.sql"),
     },
+    // These four shipped without being registered, so no database had run them. They finish the
+    // move to the unified frames table: the old job queue goes (only if empty), transform outputs
+    // get the unique index their inserts rely on (after existing duplicates are merged),
+    // keystroke frames get their canonical modality name, and search gets per-modality weights.
+    // Each is a no-op where its change is already in place.
+    EmbeddedMigration {
+        version: "20260323400000_drop_transform_jobs.sql",
+        sql: include_str!("../migrations/20260323400000_drop_transform_jobs.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260323500000_transform_idempotency.sql",
+        sql: include_str!("../migrations/20260323500000_transform_idempotency.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260324000000_fix_keystroke_modality.sql",
+        sql: include_str!("../migrations/20260324000000_fix_keystroke_modality.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260324100000_smart_search_doc.sql",
+        sql: include_str!("../migrations/20260324100000_smart_search_doc.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260325000000_privacy_scan.sql",
+        sql: include_str!("../migrations/20260325000000_privacy_scan.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260325100000_transform_dead_letters.sql",
+        sql: include_str!("../migrations/20260325100000_transform_dead_letters.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260325200000_transform_cache.sql",
+        sql: include_str!("../migrations/20260325200000_transform_cache.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260325300000_entities.sql",
+        sql: include_str!("../migrations/20260325300000_entities.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260325400000_search_language.sql",
+        sql: include_str!("../migrations/20260325400000_search_language.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260325500000_perceptual_hash.sql",
+        sql: include_str!("../migrations/20260325500000_perceptual_hash.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260325600000_cas_scrub.sql",
+        sql: include_str!("../migrations/20260325600000_cas_scrub.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260325700000_cas_key_rotation.sql",
+        sql: include_str!("../migrations/20260325700000_cas_key_rotation.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260325800000_payload_blind_index.sql",
+        sql: include_str!("../migrations/20260325800000_payload_blind_index.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260325900000_retention_tiers.sql",
+        sql: include_str!("../migrations/20260325900000_retention_tiers.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260326000000_pins.sql",
+        sql: include_str!("../migrations/20260326000000_pins.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260326100000_forget.sql",
+        sql: include_str!("../migrations/20260326100000_forget.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260326200000_partitioned_frames.sql",
+        sql: include_str!("../migrations/20260326200000_partitioned_frames.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260327000000_key_rotation_failures.sql",
+        sql: include_str!("../migrations/20260327000000_key_rotation_failures.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260327100000_frame_pinned_descendants.sql",
+        sql: include_str!("../migrations/20260327100000_frame_pinned_descendants.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260327200000_secret_scan_pending.sql",
+        sql: include_str!("../migrations/20260327200000_secret_scan_pending.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260327300000_pinned_frame_ids.sql",
+        sql: include_str!("../migrations/20260327300000_pinned_frame_ids.sql"),
+    },
 ];
 
 fn is_unix_socket_connection(cfg: &tokio_postgres::Config) -> bool {
@@ -142,7 +231,23 @@ pub async fn run_migrations(pool: &PostgresPool) -> Result<(), LifelogError> {
 
 #[cfg(test)]
 mod tests {
-    use super::is_postgres_uri;
+    use super::{is_postgres_uri, MIGRATIONS};
+
+    #[test]
+    fn every_migration_file_is_registered() -> std::io::Result<()> {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations");
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if name.ends_with(".sql") {
+                files.push(name);
+            }
+        }
+        files.sort();
+        let registered: Vec<&str> = MIGRATIONS.iter().map(|m| m.version).collect();
+        assert_eq!(files, registered);
+        Ok(())
+    }
 
     #[test]
     fn detects_postgres_uris() {
@@ -36,6 +36,14 @@ pub enum Expression {
     Contains(String, String),
     /// Matches if the record's timestamp is within [start, end).
     TimeRange(DateTime<Utc>, DateTime<Utc>),
+    /// Matches records that mention an extracted entity (e.g. an email address, domain or
+    /// ticket id), optionally restricted to one entity kind.
+    Entity {
+        kind: Option<String>,
+        value: String,
+    },
+    /// Matches pinned records: pinned frames, their lineage and records in pinned time ranges.
+    Pinned,
 
     // --- Cross-Stream Correlation (Section 10) ---
     /// `WITHIN(Target, Condition, Window)`: Matches records in Target if there exists
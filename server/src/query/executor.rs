@@ -1,13 +1,22 @@
 use super::ast::{Expression, Value};
 use super::planner::ExecutionPlan;
 use crate::postgres::PostgresPool;
+use crate::transform::entities::{self, EntityKind};
 use anyhow::anyhow;
 use lifelog_core::{DataOrigin, LifelogFrameKey};
 use std::collections::BTreeSet;
 use std::time::Duration;
 use tokio::time::timeout;
 
-const DEFAULT_DB_QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+pub(crate) const DEFAULT_DB_QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Keeps near-duplicate screenshots out of results when the frame they duplicate stands for
+/// them, i.e. when the original also matches `original_scope` (the query's conditions compiled
+/// for alias `o`). A duplicate whose original falls outside the query is kept.
+fn distinct_frames_sql(original_scope: &str) -> String {
+    format!(
+        "(NOT (t.payload ? 'duplicate_of') OR NOT EXISTS (SELECT 1 FROM frames o WHERE o.id = (t.payload->>'duplicate_of')::uuid AND {original_scope}))"
+    )
+}
 
 pub async fn execute_postgres(
     pool: &PostgresPool,
@@ -64,25 +73,31 @@ async fn execute_table_query(
         return Ok(vec![]);
     }
 
-    let origin_scope = compile_origin_scope_sql("t", &origin);
-    let filter_sql = filter
-        .as_ref()
-        .map(|f| compile_expression_pg_sql(f, "t"))
-        .unwrap_or_else(|| "TRUE".to_string());
+    let scope = |alias: &str| {
+        let filter_sql = filter
+            .as_ref()
+            .map(|f| compile_expression_pg_sql(f, alias))
+            .unwrap_or_else(|| "TRUE".to_string());
+        format!(
+            "({}) AND ({filter_sql})",
+            compile_origin_scope_sql(alias, &origin)
+        )
+    };
+    let where_sql = format!("{} AND {}", scope("t"), distinct_frames_sql(&scope("o")));
 
     let order_by = filter
         .as_ref()
         .and_then(|f| extract_search_text(f))
         .map(|text| {
             format!(
-                "ts_rank(t.search_doc, websearch_to_tsquery('english', {})) DESC, t.t_canonical DESC",
-                quote_string(text)
+                "ts_rank(t.search_doc, {}) DESC, t.t_canonical DESC",
+                compile_tsquery_sql(text)
             )
         })
         .unwrap_or_else(|| "t.t_canonical DESC NULLS LAST".to_string());
 
     let sql = format!(
-        "SELECT t.id::text AS id FROM frames t WHERE ({origin_scope}) AND ({filter_sql}) ORDER BY {order_by} LIMIT {limit}"
+        "SELECT t.id::text AS id FROM frames t WHERE {where_sql} ORDER BY {order_by} LIMIT {limit}"
     );
     tracing::debug!("Postgres TableQuery SQL: {}", sql);
 
@@ -114,49 +129,31 @@ async fn execute_during_query(
         return Ok(vec![]);
     }
 
-    let mut where_clauses = Vec::new();
-    where_clauses.push(compile_origin_scope_sql("t", &target_origin));
-    if let Some(filter) = target_base_filter.as_ref() {
-        where_clauses.push(compile_expression_pg_sql(filter, "t"));
-    }
-
-    for term in during_terms {
-        let window_ms = term.window.num_milliseconds();
-        let mut source_exists_terms = Vec::new();
-
-        for source_plan in term.source_plans {
-            let source_scope = compile_origin_scope_sql("s", &source_plan.source_origin);
-            let source_filter = compile_expression_pg_sql(&source_plan.filter, "s");
-            let expanded_overlap = format!(
-                "t.time_range && tstzrange(lower(s.time_range) - interval '{window_ms} milliseconds', upper(s.time_range) + interval '{window_ms} milliseconds', '[]')"
-            );
-
-            source_exists_terms.push(format!(
-                "EXISTS (SELECT 1 FROM frames s WHERE ({source_scope}) AND ({source_filter}) AND ({expanded_overlap}))"
-            ));
-        }
-
-        if source_exists_terms.is_empty() {
-            return Ok(vec![]);
-        }
-
-        where_clauses.push(format!("({})", source_exists_terms.join(" OR ")));
+    if during_terms.iter().any(|term| term.source_plans.is_empty()) {
+        return Ok(vec![]);
     }
-
-    let where_sql = where_clauses.join(" AND ");
+    let scope = |alias: &str| {
+        during_scope_sql(
+            alias,
+            &target_origin,
+            target_base_filter.as_ref(),
+            &during_terms,
+        )
+    };
+    let where_sql = format!("{} AND {}", scope("t"), distinct_frames_sql(&scope("o")));
     let order_by = target_base_filter
         .as_ref()
         .and_then(|f| extract_search_text(f))
         .map(|text| {
             format!(
-                "ts_rank(t.search_doc, websearch_to_tsquery('english', {})) DESC, t.t_canonical DESC",
-                quote_string(text)
+                "ts_rank(t.search_doc, {}) DESC, t.t_canonical DESC",
+                compile_tsquery_sql(text)
             )
         })
         .unwrap_or_else(|| "t.t_canonical DESC NULLS LAST".to_string());
 
     let sql = format!(
-        "SELECT DISTINCT t.id::text AS id FROM frames t WHERE {where_sql} ORDER BY {order_by} LIMIT {target_limit}"
+        "SELECT t.id::text AS id FROM frames t WHERE {where_sql} ORDER BY {order_by} LIMIT {target_limit}"
     );
 
     let client = pool.get().await?;
@@ -179,6 +176,44 @@ async fn execute_during_query(
     Ok(keys)
 }
 
+/// The target's conditions in a DURING query, compiled for the target's `alias`.
+fn during_scope_sql(
+    alias: &str,
+    target_origin: &DataOrigin,
+    target_base_filter: Option<&Expression>,
+    during_terms: &[super::planner::DuringTermPlan],
+) -> String {
+    let mut where_clauses = Vec::new();
+    where_clauses.push(format!(
+        "({})",
+        compile_origin_scope_sql(alias, target_origin)
+    ));
+    if let Some(filter) = target_base_filter {
+        where_clauses.push(format!("({})", compile_expression_pg_sql(filter, alias)));
+    }
+
+    for term in during_terms {
+        let window_ms = term.window.num_milliseconds();
+        let mut source_exists_terms = Vec::new();
+
+        for source_plan in &term.source_plans {
+            let source_scope = compile_origin_scope_sql("s", &source_plan.source_origin);
+            let source_filter = compile_expression_pg_sql(&source_plan.filter, "s");
+            let expanded_overlap = format!(
+                "{alias}.time_range && tstzrange(lower(s.time_range) - interval '{window_ms} milliseconds', upper(s.time_range) + interval '{window_ms} milliseconds', '[]')"
+            );
+
+            source_exists_terms.push(format!(
+                "EXISTS (SELECT 1 FROM frames s WHERE ({source_scope}) AND ({source_filter}) AND ({expanded_overlap}))"
+            ));
+        }
+
+        where_clauses.push(format!("({})", source_exists_terms.join(" OR ")));
+    }
+
+    where_clauses.join(" AND ")
+}
+
 fn compile_expression_pg_sql(expr: &Expression, alias: &str) -> String {
     match expr {
         Expression::And(left, right) => format!(
@@ -200,26 +235,108 @@ fn compile_expression_pg_sql(expr: &Expression, alias: &str) -> String {
                 return "FALSE".to_string();
             }
             let field_ref = format!("{alias}.payload->>'{sanitized}'");
-            format!("{field_ref} = {}", compile_pg_value(value))
+            let plain = format!("{field_ref} = {}", compile_pg_value(value));
+            let token = crate::payload_crypto::configured()
+                .filter(|cipher| cipher.indexes(&sanitized))
+                .map(|cipher| cipher.eq_token(&sanitized, &pg_value_text(value)));
+            match token {
+                Some(token) => format!(
+                    "({plain}) OR ({})",
+                    compile_blind_index_sql(alias, &[token])
+                ),
+                None => plain,
+            }
         }
         Expression::Contains(_field, text) => {
+            let full_text = format!("{alias}.search_doc @@ {}", compile_tsquery_sql(text));
+            let tokens: Vec<String> = crate::payload_crypto::configured()
+                .map(|cipher| cipher.word_tokens(text).into_iter().collect())
+                .unwrap_or_default();
+            if tokens.is_empty() {
+                full_text
+            } else {
+                format!(
+                    "({full_text}) OR ({})",
+                    compile_blind_index_sql(alias, &tokens)
+                )
+            }
+        }
+        Expression::TimeRange(start, end) => {
+            let start = quote_string(&start.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true));
+            let end = quote_string(&end.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true));
+            // The t_canonical bounds follow from the overlap and let the planner skip the
+            // partitions of months outside the range.
             format!(
-                "{alias}.search_doc @@ websearch_to_tsquery('english', {})",
-                quote_string(text)
+                "({alias}.time_range && tstzrange({start}, {end}, '[)') \
+                 AND {alias}.t_canonical < {end} \
+                 AND {alias}.t_canonical >= {start}::timestamptz - frames_max_span())"
             )
         }
-        Expression::TimeRange(start, end) => format!(
-            "{alias}.time_range && tstzrange({}, {}, '[)')",
-            quote_string(&start.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true)),
-            quote_string(&end.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true))
-        ),
+        Expression::Entity { kind, value } => compile_entity_sql(kind.as_deref(), value, alias),
+        Expression::Pinned => {
+            format!("frame_pinned({alias}.id, {alias}.source_frame_id, {alias}.time_range)")
+        }
         Expression::Within { .. } | Expression::During { .. } | Expression::Overlaps { .. } => {
             "FALSE".to_string()
         }
     }
 }
 
-fn extract_search_text(expr: &Expression) -> Option<&str> {
+/// Parses search text once per enabled search language plus `simple` and ORs the results, so
+/// frames match whichever language their text was stemmed as.
+fn compile_tsquery_sql(text: &str) -> String {
+    let quoted = quote_string(text);
+    let parts: Vec<String> = crate::search_language::configured()
+        .query_configs()
+        .into_iter()
+        .map(|config| format!("websearch_to_tsquery('{config}', {quoted})"))
+        .collect();
+    format!("({})", parts.join(" || "))
+}
+
+/// Matches sealed payloads holding every one of `tokens`; see [`crate::payload_crypto`].
+fn compile_blind_index_sql(alias: &str, tokens: &[String]) -> String {
+    let tokens = tokens
+        .iter()
+        .map(|t| quote_string(t))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("{alias}.payload->'blind_index' ?& ARRAY[{tokens}]")
+}
+
+pub(super) fn compile_entity_sql(kind: Option<&str>, value: &str, alias: &str) -> String {
+    let kind = match kind {
+        Some(k) => match EntityKind::parse(k) {
+            Some(kind) => Some(kind),
+            None => {
+                tracing::warn!(kind = %k, "Rejected unknown entity kind in query");
+                return "FALSE".to_string();
+            }
+        },
+        None => None,
+    };
+    // Without a kind, match the value as any kind would have normalized it.
+    let values: BTreeSet<String> = match kind {
+        Some(kind) => BTreeSet::from([entities::normalize(kind, value)]),
+        None => EntityKind::ALL
+            .iter()
+            .map(|k| entities::normalize(*k, value))
+            .collect(),
+    };
+    let values = values
+        .iter()
+        .map(|v| quote_string(v))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let kind_clause = kind
+        .map(|k| format!(" AND e.kind = {}", quote_string(k.as_str())))
+        .unwrap_or_default();
+    format!(
+        "EXISTS (SELECT 1 FROM entity_mentions m JOIN entities e ON e.id = m.entity_id WHERE m.frame_id = {alias}.id AND e.value IN ({values}){kind_clause})"
+    )
+}
+
+pub(super) fn extract_search_text(expr: &Expression) -> Option<&str> {
     match expr {
         Expression::Contains(_, text) => Some(text.as_str()),
         Expression::And(left, right) | Expression::Or(left, right) => {
@@ -230,7 +347,7 @@ fn extract_search_text(expr: &Expression) -> Option<&str> {
     }
 }
 
-fn compile_origin_scope_sql(alias: &str, origin: &DataOrigin) -> String {
+pub(super) fn compile_origin_scope_sql(alias: &str, origin: &DataOrigin) -> String {
     let modality = &origin.modality_name;
     let modality_clause = format!("{alias}.modality = {}", quote_string(modality));
 
@@ -245,15 +362,20 @@ fn compile_origin_scope_sql(alias: &str, origin: &DataOrigin) -> String {
 }
 
 fn compile_pg_value(value: &Value) -> String {
+    quote_string(&pg_value_text(value))
+}
+
+/// The text `payload->>'field'` would have to equal for `value` to match.
+pub(super) fn pg_value_text(value: &Value) -> String {
     match value {
-        Value::String(s) => quote_string(s),
-        Value::Int(i) => quote_string(&i.to_string()),
-        Value::Float(f) => quote_string(&f.to_string()),
-        Value::Bool(b) => quote_string(&b.to_string()),
+        Value::String(s) => s.clone(),
+        Value::Int(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Bool(b) => b.to_string(),
     }
 }
 
-fn quote_string(s: &str) -> String {
+pub(super) fn quote_string(s: &str) -> String {
     format!("'{}'", s.replace('\'', "''"))
 }
 
@@ -270,9 +392,12 @@ const ALLOWED_PAYLOAD_FIELDS: &[&str] = &[
     "language",
     "confidence",
     "duration_ms",
+    "place",
+    "category",
+    "dominant_app",
 ];
 
-fn sanitize_identifier(input: &str) -> String {
+pub(super) fn sanitize_identifier(input: &str) -> String {
     let cleaned: String = input
         .chars()
         .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
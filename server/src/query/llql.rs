@@ -94,6 +94,12 @@ enum LlqlExpr {
         start: String,
         end: String,
     },
+    Entity {
+        value: String,
+        #[serde(default)]
+        kind: Option<String>,
+    },
+    Pinned,
 
     Within {
         stream: LlqlSelector,
@@ -155,6 +161,8 @@ impl LlqlExpr {
             LlqlExpr::Not { term } => Ok(ast::Expression::Not(Box::new(term.try_into_ast()?))),
             LlqlExpr::Eq { field, value } => Ok(ast::Expression::Eq(field, value.into_ast())),
             LlqlExpr::Contains { field, text } => Ok(ast::Expression::Contains(field, text)),
+            LlqlExpr::Entity { value, kind } => Ok(ast::Expression::Entity { kind, value }),
+            LlqlExpr::Pinned => Ok(ast::Expression::Pinned),
             LlqlExpr::TimeRange { start, end } => {
                 let start = parse_rfc3339_utc(&start)?;
                 let end = parse_rfc3339_utc(&end)?;
@@ -348,4 +356,43 @@ mod tests {
             _ => Err("expected During".into()),
         }
     }
+
+    #[test]
+    fn parses_entity_predicate() -> Result<(), Box<dyn std::error::Error>> {
+        let input = r#"llql:{
+          "target": {"type":"modality","modality":"Ocr"},
+          "filter": {"op":"entity","kind":"email","value":"jane@example.com"}
+        }"#;
+
+        let q = try_parse_llql(&[input.to_string()])?.ok_or("expected query")?;
+        assert_eq!(
+            q.filter,
+            ast::Expression::Entity {
+                kind: Some("email".to_string()),
+                value: "jane@example.com".to_string(),
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parses_pinned_predicate() -> Result<(), Box<dyn std::error::Error>> {
+        let input = r#"llql:{
+          "target": {"type":"modality","modality":"Screen"},
+          "filter": {"op":"and","terms":[{"op":"pinned"},{"op":"contains","field":"text","text":"roadmap"}]}
+        }"#;
+
+        let q = try_parse_llql(&[input.to_string()])?.ok_or("expected query")?;
+        assert_eq!(
+            q.filter,
+            ast::Expression::And(
+                Box::new(ast::Expression::Pinned),
+                Box::new(ast::Expression::Contains(
+                    "text".to_string(),
+                    "roadmap".to_string()
+                )),
+            )
+        );
+        Ok(())
+    }
 }
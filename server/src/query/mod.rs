@@ -3,3 +3,4 @@ pub mod executor;
 pub mod llql;
 pub mod natural;
 pub mod planner;
+pub(crate) mod sqlite;
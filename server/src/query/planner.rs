@@ -44,17 +44,26 @@ pub struct Planner;
 
 impl Planner {
     pub fn plan(query: &Query, available_origins: &[DataOrigin]) -> ExecutionPlan {
+        const DEFAULT_MAX_TARGET_UUIDS: usize = 1_000;
+
+        Self::plan_with_limit(query, available_origins, DEFAULT_MAX_TARGET_UUIDS)
+    }
+
+    /// Like `plan`, returning at most `max_target_uuids` frames per target origin.
+    pub fn plan_with_limit(
+        query: &Query,
+        available_origins: &[DataOrigin],
+        max_target_uuids: usize,
+    ) -> ExecutionPlan {
         let origins = Self::resolve_selector(&query.target, available_origins);
 
         if origins.is_empty() {
             return ExecutionPlan::MultiQuery(vec![]);
         }
 
-        const DEFAULT_MAX_TARGET_UUIDS: usize = 1_000;
-
         let plan_ctx = PlanContext {
             available_origins,
-            max_target_uuids: DEFAULT_MAX_TARGET_UUIDS,
+            max_target_uuids,
         };
 
         let plans = origins
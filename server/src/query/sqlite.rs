@@ -0,0 +1,476 @@
+//! Runs execution plans against the SQLite storage backend; see [`crate::storage::SqliteStore`].
+//!
+//! Mirrors [`super::executor`]: `frames_fts` stands in for `search_doc` and `frame_intervals`
+//! for `time_range`. Pins live in Postgres-only tables, so a plan using them fails.
+
+use super::ast::{Expression, Value};
+use super::executor::{
+    compile_entity_sql, compile_origin_scope_sql, extract_search_text, pg_value_text, quote_string,
+    sanitize_identifier,
+};
+use super::planner::{DuringTermPlan, ExecutionPlan};
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use lifelog_core::{DataOrigin, LifelogFrameKey};
+use rusqlite::Connection;
+use std::collections::BTreeSet;
+
+/// Keeps near-duplicate screenshots out of results when the frame they duplicate stands for
+/// them, i.e. when the original also matches `original_scope` (the query's conditions compiled
+/// for alias `o`). A duplicate whose original falls outside the query is kept.
+fn distinct_frames_sql(original_scope: &str) -> String {
+    format!(
+        "(json_type(t.payload, '$.duplicate_of') IS NULL OR NOT EXISTS (SELECT 1 FROM frames o WHERE o.id = json_extract(t.payload, '$.duplicate_of') AND {original_scope}))"
+    )
+}
+
+pub(crate) fn execute_sqlite(
+    conn: &Connection,
+    plan: ExecutionPlan,
+) -> Result<Vec<LifelogFrameKey>, anyhow::Error> {
+    match plan {
+        ExecutionPlan::TableQuery {
+            origin,
+            filter,
+            limit,
+            ..
+        } => execute_table_query(conn, origin, filter, limit),
+        ExecutionPlan::MultiQuery(plans) => {
+            let mut all_keys = Vec::new();
+            let mut seen = BTreeSet::new();
+            for subplan in plans {
+                for k in execute_sqlite(conn, subplan)? {
+                    let key = format!("{}:{}", k.origin.get_table_name(), k.uuid);
+                    if seen.insert(key) {
+                        all_keys.push(k);
+                    }
+                }
+            }
+            Ok(all_keys)
+        }
+        ExecutionPlan::DuringQuery {
+            target_origin,
+            target_base_filter,
+            during_terms,
+            target_limit,
+            ..
+        } => execute_during_query(
+            conn,
+            target_origin,
+            target_base_filter,
+            during_terms,
+            target_limit,
+        ),
+        ExecutionPlan::Unsupported(msg) => Err(anyhow!("Unsupported query plan: {}", msg)),
+    }
+}
+
+fn execute_table_query(
+    conn: &Connection,
+    origin: DataOrigin,
+    filter: Option<Expression>,
+    limit: usize,
+) -> Result<Vec<LifelogFrameKey>, anyhow::Error> {
+    if limit == 0 {
+        return Ok(vec![]);
+    }
+
+    let scope = |alias: &str| -> Result<String, anyhow::Error> {
+        let filter_sql = match filter.as_ref() {
+            Some(f) => compile_expression_sqlite(f, alias)?,
+            None => "TRUE".to_string(),
+        };
+        Ok(format!(
+            "({}) AND ({filter_sql})",
+            compile_origin_scope_sql(alias, &origin)
+        ))
+    };
+    let where_sql = format!("{} AND {}", scope("t")?, distinct_frames_sql(&scope("o")?));
+    let (rank_join, order_by) = compile_order_sql(filter.as_ref());
+
+    let sql = format!(
+        "SELECT t.id FROM frames t{rank_join} WHERE {where_sql} ORDER BY {order_by} LIMIT {limit}"
+    );
+    tracing::debug!("SQLite TableQuery SQL: {}", sql);
+    query_keys(conn, &sql, &origin)
+}
+
+fn execute_during_query(
+    conn: &Connection,
+    target_origin: DataOrigin,
+    target_base_filter: Option<Expression>,
+    during_terms: Vec<DuringTermPlan>,
+    target_limit: usize,
+) -> Result<Vec<LifelogFrameKey>, anyhow::Error> {
+    if target_limit == 0 {
+        return Ok(vec![]);
+    }
+
+    if during_terms.iter().any(|term| term.source_plans.is_empty()) {
+        return Ok(vec![]);
+    }
+    let scope = |alias: &str| {
+        during_scope_sql(
+            alias,
+            &target_origin,
+            target_base_filter.as_ref(),
+            &during_terms,
+        )
+    };
+    let where_sql = format!("{} AND {}", scope("t")?, distinct_frames_sql(&scope("o")?));
+    let (rank_join, order_by) = compile_order_sql(target_base_filter.as_ref());
+
+    let sql = format!(
+        "SELECT t.id FROM frames t{rank_join} WHERE {where_sql} ORDER BY {order_by} LIMIT {target_limit}"
+    );
+    query_keys(conn, &sql, &target_origin)
+}
+
+/// The target's conditions in a DURING query, compiled for the target's `alias`.
+fn during_scope_sql(
+    alias: &str,
+    target_origin: &DataOrigin,
+    target_base_filter: Option<&Expression>,
+    during_terms: &[DuringTermPlan],
+) -> Result<String, anyhow::Error> {
+    let mut where_clauses = Vec::new();
+    where_clauses.push(format!(
+        "({})",
+        compile_origin_scope_sql(alias, target_origin)
+    ));
+    if let Some(filter) = target_base_filter {
+        where_clauses.push(format!("({})", compile_expression_sqlite(filter, alias)?));
+    }
+
+    for term in during_terms {
+        let window_us = term.window.num_microseconds().unwrap_or(i64::MAX / 4);
+        let mut source_exists_terms = Vec::new();
+
+        for source_plan in &term.source_plans {
+            let source_scope = compile_origin_scope_sql("s", &source_plan.source_origin);
+            let source_filter = compile_expression_sqlite(&source_plan.filter, "s")?;
+            // The source's range widened by the window overlaps the target's; both closed.
+            let lo = format!("{alias}.t_canonical - {window_us}");
+            let hi = format!("COALESCE({alias}.t_end, {alias}.t_canonical) + {window_us}");
+            let expanded_overlap = format!(
+                "si.t_start <= ({hi}) / 1e6 + 1 AND si.t_stop >= ({lo}) / 1e6 - 1 AND s.t_canonical <= {hi} AND COALESCE(s.t_end, s.t_canonical) >= {lo}"
+            );
+
+            source_exists_terms.push(format!(
+                "EXISTS (SELECT 1 FROM frames s JOIN frame_intervals si ON si.seq = s.seq WHERE ({source_scope}) AND ({source_filter}) AND {expanded_overlap})"
+            ));
+        }
+
+        where_clauses.push(format!("({})", source_exists_terms.join(" OR ")));
+    }
+
+    Ok(where_clauses.join(" AND "))
+}
+
+fn query_keys(
+    conn: &Connection,
+    sql: &str,
+    origin: &DataOrigin,
+) -> Result<Vec<LifelogFrameKey>, anyhow::Error> {
+    let mut stmt = conn.prepare(sql)?;
+    let ids = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut keys = Vec::new();
+    let mut seen = BTreeSet::new();
+    for id in ids {
+        if let Ok(uuid) = id.parse::<lifelog_core::uuid::Uuid>() {
+            if seen.insert(uuid) {
+                keys.push(LifelogFrameKey {
+                    uuid,
+                    origin: origin.clone(),
+                });
+            }
+        }
+    }
+    Ok(keys)
+}
+
+/// Ranks full-text matches first, best first, the way the Postgres executor orders by
+/// `ts_rank`; otherwise newest first.
+fn compile_order_sql(filter: Option<&Expression>) -> (String, String) {
+    let query = filter.and_then(extract_search_text).and_then(fts_query);
+    match query {
+        Some(query) => (
+            format!(
+                " LEFT JOIN (SELECT rowid AS seq, bm25(frames_fts, 1.0, 0.4, 0.2) AS rank FROM frames_fts WHERE frames_fts MATCH {}) r ON r.seq = t.seq",
+                quote_string(&query)
+            ),
+            "r.rank ASC NULLS LAST, t.t_canonical DESC".to_string(),
+        ),
+        None => (String::new(), "t.t_canonical DESC".to_string()),
+    }
+}
+
+/// Compiles a filter to a SQLite condition. Fails on predicates this backend cannot answer,
+/// rather than matching nothing.
+fn compile_expression_sqlite(expr: &Expression, alias: &str) -> Result<String, anyhow::Error> {
+    let sql = match expr {
+        Expression::And(left, right) => format!(
+            "({}) AND ({})",
+            compile_expression_sqlite(left, alias)?,
+            compile_expression_sqlite(right, alias)?
+        ),
+        Expression::Or(left, right) => format!(
+            "({}) OR ({})",
+            compile_expression_sqlite(left, alias)?,
+            compile_expression_sqlite(right, alias)?
+        ),
+        Expression::Not(inner) => {
+            format!("NOT ({})", compile_expression_sqlite(inner, alias)?)
+        }
+        Expression::Eq(field, value) => {
+            let sanitized = sanitize_identifier(field);
+            if sanitized.is_empty() {
+                return Ok("FALSE".to_string());
+            }
+            let plain = format!(
+                "{} = {}",
+                payload_text_sql(alias, &sanitized),
+                compile_value(value)
+            );
+            let token = crate::payload_crypto::configured()
+                .filter(|cipher| cipher.indexes(&sanitized))
+                .map(|cipher| cipher.eq_token(&sanitized, &pg_value_text(value)));
+            match token {
+                Some(token) => format!(
+                    "({plain}) OR ({})",
+                    compile_blind_index_sql(alias, &BTreeSet::from([token]))
+                ),
+                None => plain,
+            }
+        }
+        Expression::Contains(_field, text) => {
+            let full_text = match fts_query(text) {
+                Some(query) => format!(
+                    "{alias}.seq IN (SELECT rowid FROM frames_fts WHERE frames_fts MATCH {})",
+                    quote_string(&query)
+                ),
+                None => "FALSE".to_string(),
+            };
+            let tokens: BTreeSet<String> = crate::payload_crypto::configured()
+                .map(|cipher| cipher.word_tokens(text).into_iter().collect())
+                .unwrap_or_default();
+            if tokens.is_empty() {
+                full_text
+            } else {
+                format!(
+                    "({full_text}) OR ({})",
+                    compile_blind_index_sql(alias, &tokens)
+                )
+            }
+        }
+        Expression::TimeRange(start, end) => compile_time_range_sql(alias, *start, *end),
+        Expression::Entity { kind, value } => compile_entity_sql(kind.as_deref(), value, alias),
+        Expression::Pinned => {
+            return Err(anyhow!(
+            "pinned frames cannot be queried on SQLite: pins require the Postgres storage backend"
+        ))
+        }
+        Expression::Within { .. } | Expression::During { .. } | Expression::Overlaps { .. } => {
+            "FALSE".to_string()
+        }
+    };
+    Ok(sql)
+}
+
+/// Overlap of the frame's closed range with `[start, end)`. The interval index narrows the
+/// candidates; the frame columns decide.
+fn compile_time_range_sql(alias: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+    let start_us = start.timestamp_micros();
+    let end_us = end.timestamp_micros();
+    if start_us >= end_us {
+        return "FALSE".to_string();
+    }
+    format!(
+        "{alias}.seq IN (SELECT seq FROM frame_intervals WHERE t_start <= {} AND t_stop >= {}) AND {alias}.t_canonical < {end_us} AND COALESCE({alias}.t_end, {alias}.t_canonical) >= {start_us}",
+        end_us.div_euclid(1_000_000) + 1,
+        start_us.div_euclid(1_000_000) - 1
+    )
+}
+
+/// The text `payload->>'field'` gives in Postgres: JSON booleans as `true`/`false`, numbers
+/// and strings as their text.
+fn payload_text_sql(alias: &str, field: &str) -> String {
+    let path = format!("'$.{field}'");
+    format!(
+        "(CASE json_type({alias}.payload, {path}) WHEN 'true' THEN 'true' WHEN 'false' THEN 'false' ELSE CAST(json_extract({alias}.payload, {path}) AS TEXT) END)"
+    )
+}
+
+fn compile_value(value: &Value) -> String {
+    quote_string(&pg_value_text(value))
+}
+
+/// Matches sealed payloads holding every one of `tokens`; see [`crate::payload_crypto`].
+fn compile_blind_index_sql(alias: &str, tokens: &BTreeSet<String>) -> String {
+    let list = tokens
+        .iter()
+        .map(|t| quote_string(t))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "(SELECT COUNT(DISTINCT j.value) FROM json_each({alias}.payload, '$.blind_index') j WHERE j.value IN ({list})) = {}",
+        tokens.len()
+    )
+}
+
+/// Translates web search syntax, as Postgres' `websearch_to_tsquery` reads it, into an FTS5
+/// query: words and `"quoted phrases"` must all match, `or` separates alternatives and a
+/// leading `-` excludes a term. Returns `None` when nothing searchable is left, which matches
+/// no frame.
+fn fts_query(text: &str) -> Option<String> {
+    let mut groups: Vec<Vec<String>> = vec![Vec::new()];
+    let mut excluded = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let negated = c == '-';
+        if negated {
+            chars.next();
+        }
+        let (term, quoted) = if chars.peek() == Some(&'"') {
+            chars.next();
+            let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+            (phrase, true)
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            (word, false)
+        };
+
+        if !quoted && !negated && term.eq_ignore_ascii_case("or") {
+            if groups.last().is_some_and(|g| !g.is_empty()) {
+                groups.push(Vec::new());
+            }
+            continue;
+        }
+        if !term.chars().any(char::is_alphanumeric) {
+            continue;
+        }
+        let phrase = format!("\"{}\"", term.replace('"', "\"\""));
+        if negated {
+            excluded.push(phrase);
+        } else if let Some(group) = groups.last_mut() {
+            group.push(phrase);
+        }
+    }
+
+    let alternatives: Vec<String> = groups
+        .into_iter()
+        .filter(|g| !g.is_empty())
+        .map(|g| format!("({})", g.join(" AND ")))
+        .collect();
+    if alternatives.is_empty() {
+        return None;
+    }
+    let query = alternatives.join(" OR ");
+    if excluded.is_empty() {
+        Some(query)
+    } else {
+        Some(format!("({query}) NOT ({})", excluded.join(" OR ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lifelog_core::DataOriginType;
+
+    fn open() -> anyhow::Result<Connection> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute_batch(include_str!(
+            "../../migrations/sqlite/20260327000000_init_sqlite.sql"
+        ))?;
+        conn.execute_batch(include_str!(
+            "../../migrations/sqlite/20260328000000_transform_state.sql"
+        ))?;
+        Ok(conn)
+    }
+
+    fn plan(filter: Expression) -> ExecutionPlan {
+        ExecutionPlan::TableQuery {
+            origin: DataOrigin::new(DataOriginType::DeviceId("laptop".into()), "Ocr".into()),
+            filter: Some(filter),
+            limit: 10,
+        }
+    }
+
+    #[test]
+    fn entity_predicates_match_mentioned_frames() -> anyhow::Result<()> {
+        let conn = open()?;
+        let id = lifelog_core::uuid::Uuid::new_v4().to_string();
+        conn.execute_batch(&format!(
+            "INSERT INTO frames (id, collector_id, stream_id, modality, t_ingest, t_canonical, time_quality, payload)
+             VALUES ('{id}', 'laptop', 'ocr', 'Ocr', 1, 1, 'good', '{{}}');
+             INSERT INTO entities (id, kind, value, display, first_seen, last_seen)
+             VALUES (1, 'url', 'https://example.com', 'https://example.com/', 1, 1);
+             INSERT INTO entity_mentions (entity_id, frame_id, transform_id, field, start_offset, end_offset, t_canonical)
+             VALUES (1, '{id}', 'entity-extract-ocr', 'text', 0, 20, 1);"
+        ))?;
+
+        let entity = |kind: &str| Expression::Entity {
+            kind: Some(kind.to_string()),
+            value: "https://Example.com/".to_string(),
+        };
+        let keys = execute_sqlite(&conn, plan(entity("url")))?;
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].uuid.to_string(), id);
+        assert!(execute_sqlite(&conn, plan(entity("email")))?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn duplicates_show_when_their_original_is_outside_the_query() -> anyhow::Result<()> {
+        let conn = open()?;
+        let original = lifelog_core::uuid::Uuid::new_v4().to_string();
+        let duplicate = lifelog_core::uuid::Uuid::new_v4().to_string();
+        conn.execute_batch(&format!(
+            "INSERT INTO frames (id, collector_id, stream_id, modality, t_ingest, t_canonical, time_quality, payload)
+             VALUES ('{original}', 'laptop', 'screen', 'Screen', 1, 1000000, 'good', '{{}}'),
+                    ('{duplicate}', 'laptop', 'screen', 'Screen', 1, 120000000, 'good',
+                     '{{\"duplicate_of\": \"{original}\"}}');"
+        ))?;
+
+        let between = |start: i64, end: i64| -> anyhow::Result<Vec<String>> {
+            let range =
+                |secs| DateTime::from_timestamp(secs, 0).ok_or_else(|| anyhow!("bad timestamp"));
+            let plan = ExecutionPlan::TableQuery {
+                origin: DataOrigin::new(DataOriginType::DeviceId("laptop".into()), "Screen".into()),
+                filter: Some(Expression::TimeRange(range(start)?, range(end)?)),
+                limit: 10,
+            };
+            Ok(execute_sqlite(&conn, plan)?
+                .into_iter()
+                .map(|k| k.uuid.to_string())
+                .collect())
+        };
+        assert_eq!(between(0, 200)?, vec![original.clone()]);
+        assert_eq!(between(60, 200)?, vec![duplicate]);
+        Ok(())
+    }
+
+    #[test]
+    fn pinned_predicates_are_rejected() -> anyhow::Result<()> {
+        let conn = open()?;
+        let filter = Expression::Not(Box::new(Expression::Pinned));
+        assert!(execute_sqlite(&conn, plan(filter)).is_err());
+        Ok(())
+    }
+}
@@ -1,41 +1,80 @@
 use std::collections::{HashMap, HashSet};
 
 use chrono::{DateTime, Duration, Utc};
+use config::RetentionTier;
+use deadpool_postgres::Object;
+use lifelog_core::uuid::Uuid;
 use lifelog_core::LifelogError;
 use utils::cas::FsCas;
 
 use crate::postgres::PostgresPool;
+use crate::storage::SqliteStore;
+
+/// Excludes pinned frames, their lineage and overlapping pinned ranges, as recorded by
+/// [`snapshot_pins`] on the same connection. Every query that deletes or degrades frames must
+/// include it.
+pub(crate) const NOT_PINNED: &str =
+    "(NOT EXISTS (SELECT 1 FROM retention_pinned_ids WHERE pinned_id = id)
+      AND NOT EXISTS (SELECT 1 FROM retention_pinned_ranges WHERE pinned_range && time_range))";
+
+/// Upload chunk records are kept this long for resumption and ACKs.
+const UPLOAD_CHUNK_TTL_DAYS: i64 = 90;
+/// Frames updated per statement when dropping blobs or deleting thinned frames.
+const TIER_BATCH: usize = 5_000;
+/// Frames fetched per round of re-encoding; each one is decoded and encoded in memory.
+const REENCODE_BATCH: i64 = 200;
 
 #[derive(Debug, Default, Clone)]
 pub struct RetentionRunSummary {
     pub deleted_records: u64,
     pub deleted_blobs: u64,
+    /// Raw frames removed by thinning tiers.
+    pub thinned_records: u64,
+    /// Frames whose blob a tier removed while keeping the frame.
+    pub dropped_blobs: u64,
+    pub reencoded_blobs: u64,
 }
 
 pub async fn prune_once(
     pool: &PostgresPool,
     cas: &FsCas,
     retention_policy_days: &HashMap<String, u32>,
+    tiers: &[RetentionTier],
     now: DateTime<Utc>,
 ) -> Result<RetentionRunSummary, LifelogError> {
     let normalized = normalize_policy_map(retention_policy_days);
-    if normalized.is_empty() {
+    if normalized.is_empty() && tiers.is_empty() {
         return Ok(RetentionRunSummary::default());
     }
 
-    let client = pool
+    let mut client = pool
         .get()
         .await
         .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    snapshot_pins(&client).await?;
+
+    let mut candidate_hashes = HashSet::new();
+    let mut summary = RetentionRunSummary::default();
+
+    // Whole months that have expired go first, as one partition drop each.
+    let partition_frames = crate::partitions::drop_expired(
+        &mut client,
+        now,
+        |modality| {
+            ttl_days_for_modality(&normalized, &modality.to_lowercase())
+                .filter(|days| *days > 0)
+                .map(|days| now - Duration::days(i64::from(days)))
+        },
+        &mut candidate_hashes,
+    )
+    .await?;
+    summary.deleted_records = summary.deleted_records.saturating_add(partition_frames);
 
     let modality_rows = client
         .query("SELECT DISTINCT modality FROM frames", &[])
         .await
         .map_err(|e| LifelogError::Database(format!("retention modality query: {e}")))?;
 
-    let mut candidate_hashes = HashSet::new();
-    let mut summary = RetentionRunSummary::default();
-
     for row in modality_rows {
         let modality: String = row.get(0);
         let lower_modality = modality.to_lowercase();
@@ -48,7 +87,10 @@ pub async fn prune_once(
 
         let count_row = client
             .query_one(
-                "SELECT COUNT(*) AS count FROM frames WHERE modality = $1 AND t_canonical < $2",
+                &format!(
+                    "SELECT COUNT(*) AS count FROM frames
+                     WHERE modality = $1 AND t_canonical < $2 AND {NOT_PINNED}"
+                ),
                 &[&modality, &cutoff],
             )
             .await
@@ -60,7 +102,11 @@ pub async fn prune_once(
 
         let blob_rows = client
             .query(
-                "SELECT blob_hash FROM frames WHERE modality = $1 AND t_canonical < $2 AND blob_hash IS NOT NULL",
+                &format!(
+                    "SELECT blob_hash FROM frames
+                     WHERE modality = $1 AND t_canonical < $2 AND blob_hash IS NOT NULL
+                       AND {NOT_PINNED}"
+                ),
                 &[&modality, &cutoff],
             )
             .await
@@ -75,7 +121,9 @@ pub async fn prune_once(
 
         client
             .execute(
-                "DELETE FROM frames WHERE modality = $1 AND t_canonical < $2",
+                &format!(
+                    "DELETE FROM frames WHERE modality = $1 AND t_canonical < $2 AND {NOT_PINNED}"
+                ),
                 &[&modality, &cutoff],
             )
             .await
@@ -84,6 +132,32 @@ pub async fn prune_once(
         summary.deleted_records = summary.deleted_records.saturating_add(stale_count as u64);
     }
 
+    let modalities: Vec<String> = client
+        .query("SELECT DISTINCT modality FROM frames", &[])
+        .await
+        .map_err(|e| LifelogError::Database(format!("retention modality query: {e}")))?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    for tier in tiers {
+        let cutoff = now - Duration::days(i64::from(tier.after_days));
+        for modality in modalities
+            .iter()
+            .filter(|m| m.eq_ignore_ascii_case(tier.modality.trim()))
+        {
+            apply_tier(
+                &client,
+                cas,
+                tier,
+                modality,
+                cutoff,
+                &mut candidate_hashes,
+                &mut summary,
+            )
+            .await?;
+        }
+    }
+
     for hash in candidate_hashes {
         let ref_row = client
             .query_one("SELECT COUNT(*) FROM frames WHERE blob_hash = $1", &[&hash])
@@ -114,8 +188,311 @@ pub async fn prune_once(
     Ok(summary)
 }
 
+/// Copies the pinned frame ids and ranges into temporary tables read by [`NOT_PINNED`], so the
+/// lineage is walked once per run instead of once per candidate row. Pins added while a run is
+/// in progress apply from the next run.
+async fn snapshot_pins(client: &Object) -> Result<(), LifelogError> {
+    client
+        .batch_execute(
+            "CREATE TEMP TABLE IF NOT EXISTS retention_pinned_ids (pinned_id UUID PRIMARY KEY);
+             CREATE TEMP TABLE IF NOT EXISTS retention_pinned_ranges (pinned_range TSTZRANGE NOT NULL);
+             TRUNCATE retention_pinned_ids, retention_pinned_ranges;
+             INSERT INTO retention_pinned_ids SELECT DISTINCT * FROM pinned_frame_ids();
+             INSERT INTO retention_pinned_ranges
+                 SELECT time_range FROM pins WHERE time_range IS NOT NULL;
+             ANALYZE retention_pinned_ids, retention_pinned_ranges;",
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("retention pin snapshot: {e}")))
+}
+
+/// [`prune_once`] for the SQLite backend: deletes frames past their modality's
+/// `retentionPolicyDays`, then the blobs and upload chunk records nothing references any more.
+/// Tiers and pins need Postgres; the server refuses to start with tiers on SQLite.
+pub async fn prune_sqlite(
+    store: &SqliteStore,
+    cas: &FsCas,
+    retention_policy_days: &HashMap<String, u32>,
+    now: DateTime<Utc>,
+) -> Result<RetentionRunSummary, LifelogError> {
+    let normalized = normalize_policy_map(retention_policy_days);
+    if normalized.is_empty() {
+        return Ok(RetentionRunSummary::default());
+    }
+
+    let mut candidate_hashes = HashSet::new();
+    let mut summary = RetentionRunSummary::default();
+    for modality in store.modalities().await? {
+        let ttl_days = match ttl_days_for_modality(&normalized, &modality.to_lowercase()) {
+            Some(days) if days > 0 => days,
+            _ => continue,
+        };
+        let cutoff = now - Duration::days(i64::from(ttl_days));
+        let (deleted, hashes) = store.delete_before(&modality, cutoff).await?;
+        summary.deleted_records = summary.deleted_records.saturating_add(deleted);
+        candidate_hashes.extend(hashes);
+    }
+
+    for hash in candidate_hashes {
+        if store.blob_referenced(&hash).await? {
+            continue;
+        }
+        match cas.remove(&hash) {
+            Ok(()) => {
+                summary.deleted_blobs = summary.deleted_blobs.saturating_add(1);
+            }
+            Err(e) => {
+                tracing::warn!(hash = %hash, error = %e, "failed to remove orphan CAS blob");
+            }
+        }
+    }
+
+    let chunks_deleted = store
+        .prune_upload_chunks(now - Duration::days(UPLOAD_CHUNK_TTL_DAYS))
+        .await?;
+    if chunks_deleted > 0 {
+        tracing::info!(deleted = chunks_deleted, "Pruned old upload_chunks");
+    }
+    summary.deleted_records = summary.deleted_records.saturating_add(chunks_deleted);
+
+    Ok(summary)
+}
+
+/// Applies one tier's actions to the raw frames of `modality` captured before `cutoff`. Blobs
+/// the tier stops referencing are added to `candidate_hashes`.
+async fn apply_tier(
+    client: &Object,
+    cas: &FsCas,
+    tier: &RetentionTier,
+    modality: &str,
+    cutoff: DateTime<Utc>,
+    candidate_hashes: &mut HashSet<String>,
+    summary: &mut RetentionRunSummary,
+) -> Result<(), LifelogError> {
+    if let Some(secs) = tier.keep_one_every_secs {
+        thin(client, modality, cutoff, secs, candidate_hashes, summary).await?;
+    }
+    if tier.drop_blob {
+        loop {
+            let rows = client
+                .query(
+                    &format!(
+                        "WITH doomed AS (
+                            SELECT id, blob_hash FROM frames
+                            WHERE modality = $1 AND source_frame_id IS NULL AND t_canonical < $2
+                              AND blob_hash IS NOT NULL AND {NOT_PINNED}
+                            LIMIT $3
+                            FOR UPDATE
+                        )
+                        UPDATE frames f SET blob_hash = NULL, blob_size = NULL
+                        FROM doomed d WHERE f.id = d.id
+                        RETURNING d.blob_hash"
+                    ),
+                    &[&modality, &cutoff, &(TIER_BATCH as i64)],
+                )
+                .await
+                .map_err(|e| LifelogError::Database(format!("retention drop blobs: {e}")))?;
+            summary.dropped_blobs = summary.dropped_blobs.saturating_add(rows.len() as u64);
+            candidate_hashes.extend(rows.iter().map(|row| row.get::<_, String>(0)));
+            if rows.len() < TIER_BATCH {
+                break;
+            }
+        }
+    } else if let Some(quality) = tier.reencode_quality {
+        reencode(
+            client,
+            cas,
+            modality,
+            cutoff,
+            quality,
+            candidate_hashes,
+            summary,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Keeps the first frame per stream and `secs`-long interval, preferring originals over
+/// near-duplicates and then frames that still have their blob. A surplus frame that other frames
+/// were derived from or name in `duplicate_of` is kept with its blob dropped, so neither link
+/// dangles.
+async fn thin(
+    client: &Object,
+    modality: &str,
+    cutoff: DateTime<Utc>,
+    secs: u32,
+    candidate_hashes: &mut HashSet<String>,
+    summary: &mut RetentionRunSummary,
+) -> Result<(), LifelogError> {
+    let secs = i32::try_from(secs).unwrap_or(i32::MAX);
+    let rows = client
+        .query(
+            &format!(
+                "WITH ranked AS (
+                SELECT id, source_frame_id, time_range, blob_hash,
+                       row_number() OVER (
+                           PARTITION BY collector_id, stream_id,
+                                        floor(extract(epoch FROM t_canonical) / $3::INTEGER)
+                           ORDER BY payload ? 'duplicate_of', blob_hash IS NULL, t_canonical, id
+                       ) AS rn
+                FROM frames
+                WHERE modality = $1 AND source_frame_id IS NULL AND t_canonical < $2
+            ), surplus AS (
+                SELECT id, blob_hash,
+                       EXISTS (SELECT 1 FROM frames d WHERE d.source_frame_id = ranked.id)
+                       OR EXISTS (
+                           SELECT 1 FROM frames d
+                           WHERE d.payload ? 'duplicate_of'
+                             AND d.payload->>'duplicate_of' = ranked.id::text
+                       ) AS referenced
+                FROM ranked
+                WHERE rn > 1 AND {NOT_PINNED}
+            )
+            SELECT id, blob_hash, referenced FROM surplus
+            WHERE blob_hash IS NOT NULL OR NOT referenced"
+            ),
+            &[&modality, &cutoff, &secs],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("retention thin query: {e}")))?;
+
+    let mut delete = Vec::new();
+    let mut strip = Vec::new();
+    for row in rows {
+        let id: Uuid = row.get(0);
+        if let Some(hash) = row.get::<_, Option<String>>(1) {
+            candidate_hashes.insert(hash);
+        }
+        if row.get::<_, bool>(2) {
+            strip.push(id);
+        } else {
+            delete.push(id);
+        }
+    }
+    // References are re-checked in case any appeared since the query above.
+    for ids in delete.chunks(TIER_BATCH) {
+        let deleted = client
+            .execute(
+                &format!(
+                    "DELETE FROM frames f WHERE f.id = ANY($1)
+                       AND NOT EXISTS (SELECT 1 FROM frames d WHERE d.source_frame_id = f.id)
+                       AND NOT EXISTS (
+                           SELECT 1 FROM frames d
+                           WHERE d.payload ? 'duplicate_of' AND d.payload->>'duplicate_of' = f.id::text
+                       )
+                       AND {NOT_PINNED}"
+                ),
+                &[&ids],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("retention thin delete: {e}")))?;
+        summary.thinned_records = summary.thinned_records.saturating_add(deleted);
+    }
+    for ids in strip.chunks(TIER_BATCH) {
+        let stripped = client
+            .execute(
+                &format!(
+                    "UPDATE frames SET blob_hash = NULL, blob_size = NULL
+                     WHERE id = ANY($1) AND {NOT_PINNED}"
+                ),
+                &[&ids],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("retention thin strip: {e}")))?;
+        summary.dropped_blobs = summary.dropped_blobs.saturating_add(stripped);
+    }
+    Ok(())
+}
+
+/// Re-encodes image blobs as JPEG at `quality`. Frames are marked with the quality even when the
+/// blob is not an image or would not shrink, so each frame is tried once per tier.
+async fn reencode(
+    client: &Object,
+    cas: &FsCas,
+    modality: &str,
+    cutoff: DateTime<Utc>,
+    quality: u8,
+    candidate_hashes: &mut HashSet<String>,
+    summary: &mut RetentionRunSummary,
+) -> Result<(), LifelogError> {
+    let quality_sql = i16::from(quality);
+    loop {
+        let rows = client
+            .query(
+                &format!(
+                    "SELECT id, blob_hash FROM frames
+                     WHERE modality = $1 AND source_frame_id IS NULL AND t_canonical < $2
+                       AND blob_hash IS NOT NULL
+                       AND (reencoded_quality IS NULL OR reencoded_quality > $3)
+                       AND {NOT_PINNED}
+                     ORDER BY t_canonical
+                     LIMIT $4"
+                ),
+                &[&modality, &cutoff, &quality_sql, &REENCODE_BATCH],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("retention reencode query: {e}")))?;
+        let done = (rows.len() as i64) < REENCODE_BATCH;
+        for row in rows {
+            let id: Uuid = row.get(0);
+            let hash: String = row.get(1);
+            let smaller = match cas.get(&hash) {
+                Ok(bytes) => tokio::task::spawn_blocking(move || reencode_jpeg(&bytes, quality))
+                    .await
+                    .ok()
+                    .flatten(),
+                Err(e) => {
+                    tracing::warn!(hash = %hash, error = %e, "retention reencode: blob unreadable");
+                    None
+                }
+            };
+            let Some(jpeg) = smaller else {
+                client
+                    .execute(
+                        "UPDATE frames SET reencoded_quality = $2 WHERE id = $1",
+                        &[&id, &quality_sql],
+                    )
+                    .await
+                    .map_err(|e| LifelogError::Database(format!("retention reencode mark: {e}")))?;
+                continue;
+            };
+            let new_hash = cas
+                .put(&jpeg)
+                .map_err(|e| LifelogError::Database(format!("retention reencode put: {e}")))?;
+            client
+                .execute(
+                    "UPDATE frames SET blob_hash = $2, blob_size = $3, reencoded_quality = $4,
+                            payload = CASE WHEN payload ? 'mime_type'
+                                           THEN jsonb_set(payload, '{mime_type}', '\"image/jpeg\"')
+                                           ELSE payload END
+                     WHERE id = $1",
+                    &[&id, &new_hash, &(jpeg.len() as i32), &quality_sql],
+                )
+                .await
+                .map_err(|e| LifelogError::Database(format!("retention reencode update: {e}")))?;
+            candidate_hashes.insert(hash);
+            summary.reencoded_blobs = summary.reencoded_blobs.saturating_add(1);
+        }
+        if done {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// JPEG encoding of an image at `quality`; `None` when the bytes are not a decodable image or
+/// the result would not be smaller.
+fn reencode_jpeg(bytes: &[u8], quality: u8) -> Option<Vec<u8>> {
+    let image = image::load_from_memory(bytes).ok()?;
+    let mut out = Vec::new();
+    let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, quality);
+    image.to_rgb8().write_with_encoder(encoder).ok()?;
+    (out.len() < bytes.len()).then_some(out)
+}
+
 async fn prune_upload_chunks(pool: &PostgresPool, now: DateTime<Utc>) -> Result<u64, LifelogError> {
-    let cutoff = now - Duration::days(90);
+    let cutoff = now - Duration::days(UPLOAD_CHUNK_TTL_DAYS);
     let client = pool
         .get()
         .await
@@ -172,7 +549,7 @@ fn is_text_modality(modality: &str) -> bool {
 mod tests {
     use std::collections::HashMap;
 
-    use super::ttl_days_for_modality;
+    use super::{reencode_jpeg, ttl_days_for_modality};
 
     #[test]
     fn resolves_direct_and_text_bucket() {
@@ -184,4 +561,32 @@ mod tests {
         assert_eq!(ttl_days_for_modality(&policy, "ocr"), Some(30));
         assert_eq!(ttl_days_for_modality(&policy, "audio"), None);
     }
+
+    #[test]
+    #[allow(clippy::panic)]
+    fn reencodes_images_only_when_smaller() {
+        let noisy = image::RgbImage::from_fn(256, 256, |x, y| {
+            let v = (x.wrapping_mul(7919) ^ y.wrapping_mul(104_729)) as u8;
+            image::Rgb([v, v.wrapping_add(85), v.wrapping_add(170)])
+        });
+        let mut png = std::io::Cursor::new(Vec::new());
+        if image::DynamicImage::ImageRgb8(noisy)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .is_err()
+        {
+            panic!("png encode");
+        }
+        let png = png.into_inner();
+
+        let Some(jpeg) = reencode_jpeg(&png, 40) else {
+            panic!("noisy png should shrink as jpeg");
+        };
+        assert!(jpeg.len() < png.len());
+        assert!(matches!(
+            image::guess_format(&jpeg),
+            Ok(image::ImageFormat::Jpeg)
+        ));
+        assert_eq!(reencode_jpeg(&jpeg, 95), None);
+        assert_eq!(reencode_jpeg(b"not an image", 40), None);
+    }
 }
@@ -0,0 +1,515 @@
+use std::collections::{HashMap, HashSet};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Duration, Utc};
+use lifelog_core::LifelogError;
+use utils::cas::{CasError, FsCas};
+
+use crate::postgres::PostgresPool;
+
+/// Shard directories in the CAS: one per leading byte of the hash.
+pub const SHARDS: u32 = 256;
+
+/// How much of the CAS one scheduled run covers and how old an unreferenced blob must be before
+/// it is collected. The grace period covers blobs written just ahead of the frame that refers to
+/// them.
+#[derive(Debug, Clone)]
+pub struct ScrubOptions {
+    pub shards_per_run: u32,
+    pub orphan_grace: Duration,
+}
+
+impl Default for ScrubOptions {
+    fn default() -> Self {
+        Self {
+            shards_per_run: 16,
+            orphan_grace: Duration::hours(24),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ScrubRunSummary {
+    pub shards_scanned: u32,
+    pub blobs_verified: u64,
+    pub bytes_verified: u64,
+    pub blobs_unverified: u64,
+    pub corrupt_blobs: u64,
+    pub missing_blobs: u64,
+    pub orphans_deleted: u64,
+    pub pass_completed: bool,
+    /// Pack files rewritten to reclaim the space of removed blobs.
+    pub packs_compacted: u64,
+    pub bytes_reclaimed: u64,
+}
+
+/// What happened to one blob found on disk.
+#[derive(Debug, Clone, PartialEq)]
+enum BlobOutcome {
+    Verified(u64),
+    Corrupt(String),
+    /// Encrypted, but the server has no key to decrypt it with.
+    Unverifiable,
+    /// Unreferenced and past the grace period; collected once the references are checked again.
+    Orphan,
+    Collected,
+}
+
+/// Marks `hash` as an orphan when nothing references it and it is older than the grace period,
+/// otherwise verifies it.
+fn check_blob(
+    cas: &FsCas,
+    hash: &str,
+    modified: SystemTime,
+    referenced: bool,
+    grace_cutoff: SystemTime,
+) -> BlobOutcome {
+    if !referenced && modified < grace_cutoff {
+        return BlobOutcome::Orphan;
+    }
+    verify_blob(cas, hash)
+}
+
+fn verify_blob(cas: &FsCas, hash: &str) -> BlobOutcome {
+    match cas.verify(hash) {
+        Ok(size) => BlobOutcome::Verified(size),
+        Err(CasError::MissingKey | CasError::UnknownKey(_)) => BlobOutcome::Unverifiable,
+        Err(e) => BlobOutcome::Corrupt(e.to_string()),
+    }
+}
+
+/// Removes an orphan, or verifies it when it was referenced again since the shard was listed,
+/// reused by an upload since (which restarts its grace period), or cannot be removed.
+fn collect_orphan(
+    cas: &FsCas,
+    hash: &str,
+    rereferenced: bool,
+    grace_cutoff: SystemTime,
+) -> BlobOutcome {
+    if !rereferenced {
+        match cas.remove_if_older(hash, grace_cutoff) {
+            Ok(true) => return BlobOutcome::Collected,
+            Ok(false) => {}
+            Err(e) => tracing::warn!(hash = %hash, error = %e, "failed to remove orphan CAS blob"),
+        }
+    }
+    verify_blob(cas, hash)
+}
+
+/// Hash range `[lower, upper)` of a shard; the last shard has no upper bound.
+fn shard_range(shard: u32) -> (String, Option<String>) {
+    let upper = (shard + 1 < SHARDS).then(|| format!("{:02x}", shard + 1));
+    (format!("{shard:02x}"), upper)
+}
+
+/// Scrubs the next `shards_per_run` shards of the CAS: verifies every blob's hash, collects
+/// unreferenced blobs past the grace period, and records corrupt blobs and blobs frames
+/// reference but the CAS lacks. Progress is kept in `cas_scrub_state`, so successive runs walk
+/// the whole store and then start over. Afterwards pack files left mostly dead by collected
+/// blobs are compacted.
+pub async fn scrub_once(
+    pool: &PostgresPool,
+    cas: &FsCas,
+    options: &ScrubOptions,
+    now: DateTime<Utc>,
+) -> Result<ScrubRunSummary, LifelogError> {
+    let mut client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    client
+        .execute(
+            "INSERT INTO cas_scrub_state (id) VALUES (TRUE) ON CONFLICT (id) DO NOTHING",
+            &[],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("scrub state init: {e}")))?;
+    let next: i32 = client
+        .query_one("SELECT next_shard FROM cas_scrub_state", &[])
+        .await
+        .map_err(|e| LifelogError::Database(format!("scrub state query: {e}")))?
+        .get(0);
+
+    let grace_cutoff: SystemTime = (now - options.orphan_grace).into();
+    let mut shard = (next.max(0) as u32) % SHARDS;
+    let mut summary = ScrubRunSummary::default();
+
+    for _ in 0..options.shards_per_run.clamp(1, SHARDS) {
+        if shard == 0 {
+            client
+                .execute(
+                    "UPDATE cas_scrub_state SET pass_started_at = $1, blobs_verified = 0,
+                     bytes_verified = 0, blobs_unverified = 0, orphans_deleted = 0",
+                    &[&now],
+                )
+                .await
+                .map_err(|e| LifelogError::Database(format!("scrub pass reset: {e}")))?;
+        }
+
+        let (lower, upper) = shard_range(shard);
+        let frame_refs: HashMap<String, i64> = client
+            .query(
+                "SELECT blob_hash, COUNT(*) FROM frames
+                 WHERE blob_hash >= $1 AND ($2::text IS NULL OR blob_hash < $2)
+                 GROUP BY blob_hash",
+                &[&lower, &upper],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("scrub frame refs: {e}")))?
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+        let chunk_refs: HashSet<String> = client
+            .query(
+                "SELECT DISTINCT hash FROM upload_chunks
+                 WHERE hash >= $1 AND ($2::text IS NULL OR hash < $2)",
+                &[&lower, &upper],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("scrub chunk refs: {e}")))?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        let blob_cas = cas.clone();
+        let referenced: HashSet<String> = frame_refs.keys().chain(&chunk_refs).cloned().collect();
+        let outcomes = tokio::task::spawn_blocking(move || {
+            let blobs = blob_cas.list_shard(shard as u8)?;
+            Ok::<_, CasError>(
+                blobs
+                    .into_iter()
+                    .map(|(hash, modified)| {
+                        let outcome = check_blob(
+                            &blob_cas,
+                            &hash,
+                            modified,
+                            referenced.contains(&hash),
+                            grace_cutoff,
+                        );
+                        (hash, outcome)
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .await
+        .map_err(|e| LifelogError::Database(format!("scrub task: {e}")))?
+        .map_err(|e| LifelogError::Database(format!("scrub shard {lower}: {e}")))?;
+
+        // A frame or upload may have started using an orphan while the shard was being checked,
+        // so look its references up again just before removing it.
+        let orphans: Vec<&str> = outcomes
+            .iter()
+            .filter(|(_, o)| *o == BlobOutcome::Orphan)
+            .map(|(h, _)| h.as_str())
+            .collect();
+        let outcomes = if orphans.is_empty() {
+            outcomes
+        } else {
+            let rereferenced: HashSet<String> = client
+                .query(
+                    "SELECT blob_hash FROM frames WHERE blob_hash = ANY($1)
+                     UNION
+                     SELECT hash FROM upload_chunks WHERE hash = ANY($1)",
+                    &[&orphans],
+                )
+                .await
+                .map_err(|e| LifelogError::Database(format!("scrub orphan refs: {e}")))?
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+            let blob_cas = cas.clone();
+            tokio::task::spawn_blocking(move || {
+                outcomes
+                    .into_iter()
+                    .map(|(hash, outcome)| match outcome {
+                        BlobOutcome::Orphan => {
+                            let outcome = collect_orphan(
+                                &blob_cas,
+                                &hash,
+                                rereferenced.contains(&hash),
+                                grace_cutoff,
+                            );
+                            (hash, outcome)
+                        }
+                        outcome => (hash, outcome),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await
+            .map_err(|e| LifelogError::Database(format!("scrub task: {e}")))?
+        };
+
+        let on_disk: HashSet<&str> = outcomes
+            .iter()
+            .filter(|(_, o)| *o != BlobOutcome::Collected)
+            .map(|(h, _)| h.as_str())
+            .collect();
+        // (hash, kind, detail, frame count)
+        let mut findings: Vec<(String, &str, String, i64)> = Vec::new();
+        let (mut verified, mut bytes, mut unverified, mut collected) = (0i64, 0i64, 0i64, 0i64);
+        for (hash, outcome) in &outcomes {
+            match outcome {
+                BlobOutcome::Verified(size) => {
+                    verified += 1;
+                    bytes += *size as i64;
+                }
+                BlobOutcome::Corrupt(detail) => {
+                    let frames = frame_refs.get(hash).copied().unwrap_or(0);
+                    findings.push((hash.clone(), "corrupt", detail.clone(), frames));
+                }
+                BlobOutcome::Unverifiable => unverified += 1,
+                BlobOutcome::Collected => collected += 1,
+                // Collected or verified above.
+                BlobOutcome::Orphan => {}
+            }
+        }
+        for (hash, frames) in &frame_refs {
+            if !on_disk.contains(hash.as_str()) {
+                findings.push((hash.clone(), "missing", String::new(), *frames));
+            }
+        }
+
+        let corrupt = findings.iter().filter(|f| f.1 == "corrupt").count() as u64;
+        summary.corrupt_blobs += corrupt;
+        summary.missing_blobs += findings.len() as u64 - corrupt;
+        summary.blobs_verified += verified as u64;
+        summary.bytes_verified += bytes as u64;
+        summary.blobs_unverified += unverified as u64;
+        summary.orphans_deleted += collected as u64;
+        for (hash, kind, _, frames) in &findings {
+            tracing::warn!(hash = %hash, kind = %kind, frames, "CAS scrub finding");
+        }
+
+        let next_shard = (shard + 1) % SHARDS;
+        let tx = client
+            .transaction()
+            .await
+            .map_err(|e| LifelogError::Database(format!("scrub tx begin: {e}")))?;
+        let found: Vec<&str> = findings.iter().map(|f| f.0.as_str()).collect();
+        tx.execute(
+            "DELETE FROM cas_scrub_findings
+             WHERE blob_hash >= $1 AND ($2::text IS NULL OR blob_hash < $2)
+             AND NOT (blob_hash = ANY($3))",
+            &[&lower, &upper, &found],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("scrub findings clear: {e}")))?;
+        for (hash, kind, detail, frames) in &findings {
+            tx.execute(
+                "INSERT INTO cas_scrub_findings (blob_hash, kind, detail, frame_count, first_seen_at, last_seen_at)
+                 VALUES ($1, $2, $3, $4, $5, $5)
+                 ON CONFLICT (blob_hash) DO UPDATE SET kind = EXCLUDED.kind,
+                     detail = EXCLUDED.detail, frame_count = EXCLUDED.frame_count,
+                     last_seen_at = EXCLUDED.last_seen_at",
+                &[hash, kind, detail, frames, &now],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("scrub finding insert: {e}")))?;
+        }
+        tx.execute(
+            "UPDATE cas_scrub_state SET next_shard = $1, last_run_at = $2,
+             blobs_verified = blobs_verified + $3, bytes_verified = bytes_verified + $4,
+             blobs_unverified = blobs_unverified + $5, orphans_deleted = orphans_deleted + $6,
+             last_pass_completed_at = CASE WHEN $1 = 0 THEN $2 ELSE last_pass_completed_at END",
+            &[
+                &(next_shard as i32),
+                &now,
+                &verified,
+                &bytes,
+                &unverified,
+                &collected,
+            ],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("scrub state update: {e}")))?;
+        tx.commit()
+            .await
+            .map_err(|e| LifelogError::Database(format!("scrub tx commit: {e}")))?;
+
+        summary.shards_scanned += 1;
+        shard = next_shard;
+        if shard == 0 {
+            summary.pass_completed = true;
+            break;
+        }
+    }
+
+    let compact_cas = cas.clone();
+    match tokio::task::spawn_blocking(move || compact_cas.compact().map_err(|e| e.to_string()))
+        .await
+        .map_err(|e| LifelogError::Database(format!("compaction task: {e}")))?
+    {
+        Ok(compaction) => {
+            summary.packs_compacted = compaction.packs_rewritten;
+            summary.bytes_reclaimed = compaction.bytes_reclaimed;
+        }
+        Err(e) => tracing::warn!(error = %e, "CAS compaction failed"),
+    }
+
+    Ok(summary)
+}
+
+/// A corrupt or missing blob from the last time its shard was scrubbed.
+#[derive(Debug, Clone)]
+pub struct ScrubFinding {
+    pub blob_hash: String,
+    pub kind: String,
+    pub detail: String,
+    pub frame_count: i64,
+    /// Up to a few frames that reference the blob.
+    pub frame_ids: Vec<uuid::Uuid>,
+    pub first_seen_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScrubReport {
+    /// Shards done in the pass in progress.
+    pub shards_scanned: u32,
+    pub pass_started_at: Option<DateTime<Utc>>,
+    pub last_pass_completed_at: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub blobs_verified: u64,
+    pub bytes_verified: u64,
+    pub blobs_unverified: u64,
+    pub orphans_deleted: u64,
+    pub corrupt_blobs: u64,
+    pub missing_blobs: u64,
+    /// Most recently seen first, at most the requested limit.
+    pub findings: Vec<ScrubFinding>,
+}
+
+/// Scrubber progress and its outstanding findings.
+pub async fn report(pool: &PostgresPool, limit: usize) -> Result<ScrubReport, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+
+    let mut report = ScrubReport::default();
+    if let Some(row) = client
+        .query_opt(
+            "SELECT next_shard, pass_started_at, last_pass_completed_at, last_run_at,
+                    blobs_verified, bytes_verified, blobs_unverified, orphans_deleted
+             FROM cas_scrub_state",
+            &[],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("scrub state query: {e}")))?
+    {
+        let count = |i: usize| row.get::<_, i64>(i).max(0) as u64;
+        report.shards_scanned = row.get::<_, i32>(0).max(0) as u32;
+        report.pass_started_at = row.get(1);
+        report.last_pass_completed_at = row.get(2);
+        report.last_run_at = row.get(3);
+        report.blobs_verified = count(4);
+        report.bytes_verified = count(5);
+        report.blobs_unverified = count(6);
+        report.orphans_deleted = count(7);
+    }
+
+    for row in client
+        .query(
+            "SELECT kind, COUNT(*) FROM cas_scrub_findings GROUP BY kind",
+            &[],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("scrub finding counts: {e}")))?
+    {
+        let kind: String = row.get(0);
+        let n = row.get::<_, i64>(1).max(0) as u64;
+        match kind.as_str() {
+            "corrupt" => report.corrupt_blobs = n,
+            "missing" => report.missing_blobs = n,
+            _ => {}
+        }
+    }
+
+    let rows = client
+        .query(
+            "SELECT f.blob_hash, f.kind, f.detail, f.frame_count, f.first_seen_at, f.last_seen_at,
+                    ARRAY(SELECT id FROM frames WHERE blob_hash = f.blob_hash
+                          ORDER BY t_canonical LIMIT 10) AS frame_ids
+             FROM cas_scrub_findings f
+             ORDER BY f.last_seen_at DESC, f.blob_hash
+             LIMIT $1",
+            &[&(limit as i64)],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("scrub findings query: {e}")))?;
+    report.findings = rows
+        .iter()
+        .map(|row| ScrubFinding {
+            blob_hash: row.get("blob_hash"),
+            kind: row.get("kind"),
+            detail: row.get("detail"),
+            frame_count: row.get("frame_count"),
+            frame_ids: row.get("frame_ids"),
+            first_seen_at: row.get("first_seen_at"),
+            last_seen_at: row.get("last_seen_at"),
+        })
+        .collect();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shard_ranges_cover_the_hash_space_in_order() {
+        assert_eq!(shard_range(0), ("00".to_string(), Some("01".to_string())));
+        assert_eq!(shard_range(9), ("09".to_string(), Some("0a".to_string())));
+        assert_eq!(shard_range(255), ("ff".to_string(), None));
+        // Lowercase hex sorts the same way as the shards do.
+        assert!("09ff" < "0a00" && "0a00" < "0b");
+    }
+
+    #[test]
+    fn old_orphans_are_collected_and_the_rest_verified() {
+        let Ok(dir) = tempfile::tempdir() else {
+            return;
+        };
+        let cas = FsCas::new(dir.path());
+        let kept = cas.put(b"referenced").unwrap_or_default();
+        let orphan = cas.put(b"orphan").unwrap_or_default();
+        let recent = cas.put(b"recent orphan").unwrap_or_default();
+
+        let now = SystemTime::now();
+        let old = now - std::time::Duration::from_secs(3600);
+        let later = now + std::time::Duration::from_secs(60);
+        assert_eq!(
+            check_blob(&cas, &kept, old, true, now),
+            BlobOutcome::Verified(10)
+        );
+        assert_eq!(
+            check_blob(&cas, &orphan, old, false, now),
+            BlobOutcome::Orphan
+        );
+        assert_eq!(
+            collect_orphan(&cas, &orphan, true, later),
+            BlobOutcome::Verified(6)
+        );
+        // Written (or reused by a put) after the cutoff: kept for the next pass.
+        assert_eq!(
+            collect_orphan(&cas, &orphan, false, old),
+            BlobOutcome::Verified(6)
+        );
+        assert_eq!(
+            collect_orphan(&cas, &orphan, false, later),
+            BlobOutcome::Collected
+        );
+        assert!(!cas.contains(&orphan).unwrap_or(true));
+        assert_eq!(
+            check_blob(&cas, &recent, now, false, old),
+            BlobOutcome::Verified(13)
+        );
+
+        let path = dir.path().join(&kept[0..2]).join(&kept[2..]);
+        let _ = std::fs::write(path, b"bit rot");
+        assert!(matches!(
+            check_blob(&cas, &kept, old, true, now),
+            BlobOutcome::Corrupt(_)
+        ));
+    }
+}
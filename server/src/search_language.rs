@@ -0,0 +1,207 @@
+use once_cell::sync::Lazy;
+use serde_json::Value as JsonValue;
+use whatlang::Lang;
+
+/// Text shorter than this is not run through detection and is indexed as the default language.
+const MIN_DETECT_CHARS: usize = 24;
+/// Only the start of long payloads is looked at; detection does not improve past a few
+/// sentences.
+const MAX_DETECT_CHARS: usize = 2000;
+
+/// Payload fields that hold natural-language text worth detecting a language for.
+const TEXT_FIELDS: &[&str] = &["text", "content", "transcript", "title", "window_title"];
+
+/// Stored in `frames.language` for text in a language that is not enabled for stemming.
+pub const SIMPLE: &str = "simple";
+
+/// A language Postgres ships a snowball stemmer for: ISO 639-1 code, detector language and
+/// text search configuration.
+type SearchLanguage = (&'static str, Lang, &'static str);
+
+// Keep in sync with `search_config()` in migrations/20260325400000_search_language.sql.
+const SUPPORTED: &[SearchLanguage] = &[
+    ("ar", Lang::Ara, "arabic"),
+    ("da", Lang::Dan, "danish"),
+    ("de", Lang::Deu, "german"),
+    ("el", Lang::Ell, "greek"),
+    ("en", Lang::Eng, "english"),
+    ("es", Lang::Spa, "spanish"),
+    ("fi", Lang::Fin, "finnish"),
+    ("fr", Lang::Fra, "french"),
+    ("hu", Lang::Hun, "hungarian"),
+    ("id", Lang::Ind, "indonesian"),
+    ("it", Lang::Ita, "italian"),
+    ("lt", Lang::Lit, "lithuanian"),
+    ("ne", Lang::Nep, "nepali"),
+    ("nl", Lang::Nld, "dutch"),
+    ("no", Lang::Nob, "norwegian"),
+    ("pt", Lang::Por, "portuguese"),
+    ("ro", Lang::Ron, "romanian"),
+    ("ru", Lang::Rus, "russian"),
+    ("sv", Lang::Swe, "swedish"),
+    ("ta", Lang::Tam, "tamil"),
+    ("tr", Lang::Tur, "turkish"),
+];
+
+fn supported(code: &str) -> Option<&'static SearchLanguage> {
+    SUPPORTED.iter().find(|l| l.0 == code)
+}
+
+/// Language `text` is reliably detected as, whether or not it is enabled for search. `None` for
+/// text too short or ambiguous to tell.
+pub fn detect_lang(text: &str) -> Option<Lang> {
+    let text = text.trim();
+    if text.chars().count() < MIN_DETECT_CHARS {
+        return None;
+    }
+    let sample: String = text.chars().take(MAX_DETECT_CHARS).collect();
+    whatlang::detect(&sample)
+        .filter(|i| i.is_reliable())
+        .map(|i| i.lang())
+}
+
+/// Resolves an ISO 639-1 code for one of the supported languages, or any ISO 639-3 code.
+pub fn parse_lang(code: &str) -> Option<Lang> {
+    let code = code.trim().to_ascii_lowercase();
+    supported(&code)
+        .map(|l| l.1)
+        .or_else(|| Lang::from_code(&code))
+}
+
+/// Code stored for a detected language: ISO 639-1 where the supported table has one, ISO 639-3
+/// otherwise.
+pub fn lang_code(lang: Lang) -> &'static str {
+    SUPPORTED
+        .iter()
+        .find(|l| l.1 == lang)
+        .map_or(lang.code(), |l| l.0)
+}
+
+/// The languages full-text search stems for, resolved from [`config::SearchConfig`].
+#[derive(Debug, Clone)]
+pub struct SearchLanguages {
+    enabled: Vec<&'static SearchLanguage>,
+    default: Option<&'static SearchLanguage>,
+}
+
+impl SearchLanguages {
+    pub fn from_config(cfg: &config::SearchConfig) -> Self {
+        let mut enabled: Vec<&'static SearchLanguage> = Vec::new();
+        for code in &cfg.languages {
+            match supported(code) {
+                Some(lang) if !enabled.iter().any(|l| l.0 == lang.0) => enabled.push(lang),
+                Some(_) => {}
+                None => tracing::warn!(language = %code, "Unsupported search language ignored"),
+            }
+        }
+        let default = match supported(&cfg.default_language) {
+            Some(lang) if enabled.iter().any(|l| l.0 == lang.0) => Some(lang),
+            _ => {
+                tracing::warn!(
+                    language = %cfg.default_language,
+                    "Default search language is not an enabled language; using 'simple'"
+                );
+                None
+            }
+        };
+        Self { enabled, default }
+    }
+
+    /// Language code to index `text` as: an enabled language it is reliably detected as,
+    /// [`SIMPLE`] for other languages, or the default language when the text is too short or
+    /// ambiguous to tell. Returns `None` for blank text.
+    pub fn detect(&self, text: &str) -> Option<String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        let Some(lang) = detect_lang(text) else {
+            return Some(self.default_language().unwrap_or(SIMPLE).to_string());
+        };
+        let code = self
+            .enabled
+            .iter()
+            .find(|l| l.1 == lang)
+            .map_or(SIMPLE, |l| l.0);
+        Some(code.to_string())
+    }
+
+    /// ISO 639-1 code of the default language, when it is enabled.
+    pub fn default_language(&self) -> Option<&'static str> {
+        self.default.map(|l| l.0)
+    }
+
+    /// Text search configurations a query has to be parsed with to match every frame: one per
+    /// enabled language plus `simple`.
+    pub fn query_configs(&self) -> Vec<&'static str> {
+        self.enabled
+            .iter()
+            .map(|l| l.2)
+            .chain(std::iter::once(SIMPLE))
+            .collect()
+    }
+}
+
+static CONFIGURED: Lazy<SearchLanguages> =
+    Lazy::new(|| SearchLanguages::from_config(&config::load_search_config_from_unified()));
+
+/// Search languages from the unified config, loaded once.
+pub fn configured() -> &'static SearchLanguages {
+    &CONFIGURED
+}
+
+/// Language to index a frame payload as, from its natural-language text fields.
+pub fn payload_language(payload: &JsonValue) -> Option<String> {
+    let text = TEXT_FIELDS
+        .iter()
+        .filter_map(|f| payload[*f].as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    configured().detect(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn languages(enabled: &[&str]) -> SearchLanguages {
+        SearchLanguages::from_config(&config::SearchConfig {
+            languages: enabled.iter().map(|s| s.to_string()).collect(),
+            default_language: "en".to_string(),
+        })
+    }
+
+    #[test]
+    fn detects_enabled_languages_and_falls_back_to_simple() {
+        let langs = languages(&["en", "de"]);
+        let german = "Die Besprechung wurde auf Donnerstag verschoben, weil der Kunde noch \
+                      keine Rückmeldung zu den Unterlagen gegeben hat.";
+        let spanish = "La reunión se ha aplazado hasta el jueves porque el cliente todavía no \
+                       ha respondido sobre los documentos.";
+        assert_eq!(langs.detect(german).as_deref(), Some("de"));
+        assert_eq!(langs.detect(spanish).as_deref(), Some(SIMPLE));
+        assert_eq!(langs.detect("cargo build").as_deref(), Some("en"));
+        assert_eq!(langs.detect("   "), None);
+    }
+
+    #[test]
+    fn query_configs_cover_enabled_languages_and_simple() {
+        let langs = languages(&["en", "de", "xx", "de"]);
+        assert_eq!(langs.query_configs(), vec!["english", "german", "simple"]);
+        let none = SearchLanguages::from_config(&config::SearchConfig {
+            languages: Vec::new(),
+            default_language: "en".to_string(),
+        });
+        assert_eq!(none.query_configs(), vec!["simple"]);
+        assert_eq!(none.detect("short").as_deref(), Some(SIMPLE));
+    }
+
+    #[test]
+    fn parses_both_code_forms() {
+        assert_eq!(parse_lang("de"), Some(Lang::Deu));
+        assert_eq!(parse_lang("JPN"), Some(Lang::Jpn));
+        assert_eq!(parse_lang("xx"), None);
+        assert_eq!(lang_code(Lang::Deu), "de");
+        assert_eq!(lang_code(Lang::Jpn), "jpn");
+    }
+}
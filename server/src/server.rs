@@ -1,8 +1,11 @@
 use crate::policy::*;
 use crate::postgres::{connect_pool, run_migrations, PostgresPool};
+use crate::storage::{postgres_required, FrameStore, SpanFilter, SqliteStore, Storage};
+use crate::transform::state::TransformStateStore;
+use crate::transform::watermark::WatermarkStore;
 use chrono::Utc;
 use config::ServerPolicyConfig;
-use config::{ServerConfig, SystemConfig};
+use config::{ServerConfig, ServerDeployConfig, SystemConfig};
 use lifelog_core::*;
 use lifelog_types::*;
 use lifelog_types::{CollectorState, SystemState};
@@ -24,14 +27,25 @@ pub type ServerAction = lifelog_core::ServerAction<
 pub type RegisteredCollector =
     lifelog_core::RegisteredCollector<lifelog_types::ServerCommand, lifelog_types::CollectorConfig>;
 
+/// Transform cache entries neither written nor hit for this long are pruned with retention.
+const TRANSFORM_CACHE_TTL_DAYS: i64 = 30;
+
 /// (device_time, server_time) pairs for clock skew estimation.
 type SkewSamples = HashMap<String, Vec<(chrono::DateTime<Utc>, chrono::DateTime<Utc>)>>;
 
 #[derive(Debug, Clone)]
 pub struct Server {
-    pub postgres_pool: PostgresPool,
+    /// Set when running on Postgres; features without a SQLite implementation need it.
+    pub postgres_pool: Option<PostgresPool>,
+    /// Set when running on SQLite.
+    pub sqlite: Option<SqliteStore>,
+    pub store: Arc<dyn FrameStore>,
+    pub watermarks: Arc<dyn WatermarkStore>,
+    pub transform_state: Arc<dyn TransformStateStore>,
     pub cas: FsCas,
     pub config: Arc<RwLock<ServerConfig>>,
+    /// `[[server.retentionTiers]]`, read once at startup.
+    pub retention_tiers: Vec<config::RetentionTier>,
     pub state: Arc<RwLock<SystemState>>,
     pub registered_collectors: Arc<RwLock<Vec<RegisteredCollector>>>,
     pub policy: Arc<RwLock<ServerPolicy>>,
@@ -65,6 +79,11 @@ impl ServerHandle {
         }
     }
 
+    /// Whether the server runs on Postgres, which maintenance tasks like retention need.
+    pub async fn has_postgres(&self) -> bool {
+        self.server.read().await.postgres_pool.is_some()
+    }
+
     pub async fn contains_collector(&self, collector_name: String) -> bool {
         let server = self.server.read().await;
         server.contains_collector(collector_name).await
@@ -95,6 +114,55 @@ impl ServerHandle {
         server.run_retention_once().await
     }
 
+    pub async fn run_scrub_once(
+        &self,
+        options: &crate::scrub::ScrubOptions,
+    ) -> Result<crate::scrub::ScrubRunSummary, LifelogError> {
+        let server = self.server.read().await;
+        crate::scrub::scrub_once(
+            server.postgres("CAS scrub")?,
+            &server.cas,
+            options,
+            Utc::now(),
+        )
+        .await
+    }
+
+    /// Reloads the configured CAS keys and starts rotating blobs to the active one.
+    pub async fn start_key_rotation(
+        &self,
+    ) -> Result<crate::key_rotation::KeyRotation, LifelogError> {
+        let server = self.server.read().await;
+        let cas_path = server.config.read().await.cas_path.clone();
+        crate::key_rotation::rotate_to_configured_key(
+            server.postgres("CAS key rotation")?,
+            &server.cas,
+            &cas_path,
+            Utc::now(),
+        )
+        .await
+    }
+
+    pub async fn run_key_rotation_once(
+        &self,
+        shards_per_run: u32,
+    ) -> Result<Option<crate::key_rotation::KeyRotation>, LifelogError> {
+        let server = self.server.read().await;
+        crate::key_rotation::rotate_once(
+            server.postgres("CAS key rotation")?,
+            &server.cas,
+            shards_per_run,
+            Utc::now(),
+        )
+        .await
+    }
+
+    /// Seals a batch of sensitive frames stored before payload encryption was turned on.
+    pub async fn seal_payload_backlog(&self, batch: i64) -> Result<u64, LifelogError> {
+        let server = self.server.read().await;
+        crate::payload_crypto::seal_backlog(server.postgres("payload sealing")?, batch).await
+    }
+
     pub async fn get_data(
         &self,
         keys: Vec<lifelog_types::LifelogDataKey>,
@@ -102,14 +170,12 @@ impl ServerHandle {
         let server = self.server.read().await;
         let ids: Vec<uuid::Uuid> = keys.iter().filter_map(|k| k.uuid.parse().ok()).collect();
 
-        crate::frames::get_by_ids(&server.postgres_pool, &server.cas, &ids).await
+        server.store.get_by_ids(&server.cas, &ids).await
     }
 
     pub async fn list_postgres_origins(&self) -> Vec<DataOrigin> {
         let server = self.server.read().await;
-        crate::frames::get_origins(&server.postgres_pool)
-            .await
-            .unwrap_or_default()
+        server.store.origins().await.unwrap_or_default()
     }
 
     pub async fn process_query(
@@ -128,6 +194,14 @@ impl ServerHandle {
         server.process_replay(req).await
     }
 
+    pub async fn process_forget(
+        &self,
+        req: lifelog_types::ForgetRequest,
+    ) -> Result<crate::forget::ForgetReport, LifelogError> {
+        let server = self.server.read().await;
+        server.process_forget(req).await
+    }
+
     pub async fn register_collector(&self, collector: RegisteredCollector) {
         let server = self.server.write().await;
         server.registered_collectors.write().await.push(collector);
@@ -181,6 +255,80 @@ impl ServerHandle {
     }
 }
 
+/// Fails when config asks for a feature that needs Postgres, so a SQLite server does not start
+/// without it. Always-on Postgres maintenance is reported once instead.
+fn reject_postgres_only_config(
+    retention_tiers: &[config::RetentionTier],
+) -> Result<(), LifelogError> {
+    if !retention_tiers.is_empty() {
+        return Err(postgres_required("retentionTiers"));
+    }
+    if !config::load_places_from_unified().is_empty() {
+        return Err(postgres_required("places (location inference)"));
+    }
+    tracing::warn!(
+        "SQLite storage: summaries, meeting and episode detection, CAS scrub, CAS key rotation \
+         and sealing of frames stored before payload encryption was enabled need Postgres and \
+         will not run"
+    );
+    Ok(())
+}
+
+/// Opens the configured database, applying pending migrations, and the CAS with the configured
+/// keys. Shared by the server and the offline backup/restore commands.
+pub async fn open_storage(config: &ServerConfig) -> Result<(Storage, FsCas), LifelogError> {
+    open_storage_with(config, &config::load_server_deploy_config()).await
+}
+
+/// [`open_storage`] with the deployment settings given rather than loaded. `sqlite_path` takes
+/// precedence over `postgres_url`.
+pub async fn open_storage_with(
+    config: &ServerConfig,
+    deploy: &ServerDeployConfig,
+) -> Result<(Storage, FsCas), LifelogError> {
+    let storage = if let Some(sqlite_path) = &deploy.sqlite_path {
+        let store = SqliteStore::open(sqlite_path).await?;
+        tracing::info!(path = %sqlite_path, "SQLite backend enabled");
+        Storage::sqlite(store)
+    } else {
+        let postgres_url = deploy.postgres_url.clone().ok_or_else(|| {
+            LifelogError::Database(
+                "postgres_url or sqlite_path must be set in [server] config (or LIFELOG_POSTGRES_INGEST_URL / LIFELOG_SQLITE_PATH)"
+                    .to_string(),
+            )
+        })?;
+        if postgres_url.trim().is_empty() {
+            return Err(LifelogError::Database(
+                "postgres_url must not be empty".to_string(),
+            ));
+        }
+        let max_connections = deploy.postgres_max_connections.unwrap_or(16);
+        let postgres_pool = connect_pool(&postgres_url, max_connections).await?;
+        run_migrations(&postgres_pool).await?;
+        crate::partitions::ensure_ahead(&postgres_pool, Utc::now()).await?;
+        tracing::info!(max_connections, "Postgres backend enabled");
+        Storage::postgres(postgres_pool)
+    };
+
+    let cas = FsCas::open(&config.cas_path)
+        .map_err(|e| LifelogError::Database(format!("CAS open {}: {e}", config.cas_path)))?;
+    let keys = config::load_cas_keys_from_unified();
+    let keyring = crate::key_rotation::load_keyring(&keys, &config.cas_path).map_err(|e| {
+        LifelogError::Validation {
+            field: "casKeys".to_string(),
+            reason: e.to_string(),
+        }
+    })?;
+    if let Some(keyring) = keyring {
+        if let Some(pool) = &storage.postgres {
+            crate::key_rotation::check_retired_keys(pool, &keyring).await?;
+        }
+        tracing::info!(key_id = %keyring.active_id(), "CAS encryption enabled");
+        cas.set_keyring(Some(keyring));
+    }
+    Ok((storage, cas))
+}
+
 impl Server {
     async fn resolve_identity_candidates(&self, identifier: &str) -> Vec<String> {
         let collectors = self.registered_collectors.read().await;
@@ -285,28 +433,47 @@ impl Server {
     }
 
     async fn get_available_origins(&self) -> Result<Vec<DataOrigin>, LifelogError> {
-        crate::frames::get_origins(&self.postgres_pool).await
+        self.store.origins().await
+    }
+
+    /// The Postgres pool behind `feature`, which has no SQLite implementation.
+    pub fn postgres(&self, feature: &str) -> Result<&PostgresPool, LifelogError> {
+        self.postgres_pool
+            .as_ref()
+            .ok_or_else(|| postgres_required(feature))
     }
 
     pub async fn new(config: &ServerConfig) -> Result<Self, LifelogError> {
-        let deploy = config::load_server_deploy_config();
-        let postgres_url = deploy.postgres_url.ok_or_else(|| {
-            LifelogError::Database(
-                "postgres_url must be set in [server] config or LIFELOG_POSTGRES_INGEST_URL env var"
-                    .to_string(),
-            )
+        Self::new_with_deploy(config, &config::load_server_deploy_config()).await
+    }
+
+    /// [`Server::new`] with the deployment settings given rather than loaded from the
+    /// environment and config file.
+    pub async fn new_with_deploy(
+        config: &ServerConfig,
+        deploy: &ServerDeployConfig,
+    ) -> Result<Self, LifelogError> {
+        let (storage, cas) = open_storage_with(config, deploy).await?;
+        let Storage {
+            frames: store,
+            postgres: postgres_pool,
+            sqlite,
+            watermarks,
+            transform_state,
+        } = storage;
+        let retention_tiers = config::load_retention_tiers_from_unified();
+        if sqlite.is_some() {
+            reject_postgres_only_config(&retention_tiers)?;
+        }
+        let payload_cipher = crate::payload_crypto::init(&config.cas_path).map_err(|e| {
+            LifelogError::Validation {
+                field: "payloadEncryption.key".to_string(),
+                reason: e.to_string(),
+            }
         })?;
-        if postgres_url.trim().is_empty() {
-            return Err(LifelogError::Database(
-                "postgres_url must not be empty".to_string(),
-            ));
+        if let Some(cipher) = payload_cipher {
+            tracing::info!(key_id = %cipher.key_id(), "Payload encryption enabled for sensitive modalities");
         }
-        let max_connections = deploy.postgres_max_connections.unwrap_or(16);
-        let postgres_pool = connect_pool(&postgres_url, max_connections).await?;
-        run_migrations(&postgres_pool).await?;
-        tracing::info!(max_connections, "Postgres backend enabled");
-
-        let cas = FsCas::new(&config.cas_path);
 
         let system_state = SystemState {
             collector_states: HashMap::new(),
@@ -328,6 +495,7 @@ impl Server {
                 postgres_pool_size: 0,
                 postgres_pool_available: 0,
                 postgres_pool_waiting: 0,
+                transform_cache: vec![],
             }),
         };
 
@@ -430,6 +598,7 @@ impl Server {
                         source,
                         spec.service_endpoint.clone(),
                         &spec.params,
+                        startup_egress.clone(),
                     );
                     executors.push(Arc::new(executor));
                     tracing::info!(
@@ -445,6 +614,7 @@ impl Server {
                         source,
                         spec.service_endpoint.clone(),
                         &spec.params,
+                        startup_egress.clone(),
                     );
                     executors.push(Arc::new(executor));
                     tracing::info!(
@@ -459,6 +629,7 @@ impl Server {
                         source,
                         spec.service_endpoint.clone(),
                         &spec.params,
+                        startup_egress.clone(),
                     );
                     executors.push(Arc::new(executor));
                     tracing::info!(
@@ -467,6 +638,40 @@ impl Server {
                         "Registered browser topic transform"
                     );
                 }
+                "translate" => {
+                    if spec.service_endpoint.is_empty() {
+                        tracing::error!(
+                            transform_id = %spec.id,
+                            "Translate transform has no service_endpoint; skipping"
+                        );
+                        continue;
+                    }
+                    if let Err(e) = startup_egress.check(
+                        &spec.service_endpoint,
+                        lifelog_core::PrivacyLevel::LocalOnly,
+                    ) {
+                        tracing::warn!(
+                            transform_id = %spec.id,
+                            endpoint = %spec.service_endpoint,
+                            error = %e,
+                            "Skipping translate transform: endpoint is not local"
+                        );
+                        continue;
+                    }
+                    let executor = crate::transform::translate::TranslateExecutor::new(
+                        spec.id.clone(),
+                        source,
+                        spec.service_endpoint.clone(),
+                        &spec.params,
+                        startup_egress.clone(),
+                    );
+                    executors.push(Arc::new(executor));
+                    tracing::info!(
+                        id = %spec.id,
+                        endpoint = %spec.service_endpoint,
+                        "Registered translate transform"
+                    );
+                }
                 "sound-classifier" => {
                     let executor = crate::transform::sound::SoundClassifierExecutor::new(
                         spec.id.clone(),
@@ -479,6 +684,56 @@ impl Server {
                         "Registered sound classifier transform"
                     );
                 }
+                "secret-scan" => {
+                    let executor = crate::transform::secrets::SecretScanExecutor::new(
+                        spec.id.clone(),
+                        source,
+                        &spec.params,
+                    );
+                    executors.push(Arc::new(executor));
+                    tracing::info!(id = %spec.id, "Registered secret scan transform");
+                }
+                "entity-extract" => {
+                    let executor = crate::transform::entities::EntityExtractExecutor::new(
+                        spec.id.clone(),
+                        source,
+                        &spec.params,
+                    );
+                    executors.push(Arc::new(executor));
+                    tracing::info!(id = %spec.id, "Registered entity extraction transform");
+                }
+                "process" => {
+                    if spec.destination_modality.is_empty() {
+                        tracing::error!(
+                            transform_id = %spec.id,
+                            "Process transform has no destination_modality; skipping"
+                        );
+                        continue;
+                    }
+                    match crate::transform::process::ProcessExecutor::new(
+                        spec.id.clone(),
+                        source,
+                        spec.destination_modality.clone(),
+                        spec.priority.min(u8::MAX as u32) as u8,
+                        &spec.params,
+                    ) {
+                        Ok(executor) => {
+                            executors.push(Arc::new(executor));
+                            tracing::info!(
+                                id = %spec.id,
+                                command = %spec.params.get("command").map(|s| s.as_str()).unwrap_or(""),
+                                "Registered process transform"
+                            );
+                        }
+                        Err(e) => {
+                            tracing::error!(
+                                transform_id = %spec.id,
+                                error = %e,
+                                "Invalid process transform config; skipping"
+                            );
+                        }
+                    }
+                }
                 other => {
                     tracing::error!(
                         transform_id = %spec.id,
@@ -489,6 +744,60 @@ impl Server {
             }
         }
 
+        // Text modalities are scanned for secrets unless config has its own secret-scan spec for
+        // them (a disabled one opts out).
+        let configured_scans: Vec<String> = config
+            .transforms
+            .iter()
+            .filter(|spec| spec.transform_type == "secret-scan" || spec.id == "secret-scan")
+            .filter_map(|spec| DataOrigin::tryfrom_string(spec.source_origin.clone()).ok())
+            .map(|origin| origin.modality_name)
+            .collect();
+        for modality in crate::transform::secrets::TEXT_MODALITIES {
+            if !crate::transform::secrets::scanned_by_default(modality)
+                || configured_scans.iter().any(|m| m == modality)
+            {
+                continue;
+            }
+            let source = DataOrigin::new(
+                DataOriginType::DeviceId("*".to_string()),
+                modality.to_string(),
+            );
+            executors.push(Arc::new(
+                crate::transform::secrets::SecretScanExecutor::new(
+                    format!("secret-scan-{}", modality.to_lowercase()),
+                    source,
+                    &HashMap::new(),
+                ),
+            ));
+        }
+
+        // Entities are extracted from the main text modalities unless config has its own
+        // entity-extract spec for them (a disabled one opts out).
+        let configured_extracts: Vec<String> = config
+            .transforms
+            .iter()
+            .filter(|spec| spec.transform_type == "entity-extract" || spec.id == "entity-extract")
+            .filter_map(|spec| DataOrigin::tryfrom_string(spec.source_origin.clone()).ok())
+            .map(|origin| origin.modality_name)
+            .collect();
+        for modality in crate::transform::entities::ENTITY_MODALITIES {
+            if configured_extracts.iter().any(|m| m == modality) {
+                continue;
+            }
+            let source = DataOrigin::new(
+                DataOriginType::DeviceId("*".to_string()),
+                modality.to_string(),
+            );
+            executors.push(Arc::new(
+                crate::transform::entities::EntityExtractExecutor::new(
+                    format!("entity-extract-{}", modality.to_lowercase()),
+                    source,
+                    &HashMap::new(),
+                ),
+            ));
+        }
+
         let transform_dag = match TransformDag::new(executors) {
             Ok(dag) => {
                 tracing::info!(?dag, "Transform DAG constructed");
@@ -500,6 +809,28 @@ impl Server {
             }
         };
 
+        for transform in transform_dag.all_transforms() {
+            let Some(fingerprint) = transform.cache_fingerprint() else {
+                continue;
+            };
+            match transform_state
+                .invalidate_stale_cache(transform.id(), fingerprint)
+                .await
+            {
+                Ok(0) => {}
+                Ok(dropped) => tracing::info!(
+                    transform_id = %transform.id(),
+                    dropped,
+                    "Transform configuration changed; dropped stale cache entries"
+                ),
+                Err(e) => tracing::warn!(
+                    transform_id = %transform.id(),
+                    error = %e,
+                    "Failed to invalidate stale transform cache entries"
+                ),
+            }
+        }
+
         let http_client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(120))
             .build()
@@ -507,8 +838,13 @@ impl Server {
 
         Ok(Server {
             postgres_pool,
+            sqlite,
+            store,
+            watermarks,
+            transform_state,
             cas,
             config: Arc::new(RwLock::new(config.clone())),
+            retention_tiers,
             state: Arc::new(RwLock::new(system_state)),
             registered_collectors: Arc::new(RwLock::new(vec![])),
             policy: Arc::new(RwLock::new(ServerPolicy::new(policy_config))),
@@ -545,28 +881,35 @@ impl Server {
     async fn get_state(&self) -> SystemState {
         let mut state = self.state.read().await.clone();
         if let Some(server_state) = state.server_state.as_mut() {
-            {
-                let pool_status = self.postgres_pool.status();
+            if let Some(pool) = &self.postgres_pool {
+                let pool_status = pool.status();
                 server_state.postgres_pool_enabled = true;
                 server_state.postgres_pool_max_size = pool_status.max_size as u32;
                 server_state.postgres_pool_size = pool_status.size as u32;
                 server_state.postgres_pool_available = pool_status.available as u32;
                 server_state.postgres_pool_waiting = pool_status.waiting as u32;
 
-                if let Ok(client) = self.postgres_pool.get().await {
-                    let count_sql = "SELECT COUNT(*)::BIGINT AS total FROM frames";
-                    if let Ok(row) = client.query_one(count_sql, &[]).await {
-                        let total: i64 = row.get(0);
-                        server_state.total_frames_stored = total as u64;
-                    }
-
-                    let size_sql = "SELECT pg_database_size(current_database())::BIGINT";
-                    if let Ok(row) = client.query_one(size_sql, &[]).await {
-                        let size: i64 = row.get(0);
-                        server_state.disk_usage_bytes = size as u64;
+                match crate::transform::cache::stats(pool).await {
+                    Ok(stats) => {
+                        server_state.transform_cache = stats
+                            .iter()
+                            .map(|s| lifelog_types::TransformCacheStats {
+                                transform_id: s.transform_id.clone(),
+                                hits: s.hits,
+                                misses: s.misses,
+                                entries: s.entries,
+                                hit_rate: s.hit_rate(),
+                            })
+                            .collect();
                     }
+                    Err(e) => tracing::warn!(error = %e, "Failed to read transform cache stats"),
                 }
             }
+
+            if let Ok(stats) = self.store.stats().await {
+                server_state.total_frames_stored = stats.frames;
+                server_state.disk_usage_bytes = stats.bytes;
+            }
         }
         state
     }
@@ -632,27 +975,14 @@ impl Server {
             };
 
             let plan = crate::query::planner::Planner::plan(&ast_query, &scoped_origins);
-            let res = crate::query::executor::execute_postgres(&self.postgres_pool, plan).await;
+            let res = self.store.execute(plan).await;
             return res.map_err(|e| LifelogError::Database(format!("query execution failed: {e}")));
         }
 
         // Fast path: no text search and no time ranges → single query across all origins
         if query_msg.text.is_empty() && query_msg.time_ranges.is_empty() {
-            let sql =
-                "SELECT id, collector_id, stream_id, modality FROM frames ORDER BY t_canonical DESC NULLS LAST LIMIT 1000";
-            if let Ok(client) = self.postgres_pool.get().await {
-                if let Ok(rows) = client.query(sql, &[]).await {
-                    for row in rows {
-                        let id: uuid::Uuid = row.get("id");
-                        let collector_id: String = row.get("collector_id");
-                        let stream_id: String = row.get("stream_id");
-                        let origin = DataOrigin {
-                            modality_name: stream_id,
-                            origin: DataOriginType::DeviceId(collector_id),
-                        };
-                        keys.push(LifelogFrameKey::new(id, origin));
-                    }
-                }
+            if let Ok(recent) = self.store.recent_keys(1000).await {
+                keys = recent;
             }
             return Ok(keys);
         }
@@ -734,8 +1064,7 @@ impl Server {
 
             // Pass the full catalog so temporal operators like WITHIN can resolve other streams.
             let plan = crate::query::planner::Planner::plan(&query, &available_origins);
-            let query_result =
-                crate::query::executor::execute_postgres(&self.postgres_pool, plan).await;
+            let query_result = self.store.execute(plan).await;
             match query_result {
                 Ok(res) => keys.extend(res),
                 Err(e) => tracing::error!("Query execution failed for {}: {}", table, e),
@@ -745,6 +1074,150 @@ impl Server {
         Ok(keys)
     }
 
+    /// Resolves a forget request to the frames it selects, then previews or forgets them.
+    async fn process_forget(
+        &self,
+        req: lifelog_types::ForgetRequest,
+    ) -> Result<crate::forget::ForgetReport, LifelogError> {
+        use crate::forget::{self, ForgetSelector, MAX_FRAMES};
+        use crate::query::ast::Expression;
+
+        let pool = self.postgres("forget")?;
+        let mut ranges = Vec::with_capacity(req.time_ranges.len());
+        for tr in &req.time_ranges {
+            let (Some(start), Some(end)) = (tr.start, tr.end) else {
+                return Err(LifelogError::Validation {
+                    field: "time_ranges".to_string(),
+                    reason: "each range needs a start and an end".to_string(),
+                });
+            };
+            let (start, end) = (
+                lifelog_types::to_dt(Some(start)),
+                lifelog_types::to_dt(Some(end)),
+            );
+            if start >= end {
+                return Err(LifelogError::Validation {
+                    field: "time_ranges".to_string(),
+                    reason: "start must be before end".to_string(),
+                });
+            }
+            ranges.push((start, end));
+        }
+
+        let available_origins = self.get_available_origins().await?;
+        let mut scoped_origins = Vec::new();
+        let mut seen = HashSet::new();
+        for s in &req.origins {
+            let resolved = self.resolve_search_origins(s, &available_origins).await;
+            if resolved.is_empty() {
+                return Err(LifelogError::Validation {
+                    field: "origins".to_string(),
+                    reason: format!("no origin matches {s}"),
+                });
+            }
+            for o in resolved {
+                if seen.insert(o.get_table_name()) {
+                    scoped_origins.push(o);
+                }
+            }
+        }
+
+        let query_text = req.query.trim();
+        let mut seeds: Vec<uuid::Uuid> = if !query_text.is_empty() {
+            let text = if query_text.starts_with("llql") {
+                query_text.to_string()
+            } else {
+                format!("llql:{query_text}")
+            };
+            let parsed = crate::query::llql::try_parse_llql(&[text])?.ok_or_else(|| {
+                LifelogError::Validation {
+                    field: "query".to_string(),
+                    reason: "not an LLQL query".to_string(),
+                }
+            })?;
+            let default_window_ms = self.config.read().await.default_correlation_window_ms;
+            let default_window =
+                chrono::Duration::milliseconds(i64::try_from(default_window_ms).unwrap_or(30_000));
+            let mut filter = parsed.filter.with_default_temporal_windows(default_window);
+            let range_filter = ranges
+                .iter()
+                .map(|&(start, end)| Expression::TimeRange(start, end))
+                .reduce(|a, b| Expression::Or(Box::new(a), Box::new(b)));
+            if let Some(range_filter) = range_filter {
+                filter = Expression::And(Box::new(filter), Box::new(range_filter));
+            }
+            let query = crate::query::ast::Query {
+                target: parsed.target,
+                filter,
+            };
+            let origins = if req.origins.is_empty() {
+                &available_origins
+            } else {
+                &scoped_origins
+            };
+            let plan =
+                crate::query::planner::Planner::plan_with_limit(&query, origins, MAX_FRAMES + 1);
+            self.store
+                .execute(plan)
+                .await
+                .map_err(|e| LifelogError::Database(format!("query execution failed: {e}")))?
+                .into_iter()
+                .map(|key| key.uuid)
+                .collect()
+        } else if !ranges.is_empty() {
+            forget::frames_in_ranges(pool, &ranges, &scoped_origins).await?
+        } else {
+            return Err(LifelogError::Validation {
+                field: "query".to_string(),
+                reason: "forget needs a query or at least one time range".to_string(),
+            });
+        };
+        seeds.sort();
+        seeds.dedup();
+        if seeds.len() > MAX_FRAMES {
+            return Err(LifelogError::Validation {
+                field: "query".to_string(),
+                reason: format!("selects more than {MAX_FRAMES} frames; narrow it down"),
+            });
+        }
+
+        if !req.confirm {
+            return forget::preview(pool, &seeds).await;
+        }
+        if req.expected_frames != 0 && req.expected_frames != seeds.len() as u64 {
+            return Err(LifelogError::Validation {
+                field: "expected_frames".to_string(),
+                reason: format!(
+                    "the request now selects {} frames, not {}",
+                    seeds.len(),
+                    req.expected_frames
+                ),
+            });
+        }
+        let selector = ForgetSelector {
+            query_sha256: (!query_text.is_empty())
+                .then(|| utils::cas::sha256_hex(query_text.as_bytes())),
+            time_ranges: ranges,
+            origins: req.origins.clone(),
+        };
+        let report = forget::forget(
+            pool,
+            &self.cas,
+            &seeds,
+            &selector,
+            req.reason.trim(),
+            Utc::now(),
+        )
+        .await?;
+        tracing::info!(
+            frames = report.total_frames(),
+            blobs = report.blobs,
+            audit_id = ?report.audit_id,
+            "Forgot frames"
+        );
+        Ok(report)
+    }
+
     async fn process_replay(
         &self,
         req: lifelog_types::ReplayRequest,
@@ -818,38 +1291,22 @@ impl Server {
 
         // Phase 1: load screen frames in the window.
         let screen_frames: Vec<(String, chrono::DateTime<Utc>)> = {
-            let pool = &self.postgres_pool;
-            let collector_id = Self::collector_id_from_origin(&screen_origin).ok_or_else(|| {
-                LifelogError::Validation {
+            if Self::collector_id_from_origin(&screen_origin).is_none() {
+                return Err(LifelogError::Validation {
                     field: "screen_origin".to_string(),
                     reason: "screen origin must include collector identity".to_string(),
-                }
-            })?;
-            let client = pool
-                .get()
-                .await
-                .map_err(|e| LifelogError::Database(format!("postgres pool get failed: {e}")))?;
-            let rows = client
-                .query(
-                    "SELECT id::text AS id, t_canonical FROM frames WHERE modality = $1 AND collector_id = $2 AND time_range && tstzrange($3::timestamptz, $4::timestamptz, '[)') ORDER BY t_canonical ASC LIMIT $5",
-                    &[
-                        &screen_origin.modality_name,
-                        &collector_id,
-                        &start,
-                        &end,
-                        &(max_steps as i64),
-                    ],
-                )
-                .await
-                .map_err(|e| {
-                    LifelogError::Database(format!("replay screen query failed: {e}"))
-                })?;
-            rows.into_iter()
-                .filter_map(|r| {
-                    let uuid: String = r.get("id");
-                    let t_canonical: Option<chrono::DateTime<Utc>> = r.get("t_canonical");
-                    Some((uuid, t_canonical?))
-                })
+                });
+            }
+            let filter = SpanFilter {
+                end_inclusive: false,
+                distinct: true,
+                limit: max_steps,
+            };
+            self.store
+                .frame_spans(&screen_origin, start, end, filter)
+                .await?
+                .into_iter()
+                .map(|span| (span.key.uuid.to_string(), span.start))
                 .collect()
         };
 
@@ -898,46 +1355,20 @@ impl Server {
             };
 
             for origin in resolved {
-                let pool = &self.postgres_pool;
-                let collector_id = Self::collector_id_from_origin(&origin).unwrap_or("unknown");
-                let client = pool.get().await.map_err(|e| {
-                    LifelogError::Database(format!("postgres pool get failed: {e}"))
-                })?;
-                let rows = client
-                    .query(
-                        "SELECT id::text AS id, t_canonical, t_end FROM frames WHERE modality = $1 AND collector_id = $2 AND time_range && tstzrange($3::timestamptz, $4::timestamptz, '[]') ORDER BY t_canonical ASC LIMIT $5",
-                        &[
-                            &origin.modality_name,
-                            &collector_id,
-                            &window_start,
-                            &window_end,
-                            &(per_origin_limit as i64),
-                        ],
-                    )
-                    .await
-                    .map_err(|e| {
-                        LifelogError::Database(format!(
-                            "replay context query failed for {}: {e}",
-                            origin.modality_name
-                        ))
-                    })?;
-                for r in rows {
-                    let uuid_str: String = r.get("id");
-                    let t_canonical: Option<chrono::DateTime<Utc>> = r.get("t_canonical");
-                    let t_end: Option<chrono::DateTime<Utc>> = r.get("t_end");
-                    let Some(t0) = t_canonical else { continue };
-                    let end_t = t_end.unwrap_or(t0);
-                    if let Ok(uuid) = uuid_str.parse() {
-                        ctx_records.push(crate::replay::IntervalKey {
-                            key: LifelogFrameKey {
-                                uuid,
-                                origin: origin.clone(),
-                            },
-                            start: t0,
-                            end: end_t,
-                        });
-                    }
-                }
+                let filter = SpanFilter {
+                    end_inclusive: true,
+                    distinct: false,
+                    limit: per_origin_limit,
+                };
+                let spans = self
+                    .store
+                    .frame_spans(&origin, window_start, window_end, filter)
+                    .await?;
+                ctx_records.extend(spans.into_iter().map(|span| crate::replay::IntervalKey {
+                    key: span.key,
+                    start: span.start,
+                    end: span.end,
+                }));
             }
         }
 
@@ -964,7 +1395,38 @@ impl Server {
         &self,
     ) -> Result<crate::retention::RetentionRunSummary, LifelogError> {
         let policy = self.config.read().await.retention_policy_days.clone();
-        prune_once(&self.postgres_pool, &self.cas, &policy, Utc::now()).await
+        let summary = match (&self.postgres_pool, &self.sqlite) {
+            (Some(pool), _) => {
+                if let Err(e) = crate::partitions::ensure_ahead(pool, Utc::now()).await {
+                    tracing::warn!(error = %e, "Frame partition creation failed");
+                }
+                prune_once(pool, &self.cas, &policy, &self.retention_tiers, Utc::now()).await?
+            }
+            (None, Some(store)) => {
+                crate::retention::prune_sqlite(store, &self.cas, &policy, Utc::now()).await?
+            }
+            (None, None) => return Err(postgres_required("retention")),
+        };
+
+        let cache_cutoff = Utc::now() - chrono::Duration::days(TRANSFORM_CACHE_TTL_DAYS);
+        match self.transform_state.prune_cache(cache_cutoff).await {
+            Ok(0) => {}
+            Ok(pruned) => tracing::info!(pruned, "Pruned unused transform cache entries"),
+            Err(e) => tracing::warn!(error = %e, "Transform cache prune failed"),
+        }
+        match self.transform_state.prune_entities().await {
+            Ok(0) => {}
+            Ok(pruned) => tracing::info!(pruned, "Pruned entity mentions of deleted frames"),
+            Err(e) => tracing::warn!(error = %e, "Entity index prune failed"),
+        }
+        if let Some(pool) = &self.postgres_pool {
+            match crate::partitions::maintain_closed(pool, Utc::now()).await {
+                Ok(0) => {}
+                Ok(maintained) => tracing::info!(maintained, "Maintained closed frame partitions"),
+                Err(e) => tracing::warn!(error = %e, "Frame partition maintenance failed"),
+            }
+        }
+        Ok(summary)
     }
 
     async fn apply_system_config(&self, system_config: SystemConfig) -> Result<(), LifelogError> {
@@ -1049,21 +1511,21 @@ impl Server {
                 }
                 let state_clone = self.state.clone();
                 let cas_clone = self.cas.clone();
-                let pool_clone = self.postgres_pool.clone();
                 let dag = self.transform_dag.clone();
                 let http = self.http_client.clone();
+                let watermarks = self.watermarks.clone();
+                let store = self.store.clone();
+                let transform_state = self.transform_state.clone();
                 tokio::spawn(async move {
-                    let watermarks: std::sync::Arc<
-                        dyn crate::transform::watermark::WatermarkStore,
-                    > = std::sync::Arc::new(
-                        crate::transform::watermark::PostgresWatermarkStore::new(
-                            pool_clone.clone(),
-                        ),
-                    );
-
                     let worker =
                         std::sync::Arc::new(crate::transform::worker::PipelineWorker::new(
-                            dag, watermarks, pool_clone, cas_clone, http, 50,
+                            dag,
+                            watermarks,
+                            store,
+                            transform_state,
+                            cas_clone,
+                            http,
+                            50,
                         ));
 
                     if let Err(e) = worker.poll_once().await {
@@ -0,0 +1,196 @@
+//! Where frames live. The server keeps them in Postgres by default, or in a single SQLite file
+//! for laptop-only deployments (`[server] sqlitePath`).
+//!
+//! [`FrameStore`] covers what ingest, search, replay and data retrieval need; the transform
+//! worker's state and retention's `retentionPolicyDays` also run on both. Features built on
+//! Postgres-only tables (retention tiers, pins, forget, summaries, meeting, episode and location
+//! detection, CAS scrub and key rotation, backups) are available only when [`Storage::postgres`]
+//! is set, and the server refuses to start when one of them is configured on SQLite.
+
+mod postgres;
+mod sqlite;
+
+pub use postgres::PostgresStore;
+pub use sqlite::{SqliteStore, SqliteTransformState, SqliteWatermarkStore};
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lifelog_core::{DataOrigin, LifelogError, LifelogFrameKey};
+use utils::cas::FsCas;
+
+use crate::frames::FrameRow;
+use crate::postgres::PostgresPool;
+use crate::query::planner::ExecutionPlan;
+use crate::transform::state::{PostgresTransformState, TransformStateStore};
+use crate::transform::watermark::{PostgresWatermarkStore, WatermarkStore};
+
+/// One received upload chunk, recorded so collectors can resume and ACKs can wait for indexing.
+#[derive(Debug, Clone)]
+pub struct UploadChunk {
+    pub collector_id: String,
+    pub stream_id: String,
+    pub session_id: i64,
+    pub offset: i64,
+    pub length: i32,
+    pub hash: String,
+    pub frame_uuid: Option<String>,
+    pub indexed: bool,
+}
+
+impl UploadChunk {
+    pub fn id(&self) -> String {
+        chunk_id(
+            &self.collector_id,
+            &self.stream_id,
+            self.session_id,
+            self.offset,
+        )
+    }
+}
+
+pub fn chunk_id(collector_id: &str, stream_id: &str, session_id: i64, offset: i64) -> String {
+    format!("{}_{}_{}_{}", collector_id, stream_id, session_id, offset)
+}
+
+/// Which frames [`FrameStore::frame_spans`] returns.
+#[derive(Debug, Clone, Copy)]
+pub struct SpanFilter {
+    /// Frames touching `end` exactly are included.
+    pub end_inclusive: bool,
+    /// Near-duplicate images are left out.
+    pub distinct: bool,
+    pub limit: usize,
+}
+
+/// A frame's key and the time it covers.
+#[derive(Debug, Clone)]
+pub struct FrameSpan {
+    pub key: LifelogFrameKey,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// Frame count and on-disk size reported in the server state.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StoreStats {
+    pub frames: u64,
+    pub bytes: u64,
+}
+
+#[async_trait]
+pub trait FrameStore: Send + Sync + std::fmt::Debug {
+    /// Stores a frame decoded from an upload chunk. Near-duplicate images are marked (see
+    /// [`crate::phash`]), sensitive payloads sealed, and the frame's origin registered. A frame
+    /// whose ID already exists is left as it is.
+    async fn insert_ingested(&self, row: &mut FrameRow) -> Result<(), LifelogError>;
+
+    /// Inserts a derived frame unless one already exists for the same source, stream and
+    /// modality. Returns whether a row was written.
+    async fn insert_transform_output(&self, row: &FrameRow) -> Result<bool, LifelogError>;
+
+    async fn upsert(&self, row: &FrameRow) -> Result<(), LifelogError>;
+
+    async fn get_by_ids(
+        &self,
+        cas: &FsCas,
+        ids: &[uuid::Uuid],
+    ) -> Result<Vec<lifelog_types::LifelogData>, LifelogError>;
+
+    /// Whether the frame's payload is stored sealed; see [`crate::payload_crypto`].
+    async fn is_sealed(&self, id: uuid::Uuid) -> Result<bool, LifelogError>;
+
+    /// Keys of `origin`'s frames after `after`, oldest first, without near-duplicates.
+    async fn get_keys_after(
+        &self,
+        origin: &DataOrigin,
+        after: DateTime<Utc>,
+        limit: usize,
+        exclude_derived: bool,
+    ) -> Result<Vec<LifelogFrameKey>, LifelogError>;
+
+    async fn count_keys_after(
+        &self,
+        origin: &DataOrigin,
+        after: DateTime<Utc>,
+    ) -> Result<i64, LifelogError>;
+
+    /// Every (collector, modality) pair that has frames.
+    async fn origins(&self) -> Result<Vec<DataOrigin>, LifelogError>;
+
+    /// The newest frames across all origins, without near-duplicates.
+    async fn recent_keys(&self, limit: usize) -> Result<Vec<LifelogFrameKey>, LifelogError>;
+
+    /// The frames of `origin`'s collector and modality overlapping `[start, end)`, or
+    /// `[start, end]` with [`SpanFilter::end_inclusive`], oldest first.
+    async fn frame_spans(
+        &self,
+        origin: &DataOrigin,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        filter: SpanFilter,
+    ) -> Result<Vec<FrameSpan>, LifelogError>;
+
+    async fn execute(&self, plan: ExecutionPlan) -> Result<Vec<LifelogFrameKey>, anyhow::Error>;
+
+    /// Records a chunk. A chunk recorded again stays indexed once it has been.
+    async fn record_chunk(&self, chunk: &UploadChunk) -> Result<(), LifelogError>;
+
+    async fn chunk_indexed(&self, chunk_id: &str) -> Result<bool, LifelogError>;
+
+    /// The byte offset a collector resumes the session's stream from.
+    async fn upload_offset(
+        &self,
+        collector_id: &str,
+        stream_id: &str,
+        session_id: i64,
+    ) -> Result<u64, LifelogError>;
+
+    async fn stats(&self) -> Result<StoreStats, LifelogError>;
+
+    /// Checks the database answers, for health reporting.
+    async fn ping(&self) -> Result<(), LifelogError>;
+}
+
+/// The storage a server runs on.
+#[derive(Debug, Clone)]
+pub struct Storage {
+    pub frames: Arc<dyn FrameStore>,
+    /// Set when running on Postgres.
+    pub postgres: Option<PostgresPool>,
+    /// Set when running on SQLite.
+    pub sqlite: Option<SqliteStore>,
+    pub watermarks: Arc<dyn WatermarkStore>,
+    pub transform_state: Arc<dyn TransformStateStore>,
+}
+
+impl Storage {
+    pub fn postgres(pool: PostgresPool) -> Self {
+        Self {
+            frames: Arc::new(PostgresStore::new(pool.clone())),
+            watermarks: Arc::new(PostgresWatermarkStore::new(pool.clone())),
+            transform_state: Arc::new(PostgresTransformState::new(pool.clone())),
+            postgres: Some(pool),
+            sqlite: None,
+        }
+    }
+
+    pub fn sqlite(store: SqliteStore) -> Self {
+        Self {
+            frames: Arc::new(store.clone()),
+            watermarks: Arc::new(SqliteWatermarkStore::new(store.clone())),
+            transform_state: Arc::new(SqliteTransformState::new(store.clone())),
+            postgres: None,
+            sqlite: Some(store),
+        }
+    }
+}
+
+/// Error for a feature used on a server without Postgres.
+pub fn postgres_required(feature: &str) -> LifelogError {
+    LifelogError::Validation {
+        field: "storage".to_string(),
+        reason: format!("{feature} requires the Postgres storage backend"),
+    }
+}
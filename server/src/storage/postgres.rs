@@ -0,0 +1,315 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lifelog_core::{DataOrigin, DataOriginType, LifelogError, LifelogFrameKey};
+use utils::cas::FsCas;
+
+use super::{FrameSpan, FrameStore, SpanFilter, StoreStats, UploadChunk};
+use crate::frames::{self, FrameRow};
+use crate::postgres::PostgresPool;
+use crate::query::planner::ExecutionPlan;
+
+#[derive(Debug, Clone)]
+pub struct PostgresStore {
+    pool: PostgresPool,
+}
+
+impl PostgresStore {
+    pub fn new(pool: PostgresPool) -> Self {
+        Self { pool }
+    }
+
+    async fn client(&self) -> Result<deadpool_postgres::Object, LifelogError> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| LifelogError::Database(format!("pool: {e}")))
+    }
+}
+
+#[async_trait]
+impl FrameStore for PostgresStore {
+    async fn insert_ingested(&self, row: &mut FrameRow) -> Result<(), LifelogError> {
+        let client = self.client().await?;
+
+        // Near-identical captures are kept but marked, so transforms skip them and
+        // timelines show the run once.
+        if let Some(hash) = row.payload["phash"]
+            .as_str()
+            .and_then(crate::phash::from_hex)
+        {
+            match crate::phash::find_duplicate_of(&client, row, hash).await {
+                Ok(Some(original)) => {
+                    row.payload["duplicate_of"] = original.to_string().into();
+                    row.indexed = true;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!(frame_id = %row.id, error = %e, "Duplicate check failed")
+                }
+            }
+        }
+
+        let row = row.sealed().map_err(|e| {
+            LifelogError::Database(format!(
+                "payload seal failed for {} (id={}): {e}",
+                row.modality, row.id
+            ))
+        })?;
+        let language = row.search_language();
+        let params = row.insert_params(&language);
+        crate::partitions::execute_routed(
+            &client,
+            FrameRow::insert_sql(),
+            &params,
+            row.t_canonical,
+        )
+        .await
+        .map_err(|e| {
+            LifelogError::Database(format!(
+                "postgres insert frames failed for {} (id={}): {e:?}",
+                row.modality, row.id
+            ))
+        })?;
+
+        let origin_key = format!("{}:{}", row.collector_id, row.modality);
+        client
+            .execute(
+                "INSERT INTO catalog (origin, collector_id, modality, stream_id)
+                 VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (origin) DO NOTHING",
+                &[
+                    &origin_key,
+                    &row.collector_id,
+                    &row.modality,
+                    &row.stream_id,
+                ],
+            )
+            .await
+            .map_err(|e| {
+                LifelogError::Database(format!(
+                    "postgres catalog registration failed for origin={origin_key}: {e}"
+                ))
+            })?;
+        Ok(())
+    }
+
+    async fn insert_transform_output(&self, row: &FrameRow) -> Result<bool, LifelogError> {
+        frames::insert_transform_output(&self.pool, row).await
+    }
+
+    async fn upsert(&self, row: &FrameRow) -> Result<(), LifelogError> {
+        frames::upsert(&self.pool, row).await
+    }
+
+    async fn get_by_ids(
+        &self,
+        cas: &FsCas,
+        ids: &[uuid::Uuid],
+    ) -> Result<Vec<lifelog_types::LifelogData>, LifelogError> {
+        frames::get_by_ids(&self.pool, cas, ids).await
+    }
+
+    async fn is_sealed(&self, id: uuid::Uuid) -> Result<bool, LifelogError> {
+        let client = self.client().await?;
+        let row = client
+            .query_opt(
+                "SELECT payload ? 'sealed' FROM frames WHERE id = $1",
+                &[&id],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("sealed lookup: {e}")))?;
+        Ok(row.is_some_and(|r| r.get::<_, bool>(0)))
+    }
+
+    async fn get_keys_after(
+        &self,
+        origin: &DataOrigin,
+        after: DateTime<Utc>,
+        limit: usize,
+        exclude_derived: bool,
+    ) -> Result<Vec<LifelogFrameKey>, LifelogError> {
+        frames::get_keys_after_filtered(&self.pool, origin, after, limit, exclude_derived).await
+    }
+
+    async fn count_keys_after(
+        &self,
+        origin: &DataOrigin,
+        after: DateTime<Utc>,
+    ) -> Result<i64, LifelogError> {
+        frames::count_keys_after(&self.pool, origin, after).await
+    }
+
+    async fn origins(&self) -> Result<Vec<DataOrigin>, LifelogError> {
+        frames::get_origins(&self.pool).await
+    }
+
+    async fn recent_keys(&self, limit: usize) -> Result<Vec<LifelogFrameKey>, LifelogError> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                "SELECT id, collector_id, stream_id FROM frames WHERE NOT (payload ? 'duplicate_of') ORDER BY t_canonical DESC NULLS LAST LIMIT $1",
+                &[&(limit as i64)],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("recent frames query: {e}")))?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let id: uuid::Uuid = row.get("id");
+                let collector_id: String = row.get("collector_id");
+                let stream_id: String = row.get("stream_id");
+                let origin = DataOrigin {
+                    modality_name: stream_id,
+                    origin: DataOriginType::DeviceId(collector_id),
+                };
+                LifelogFrameKey::new(id, origin)
+            })
+            .collect())
+    }
+
+    async fn frame_spans(
+        &self,
+        origin: &DataOrigin,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        filter: SpanFilter,
+    ) -> Result<Vec<FrameSpan>, LifelogError> {
+        let collector_id = origin.collector_id().unwrap_or("unknown");
+        let bounds = if filter.end_inclusive { "[]" } else { "[)" };
+        let distinct = if filter.distinct {
+            " AND NOT (payload ? 'duplicate_of')"
+        } else {
+            ""
+        };
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                &format!(
+                    "SELECT id, t_canonical, t_end FROM frames WHERE modality = $1 AND collector_id = $2 AND time_range && tstzrange($3::timestamptz, $4::timestamptz, '{bounds}') AND t_canonical <= $4 AND t_canonical >= $3::timestamptz - frames_max_span(){distinct} ORDER BY t_canonical ASC LIMIT $5"
+                ),
+                &[
+                    &origin.modality_name,
+                    &collector_id,
+                    &start,
+                    &end,
+                    &(filter.limit as i64),
+                ],
+            )
+            .await
+            .map_err(|e| {
+                LifelogError::Database(format!(
+                    "frame span query failed for {}: {e}",
+                    origin.modality_name
+                ))
+            })?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let id: uuid::Uuid = row.get("id");
+                let start: DateTime<Utc> = row.get("t_canonical");
+                let end: Option<DateTime<Utc>> = row.get("t_end");
+                FrameSpan {
+                    key: LifelogFrameKey::new(id, origin.clone()),
+                    start,
+                    end: end.unwrap_or(start),
+                }
+            })
+            .collect())
+    }
+
+    async fn execute(&self, plan: ExecutionPlan) -> Result<Vec<LifelogFrameKey>, anyhow::Error> {
+        crate::query::executor::execute_postgres(&self.pool, plan).await
+    }
+
+    async fn record_chunk(&self, chunk: &UploadChunk) -> Result<(), LifelogError> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "INSERT INTO upload_chunks (
+                    id, collector_id, stream_id, session_id, \"offset\", length, hash, frame_uuid, indexed
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                ON CONFLICT (id) DO UPDATE
+                SET indexed = (upload_chunks.indexed OR EXCLUDED.indexed)",
+                &[
+                    &chunk.id(),
+                    &chunk.collector_id,
+                    &chunk.stream_id,
+                    &chunk.session_id,
+                    &chunk.offset,
+                    &chunk.length,
+                    &chunk.hash,
+                    &chunk.frame_uuid,
+                    &chunk.indexed,
+                ],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("postgres upsert upload_chunks failed: {e}")))?;
+        Ok(())
+    }
+
+    async fn chunk_indexed(&self, chunk_id: &str) -> Result<bool, LifelogError> {
+        let client = self.client().await?;
+        let row = client
+            .query_opt(
+                "SELECT indexed FROM upload_chunks WHERE id = $1",
+                &[&chunk_id],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("upload_chunks select: {e}")))?;
+        Ok(row.map(|r| r.get::<_, bool>(0)).unwrap_or(false))
+    }
+
+    async fn upload_offset(
+        &self,
+        collector_id: &str,
+        stream_id: &str,
+        session_id: i64,
+    ) -> Result<u64, LifelogError> {
+        let client = self.client().await?;
+        let row = client
+            .query_opt(
+                "SELECT \"offset\", length
+                 FROM upload_chunks
+                 WHERE collector_id = $1 AND stream_id = $2 AND session_id = $3
+                 ORDER BY \"offset\" DESC
+                 LIMIT 1",
+                &[&collector_id, &stream_id, &session_id],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("Postgres query error: {e}")))?;
+        Ok(row
+            .map(|r| {
+                let offset: i64 = r.get(0);
+                let length: i32 = r.get(1);
+                offset as u64 + length as u64
+            })
+            .unwrap_or(0))
+    }
+
+    async fn stats(&self) -> Result<StoreStats, LifelogError> {
+        let client = self.client().await?;
+        let frames: i64 = client
+            .query_one("SELECT COUNT(*)::BIGINT AS total FROM frames", &[])
+            .await
+            .map_err(|e| LifelogError::Database(format!("frame count: {e}")))?
+            .get(0);
+        let bytes: i64 = client
+            .query_one("SELECT pg_database_size(current_database())::BIGINT", &[])
+            .await
+            .map_err(|e| LifelogError::Database(format!("database size: {e}")))?
+            .get(0);
+        Ok(StoreStats {
+            frames: frames as u64,
+            bytes: bytes as u64,
+        })
+    }
+
+    async fn ping(&self) -> Result<(), LifelogError> {
+        self.client()
+            .await?
+            .execute("SELECT 1", &[])
+            .await
+            .map_err(|e| LifelogError::Database(format!("postgres query failed: {e}")))?;
+        Ok(())
+    }
+}
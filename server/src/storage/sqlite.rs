@@ -0,0 +1,1458 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lifelog_core::{DataOrigin, DataOriginType, LifelogError, LifelogFrameKey};
+use rusqlite::{params, Connection, InterruptHandle, OptionalExtension};
+use serde_json::Value as JsonValue;
+use utils::cas::FsCas;
+
+use super::{FrameSpan, FrameStore, SpanFilter, StoreStats, UploadChunk};
+use crate::frames::{to_lifelog_data, FrameRow};
+use crate::query::planner::ExecutionPlan;
+use crate::transform::cache::CachedOutput;
+use crate::transform::dead_letter::{self, DeadLetter};
+use crate::transform::entities::EntityOutput;
+use crate::transform::secrets::SCAN_PENDING_FIELD;
+use crate::transform::state::{PrivacyFindings, TransformStateStore};
+use crate::transform::watermark::WatermarkStore;
+
+struct EmbeddedMigration {
+    version: &'static str,
+    sql: &'static str,
+}
+
+const MIGRATIONS: &[EmbeddedMigration] = &[
+    EmbeddedMigration {
+        version: "20260327000000_init_sqlite.sql",
+        sql: include_str!("../../migrations/sqlite/20260327000000_init_sqlite.sql"),
+    },
+    EmbeddedMigration {
+        version: "20260328000000_transform_state.sql",
+        sql: include_str!("../../migrations/sqlite/20260328000000_transform_state.sql"),
+    },
+];
+
+const FRAME_COLUMNS: &str = "id, collector_id, stream_id, modality, t_device, t_ingest, t_canonical, t_end, time_quality, blob_hash, blob_size, indexed, source_frame_id, payload";
+
+const INSERT_FRAME_SQL: &str = "INSERT INTO frames (
+        id, collector_id, stream_id, modality, t_device, t_ingest, t_canonical, t_end,
+        time_quality, blob_hash, blob_size, indexed, source_frame_id, payload, language, phash
+    ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)";
+
+/// Frames in a single SQLite file, for running the server without Postgres.
+///
+/// Writes go through one connection; plans run on a second one so a slow search can be
+/// interrupted without disturbing ingest. The file is in WAL mode, so searches see every
+/// committed frame.
+#[derive(Clone)]
+pub struct SqliteStore {
+    path: PathBuf,
+    conn: Arc<Mutex<Connection>>,
+    query_conn: Arc<Mutex<Connection>>,
+    interrupt: Arc<InterruptHandle>,
+}
+
+impl std::fmt::Debug for SqliteStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqliteStore")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SqliteStore {
+    /// Opens the database at `path`, creating it and its directory if needed, and applies
+    /// pending migrations.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, LifelogError> {
+        let path = path.as_ref().to_path_buf();
+        let opened = path.clone();
+        let (conn, query_conn) = tokio::task::spawn_blocking(move || {
+            if let Some(dir) = opened.parent().filter(|d| !d.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("create {}", dir.display()))?;
+            }
+            let mut conn = open_connection(&opened)?;
+            run_migrations(&mut conn)?;
+            let query_conn = open_connection(&opened)?;
+            Ok::<_, anyhow::Error>((conn, query_conn))
+        })
+        .await
+        .map_err(|e| LifelogError::Database(format!("sqlite open: {e}")))?
+        .map_err(|e| LifelogError::Database(format!("sqlite open {}: {e:#}", path.display())))?;
+
+        Ok(Self {
+            path,
+            interrupt: Arc::new(query_conn.get_interrupt_handle()),
+            conn: Arc::new(Mutex::new(conn)),
+            query_conn: Arc::new(Mutex::new(query_conn)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Modalities that have frames, for retention.
+    pub async fn modalities(&self) -> Result<Vec<String>, LifelogError> {
+        self.with_conn("sqlite retention modality query", |conn| {
+            let mut stmt = conn.prepare("SELECT DISTINCT modality FROM frames")?;
+            let modalities = stmt
+                .query_map([], |r| r.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok(modalities)
+        })
+        .await
+    }
+
+    /// Deletes `modality`'s frames captured before `cutoff`. Returns how many were deleted and
+    /// the blobs they referenced.
+    pub async fn delete_before(
+        &self,
+        modality: &str,
+        cutoff: DateTime<Utc>,
+    ) -> Result<(u64, Vec<String>), LifelogError> {
+        let modality = modality.to_string();
+        self.with_conn("sqlite retention delete", move |conn| {
+            let tx = conn.transaction()?;
+            let hashes = {
+                let mut stmt = tx.prepare(
+                    "SELECT DISTINCT blob_hash FROM frames
+                     WHERE modality = ?1 AND t_canonical < ?2 AND blob_hash IS NOT NULL AND blob_hash <> ''",
+                )?;
+                let hashes = stmt
+                    .query_map(params![modality, micros(cutoff)], |r| r.get(0))?
+                    .collect::<Result<Vec<String>, _>>()?;
+                hashes
+            };
+            let deleted = tx.execute(
+                "DELETE FROM frames WHERE modality = ?1 AND t_canonical < ?2",
+                params![modality, micros(cutoff)],
+            )?;
+            tx.commit()?;
+            Ok((deleted as u64, hashes))
+        })
+        .await
+    }
+
+    /// Whether any frame still references the blob.
+    pub async fn blob_referenced(&self, hash: &str) -> Result<bool, LifelogError> {
+        let hash = hash.to_string();
+        self.with_conn("sqlite retention ref check", move |conn| {
+            Ok(conn
+                .query_row(
+                    "SELECT 1 FROM frames WHERE blob_hash = ?1 LIMIT 1",
+                    [hash],
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_some())
+        })
+        .await
+    }
+
+    /// Deletes upload chunk records received before `cutoff`.
+    pub async fn prune_upload_chunks(&self, cutoff: DateTime<Utc>) -> Result<u64, LifelogError> {
+        self.with_conn("sqlite prune upload_chunks", move |conn| {
+            Ok(conn.execute(
+                "DELETE FROM upload_chunks WHERE created_at < ?1",
+                [micros(cutoff)],
+            )? as u64)
+        })
+        .await
+    }
+
+    async fn with_conn<T, F>(&self, context: &'static str, f: F) -> Result<T, LifelogError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> anyhow::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| anyhow!("sqlite connection lock poisoned"))?;
+            f(&mut conn)
+        })
+        .await
+        .map_err(|e| LifelogError::Database(format!("{context}: {e}")))?
+        .map_err(|e| LifelogError::Database(format!("{context}: {e:#}")))
+    }
+}
+
+fn open_connection(path: &Path) -> anyhow::Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(std::time::Duration::from_secs(5))?;
+    conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+    conn.execute_batch("PRAGMA synchronous = NORMAL;")?;
+    Ok(conn)
+}
+
+fn run_migrations(conn: &mut Connection) -> anyhow::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version TEXT PRIMARY KEY,
+            applied_at INTEGER NOT NULL
+        );",
+    )?;
+    for migration in MIGRATIONS {
+        let tx = conn.transaction()?;
+        let applied = tx
+            .query_row(
+                "SELECT 1 FROM schema_migrations WHERE version = ?1",
+                [migration.version],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        if applied {
+            continue;
+        }
+        tx.execute_batch(migration.sql)
+            .with_context(|| format!("migration {}", migration.version))?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, applied_at) VALUES (?1, ?2)",
+            params![migration.version, micros(Utc::now())],
+        )?;
+        tx.commit()?;
+        tracing::info!(version = migration.version, "Applied SQLite migration");
+    }
+    Ok(())
+}
+
+fn micros(t: DateTime<Utc>) -> i64 {
+    t.timestamp_micros()
+}
+
+fn from_micros(us: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_micros(us).unwrap_or_default()
+}
+
+fn phash_of(row: &FrameRow) -> Option<u64> {
+    row.payload["phash"]
+        .as_str()
+        .and_then(crate::phash::from_hex)
+}
+
+/// Writes `row`, already sealed, with the given conflict handling, and indexes its text.
+/// Returns whether a row was written.
+fn write_frame(conn: &Connection, row: &FrameRow, on_conflict: &str) -> anyhow::Result<bool> {
+    let language = row.search_language();
+    let payload = serde_json::to_string(&row.payload)?;
+    let written = conn.execute(
+        &format!("{INSERT_FRAME_SQL} {on_conflict}"),
+        params![
+            row.id.to_string(),
+            row.collector_id,
+            row.stream_id,
+            row.modality,
+            row.t_device.map(micros),
+            micros(row.t_ingest),
+            micros(row.t_canonical),
+            row.t_end.map(micros),
+            row.time_quality,
+            row.blob_hash,
+            row.blob_size,
+            row.indexed,
+            row.source_frame_id.map(|id| id.to_string()),
+            payload,
+            language,
+            phash_of(row).map(|h| h as i64),
+        ],
+    )?;
+    if written == 0 {
+        return Ok(false);
+    }
+
+    let seq: i64 = conn.query_row(
+        "SELECT seq FROM frames WHERE id = ?1",
+        [row.id.to_string()],
+        |r| r.get(0),
+    )?;
+    index_text(conn, seq, &row.modality, &row.payload)?;
+    Ok(true)
+}
+
+/// Replaces the full-text entry of the frame at `seq` with its stored payload's text.
+fn index_text(
+    conn: &Connection,
+    seq: i64,
+    modality: &str,
+    payload: &JsonValue,
+) -> anyhow::Result<()> {
+    conn.execute("DELETE FROM frames_fts WHERE rowid = ?1", [seq])?;
+    if let Some([primary, secondary, window_title]) = search_text(modality, payload) {
+        conn.execute(
+            "INSERT INTO frames_fts (rowid, primary_text, secondary_text, window_title)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![seq, primary, secondary, window_title],
+        )?;
+    }
+    Ok(())
+}
+
+/// The text `search_doc` indexes for a stored payload, split into its A, B and C weighted
+/// parts. Keep in sync with the `smart_search_doc` Postgres function.
+fn search_text(modality: &str, payload: &JsonValue) -> Option<[String; 3]> {
+    if matches!(
+        modality,
+        "Processes" | "Hyprland" | "Mouse" | "VectorEmbedding"
+    ) {
+        return None;
+    }
+    if payload.get("pii_findings").is_some() && payload_text(payload, "pii_redacted") != "true" {
+        return None;
+    }
+    if payload.get(SCAN_PENDING_FIELD).is_some() {
+        return None;
+    }
+    let join = |keys: &[&str]| {
+        keys.iter()
+            .map(|k| payload_text(payload, k))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let parts = [
+        join(&["text", "content", "transcript"]),
+        join(&["title", "url", "command", "application"]),
+        payload_text(payload, "window_title"),
+    ];
+    parts.iter().any(|p| !p.trim().is_empty()).then_some(parts)
+}
+
+/// `payload->>'key'`: strings as they are, other values as JSON, missing and null as empty.
+fn payload_text(payload: &JsonValue, key: &str) -> String {
+    match payload.get(key) {
+        None | Some(JsonValue::Null) => String::new(),
+        Some(JsonValue::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+/// [`crate::phash::find_duplicate_of`] against the SQLite frames table.
+fn find_duplicate_of(
+    conn: &Connection,
+    row: &FrameRow,
+    hash: u64,
+) -> anyhow::Result<Option<String>> {
+    let since = row.t_canonical - crate::phash::MAX_DUPLICATE_GAP;
+    let prev = conn
+        .query_row(
+            "SELECT p.id, p.phash, r.id, r.phash
+             FROM frames p
+             LEFT JOIN frames r ON r.id = json_extract(p.payload, '$.duplicate_of')
+             WHERE p.collector_id = ?1 AND p.stream_id = ?2 AND p.modality = ?3
+             AND p.t_canonical < ?4 AND p.t_canonical >= ?5 AND p.phash IS NOT NULL
+             ORDER BY p.t_canonical DESC
+             LIMIT 1",
+            params![
+                row.collector_id,
+                row.stream_id,
+                row.modality,
+                micros(row.t_canonical),
+                micros(since)
+            ],
+            |r| {
+                Ok((
+                    r.get::<_, String>(0)?,
+                    r.get::<_, i64>(1)?,
+                    r.get::<_, Option<String>>(2)?,
+                    r.get::<_, Option<i64>>(3)?,
+                ))
+            },
+        )
+        .optional()?;
+    let Some((prev_id, prev_hash, rep_id, rep_hash)) = prev else {
+        return Ok(None);
+    };
+    let (id, other) = match (rep_id, rep_hash) {
+        (Some(id), Some(h)) => (id, h),
+        _ => (prev_id, prev_hash),
+    };
+    Ok(
+        (crate::phash::hamming(hash, other as u64) <= crate::phash::DUPLICATE_DISTANCE)
+            .then_some(id),
+    )
+}
+
+fn read_frame_row(row: &rusqlite::Row) -> anyhow::Result<FrameRow> {
+    let id: uuid::Uuid = row.get::<_, String>("id")?.parse()?;
+    let payload: String = row.get("payload")?;
+    let mut payload: JsonValue = serde_json::from_str(&payload)?;
+    crate::payload_crypto::open_payload(id, &mut payload)
+        .map_err(|e| anyhow!("frame {id} payload: {e}"))?;
+    let source_frame_id = row
+        .get::<_, Option<String>>("source_frame_id")?
+        .map(|s| s.parse())
+        .transpose()?;
+    Ok(FrameRow {
+        id,
+        collector_id: row.get("collector_id")?,
+        stream_id: row.get("stream_id")?,
+        modality: row.get("modality")?,
+        t_device: row.get::<_, Option<i64>>("t_device")?.map(from_micros),
+        t_ingest: from_micros(row.get("t_ingest")?),
+        t_canonical: from_micros(row.get("t_canonical")?),
+        t_end: row.get::<_, Option<i64>>("t_end")?.map(from_micros),
+        time_quality: row.get("time_quality")?,
+        blob_hash: row.get("blob_hash")?,
+        blob_size: row.get("blob_size")?,
+        indexed: row.get("indexed")?,
+        source_frame_id,
+        payload,
+    })
+}
+
+fn parse_key(id: &str, origin: DataOrigin) -> Option<LifelogFrameKey> {
+    id.parse::<lifelog_core::uuid::Uuid>()
+        .ok()
+        .map(|uuid| LifelogFrameKey { uuid, origin })
+}
+
+#[async_trait]
+impl FrameStore for SqliteStore {
+    async fn insert_ingested(&self, row: &mut FrameRow) -> Result<(), LifelogError> {
+        let pending = row.clone();
+        *row = self
+            .with_conn("sqlite insert frame", move |conn| {
+                let mut row = pending;
+                let tx = conn.transaction()?;
+                // Near-identical captures are kept but marked, so transforms skip them and
+                // timelines show the run once.
+                if let Some(hash) = phash_of(&row) {
+                    match find_duplicate_of(&tx, &row, hash) {
+                        Ok(Some(original)) => {
+                            row.payload["duplicate_of"] = original.into();
+                            row.indexed = true;
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            tracing::warn!(frame_id = %row.id, error = %e, "Duplicate check failed")
+                        }
+                    }
+                }
+
+                let sealed = row.sealed().map_err(|e| {
+                    anyhow!(
+                        "payload seal failed for {} (id={}): {e}",
+                        row.modality,
+                        row.id
+                    )
+                })?;
+                write_frame(&tx, &sealed, "ON CONFLICT (id) DO NOTHING")?;
+                tx.execute(
+                    "INSERT INTO catalog (origin, collector_id, modality, stream_id, created_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT (origin) DO NOTHING",
+                    params![
+                        format!("{}:{}", row.collector_id, row.modality),
+                        row.collector_id,
+                        row.modality,
+                        row.stream_id,
+                        micros(Utc::now())
+                    ],
+                )?;
+                tx.commit()?;
+                Ok(row)
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_transform_output(&self, row: &FrameRow) -> Result<bool, LifelogError> {
+        let row = row.clone();
+        self.with_conn("sqlite transform output insert", move |conn| {
+            let tx = conn.transaction()?;
+            let sensitive_source = match row
+                .source_frame_id
+                .filter(|_| crate::payload_crypto::configured().is_some())
+            {
+                Some(source) => tx
+                    .query_row(
+                        "SELECT modality, json_extract(payload, '$.sealed') IS NOT NULL
+                         FROM frames WHERE id = ?1",
+                        [source.to_string()],
+                        |r| Ok(crate::payload_crypto::is_sensitive(&r.get::<_, String>(0)?) || r.get(1)?),
+                    )
+                    .optional()?
+                    .unwrap_or(false),
+                None => false,
+            };
+            let sealed = row
+                .sealed_derived(sensitive_source)
+                .map_err(|e| anyhow!("transform output seal (id={}): {e}", row.id))?;
+            let written = write_frame(
+                &tx,
+                &sealed,
+                "ON CONFLICT (source_frame_id, stream_id, modality) WHERE source_frame_id IS NOT NULL DO NOTHING",
+            )?;
+            tx.commit()?;
+            Ok(written)
+        })
+        .await
+    }
+
+    async fn upsert(&self, row: &FrameRow) -> Result<(), LifelogError> {
+        let row = row.clone();
+        self.with_conn("sqlite frames upsert", move |conn| {
+            let sealed = row
+                .sealed()
+                .map_err(|e| anyhow!("frames upsert seal (id={}): {e}", row.id))?;
+            let tx = conn.transaction()?;
+            write_frame(
+                &tx,
+                &sealed,
+                "ON CONFLICT (id) DO UPDATE SET
+                    payload = excluded.payload,
+                    t_ingest = excluded.t_ingest,
+                    t_canonical = excluded.t_canonical,
+                    t_end = excluded.t_end,
+                    time_quality = excluded.time_quality,
+                    indexed = excluded.indexed,
+                    language = excluded.language,
+                    phash = excluded.phash",
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_by_ids(
+        &self,
+        cas: &FsCas,
+        ids: &[uuid::Uuid],
+    ) -> Result<Vec<lifelog_types::LifelogData>, LifelogError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let ids = serde_json::to_string(&ids.iter().map(|id| id.to_string()).collect::<Vec<_>>())
+            .map_err(|e| LifelogError::Database(format!("frame ids: {e}")))?;
+        let rows = self
+            .with_conn("sqlite frames batch select", move |conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT {FRAME_COLUMNS} FROM frames WHERE id IN (SELECT value FROM json_each(?1))"
+                ))?;
+                let mut rows = stmt.query([ids])?;
+                let mut out = Vec::new();
+                while let Some(row) = rows.next()? {
+                    out.push(read_frame_row(row));
+                }
+                Ok(out)
+            })
+            .await?;
+
+        let mut results = Vec::with_capacity(rows.len());
+        for row in rows {
+            match row {
+                Ok(frame_row) => match to_lifelog_data(&frame_row, cas) {
+                    Ok(data) => results.push(data),
+                    Err(e) => tracing::error!(error = %e, "Failed to convert frame row"),
+                },
+                Err(e) => tracing::error!(error = %e, "Failed to parse frame row"),
+            }
+        }
+        Ok(results)
+    }
+
+    async fn is_sealed(&self, id: uuid::Uuid) -> Result<bool, LifelogError> {
+        self.with_conn("sqlite sealed lookup", move |conn| {
+            Ok(conn
+                .query_row(
+                    "SELECT json_extract(payload, '$.sealed') IS NOT NULL FROM frames WHERE id = ?1",
+                    [id.to_string()],
+                    |r| r.get::<_, bool>(0),
+                )
+                .optional()?
+                .unwrap_or(false))
+        })
+        .await
+    }
+
+    async fn get_keys_after(
+        &self,
+        origin: &DataOrigin,
+        after: DateTime<Utc>,
+        limit: usize,
+        exclude_derived: bool,
+    ) -> Result<Vec<LifelogFrameKey>, LifelogError> {
+        let origin = origin.clone();
+        self.with_conn("sqlite frames keys query", move |conn| {
+            // Near-duplicate images are never handed to transforms; see `crate::phash`.
+            let derived_filter = if exclude_derived {
+                " AND source_frame_id IS NULL"
+            } else {
+                ""
+            };
+            let mut stmt = conn.prepare(&format!(
+                "SELECT id FROM frames WHERE modality = ?1 AND collector_id = ?2 AND t_canonical > ?3 AND json_type(payload, '$.duplicate_of') IS NULL{derived_filter} ORDER BY t_canonical ASC LIMIT ?4"
+            ))?;
+            let ids = stmt
+                .query_map(
+                    params![
+                        origin.modality_name,
+                        origin.collector_id().unwrap_or_default(),
+                        micros(after),
+                        limit as i64
+                    ],
+                    |r| r.get::<_, String>(0),
+                )?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(ids
+                .iter()
+                .filter_map(|id| parse_key(id, origin.clone()))
+                .collect())
+        })
+        .await
+    }
+
+    async fn count_keys_after(
+        &self,
+        origin: &DataOrigin,
+        after: DateTime<Utc>,
+    ) -> Result<i64, LifelogError> {
+        let origin = origin.clone();
+        self.with_conn("sqlite frames count query", move |conn| {
+            Ok(conn.query_row(
+                "SELECT COUNT(*) FROM frames WHERE modality = ?1 AND collector_id = ?2 AND t_canonical > ?3 AND json_type(payload, '$.duplicate_of') IS NULL",
+                params![
+                    origin.modality_name,
+                    origin.collector_id().unwrap_or_default(),
+                    micros(after)
+                ],
+                |r| r.get(0),
+            )?)
+        })
+        .await
+    }
+
+    async fn origins(&self) -> Result<Vec<DataOrigin>, LifelogError> {
+        self.with_conn("sqlite frames origins query", |conn| {
+            let mut stmt = conn.prepare("SELECT DISTINCT collector_id, modality FROM frames")?;
+            let origins = stmt
+                .query_map([], |r| {
+                    Ok(DataOrigin::new(
+                        DataOriginType::DeviceId(r.get(0)?),
+                        r.get(1)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(origins)
+        })
+        .await
+    }
+
+    async fn recent_keys(&self, limit: usize) -> Result<Vec<LifelogFrameKey>, LifelogError> {
+        self.with_conn("sqlite recent frames query", move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, collector_id, stream_id FROM frames WHERE json_type(payload, '$.duplicate_of') IS NULL ORDER BY t_canonical DESC LIMIT ?1",
+            )?;
+            let rows = stmt
+                .query_map([limit as i64], |r| {
+                    Ok((
+                        r.get::<_, String>(0)?,
+                        r.get::<_, String>(1)?,
+                        r.get::<_, String>(2)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows
+                .into_iter()
+                .filter_map(|(id, collector_id, stream_id)| {
+                    let origin = DataOrigin {
+                        modality_name: stream_id,
+                        origin: DataOriginType::DeviceId(collector_id),
+                    };
+                    parse_key(&id, origin)
+                })
+                .collect())
+        })
+        .await
+    }
+
+    async fn frame_spans(
+        &self,
+        origin: &DataOrigin,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        filter: SpanFilter,
+    ) -> Result<Vec<FrameSpan>, LifelogError> {
+        let origin = origin.clone();
+        self.with_conn("sqlite frame span query", move |conn| {
+            let end_op = if filter.end_inclusive { "<=" } else { "<" };
+            let distinct = if filter.distinct {
+                " AND json_type(f.payload, '$.duplicate_of') IS NULL"
+            } else {
+                ""
+            };
+            let (start_us, end_us) = (micros(start), micros(end));
+            let mut stmt = conn.prepare(&format!(
+                "SELECT f.id, f.t_canonical, f.t_end FROM frames f
+                 WHERE f.modality = ?1 AND f.collector_id = ?2
+                 AND f.seq IN (SELECT seq FROM frame_intervals WHERE t_start <= ?3 AND t_stop >= ?4)
+                 AND f.t_canonical {end_op} ?5 AND COALESCE(f.t_end, f.t_canonical) >= ?6{distinct}
+                 ORDER BY f.t_canonical ASC LIMIT ?7"
+            ))?;
+            let rows = stmt
+                .query_map(
+                    params![
+                        origin.modality_name,
+                        origin.collector_id().unwrap_or("unknown"),
+                        end_us.div_euclid(1_000_000) + 1,
+                        start_us.div_euclid(1_000_000) - 1,
+                        end_us,
+                        start_us,
+                        filter.limit as i64
+                    ],
+                    |r| {
+                        Ok((
+                            r.get::<_, String>(0)?,
+                            r.get::<_, i64>(1)?,
+                            r.get::<_, Option<i64>>(2)?,
+                        ))
+                    },
+                )?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows
+                .into_iter()
+                .filter_map(|(id, t0, t1)| {
+                    let start = from_micros(t0);
+                    Some(FrameSpan {
+                        key: parse_key(&id, origin.clone())?,
+                        start,
+                        end: t1.map(from_micros).unwrap_or(start),
+                    })
+                })
+                .collect())
+        })
+        .await
+    }
+
+    async fn execute(&self, plan: ExecutionPlan) -> Result<Vec<LifelogFrameKey>, anyhow::Error> {
+        let conn = self.query_conn.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            let conn = conn
+                .lock()
+                .map_err(|_| anyhow!("sqlite query connection lock poisoned"))?;
+            crate::query::sqlite::execute_sqlite(&conn, plan)
+        });
+        match tokio::time::timeout(crate::query::executor::DEFAULT_DB_QUERY_TIMEOUT, task).await {
+            Ok(joined) => joined?,
+            Err(elapsed) => {
+                self.interrupt.interrupt();
+                Err(elapsed.into())
+            }
+        }
+    }
+
+    async fn record_chunk(&self, chunk: &UploadChunk) -> Result<(), LifelogError> {
+        let chunk = chunk.clone();
+        self.with_conn("sqlite upsert upload_chunks", move |conn| {
+            conn.execute(
+                "INSERT INTO upload_chunks (
+                    id, collector_id, stream_id, session_id, \"offset\", length, hash, frame_uuid, indexed, created_at
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                ON CONFLICT (id) DO UPDATE
+                SET indexed = (upload_chunks.indexed OR excluded.indexed)",
+                params![
+                    chunk.id(),
+                    chunk.collector_id,
+                    chunk.stream_id,
+                    chunk.session_id,
+                    chunk.offset,
+                    chunk.length,
+                    chunk.hash,
+                    chunk.frame_uuid,
+                    chunk.indexed,
+                    micros(Utc::now())
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn chunk_indexed(&self, chunk_id: &str) -> Result<bool, LifelogError> {
+        let chunk_id = chunk_id.to_string();
+        self.with_conn("sqlite upload_chunks select", move |conn| {
+            Ok(conn
+                .query_row(
+                    "SELECT indexed FROM upload_chunks WHERE id = ?1",
+                    [chunk_id],
+                    |r| r.get::<_, bool>(0),
+                )
+                .optional()?
+                .unwrap_or(false))
+        })
+        .await
+    }
+
+    async fn upload_offset(
+        &self,
+        collector_id: &str,
+        stream_id: &str,
+        session_id: i64,
+    ) -> Result<u64, LifelogError> {
+        let (collector_id, stream_id) = (collector_id.to_string(), stream_id.to_string());
+        self.with_conn("sqlite upload offset query", move |conn| {
+            let row = conn
+                .query_row(
+                    "SELECT \"offset\", length FROM upload_chunks
+                     WHERE collector_id = ?1 AND stream_id = ?2 AND session_id = ?3
+                     ORDER BY \"offset\" DESC
+                     LIMIT 1",
+                    params![collector_id, stream_id, session_id],
+                    |r| Ok((r.get::<_, i64>(0)?, r.get::<_, i64>(1)?)),
+                )
+                .optional()?;
+            Ok(row
+                .map(|(offset, length)| offset as u64 + length as u64)
+                .unwrap_or(0))
+        })
+        .await
+    }
+
+    async fn stats(&self) -> Result<StoreStats, LifelogError> {
+        self.with_conn("sqlite stats", |conn| {
+            let frames: i64 = conn.query_row("SELECT COUNT(*) FROM frames", [], |r| r.get(0))?;
+            let bytes: i64 = conn.query_row(
+                "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+                [],
+                |r| r.get(0),
+            )?;
+            Ok(StoreStats {
+                frames: frames as u64,
+                bytes: bytes as u64,
+            })
+        })
+        .await
+    }
+
+    async fn ping(&self) -> Result<(), LifelogError> {
+        self.with_conn("sqlite ping", |conn| {
+            conn.query_row("SELECT 1", [], |_| Ok(()))?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Transform watermarks kept in the SQLite database.
+#[derive(Debug)]
+pub struct SqliteWatermarkStore {
+    store: SqliteStore,
+}
+
+impl SqliteWatermarkStore {
+    pub fn new(store: SqliteStore) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait]
+impl WatermarkStore for SqliteWatermarkStore {
+    async fn get(&self, transform_id: &str, origin: &str) -> Result<DateTime<Utc>, LifelogError> {
+        let (transform_id, origin) = (transform_id.to_string(), origin.to_string());
+        let value = self
+            .store
+            .with_conn("watermark get", move |conn| {
+                Ok(conn
+                    .query_row(
+                        "SELECT cursor_value FROM transform_watermarks WHERE transform_id = ?1 AND origin = ?2",
+                        params![transform_id, origin],
+                        |r| r.get::<_, String>(0),
+                    )
+                    .optional()?)
+            })
+            .await?;
+
+        match value {
+            Some(val) => val
+                .parse::<DateTime<Utc>>()
+                .map_err(|e| LifelogError::Database(format!("watermark parse: {e}"))),
+            None => Ok(DateTime::<Utc>::from_timestamp(0, 0).unwrap_or_default()),
+        }
+    }
+
+    async fn set(
+        &self,
+        transform_id: &str,
+        origin: &str,
+        ts: DateTime<Utc>,
+    ) -> Result<(), LifelogError> {
+        let (transform_id, origin) = (transform_id.to_string(), origin.to_string());
+        self.store
+            .with_conn("watermark set", move |conn| {
+                conn.execute(
+                    "INSERT INTO transform_watermarks (transform_id, origin, cursor_value, updated_at)
+                     VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT (transform_id, origin) DO UPDATE
+                     SET cursor_value = excluded.cursor_value, updated_at = excluded.updated_at",
+                    params![transform_id, origin, ts.to_rfc3339(), micros(Utc::now())],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+}
+
+/// Transform cache, dead letters, entity index and privacy audit kept in the SQLite database.
+#[derive(Debug)]
+pub struct SqliteTransformState {
+    store: SqliteStore,
+}
+
+impl SqliteTransformState {
+    pub fn new(store: SqliteStore) -> Self {
+        Self { store }
+    }
+}
+
+fn read_dead_letter(row: &rusqlite::Row) -> rusqlite::Result<DeadLetter> {
+    let frame_id: String = row.get("frame_id")?;
+    let frame_id = frame_id.parse().map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+    Ok(DeadLetter {
+        transform_id: row.get("transform_id")?,
+        frame_id,
+        origin: row.get("origin")?,
+        error: row.get("error")?,
+        attempts: row.get("attempts")?,
+        first_failed_at: from_micros(row.get("first_failed_at")?),
+        last_failed_at: from_micros(row.get("last_failed_at")?),
+        next_retry_at: row.get::<_, Option<i64>>("next_retry_at")?.map(from_micros),
+    })
+}
+
+const DEAD_LETTER_COLUMNS: &str = "transform_id, frame_id, origin, error, attempts, first_failed_at, last_failed_at, next_retry_at";
+
+/// `frame_ids` as the JSON array the dead-letter statements take; empty selects every entry.
+fn frame_ids_json(frame_ids: &[uuid::Uuid]) -> String {
+    JsonValue::from(
+        frame_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>(),
+    )
+    .to_string()
+}
+
+#[async_trait]
+impl TransformStateStore for SqliteTransformState {
+    async fn cache_lookup(
+        &self,
+        transform_id: &str,
+        fingerprint: &str,
+        input_hash: &str,
+    ) -> Result<Option<CachedOutput>, LifelogError> {
+        let key = (
+            transform_id.to_string(),
+            fingerprint.to_string(),
+            input_hash.to_string(),
+        );
+        let output = self
+            .store
+            .with_conn("transform cache lookup", move |conn| {
+                Ok(conn
+                    .query_row(
+                        "UPDATE transform_cache SET hits = hits + 1, last_hit_at = ?4
+                         WHERE transform_id = ?1 AND fingerprint = ?2 AND input_hash = ?3
+                         RETURNING output",
+                        params![key.0, key.1, key.2, micros(Utc::now())],
+                        |r| r.get::<_, String>(0),
+                    )
+                    .optional()?)
+            })
+            .await?;
+        let Some(output) = output else {
+            return Ok(None);
+        };
+        match serde_json::from_str(&output) {
+            Ok(cached) => Ok(Some(cached)),
+            Err(e) => {
+                tracing::warn!(transform_id, input_hash, error = %e, "Ignoring unreadable cache entry");
+                Ok(None)
+            }
+        }
+    }
+
+    async fn cache_store(
+        &self,
+        transform_id: &str,
+        fingerprint: &str,
+        input_hash: &str,
+        output: &CachedOutput,
+    ) -> Result<(), LifelogError> {
+        let row = (
+            transform_id.to_string(),
+            fingerprint.to_string(),
+            input_hash.to_string(),
+            serde_json::to_string(output)?,
+        );
+        self.store
+            .with_conn("transform cache store", move |conn| {
+                conn.execute(
+                    "INSERT INTO transform_cache (transform_id, fingerprint, input_hash, output, created_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT (transform_id, fingerprint, input_hash) DO NOTHING",
+                    params![row.0, row.1, row.2, row.3, micros(Utc::now())],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn record_cache_stats(
+        &self,
+        transform_id: &str,
+        hits: u64,
+        misses: u64,
+    ) -> Result<(), LifelogError> {
+        if hits == 0 && misses == 0 {
+            return Ok(());
+        }
+        let transform_id = transform_id.to_string();
+        self.store
+            .with_conn("transform cache stats", move |conn| {
+                conn.execute(
+                    "INSERT INTO transform_cache_stats (transform_id, hits, misses, updated_at)
+                     VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT (transform_id) DO UPDATE SET
+                         hits = transform_cache_stats.hits + excluded.hits,
+                         misses = transform_cache_stats.misses + excluded.misses,
+                         updated_at = excluded.updated_at",
+                    params![transform_id, hits as i64, misses as i64, micros(Utc::now())],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn invalidate_stale_cache(
+        &self,
+        transform_id: &str,
+        fingerprint: &str,
+    ) -> Result<u64, LifelogError> {
+        let (transform_id, fingerprint) = (transform_id.to_string(), fingerprint.to_string());
+        self.store
+            .with_conn("transform cache invalidate", move |conn| {
+                Ok(conn.execute(
+                    "DELETE FROM transform_cache WHERE transform_id = ?1 AND fingerprint <> ?2",
+                    params![transform_id, fingerprint],
+                )? as u64)
+            })
+            .await
+    }
+
+    async fn prune_cache(&self, before: DateTime<Utc>) -> Result<u64, LifelogError> {
+        self.store
+            .with_conn("transform cache prune", move |conn| {
+                Ok(conn.execute(
+                    "DELETE FROM transform_cache WHERE COALESCE(last_hit_at, created_at) < ?1",
+                    [micros(before)],
+                )? as u64)
+            })
+            .await
+    }
+
+    async fn record_failure(
+        &self,
+        transform_id: &str,
+        frame_id: uuid::Uuid,
+        origin: &str,
+        error: &str,
+        now: DateTime<Utc>,
+    ) -> Result<i32, LifelogError> {
+        let row = (
+            transform_id.to_string(),
+            frame_id.to_string(),
+            origin.to_string(),
+            error.to_string(),
+        );
+        self.store
+            .with_conn("dead letter insert", move |conn| {
+                let tx = conn.transaction()?;
+                let attempts: i32 = tx.query_row(
+                    "INSERT INTO transform_dead_letters
+                         (transform_id, frame_id, origin, error, attempts, first_failed_at, last_failed_at)
+                     VALUES (?1, ?2, ?3, ?4, 1, ?5, ?5)
+                     ON CONFLICT (transform_id, frame_id) DO UPDATE SET
+                         error = excluded.error,
+                         origin = excluded.origin,
+                         attempts = transform_dead_letters.attempts + 1,
+                         last_failed_at = excluded.last_failed_at
+                     RETURNING attempts",
+                    params![row.0, row.1, row.2, row.3, micros(now)],
+                    |r| r.get(0),
+                )?;
+                tx.execute(
+                    "UPDATE transform_dead_letters SET next_retry_at = ?3
+                     WHERE transform_id = ?1 AND frame_id = ?2",
+                    params![row.0, row.1, dead_letter::next_retry(attempts, now).map(micros)],
+                )?;
+                tx.commit()?;
+                Ok(attempts)
+            })
+            .await
+    }
+
+    async fn resolve_dead_letter(
+        &self,
+        transform_id: &str,
+        frame_id: uuid::Uuid,
+    ) -> Result<(), LifelogError> {
+        let (transform_id, frame_id) = (transform_id.to_string(), frame_id.to_string());
+        self.store
+            .with_conn("dead letter resolve", move |conn| {
+                conn.execute(
+                    "DELETE FROM transform_dead_letters WHERE transform_id = ?1 AND frame_id = ?2",
+                    params![transform_id, frame_id],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn due_dead_letters(
+        &self,
+        transform_id: &str,
+        now: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<DeadLetter>, LifelogError> {
+        let transform_id = transform_id.to_string();
+        self.store
+            .with_conn("dead letter due query", move |conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT {DEAD_LETTER_COLUMNS} FROM transform_dead_letters
+                     WHERE transform_id = ?1 AND next_retry_at IS NOT NULL AND next_retry_at <= ?2
+                     ORDER BY next_retry_at ASC LIMIT ?3"
+                ))?;
+                let entries = stmt
+                    .query_map(
+                        params![transform_id, micros(now), limit as i64],
+                        read_dead_letter,
+                    )?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(entries)
+            })
+            .await
+    }
+
+    async fn list_dead_letters(
+        &self,
+        transform_id: Option<&str>,
+        include_exhausted: bool,
+        limit: usize,
+    ) -> Result<Vec<DeadLetter>, LifelogError> {
+        let transform_id = transform_id.map(str::to_string);
+        self.store
+            .with_conn("dead letter list", move |conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT {DEAD_LETTER_COLUMNS} FROM transform_dead_letters
+                     WHERE (?1 IS NULL OR transform_id = ?1)
+                     AND (?2 OR next_retry_at IS NOT NULL)
+                     ORDER BY last_failed_at DESC LIMIT ?3"
+                ))?;
+                let entries = stmt
+                    .query_map(
+                        params![transform_id, include_exhausted, limit as i64],
+                        read_dead_letter,
+                    )?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(entries)
+            })
+            .await
+    }
+
+    async fn retry_dead_letters(
+        &self,
+        transform_id: &str,
+        frame_ids: &[uuid::Uuid],
+        now: DateTime<Utc>,
+    ) -> Result<u64, LifelogError> {
+        let (transform_id, frame_ids) = (transform_id.to_string(), frame_ids_json(frame_ids));
+        self.store
+            .with_conn("dead letter retry", move |conn| {
+                Ok(conn.execute(
+                    "UPDATE transform_dead_letters SET next_retry_at = ?3, attempts = 0
+                     WHERE transform_id = ?1
+                     AND (json_array_length(?2) = 0 OR frame_id IN (SELECT value FROM json_each(?2)))",
+                    params![transform_id, frame_ids, micros(now)],
+                )? as u64)
+            })
+            .await
+    }
+
+    async fn discard_dead_letters(
+        &self,
+        transform_id: &str,
+        frame_ids: &[uuid::Uuid],
+    ) -> Result<u64, LifelogError> {
+        let (transform_id, frame_ids) = (transform_id.to_string(), frame_ids_json(frame_ids));
+        self.store
+            .with_conn("dead letter discard", move |conn| {
+                Ok(conn.execute(
+                    "DELETE FROM transform_dead_letters
+                     WHERE transform_id = ?1
+                     AND (json_array_length(?2) = 0 OR frame_id IN (SELECT value FROM json_each(?2)))",
+                    params![transform_id, frame_ids],
+                )? as u64)
+            })
+            .await
+    }
+
+    async fn record_privacy_findings(&self, findings: PrivacyFindings) -> Result<(), LifelogError> {
+        self.store
+            .with_conn("privacy scan update", move |conn| {
+                let PrivacyFindings {
+                    frame_id,
+                    transform_id,
+                    patch,
+                    dropped,
+                    action,
+                    kinds,
+                    count,
+                } = findings;
+                let tx = conn.transaction()?;
+                let stored = tx
+                    .query_row(
+                        "SELECT seq, modality, payload FROM frames WHERE id = ?1",
+                        [frame_id.to_string()],
+                        |r| {
+                            Ok((
+                                r.get::<_, i64>(0)?,
+                                r.get::<_, String>(1)?,
+                                r.get::<_, String>(2)?,
+                            ))
+                        },
+                    )
+                    .optional()?;
+                if let Some((seq, modality, payload)) = stored {
+                    let payload: JsonValue = serde_json::from_str(&payload)?;
+                    let payload = if payload.get(crate::payload_crypto::SEALED_FIELD).is_some() {
+                        crate::payload_crypto::patch_sealed(frame_id, payload, patch, &dropped)
+                            .map_err(|e| anyhow!("privacy scan reseal: {e}"))?
+                    } else {
+                        let mut payload = payload;
+                        if let JsonValue::Object(fields) = &mut payload {
+                            for field in &dropped {
+                                fields.remove(*field);
+                            }
+                            fields.extend(patch);
+                        }
+                        payload
+                    };
+                    tx.execute(
+                        "UPDATE frames SET payload = ?2 WHERE seq = ?1",
+                        params![seq, serde_json::to_string(&payload)?],
+                    )?;
+                    index_text(&tx, seq, &modality, &payload)?;
+                }
+                tx.execute(
+                    "INSERT INTO privacy_audit
+                         (frame_id, transform_id, action, finding_kinds, finding_count, created_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        frame_id.to_string(),
+                        transform_id,
+                        action,
+                        serde_json::to_string(&kinds)?,
+                        count,
+                        micros(Utc::now())
+                    ],
+                )?;
+                tx.commit()?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn clear_scan_pending(&self, frame_id: uuid::Uuid) -> Result<(), LifelogError> {
+        self.store
+            .with_conn("scan pending clear", move |conn| {
+                let tx = conn.transaction()?;
+                let stored = tx
+                    .query_row(
+                        "SELECT seq, modality, payload FROM frames WHERE id = ?1",
+                        [frame_id.to_string()],
+                        |r| {
+                            Ok((
+                                r.get::<_, i64>(0)?,
+                                r.get::<_, String>(1)?,
+                                r.get::<_, String>(2)?,
+                            ))
+                        },
+                    )
+                    .optional()?;
+                let Some((seq, modality, payload)) = stored else {
+                    return Ok(());
+                };
+                let mut payload: JsonValue = serde_json::from_str(&payload)?;
+                let cleared = payload
+                    .as_object_mut()
+                    .and_then(|fields| fields.remove(SCAN_PENDING_FIELD))
+                    .is_some();
+                if cleared {
+                    tx.execute(
+                        "UPDATE frames SET payload = ?2 WHERE seq = ?1",
+                        params![seq, serde_json::to_string(&payload)?],
+                    )?;
+                    index_text(&tx, seq, &modality, &payload)?;
+                }
+                tx.commit()?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn write_entity_mentions(
+        &self,
+        frame_id: uuid::Uuid,
+        output: &EntityOutput,
+        seen_at: DateTime<Utc>,
+    ) -> Result<(), LifelogError> {
+        let output = output.clone();
+        self.store
+            .with_conn("entity mentions write", move |conn| {
+                let (frame_id, seen_at) = (frame_id.to_string(), micros(seen_at));
+                let tx = conn.transaction()?;
+                tx.execute(
+                    "DELETE FROM entity_mentions WHERE frame_id = ?1 AND transform_id = ?2",
+                    params![frame_id, output.transform_id],
+                )?;
+                for mention in &output.mentions {
+                    let entity_id: i64 = tx.query_row(
+                        "INSERT INTO entities (kind, value, display, first_seen, last_seen)
+                         VALUES (?1, ?2, ?3, ?4, ?4)
+                         ON CONFLICT (kind, value) DO UPDATE SET
+                             first_seen = min(entities.first_seen, excluded.first_seen),
+                             last_seen = max(entities.last_seen, excluded.last_seen)
+                         RETURNING id",
+                        params![
+                            mention.kind.as_str(),
+                            mention.value,
+                            mention.display,
+                            seen_at
+                        ],
+                        |r| r.get(0),
+                    )?;
+                    tx.execute(
+                        "INSERT INTO entity_mentions
+                             (entity_id, frame_id, transform_id, field, start_offset, end_offset, t_canonical)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                         ON CONFLICT DO NOTHING",
+                        params![
+                            entity_id,
+                            frame_id,
+                            output.transform_id,
+                            mention.field,
+                            mention.start as i64,
+                            mention.end as i64,
+                            seen_at
+                        ],
+                    )?;
+                }
+                tx.commit()?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn prune_entities(&self) -> Result<u64, LifelogError> {
+        self.store
+            .with_conn("entity prune", |conn| {
+                let mentions = conn.execute(
+                    "DELETE FROM entity_mentions
+                     WHERE NOT EXISTS (SELECT 1 FROM frames f WHERE f.id = entity_mentions.frame_id)",
+                    [],
+                )?;
+                conn.execute(
+                    "DELETE FROM entities
+                     WHERE NOT EXISTS (SELECT 1 FROM entity_mentions m WHERE m.entity_id = entities.id)",
+                    [],
+                )?;
+                Ok(mentions as u64)
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(modality: &str, t_canonical: DateTime<Utc>, blob_hash: &str) -> FrameRow {
+        FrameRow {
+            id: uuid::Uuid::new_v4(),
+            collector_id: "laptop".to_string(),
+            stream_id: modality.to_lowercase(),
+            modality: modality.to_string(),
+            t_device: Some(t_canonical),
+            t_ingest: t_canonical,
+            t_canonical,
+            t_end: None,
+            time_quality: "good".to_string(),
+            blob_hash: Some(blob_hash.to_string()),
+            blob_size: Some(3),
+            indexed: true,
+            source_frame_id: None,
+            payload: serde_json::json!({}),
+        }
+    }
+
+    #[tokio::test]
+    async fn dead_letters_back_off_and_resolve() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let state = SqliteTransformState::new(SqliteStore::open(dir.path().join("l.db")).await?);
+        let (frame_id, now) = (uuid::Uuid::new_v4(), Utc::now());
+
+        state
+            .record_failure("ocr", frame_id, "laptop:Screen", "boom", now)
+            .await?;
+        let attempts = state
+            .record_failure("ocr", frame_id, "laptop:Screen", "boom again", now)
+            .await?;
+        assert_eq!(attempts, 2);
+        assert!(state.due_dead_letters("ocr", now, 10).await?.is_empty());
+
+        let due = state
+            .due_dead_letters("ocr", now + dead_letter::backoff_delay(2), 10)
+            .await?;
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].frame_id, frame_id);
+        assert_eq!(due[0].error, "boom again");
+
+        assert_eq!(state.retry_dead_letters("ocr", &[], now).await?, 1);
+        assert_eq!(state.due_dead_letters("ocr", now, 10).await?[0].attempts, 0);
+        state.resolve_dead_letter("ocr", frame_id).await?;
+        assert!(state.list_dead_letters(None, true, 10).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retention_deletes_expired_frames_and_reports_their_blobs() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = SqliteStore::open(dir.path().join("l.db")).await?;
+        let now = Utc::now();
+        let mut old = frame("Screen", now - chrono::Duration::days(10), "aa");
+        let mut shared = frame("Screen", now - chrono::Duration::days(10), "bb");
+        let mut recent = frame("Screen", now, "bb");
+        for row in [&mut old, &mut shared, &mut recent] {
+            store.insert_ingested(row).await?;
+        }
+
+        let (deleted, mut hashes) = store
+            .delete_before("Screen", now - chrono::Duration::days(7))
+            .await?;
+        hashes.sort();
+        assert_eq!(deleted, 2);
+        assert_eq!(hashes, ["aa", "bb"]);
+        assert!(!store.blob_referenced("aa").await?);
+        assert!(store.blob_referenced("bb").await?);
+        assert_eq!(store.modalities().await?, ["Screen"]);
+        Ok(())
+    }
+}
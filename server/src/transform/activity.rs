@@ -2,16 +2,34 @@ use async_trait::async_trait;
 use lifelog_core::{DataOrigin, DataOriginType, LifelogFrameKey, PrivacyLevel};
 use lifelog_types::{DataModality, LifelogData};
 
+use super::egress::EgressGuard;
+use super::llm_provider::LlmClient;
+use super::structured::{frame_fields, summary_text, StructuredOutput, StructuredPrompt};
 use super::{TransformExecutor, TransformOutput, TransformPipelineError};
 
+const DEFAULT_SYSTEM_PROMPT: &str = "Classify the user's current activity based on this screen text. Pick the closest category and describe the activity in one sentence, e.g. category \"coding\" with description \"editing Rust server code in neovim\".";
+const DEFAULT_PROMPT_TEMPLATE: &str = "{{text}}";
+
+fn default_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "category": {
+                "type": "string",
+                "enum": ["coding", "browsing", "email", "documentation", "social-media", "video", "chat", "terminal", "file-management", "design", "writing", "meeting", "idle", "other"]
+            },
+            "description": { "type": "string" }
+        },
+        "required": ["category"]
+    })
+}
+
 pub struct ActivityClassifierExecutor {
     id: String,
     source: DataOrigin,
-    endpoint: String,
-    model: String,
-    system_prompt: String,
-    timeout_secs: u64,
-    privacy_level: PrivacyLevel,
+    prompt: StructuredPrompt,
+    llm: LlmClient,
+    cache_fingerprint: Option<String>,
 }
 
 impl ActivityClassifierExecutor {
@@ -20,26 +38,20 @@ impl ActivityClassifierExecutor {
         source: DataOrigin,
         endpoint: String,
         params: &std::collections::HashMap<String, String>,
+        egress: EgressGuard,
     ) -> Self {
         Self {
+            prompt: StructuredPrompt::from_params(
+                &id,
+                params,
+                DEFAULT_SYSTEM_PROMPT,
+                DEFAULT_PROMPT_TEMPLATE,
+                default_schema(),
+            ),
+            cache_fingerprint: super::cache::fingerprint("activity-classifier", &endpoint, params),
+            llm: LlmClient::from_params(&id, endpoint, params, egress),
             id,
             source,
-            endpoint,
-            model: params
-                .get("model")
-                .cloned()
-                .unwrap_or_else(|| "gemma3:4b-it-qat".to_string()),
-            system_prompt: params
-                .get("system_prompt")
-                .cloned()
-                .unwrap_or_else(|| {
-                    "Classify the user's current activity based on this screen text. Respond with ONLY a short category label from: coding, browsing, email, documentation, social-media, video, chat, terminal, file-management, design, writing, meeting, idle, other. Then a colon and a one-sentence description. Example: 'coding: editing Rust server code in neovim'".to_string()
-                }),
-            timeout_secs: params
-                .get("timeout_secs")
-                .and_then(|v| v.parse().ok())
-                .unwrap_or(60),
-            privacy_level: PrivacyLevel::from_params(params),
         }
     }
 }
@@ -63,7 +75,11 @@ impl TransformExecutor for ActivityClassifierExecutor {
     }
 
     fn privacy_level(&self) -> PrivacyLevel {
-        self.privacy_level
+        self.llm.privacy_level()
+    }
+
+    fn cache_fingerprint(&self) -> Option<&str> {
+        self.cache_fingerprint.as_deref()
     }
 
     fn matches_origin(&self, key_origin: &DataOrigin) -> bool {
@@ -109,71 +125,26 @@ impl TransformExecutor for ActivityClassifierExecutor {
             }
         };
 
-        if ocr_frame.text.is_empty() {
-            return Ok(TransformOutput::Transcription(
-                lifelog_types::TranscriptionFrame {
-                    uuid: key.uuid.to_string(),
-                    text: "idle".to_string(),
-                    source_uuid: ocr_frame.uuid.clone(),
-                    model: self.model.clone(),
-                    timestamp: ocr_frame.timestamp,
-                    confidence: 0.0,
-                    t_device: ocr_frame.t_device,
-                    t_ingest: None,
-                    t_canonical: ocr_frame.t_canonical,
-                    t_end: ocr_frame.t_end,
-                    time_quality: ocr_frame.time_quality,
-                    record_type: ocr_frame.record_type,
-                },
-            ));
-        }
-
-        let url = format!("{}/api/chat", self.endpoint.trim_end_matches('/'));
-
-        let body = serde_json::json!({
-            "model": self.model,
-            "messages": [
-                { "role": "system", "content": self.system_prompt },
-                { "role": "user", "content": &ocr_frame.text }
-            ],
-            "stream": false
-        });
-
-        let resp = http
-            .post(&url)
-            .json(&body)
-            .timeout(std::time::Duration::from_secs(self.timeout_secs))
-            .send()
-            .await
-            .map_err(|e| TransformPipelineError::ServiceUnavailable {
-                endpoint: format!("{url}: {e}"),
-            })?;
-
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let text = resp.text().await.unwrap_or_default();
-            return Err(TransformPipelineError::ServiceError(format!(
-                "ollama {status}: {text}"
-            )));
-        }
-
-        let json: serde_json::Value = resp
-            .json()
-            .await
-            .map_err(|e| TransformPipelineError::ServiceError(format!("json: {e}")))?;
-
-        let classification = json["message"]["content"]
-            .as_str()
-            .unwrap_or("other")
-            .to_string();
+        let fields = if ocr_frame.text.is_empty() {
+            let mut fields = serde_json::Map::new();
+            fields.insert("category".to_string(), "idle".into());
+            fields
+        } else {
+            self.prompt
+                .run(&self.llm, http, &frame_fields(ocr_frame))
+                .await?
+        };
 
         let frame = lifelog_types::TranscriptionFrame {
             uuid: key.uuid.to_string(),
-            text: classification,
+            text: summary_text(&fields, "category", "description"),
             source_uuid: ocr_frame.uuid.clone(),
-            model: self.model.clone(),
+            model: self.llm.model().to_string(),
             timestamp: ocr_frame.timestamp,
-            confidence: 0.0,
+            confidence: fields
+                .get("confidence")
+                .and_then(|c| c.as_f64())
+                .unwrap_or(0.0) as f32,
             t_device: ocr_frame.t_device,
             t_ingest: None,
             t_canonical: ocr_frame.t_canonical,
@@ -182,6 +153,9 @@ impl TransformExecutor for ActivityClassifierExecutor {
             record_type: ocr_frame.record_type,
         };
 
-        Ok(TransformOutput::Transcription(frame))
+        Ok(TransformOutput::Structured(StructuredOutput {
+            frame,
+            fields,
+        }))
     }
 }
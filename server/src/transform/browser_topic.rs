@@ -2,16 +2,34 @@ use async_trait::async_trait;
 use lifelog_core::{DataOrigin, DataOriginType, LifelogFrameKey, PrivacyLevel};
 use lifelog_types::{DataModality, LifelogData};
 
+use super::egress::EgressGuard;
+use super::llm_provider::LlmClient;
+use super::structured::{frame_fields, summary_text, StructuredOutput, StructuredPrompt};
 use super::{TransformExecutor, TransformOutput, TransformPipelineError};
 
+const DEFAULT_SYSTEM_PROMPT: &str = "Given this browser URL and page title, classify the topic and list a few keywords, e.g. category \"development\" with keywords [\"rust\", \"async\", \"tokio\"].";
+const DEFAULT_PROMPT_TEMPLATE: &str = "URL: {{url}}\nTitle: {{title}}";
+
+fn default_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "category": {
+                "type": "string",
+                "enum": ["work", "research", "social", "entertainment", "shopping", "news", "communication", "development", "finance", "education", "health", "other"]
+            },
+            "keywords": { "type": "array", "items": { "type": "string" } }
+        },
+        "required": ["category"]
+    })
+}
+
 pub struct BrowserTopicExecutor {
     id: String,
     source: DataOrigin,
-    endpoint: String,
-    model: String,
-    system_prompt: String,
-    timeout_secs: u64,
-    privacy_level: PrivacyLevel,
+    prompt: StructuredPrompt,
+    llm: LlmClient,
+    cache_fingerprint: Option<String>,
 }
 
 impl BrowserTopicExecutor {
@@ -20,26 +38,20 @@ impl BrowserTopicExecutor {
         source: DataOrigin,
         endpoint: String,
         params: &std::collections::HashMap<String, String>,
+        egress: EgressGuard,
     ) -> Self {
         Self {
+            prompt: StructuredPrompt::from_params(
+                &id,
+                params,
+                DEFAULT_SYSTEM_PROMPT,
+                DEFAULT_PROMPT_TEMPLATE,
+                default_schema(),
+            ),
+            cache_fingerprint: super::cache::fingerprint("browser-topic", &endpoint, params),
+            llm: LlmClient::from_params(&id, endpoint, params, egress),
             id,
             source,
-            endpoint,
-            model: params
-                .get("model")
-                .cloned()
-                .unwrap_or_else(|| "gemma3:4b-it-qat".to_string()),
-            system_prompt: params
-                .get("system_prompt")
-                .cloned()
-                .unwrap_or_else(|| {
-                    "Given this browser URL and page title, classify the topic. Respond with ONLY a category from: work, research, social, entertainment, shopping, news, communication, development, finance, education, health, other. Then a colon and keywords. Example: 'development: rust, async, tokio'".to_string()
-                }),
-            timeout_secs: params
-                .get("timeout_secs")
-                .and_then(|v| v.parse().ok())
-                .unwrap_or(60),
-            privacy_level: PrivacyLevel::from_params(params),
         }
     }
 }
@@ -63,7 +75,11 @@ impl TransformExecutor for BrowserTopicExecutor {
     }
 
     fn privacy_level(&self) -> PrivacyLevel {
-        self.privacy_level
+        self.llm.privacy_level()
+    }
+
+    fn cache_fingerprint(&self) -> Option<&str> {
+        self.cache_fingerprint.as_deref()
     }
 
     fn matches_origin(&self, key_origin: &DataOrigin) -> bool {
@@ -109,73 +125,26 @@ impl TransformExecutor for BrowserTopicExecutor {
             }
         };
 
-        if browser_frame.url.is_empty() && browser_frame.title.is_empty() {
-            return Ok(TransformOutput::Transcription(
-                lifelog_types::TranscriptionFrame {
-                    uuid: key.uuid.to_string(),
-                    text: "other".to_string(),
-                    source_uuid: browser_frame.uuid.clone(),
-                    model: self.model.clone(),
-                    timestamp: browser_frame.timestamp,
-                    confidence: 0.0,
-                    t_device: browser_frame.t_device,
-                    t_ingest: None,
-                    t_canonical: browser_frame.t_canonical,
-                    t_end: browser_frame.t_end,
-                    time_quality: browser_frame.time_quality,
-                    record_type: browser_frame.record_type,
-                },
-            ));
-        }
-
-        let url = format!("{}/api/chat", self.endpoint.trim_end_matches('/'));
-
-        let content = format!("URL: {}\nTitle: {}", browser_frame.url, browser_frame.title);
-
-        let body = serde_json::json!({
-            "model": self.model,
-            "messages": [
-                { "role": "system", "content": self.system_prompt },
-                { "role": "user", "content": &content }
-            ],
-            "stream": false
-        });
-
-        let resp = http
-            .post(&url)
-            .json(&body)
-            .timeout(std::time::Duration::from_secs(self.timeout_secs))
-            .send()
-            .await
-            .map_err(|e| TransformPipelineError::ServiceUnavailable {
-                endpoint: format!("{url}: {e}"),
-            })?;
-
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let text = resp.text().await.unwrap_or_default();
-            return Err(TransformPipelineError::ServiceError(format!(
-                "ollama {status}: {text}"
-            )));
-        }
-
-        let json: serde_json::Value = resp
-            .json()
-            .await
-            .map_err(|e| TransformPipelineError::ServiceError(format!("json: {e}")))?;
-
-        let classification = json["message"]["content"]
-            .as_str()
-            .unwrap_or("other")
-            .to_string();
+        let fields = if browser_frame.url.is_empty() && browser_frame.title.is_empty() {
+            let mut fields = serde_json::Map::new();
+            fields.insert("category".to_string(), "other".into());
+            fields
+        } else {
+            self.prompt
+                .run(&self.llm, http, &frame_fields(browser_frame))
+                .await?
+        };
 
         let frame = lifelog_types::TranscriptionFrame {
             uuid: key.uuid.to_string(),
-            text: classification,
+            text: summary_text(&fields, "category", "keywords"),
             source_uuid: browser_frame.uuid.clone(),
-            model: self.model.clone(),
+            model: self.llm.model().to_string(),
             timestamp: browser_frame.timestamp,
-            confidence: 0.0,
+            confidence: fields
+                .get("confidence")
+                .and_then(|c| c.as_f64())
+                .unwrap_or(0.0) as f32,
             t_device: browser_frame.t_device,
             t_ingest: None,
             t_canonical: browser_frame.t_canonical,
@@ -184,6 +153,9 @@ impl TransformExecutor for BrowserTopicExecutor {
             record_type: browser_frame.record_type,
         };
 
-        Ok(TransformOutput::Transcription(frame))
+        Ok(TransformOutput::Structured(StructuredOutput {
+            frame,
+            fields,
+        }))
     }
 }
@@ -0,0 +1,497 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use lifelog_core::{LifelogError, LifelogFrameKey};
+use lifelog_types::LifelogData;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use utils::cas::sha256_hex;
+
+use crate::postgres::PostgresPool;
+
+use super::structured::StructuredOutput;
+use super::writer::SourceTimestamps;
+use super::{GenericTransformOutput, TransformOutput};
+
+/// Params that change how a transform is called but not what it returns.
+const NON_SEMANTIC_PARAMS: &[&str] = &[
+    "api_key",
+    "cache",
+    "cost_per_1k_completion_tokens",
+    "cost_per_1k_prompt_tokens",
+    "max_calls_per_hour",
+    "max_retries",
+    "timeout_secs",
+];
+
+/// Payload fields that identify or place a frame in time rather than describe its content.
+const IDENTITY_FIELDS: &[&str] = &[
+    "uuid",
+    "timestamp",
+    "tDevice",
+    "tIngest",
+    "tCanonical",
+    "tEnd",
+    "timeQuality",
+    "recordType",
+];
+
+/// Identifies a transform's behaviour: server version, transform type, endpoint and the params
+/// that affect output. Cached results under any other fingerprint are stale. `None` when the
+/// transform opts out with `cache = "false"`.
+pub fn fingerprint(
+    transform_type: &str,
+    endpoint: &str,
+    params: &HashMap<String, String>,
+) -> Option<String> {
+    if params.get("cache").is_some_and(|v| v == "false") {
+        return None;
+    }
+    let mut relevant: Vec<(&String, &String)> = params
+        .iter()
+        .filter(|(k, _)| !NON_SEMANTIC_PARAMS.contains(&k.as_str()))
+        .collect();
+    relevant.sort();
+    let mut material = format!(
+        "{}\n{transform_type}\n{endpoint}",
+        env!("CARGO_PKG_VERSION")
+    );
+    for (k, v) in relevant {
+        material.push_str(&format!("\n{k}={v}"));
+    }
+    Some(sha256_hex(material.as_bytes()))
+}
+
+/// Hash of a source frame's content, ignoring its id and timestamps, so identical consecutive
+/// frames share a cache entry. An image, audio clip or clipboard blob enters as its CAS hash
+/// rather than being serialized along with the other fields.
+pub fn input_hash(data: &LifelogData) -> Option<String> {
+    let mut data = data.clone();
+    let blob_hash = take_blob(&mut data).map(|bytes| sha256_hex(&bytes));
+    let mut value = serde_json::to_value(&data).ok()?;
+    if let Value::Object(outer) = &mut value {
+        for frame in outer.values_mut() {
+            if let Value::Object(fields) = frame {
+                fields.retain(|k, _| !IDENTITY_FIELDS.contains(&k.as_str()));
+            }
+        }
+    }
+    let mut material = value.to_string();
+    if let Some(blob_hash) = blob_hash {
+        material.push('\n');
+        material.push_str(&blob_hash);
+    }
+    Some(sha256_hex(material.as_bytes()))
+}
+
+/// Moves the bytes the frame keeps in the CAS out of it, if it has any.
+fn take_blob(data: &mut LifelogData) -> Option<Vec<u8>> {
+    use lifelog_types::lifelog_data::Payload;
+    let bytes = match data.payload.as_mut()? {
+        Payload::Screenframe(f) => &mut f.image_bytes,
+        Payload::Cameraframe(f) => &mut f.image_bytes,
+        Payload::Audioframe(f) => &mut f.audio_bytes,
+        Payload::Clipboardframe(f) => &mut f.binary_data,
+        _ => return None,
+    };
+    Some(std::mem::take(bytes)).filter(|b| !b.is_empty())
+}
+
+/// The content of a transform output, without the ids and timestamps of the frame it came from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CachedOutput {
+    Ocr {
+        text: String,
+        #[serde(default)]
+        words: Vec<lifelog_types::OcrBox>,
+        #[serde(default)]
+        lines: Vec<lifelog_types::OcrBox>,
+    },
+    Transcription {
+        text: String,
+        model: String,
+        confidence: f32,
+    },
+    Structured {
+        text: String,
+        model: String,
+        confidence: f32,
+        fields: Map<String, Value>,
+    },
+    Generic {
+        modality: String,
+        payload: Value,
+    },
+}
+
+impl CachedOutput {
+    /// `None` for outputs that depend on more than the input content (in-place annotations)
+    /// or that are not written at all.
+    pub fn from_output(output: &TransformOutput) -> Option<Self> {
+        match output {
+            TransformOutput::Ocr(f) => Some(CachedOutput::Ocr {
+                text: f.text.clone(),
+                words: f.words.clone(),
+                lines: f.lines.clone(),
+            }),
+            TransformOutput::Transcription(f) => Some(CachedOutput::Transcription {
+                text: f.text.clone(),
+                model: f.model.clone(),
+                confidence: f.confidence,
+            }),
+            TransformOutput::Structured(s) => Some(CachedOutput::Structured {
+                text: s.frame.text.clone(),
+                model: s.frame.model.clone(),
+                confidence: s.frame.confidence,
+                fields: s.fields.clone(),
+            }),
+            TransformOutput::Generic(g) => Some(CachedOutput::Generic {
+                modality: g.modality.clone(),
+                payload: g.payload.clone(),
+            }),
+            TransformOutput::Embedding(_)
+            | TransformOutput::PrivacyScan(_)
+            | TransformOutput::Entities(_)
+            | TransformOutput::Skipped => None,
+        }
+    }
+
+    /// Rebuilds the output for a new source frame.
+    pub fn into_output(self, key: &LifelogFrameKey, source: &SourceTimestamps) -> TransformOutput {
+        let uuid = key.uuid.to_string();
+        let transcription =
+            |text: String, model: String, confidence: f32| lifelog_types::TranscriptionFrame {
+                uuid: uuid.clone(),
+                text,
+                source_uuid: uuid.clone(),
+                model,
+                timestamp: source.t_canonical,
+                confidence,
+                t_device: source.t_canonical,
+                t_ingest: None,
+                t_canonical: source.t_canonical,
+                t_end: source.t_end,
+                time_quality: 0,
+                record_type: 0,
+            };
+        match self {
+            CachedOutput::Ocr { text, words, lines } => {
+                TransformOutput::Ocr(lifelog_types::OcrFrame {
+                    uuid: uuid.clone(),
+                    timestamp: source.t_canonical,
+                    text,
+                    t_device: source.t_canonical,
+                    t_ingest: None,
+                    t_canonical: source.t_canonical,
+                    t_end: source.t_end,
+                    time_quality: 0,
+                    record_type: 0,
+                    words,
+                    lines,
+                })
+            }
+            CachedOutput::Transcription {
+                text,
+                model,
+                confidence,
+            } => TransformOutput::Transcription(transcription(text, model, confidence)),
+            CachedOutput::Structured {
+                text,
+                model,
+                confidence,
+                fields,
+            } => TransformOutput::Structured(StructuredOutput {
+                frame: transcription(text, model, confidence),
+                fields,
+            }),
+            CachedOutput::Generic { modality, payload } => {
+                TransformOutput::Generic(GenericTransformOutput {
+                    source_uuid: uuid,
+                    modality,
+                    payload,
+                })
+            }
+        }
+    }
+}
+
+/// Returns the cached output for `input_hash`, counting the hit.
+pub async fn lookup(
+    pool: &PostgresPool,
+    transform_id: &str,
+    fingerprint: &str,
+    input_hash: &str,
+) -> Result<Option<CachedOutput>, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let row = client
+        .query_opt(
+            "UPDATE transform_cache SET hits = hits + 1, last_hit_at = now()
+             WHERE transform_id = $1 AND fingerprint = $2 AND input_hash = $3
+             RETURNING output",
+            &[&transform_id, &fingerprint, &input_hash],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("transform cache lookup: {e}")))?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let output: Value = row.get(0);
+    match serde_json::from_value(output) {
+        Ok(cached) => Ok(Some(cached)),
+        Err(e) => {
+            tracing::warn!(transform_id, input_hash, error = %e, "Ignoring unreadable cache entry");
+            Ok(None)
+        }
+    }
+}
+
+pub async fn store(
+    pool: &PostgresPool,
+    transform_id: &str,
+    fingerprint: &str,
+    input_hash: &str,
+    output: &CachedOutput,
+) -> Result<(), LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    client
+        .execute(
+            "INSERT INTO transform_cache (transform_id, fingerprint, input_hash, output)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (transform_id, fingerprint, input_hash) DO NOTHING",
+            &[
+                &transform_id,
+                &fingerprint,
+                &input_hash,
+                &serde_json::to_value(output)?,
+            ],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("transform cache store: {e}")))?;
+    Ok(())
+}
+
+/// Adds one batch's hits and misses to the transform's running totals.
+pub async fn record_stats(
+    pool: &PostgresPool,
+    transform_id: &str,
+    hits: u64,
+    misses: u64,
+) -> Result<(), LifelogError> {
+    if hits == 0 && misses == 0 {
+        return Ok(());
+    }
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    client
+        .execute(
+            "INSERT INTO transform_cache_stats (transform_id, hits, misses, updated_at)
+             VALUES ($1, $2, $3, now())
+             ON CONFLICT (transform_id) DO UPDATE SET
+                 hits = transform_cache_stats.hits + EXCLUDED.hits,
+                 misses = transform_cache_stats.misses + EXCLUDED.misses,
+                 updated_at = EXCLUDED.updated_at",
+            &[&transform_id, &(hits as i64), &(misses as i64)],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("transform cache stats: {e}")))?;
+    Ok(())
+}
+
+/// Drops a transform's entries cached under any other fingerprint.
+pub async fn invalidate_stale(
+    pool: &PostgresPool,
+    transform_id: &str,
+    fingerprint: &str,
+) -> Result<u64, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    client
+        .execute(
+            "DELETE FROM transform_cache WHERE transform_id = $1 AND fingerprint <> $2",
+            &[&transform_id, &fingerprint],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("transform cache invalidate: {e}")))
+}
+
+/// Drops entries neither written nor hit since `before`.
+pub async fn prune_unused(pool: &PostgresPool, before: DateTime<Utc>) -> Result<u64, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    client
+        .execute(
+            "DELETE FROM transform_cache WHERE COALESCE(last_hit_at, created_at) < $1",
+            &[&before],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("transform cache prune: {e}")))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheStats {
+    pub transform_id: String,
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: u64,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+pub async fn stats(pool: &PostgresPool) -> Result<Vec<CacheStats>, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let rows = client
+        .query(
+            "SELECT s.transform_id, s.hits, s.misses,
+                    (SELECT COUNT(*) FROM transform_cache c WHERE c.transform_id = s.transform_id)
+             FROM transform_cache_stats s
+             ORDER BY s.transform_id",
+            &[],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("transform cache stats query: {e}")))?;
+    Ok(rows
+        .iter()
+        .map(|row| CacheStats {
+            transform_id: row.get(0),
+            hits: row.get::<_, i64>(1).max(0) as u64,
+            misses: row.get::<_, i64>(2).max(0) as u64,
+            entries: row.get::<_, i64>(3).max(0) as u64,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lifelog_types::lifelog_data::Payload;
+
+    fn ocr(uuid: &str, seconds: i64, text: &str) -> LifelogData {
+        LifelogData {
+            payload: Some(Payload::Ocrframe(lifelog_types::OcrFrame {
+                uuid: uuid.to_string(),
+                timestamp: Some(pbjson_types::Timestamp { seconds, nanos: 0 }),
+                t_canonical: Some(pbjson_types::Timestamp { seconds, nanos: 0 }),
+                text: text.to_string(),
+                ..Default::default()
+            })),
+        }
+    }
+
+    #[test]
+    fn input_hash_ignores_identity_and_time() {
+        let a = input_hash(&ocr("a", 1, "same text"));
+        let b = input_hash(&ocr("b", 99, "same text"));
+        let c = input_hash(&ocr("a", 1, "other text"));
+        assert!(a.is_some());
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn input_hash_keys_images_by_content() {
+        let screen = |uuid: &str, image: &[u8]| LifelogData {
+            payload: Some(Payload::Screenframe(lifelog_types::ScreenFrame {
+                uuid: uuid.to_string(),
+                image_bytes: image.to_vec(),
+                ..Default::default()
+            })),
+        };
+        let a = input_hash(&screen("a", b"same image"));
+        assert!(a.is_some());
+        assert_eq!(a, input_hash(&screen("b", b"same image")));
+        assert_ne!(a, input_hash(&screen("a", b"other image")));
+        assert_ne!(a, input_hash(&screen("a", b"")));
+    }
+
+    #[test]
+    fn fingerprint_tracks_semantic_params_only() {
+        let params = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let base = fingerprint("llm", "http://localhost:11434", &params(&[("model", "a")]));
+        assert_eq!(
+            base,
+            fingerprint(
+                "llm",
+                "http://localhost:11434",
+                &params(&[("model", "a"), ("timeout_secs", "5")])
+            )
+        );
+        assert_ne!(
+            base,
+            fingerprint("llm", "http://localhost:11434", &params(&[("model", "b")]))
+        );
+        assert_eq!(fingerprint("llm", "", &params(&[("cache", "false")])), None);
+    }
+
+    #[test]
+    fn cached_output_round_trips_onto_new_frame() {
+        let cached = CachedOutput::Transcription {
+            text: "coding".to_string(),
+            model: "m".to_string(),
+            confidence: 0.5,
+        };
+        let json = serde_json::to_value(&cached).unwrap_or_default();
+        assert_eq!(json["kind"], "transcription");
+        assert_eq!(
+            serde_json::from_value::<CachedOutput>(json).ok(),
+            Some(cached.clone())
+        );
+
+        let key = LifelogFrameKey {
+            uuid: lifelog_core::uuid::Uuid::new_v4(),
+            origin: lifelog_core::DataOrigin::new(
+                lifelog_core::DataOriginType::DeviceId("d".to_string()),
+                "Ocr".to_string(),
+            ),
+        };
+        let source = SourceTimestamps {
+            t_canonical: Some(pbjson_types::Timestamp {
+                seconds: 42,
+                nanos: 0,
+            }),
+            t_end: None,
+            time_quality: "good".to_string(),
+        };
+        let frame = match cached.into_output(&key, &source) {
+            TransformOutput::Transcription(f) => Some(f),
+            _ => None,
+        };
+        assert_eq!(
+            frame.as_ref().map(|f| f.source_uuid.clone()),
+            Some(key.uuid.to_string())
+        );
+        assert_eq!(
+            frame.and_then(|f| f.t_canonical).map(|t| t.seconds),
+            Some(42)
+        );
+    }
+}
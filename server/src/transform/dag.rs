@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use lifelog_core::DataOrigin;
+
 use super::{TransformExecutor, TransformPipelineError};
 
 pub struct TransformDag {
@@ -49,6 +51,14 @@ impl TransformDag {
         &self.transforms
     }
 
+    /// Whether frames from `origin` stay out of search until a transform has scanned them; see
+    /// [`TransformExecutor::gates_search`].
+    pub fn search_gated(&self, origin: &DataOrigin) -> bool {
+        self.transforms_for_modality(&origin.modality_name)
+            .iter()
+            .any(|t| t.gates_search() && t.matches_origin(origin))
+    }
+
     fn validate_no_cycles(&self) -> Result<(), TransformPipelineError> {
         let mut visited: HashMap<String, u8> = HashMap::new(); // 0=unvisited, 1=in-progress, 2=done
 
@@ -0,0 +1,228 @@
+use chrono::{DateTime, Duration, Utc};
+use lifelog_core::LifelogError;
+use uuid::Uuid;
+
+use crate::postgres::PostgresPool;
+
+/// After this many failed attempts an entry stops being retried automatically.
+pub const MAX_ATTEMPTS: i32 = 6;
+const BASE_BACKOFF_SECS: i64 = 60;
+const MAX_BACKOFF_SECS: i64 = 24 * 60 * 60;
+
+/// A frame a transform failed to process.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeadLetter {
+    pub transform_id: String,
+    pub frame_id: Uuid,
+    pub origin: String,
+    pub error: String,
+    pub attempts: i32,
+    pub first_failed_at: DateTime<Utc>,
+    pub last_failed_at: DateTime<Utc>,
+    /// `None` once the entry has exhausted its automatic retries.
+    pub next_retry_at: Option<DateTime<Utc>>,
+}
+
+/// Delay before the next retry after `attempts` failures: 1m, 2m, 4m, ... capped at a day.
+pub fn backoff_delay(attempts: i32) -> Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 30) as u32;
+    let secs = BASE_BACKOFF_SECS.saturating_mul(1i64 << exponent);
+    Duration::seconds(secs.min(MAX_BACKOFF_SECS))
+}
+
+pub(crate) fn next_retry(attempts: i32, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    (attempts < MAX_ATTEMPTS).then(|| now + backoff_delay(attempts))
+}
+
+fn row_to_dead_letter(row: &tokio_postgres::Row) -> DeadLetter {
+    DeadLetter {
+        transform_id: row.get("transform_id"),
+        frame_id: row.get("frame_id"),
+        origin: row.get("origin"),
+        error: row.get("error"),
+        attempts: row.get("attempts"),
+        first_failed_at: row.get("first_failed_at"),
+        last_failed_at: row.get("last_failed_at"),
+        next_retry_at: row.get("next_retry_at"),
+    }
+}
+
+const COLUMNS: &str = "transform_id, frame_id, origin, error, attempts, first_failed_at, last_failed_at, next_retry_at";
+
+/// Records a failed attempt, creating the entry or bumping its attempt count and backoff.
+pub async fn record_failure(
+    pool: &PostgresPool,
+    transform_id: &str,
+    frame_id: Uuid,
+    origin: &str,
+    error: &str,
+    now: DateTime<Utc>,
+) -> Result<i32, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+
+    let row = client
+        .query_one(
+            "INSERT INTO transform_dead_letters
+                 (transform_id, frame_id, origin, error, attempts, first_failed_at, last_failed_at)
+             VALUES ($1, $2, $3, $4, 1, $5, $5)
+             ON CONFLICT (transform_id, frame_id) DO UPDATE SET
+                 error = EXCLUDED.error,
+                 origin = EXCLUDED.origin,
+                 attempts = transform_dead_letters.attempts + 1,
+                 last_failed_at = EXCLUDED.last_failed_at
+             RETURNING attempts",
+            &[&transform_id, &frame_id, &origin, &error, &now],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("dead letter insert: {e}")))?;
+    let attempts: i32 = row.get(0);
+
+    client
+        .execute(
+            "UPDATE transform_dead_letters SET next_retry_at = $3
+             WHERE transform_id = $1 AND frame_id = $2",
+            &[&transform_id, &frame_id, &next_retry(attempts, now)],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("dead letter backoff: {e}")))?;
+
+    Ok(attempts)
+}
+
+/// Removes the entry after a successful retry. A no-op when the frame was never dead-lettered.
+pub async fn resolve(
+    pool: &PostgresPool,
+    transform_id: &str,
+    frame_id: Uuid,
+) -> Result<(), LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    client
+        .execute(
+            "DELETE FROM transform_dead_letters WHERE transform_id = $1 AND frame_id = $2",
+            &[&transform_id, &frame_id],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("dead letter resolve: {e}")))?;
+    Ok(())
+}
+
+/// Entries of `transform_id` whose backoff has elapsed, oldest first.
+pub async fn due(
+    pool: &PostgresPool,
+    transform_id: &str,
+    now: DateTime<Utc>,
+    limit: usize,
+) -> Result<Vec<DeadLetter>, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let rows = client
+        .query(
+            &format!(
+                "SELECT {COLUMNS} FROM transform_dead_letters
+                 WHERE transform_id = $1 AND next_retry_at IS NOT NULL AND next_retry_at <= $2
+                 ORDER BY next_retry_at ASC LIMIT $3"
+            ),
+            &[&transform_id, &now, &(limit as i64)],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("dead letter due query: {e}")))?;
+    Ok(rows.iter().map(row_to_dead_letter).collect())
+}
+
+/// Lists entries, optionally for one transform, most recently failed first.
+pub async fn list(
+    pool: &PostgresPool,
+    transform_id: Option<&str>,
+    include_exhausted: bool,
+    limit: usize,
+) -> Result<Vec<DeadLetter>, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let rows = client
+        .query(
+            &format!(
+                "SELECT {COLUMNS} FROM transform_dead_letters
+                 WHERE ($1::text IS NULL OR transform_id = $1)
+                 AND ($2 OR next_retry_at IS NOT NULL)
+                 ORDER BY last_failed_at DESC LIMIT $3"
+            ),
+            &[&transform_id, &include_exhausted, &(limit as i64)],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("dead letter list: {e}")))?;
+    Ok(rows.iter().map(row_to_dead_letter).collect())
+}
+
+/// Makes entries due immediately, including exhausted ones, and resets their attempt budget.
+/// An empty `frame_ids` selects every entry of the transform.
+pub async fn retry_now(
+    pool: &PostgresPool,
+    transform_id: &str,
+    frame_ids: &[Uuid],
+    now: DateTime<Utc>,
+) -> Result<u64, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    client
+        .execute(
+            "UPDATE transform_dead_letters SET next_retry_at = $3, attempts = 0
+             WHERE transform_id = $1 AND (cardinality($2::uuid[]) = 0 OR frame_id = ANY($2))",
+            &[&transform_id, &frame_ids, &now],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("dead letter retry: {e}")))
+}
+
+/// Drops entries without retrying them. An empty `frame_ids` selects every entry of the
+/// transform.
+pub async fn discard(
+    pool: &PostgresPool,
+    transform_id: &str,
+    frame_ids: &[Uuid],
+) -> Result<u64, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    client
+        .execute(
+            "DELETE FROM transform_dead_letters
+             WHERE transform_id = $1 AND (cardinality($2::uuid[]) = 0 OR frame_id = ANY($2))",
+            &[&transform_id, &frame_ids],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("dead letter discard: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_and_caps() {
+        assert_eq!(backoff_delay(1), Duration::seconds(60));
+        assert_eq!(backoff_delay(2), Duration::seconds(120));
+        assert_eq!(backoff_delay(4), Duration::seconds(480));
+        assert_eq!(backoff_delay(40), Duration::seconds(MAX_BACKOFF_SECS));
+        assert_eq!(backoff_delay(0), Duration::seconds(60));
+    }
+
+    #[test]
+    fn retries_stop_after_max_attempts() {
+        let now = Utc::now();
+        assert_eq!(next_retry(1, now), Some(now + Duration::seconds(60)));
+        assert_eq!(next_retry(MAX_ATTEMPTS, now), None);
+    }
+}
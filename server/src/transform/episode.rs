@@ -0,0 +1,645 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value as JsonValue};
+use uuid::Uuid;
+
+use crate::frames::FrameRow;
+use crate::postgres::PostgresPool;
+use crate::transform::entities::{domain_of, EntityKind};
+use lifelog_core::LifelogError;
+
+/// Width of the time slots activity is bucketed into before segmentation.
+const SLOT_SECS: i64 = 60;
+/// No observations for this long ends an episode.
+const IDLE_GAP: Duration = Duration::minutes(5);
+/// Episodes shorter than this are folded into the more similar neighbour when one is adjacent.
+const MIN_EPISODE: Duration = Duration::minutes(5);
+/// A slot less similar than this to the recent context starts a new episode.
+const SPLIT_SIMILARITY: f64 = 0.5;
+/// Adjacent episodes at least this similar overall are joined back together.
+const JOIN_SIMILARITY: f64 = 0.5;
+/// How much of the running context survives each slot; lower values react faster to switches.
+const CONTEXT_DECAY: f64 = 0.6;
+/// Combined weight of OCR words relative to one app, domain or directory signal.
+const TEXT_WEIGHT: f64 = 0.3;
+const MAX_TEXT_TOKENS: usize = 50;
+/// Upper bound on member ids stored in an episode payload; `member_count` has the full count.
+const MAX_MEMBER_IDS: usize = 2000;
+const MAX_TITLE_CHARS: usize = 120;
+
+const EPISODE_STREAM_ID: &str = "episode";
+
+#[derive(Debug, Default, Clone)]
+pub struct EpisodeRunSummary {
+    pub episodes_written: u64,
+    pub episodes_removed: u64,
+}
+
+/// One frame reduced to the signals segmentation looks at.
+#[derive(Debug, Clone, Default)]
+pub struct Observation {
+    pub frame_id: Uuid,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub app: Option<String>,
+    pub window_title: Option<String>,
+    pub domain: Option<String>,
+    pub cwd: Option<String>,
+    pub text_tokens: Vec<String>,
+}
+
+/// A contiguous period of coherent activity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Episode {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub title: String,
+    pub dominant_app: Option<String>,
+    pub apps: Vec<String>,
+    pub domains: Vec<String>,
+    pub cwds: Vec<String>,
+    pub member_frame_ids: Vec<Uuid>,
+}
+
+type Features = HashMap<String, f64>;
+
+impl Observation {
+    fn features(&self) -> Features {
+        let mut out = Features::new();
+        if let Some(app) = &self.app {
+            out.insert(format!("app:{}", app.to_lowercase()), 1.0);
+        }
+        if let Some(domain) = &self.domain {
+            out.insert(format!("domain:{domain}"), 1.0);
+        }
+        if let Some(cwd) = &self.cwd {
+            out.insert(format!("cwd:{cwd}"), 1.0);
+        }
+        if !self.text_tokens.is_empty() {
+            let w = TEXT_WEIGHT / (self.text_tokens.len() as f64).sqrt();
+            for token in &self.text_tokens {
+                out.insert(format!("word:{token}"), w);
+            }
+        }
+        out
+    }
+
+    fn weight(&self) -> f64 {
+        ((self.end - self.start).num_seconds() as f64).max(1.0)
+    }
+}
+
+/// Builds an observation from a stored frame, or `None` for frames that carry no usable signal.
+pub fn observation_from_frame(
+    frame_id: Uuid,
+    modality: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    payload: &JsonValue,
+) -> Option<Observation> {
+    let text = |key: &str| {
+        payload[key]
+            .as_str()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+    let mut obs = Observation {
+        frame_id,
+        start,
+        end: end.max(start),
+        ..Observation::default()
+    };
+    match modality {
+        "WindowActivity" => {
+            obs.app = text("application");
+            obs.window_title = text("window_title");
+        }
+        "Browser" => {
+            obs.domain = text("url").and_then(|url| domain_of(EntityKind::Url, &url));
+        }
+        "ShellHistory" => {
+            obs.cwd = text("working_dir").map(|d| d.trim_end_matches('/').to_string());
+        }
+        "Ocr" => {
+            obs.text_tokens = text("text").map(|t| tokens(&t)).unwrap_or_default();
+        }
+        _ => return None,
+    }
+    let useful = obs.app.is_some()
+        || obs.domain.is_some()
+        || obs.cwd.is_some()
+        || !obs.text_tokens.is_empty();
+    useful.then_some(obs)
+}
+
+fn tokens(text: &str) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        if word.chars().count() >= 4 && !word.chars().all(|c| c.is_ascii_digit()) {
+            seen.insert(word.to_lowercase());
+        }
+        if seen.len() >= MAX_TEXT_TOKENS {
+            break;
+        }
+    }
+    seen.into_iter().collect()
+}
+
+fn namespace(key: &str) -> &str {
+    key.split_once(':').map(|(ns, _)| ns).unwrap_or(key)
+}
+
+fn cosine(a: &Features, b: &Features) -> f64 {
+    let dot: f64 = a.iter().filter_map(|(k, v)| b.get(k).map(|w| v * w)).sum();
+    let norm = |f: &Features| f.values().map(|v| v * v).sum::<f64>().sqrt();
+    let denom = norm(a) * norm(b);
+    if denom == 0.0 {
+        0.0
+    } else {
+        dot / denom
+    }
+}
+
+/// Similarity of a slot to the running context, looking only at the kinds of signal the slot
+/// has. A slot with only OCR text says nothing about which app is focused, so it is not
+/// compared on apps. Returns `None` when the context has no overlapping kinds.
+fn slot_similarity(slot: &Features, context: &Features) -> Option<f64> {
+    let kinds: HashSet<&str> = slot.keys().map(|k| namespace(k)).collect();
+    let relevant: Features = context
+        .iter()
+        .filter(|(k, _)| kinds.contains(namespace(k)))
+        .map(|(k, v)| (k.clone(), *v))
+        .collect();
+    (!relevant.is_empty()).then(|| cosine(slot, &relevant))
+}
+
+fn add_into(target: &mut Features, source: &Features) {
+    for (k, v) in source {
+        *target.entry(k.clone()).or_default() += v;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Draft {
+    first_slot: i64,
+    last_slot: i64,
+    members: BTreeSet<usize>,
+    profile: Features,
+}
+
+impl Draft {
+    fn slots(&self) -> i64 {
+        self.last_slot - self.first_slot + 1
+    }
+
+    fn absorb(&mut self, other: Draft) {
+        self.first_slot = self.first_slot.min(other.first_slot);
+        self.last_slot = self.last_slot.max(other.last_slot);
+        self.members.extend(other.members);
+        add_into(&mut self.profile, &other.profile);
+    }
+}
+
+fn idle_between(a: &Draft, b: &Draft) -> bool {
+    (b.first_slot - a.last_slot - 1) * SLOT_SECS >= IDLE_GAP.num_seconds()
+}
+
+/// Segments one collector's observations into episodes.
+///
+/// Observations are bucketed into one-minute slots. A new episode starts after an idle gap or
+/// when a slot's apps, domains, directories or screen text diverge from the recent context.
+/// Short fragments are then folded into the more similar adjacent episode, and adjacent
+/// episodes that turn out to be about the same thing are joined.
+pub fn segment(observations: &[Observation]) -> Vec<Episode> {
+    let max_slots_per_obs = 24 * 3600 / SLOT_SECS;
+    let mut slots: BTreeMap<i64, (Features, Vec<usize>)> = BTreeMap::new();
+    for (i, obs) in observations.iter().enumerate() {
+        let features = obs.features();
+        let first = obs.start.timestamp().div_euclid(SLOT_SECS);
+        // An observation ending exactly on a slot boundary does not occupy the next slot.
+        let last = (obs.end.timestamp() - 1)
+            .max(obs.start.timestamp())
+            .div_euclid(SLOT_SECS)
+            .min(first + max_slots_per_obs);
+        for slot in first..=last {
+            let entry = slots.entry(slot).or_default();
+            add_into(&mut entry.0, &features);
+            entry.1.push(i);
+        }
+    }
+
+    let mut drafts: Vec<Draft> = Vec::new();
+    let mut context = Features::new();
+    for (slot, (features, members)) in slots {
+        let continues = drafts.last().is_some_and(|cur| {
+            (slot - cur.last_slot - 1) * SLOT_SECS < IDLE_GAP.num_seconds()
+                && slot_similarity(&features, &context).is_none_or(|s| s >= SPLIT_SIMILARITY)
+        });
+        match drafts.last_mut() {
+            Some(cur) if continues => {
+                cur.last_slot = slot;
+                cur.members.extend(members);
+                add_into(&mut cur.profile, &features);
+                context.values_mut().for_each(|v| *v *= CONTEXT_DECAY);
+                add_into(&mut context, &features);
+            }
+            _ => {
+                context = features.clone();
+                drafts.push(Draft {
+                    first_slot: slot,
+                    last_slot: slot,
+                    members: members.into_iter().collect(),
+                    profile: features,
+                });
+            }
+        }
+    }
+
+    fold_short(&mut drafts);
+    join_similar(&mut drafts);
+
+    drafts
+        .into_iter()
+        .map(|d| summarize(&d, observations))
+        .collect()
+}
+
+fn fold_short(drafts: &mut Vec<Draft>) {
+    let min_slots = MIN_EPISODE.num_seconds() / SLOT_SECS;
+    loop {
+        let candidate = drafts
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.slots() < min_slots)
+            .filter_map(|(i, d)| {
+                let prev = i
+                    .checked_sub(1)
+                    .filter(|&p| !idle_between(&drafts[p], d))
+                    .map(|p| (p, cosine(&drafts[p].profile, &d.profile)));
+                let next = drafts
+                    .get(i + 1)
+                    .filter(|n| !idle_between(d, n))
+                    .map(|n| (i + 1, cosine(&n.profile, &d.profile)));
+                let target = match (prev, next) {
+                    (Some(p), Some(n)) => Some(if n.1 > p.1 { n.0 } else { p.0 }),
+                    (p, n) => p.or(n).map(|(t, _)| t),
+                }?;
+                Some((i, target, d.slots()))
+            })
+            .min_by_key(|(_, _, slots)| *slots);
+        let Some((i, target, _)) = candidate else {
+            break;
+        };
+        let short = drafts.remove(i);
+        let target = if target > i { target - 1 } else { target };
+        drafts[target].absorb(short);
+    }
+}
+
+fn join_similar(drafts: &mut Vec<Draft>) {
+    let mut i = 1;
+    while i < drafts.len() {
+        if !idle_between(&drafts[i - 1], &drafts[i])
+            && cosine(&drafts[i - 1].profile, &drafts[i].profile) >= JOIN_SIMILARITY
+        {
+            let next = drafts.remove(i);
+            drafts[i - 1].absorb(next);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Ranks values by accumulated weight, heaviest first, ties broken alphabetically.
+fn ranked<'a>(values: impl Iterator<Item = (&'a str, f64)>) -> Vec<String> {
+    let mut totals: HashMap<&str, f64> = HashMap::new();
+    for (value, weight) in values {
+        *totals.entry(value).or_default() += weight;
+    }
+    let mut out: Vec<(&str, f64)> = totals.into_iter().collect();
+    out.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+    out.into_iter().map(|(v, _)| v.to_string()).collect()
+}
+
+fn summarize(draft: &Draft, observations: &[Observation]) -> Episode {
+    let slot_start = DateTime::from_timestamp(draft.first_slot * SLOT_SECS, 0).unwrap_or_default();
+    let slot_end =
+        DateTime::from_timestamp((draft.last_slot + 1) * SLOT_SECS, 0).unwrap_or_default();
+
+    let mut members: Vec<&Observation> = draft.members.iter().map(|&i| &observations[i]).collect();
+    members.sort_by_key(|o| (o.start, o.frame_id));
+
+    let start = members
+        .iter()
+        .map(|o| o.start)
+        .min()
+        .unwrap_or(slot_start)
+        .max(slot_start);
+    let end = members
+        .iter()
+        .map(|o| o.end)
+        .max()
+        .unwrap_or(slot_end)
+        .clamp(start, slot_end);
+
+    let apps = ranked(
+        members
+            .iter()
+            .filter_map(|o| o.app.as_deref().map(|a| (a, o.weight()))),
+    );
+    let titles = ranked(
+        members
+            .iter()
+            .filter_map(|o| o.window_title.as_deref().map(|t| (t, o.weight()))),
+    );
+    let domains = ranked(
+        members
+            .iter()
+            .filter_map(|o| o.domain.as_deref().map(|d| (d, 1.0))),
+    );
+    let cwds = ranked(
+        members
+            .iter()
+            .filter_map(|o| o.cwd.as_deref().map(|c| (c, 1.0))),
+    );
+
+    let title = titles
+        .first()
+        .or(domains.first())
+        .or(cwds.first())
+        .or(apps.first())
+        .map(|t| t.chars().take(MAX_TITLE_CHARS).collect())
+        .unwrap_or_else(|| "Activity".to_string());
+
+    let mut seen = HashSet::new();
+    let member_frame_ids = members
+        .iter()
+        .map(|o| o.frame_id)
+        .filter(|id| seen.insert(*id))
+        .collect();
+
+    Episode {
+        start,
+        end,
+        title,
+        dominant_app: apps.first().cloned(),
+        apps: apps.into_iter().take(5).collect(),
+        domains: domains.into_iter().take(5).collect(),
+        cwds: cwds.into_iter().take(3).collect(),
+        member_frame_ids,
+    }
+}
+
+/// Stable frame id for an episode, so re-segmenting the same day overwrites it in place.
+pub fn episode_frame_id(collector_id: &str, start: DateTime<Utc>) -> Uuid {
+    crate::frames::derived_frame_id(&format!("episode:{collector_id}:{}", start.timestamp()))
+}
+
+fn day_start(t: DateTime<Utc>) -> DateTime<Utc> {
+    DateTime::from_timestamp(t.timestamp() - t.timestamp().rem_euclid(86_400), 0).unwrap_or(t)
+}
+
+/// Re-segments yesterday and today (UTC) into `Episode` interval frames, one set per collector.
+///
+/// Episodes are built from window activity, browser domains, shell working directories and OCR
+/// text, and carry a title, the dominant app and the ids of their member frames. Episodes from
+/// an earlier run that no longer exist after re-segmentation are removed.
+pub async fn segment_episodes(
+    pool: &PostgresPool,
+    now: DateTime<Utc>,
+) -> Result<EpisodeRunSummary, LifelogError> {
+    let mut summary = EpisodeRunSummary::default();
+    let today = day_start(now);
+    for day in [today - Duration::days(1), today] {
+        let day_end = (day + Duration::days(1)).min(now);
+        segment_day(pool, day, day_end, now, &mut summary).await?;
+    }
+    Ok(summary)
+}
+
+async fn segment_day(
+    pool: &PostgresPool,
+    day: DateTime<Utc>,
+    day_end: DateTime<Utc>,
+    now: DateTime<Utc>,
+    summary: &mut EpisodeRunSummary,
+) -> Result<(), LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+
+    let rows = client
+        .query(
+            "SELECT id, collector_id, modality, t_canonical, t_end, payload FROM frames
+             WHERE modality IN ('WindowActivity', 'Browser', 'ShellHistory', 'Ocr', 'Episode')
+             AND t_canonical >= $1 AND t_canonical < $2
+             ORDER BY t_canonical ASC",
+            &[&day, &day_end],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("episode query: {e}")))?;
+
+    let mut observations: HashMap<String, Vec<Observation>> = HashMap::new();
+    let mut existing: Vec<Uuid> = Vec::new();
+    for row in &rows {
+        let id: Uuid = row.get("id");
+        let collector_id: String = row.get("collector_id");
+        let modality: String = row.get("modality");
+        let start: DateTime<Utc> = row.get("t_canonical");
+        let t_end: Option<DateTime<Utc>> = row.get("t_end");
+        let payload: JsonValue = row.get("payload");
+
+        if modality == "Episode" {
+            existing.push(id);
+            continue;
+        }
+        let end = t_end.unwrap_or(start).min(day_end);
+        if let Some(obs) = observation_from_frame(id, &modality, start, end, &payload) {
+            observations.entry(collector_id).or_default().push(obs);
+        }
+    }
+
+    let mut written = HashSet::new();
+    for (collector_id, obs) in &observations {
+        for episode in segment(obs) {
+            let id = episode_frame_id(collector_id, episode.start);
+            let member_count = episode.member_frame_ids.len();
+            let frame = FrameRow {
+                id,
+                collector_id: collector_id.clone(),
+                stream_id: EPISODE_STREAM_ID.to_string(),
+                modality: "Episode".to_string(),
+                t_device: None,
+                t_ingest: now,
+                t_canonical: episode.start,
+                t_end: Some(episode.end),
+                time_quality: "inferred".to_string(),
+                blob_hash: None,
+                blob_size: None,
+                indexed: true,
+                source_frame_id: None,
+                payload: json!({
+                    "title": episode.title,
+                    "dominant_app": episode.dominant_app,
+                    "apps": episode.apps,
+                    "domains": episode.domains,
+                    "cwds": episode.cwds,
+                    "member_frame_ids": episode
+                        .member_frame_ids
+                        .iter()
+                        .take(MAX_MEMBER_IDS)
+                        .map(|id| id.to_string())
+                        .collect::<Vec<_>>(),
+                    "member_count": member_count,
+                    "duration_secs": (episode.end - episode.start).num_seconds(),
+                    "source": "episode_segmentation",
+                }),
+            };
+            crate::frames::upsert(pool, &frame).await?;
+            written.insert(id);
+            summary.episodes_written += 1;
+        }
+    }
+
+    // Includes episodes of collectors with no observations left, e.g. after they were forgotten.
+    let stale: Vec<Uuid> = existing
+        .into_iter()
+        .filter(|id| !written.contains(id))
+        .collect();
+    if !stale.is_empty() {
+        let removed = client
+            .execute("DELETE FROM frames WHERE id = ANY($1)", &[&stale])
+            .await
+            .map_err(|e| LifelogError::Database(format!("episode cleanup: {e}")))?;
+        summary.episodes_removed += removed;
+    }
+    Ok(())
+}
+
+/// An `Episode` frame as listed for timeline chapters.
+#[derive(Debug, Clone)]
+pub struct EpisodeRow {
+    pub id: Uuid,
+    pub collector_id: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub payload: JsonValue,
+}
+
+/// Episodes overlapping `[start, end)`, oldest first, optionally for a single collector.
+pub async fn list(
+    pool: &PostgresPool,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    collector_id: Option<&str>,
+) -> Result<Vec<EpisodeRow>, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let rows = client
+        .query(
+            "SELECT id, collector_id, t_canonical, t_end, payload FROM frames
+             WHERE modality = 'Episode'
+             AND time_range && tstzrange($1, $2, '[)')
+             AND ($3::text IS NULL OR collector_id = $3)
+             ORDER BY t_canonical ASC",
+            &[&start, &end, &collector_id],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("episode list: {e}")))?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let start: DateTime<Utc> = row.get("t_canonical");
+            let t_end: Option<DateTime<Utc>> = row.get("t_end");
+            EpisodeRow {
+                id: row.get("id"),
+                collector_id: row.get("collector_id"),
+                start,
+                end: t_end.unwrap_or(start),
+                payload: row.get("payload"),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(mins: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_040 + mins * 60, 0).unwrap_or_default()
+    }
+
+    fn window(mins: i64, len: i64, app: &str, title: &str) -> Observation {
+        Observation {
+            frame_id: Uuid::new_v4(),
+            start: at(mins),
+            end: at(mins + len),
+            app: Some(app.to_string()),
+            window_title: Some(title.to_string()),
+            ..Observation::default()
+        }
+    }
+
+    #[test]
+    fn splits_on_context_switch_and_idle_gap() {
+        let obs = vec![
+            window(0, 20, "code", "main.rs - lifelog"),
+            window(20, 15, "firefox", "Inbox - Mail"),
+            // Idle, then back to the editor.
+            window(45, 10, "code", "main.rs - lifelog"),
+        ];
+        let episodes = segment(&obs);
+        assert_eq!(episodes.len(), 3);
+        assert_eq!(episodes[0].dominant_app.as_deref(), Some("code"));
+        assert_eq!(episodes[0].title, "main.rs - lifelog");
+        assert_eq!(episodes[1].dominant_app.as_deref(), Some("firefox"));
+        assert_eq!(episodes[2].start, at(45));
+        assert_eq!(episodes[2].member_frame_ids, vec![obs[2].frame_id]);
+    }
+
+    #[test]
+    fn folds_brief_interruptions_into_surrounding_episode() {
+        let obs = vec![
+            window(0, 15, "code", "lib.rs - lifelog"),
+            window(15, 1, "slack", "general - Slack"),
+            window(16, 15, "code", "lib.rs - lifelog"),
+        ];
+        let episodes = segment(&obs);
+        assert_eq!(episodes.len(), 1);
+        assert_eq!(episodes[0].dominant_app.as_deref(), Some("code"));
+        assert_eq!(episodes[0].apps, vec!["code", "slack"]);
+        assert_eq!(episodes[0].member_frame_ids.len(), 3);
+        assert_eq!(episodes[0].end, at(31));
+    }
+
+    #[test]
+    fn text_only_slots_do_not_split_on_missing_app_signal() {
+        let mut obs = vec![window(0, 12, "kitty", "~/src/lifelog")];
+        for m in 0..12 {
+            obs.push(Observation {
+                frame_id: Uuid::new_v4(),
+                start: at(m),
+                end: at(m),
+                cwd: Some("/home/me/src/lifelog".to_string()),
+                ..Observation::default()
+            });
+            obs.push(Observation {
+                frame_id: Uuid::new_v4(),
+                start: at(m) + Duration::seconds(30),
+                end: at(m) + Duration::seconds(30),
+                text_tokens: tokens(&format!("cargo build finished step {m}")),
+                ..Observation::default()
+            });
+        }
+        let episodes = segment(&obs);
+        assert_eq!(episodes.len(), 1);
+        assert_eq!(episodes[0].cwds, vec!["/home/me/src/lifelog"]);
+        assert_eq!(episodes[0].title, "~/src/lifelog");
+    }
+}
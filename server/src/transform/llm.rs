@@ -1,25 +1,17 @@
-use std::sync::Mutex;
-
 use async_trait::async_trait;
 use lifelog_core::{DataOrigin, DataOriginType, LifelogFrameKey, PrivacyLevel};
 use lifelog_types::{DataModality, LifelogData};
 
+use super::egress::EgressGuard;
+use super::llm_provider::{ChatMessage, LlmClient};
 use super::{TransformExecutor, TransformOutput, TransformPipelineError};
 
-struct RateState {
-    call_timestamps: Vec<chrono::DateTime<chrono::Utc>>,
-}
-
 pub struct LlmExecutor {
     id: String,
     source: DataOrigin,
-    endpoint: String,
-    model: String,
     system_prompt: String,
-    timeout_secs: u64,
-    privacy_level: PrivacyLevel,
-    max_calls_per_hour: Option<u32>,
-    rate_state: Mutex<RateState>,
+    llm: LlmClient,
+    cache_fingerprint: Option<String>,
 }
 
 impl LlmExecutor {
@@ -28,67 +20,20 @@ impl LlmExecutor {
         source: DataOrigin,
         endpoint: String,
         params: &std::collections::HashMap<String, String>,
+        egress: EgressGuard,
     ) -> Self {
-        let max_calls_per_hour = params
-            .get("max_calls_per_hour")
-            .and_then(|v| v.parse().ok());
-
-        if let Some(limit) = max_calls_per_hour {
-            tracing::info!(
-                transform_id = %id,
-                max_calls_per_hour = limit,
-                "LLM transform rate limit configured"
-            );
-        }
-
         Self {
+            cache_fingerprint: super::cache::fingerprint("llm", &endpoint, params),
+            llm: LlmClient::from_params(&id, endpoint, params, egress),
             id,
             source,
-            endpoint,
-            model: params
-                .get("model")
-                .cloned()
-                .unwrap_or_else(|| "gemma3:4b-it-qat".to_string()),
             system_prompt: params
                 .get("system_prompt")
                 .cloned()
                 .unwrap_or_else(|| {
                     "Clean up this speech-to-text transcription. Fix grammar, remove filler words, maintain the original meaning. Output only the cleaned text.".to_string()
                 }),
-            timeout_secs: params
-                .get("timeout_secs")
-                .and_then(|v| v.parse().ok())
-                .unwrap_or(60),
-            privacy_level: PrivacyLevel::from_params(params),
-            max_calls_per_hour,
-            rate_state: Mutex::new(RateState {
-                call_timestamps: Vec::new(),
-            }),
-        }
-    }
-
-    fn check_rate_limit(&self) -> Result<(), TransformPipelineError> {
-        let limit = match self.max_calls_per_hour {
-            Some(l) => l,
-            None => return Ok(()),
-        };
-
-        let now = chrono::Utc::now();
-        let one_hour_ago = now - chrono::Duration::hours(1);
-
-        let mut state = self.rate_state.lock().unwrap_or_else(|e| e.into_inner());
-        state.call_timestamps.retain(|ts| *ts > one_hour_ago);
-
-        if state.call_timestamps.len() >= limit as usize {
-            return Err(TransformPipelineError::ServiceError(format!(
-                "rate limit exceeded: {} calls in the last hour (limit: {})",
-                state.call_timestamps.len(),
-                limit
-            )));
         }
-
-        state.call_timestamps.push(now);
-        Ok(())
     }
 }
 
@@ -111,7 +56,11 @@ impl TransformExecutor for LlmExecutor {
     }
 
     fn privacy_level(&self) -> PrivacyLevel {
-        self.privacy_level
+        self.llm.privacy_level()
+    }
+
+    fn cache_fingerprint(&self) -> Option<&str> {
+        self.cache_fingerprint.as_deref()
     }
 
     fn matches_origin(&self, key_origin: &DataOrigin) -> bool {
@@ -142,8 +91,6 @@ impl TransformExecutor for LlmExecutor {
         data: &LifelogData,
         key: &LifelogFrameKey,
     ) -> Result<TransformOutput, TransformPipelineError> {
-        self.check_rate_limit()?;
-
         let payload = data
             .payload
             .as_ref()
@@ -165,87 +112,16 @@ impl TransformExecutor for LlmExecutor {
 
         let sanitized_input = sanitize_llm_input(&transcription.text, &self.id);
 
-        let url = format!("{}/api/chat", self.endpoint.trim_end_matches('/'));
-
-        let body = serde_json::json!({
-            "model": self.model,
-            "messages": [
-                { "role": "system", "content": self.system_prompt },
-                { "role": "user", "content": sanitized_input }
-            ],
-            "stream": false
-        });
-
-        let json = {
-            let mut last_err = None;
-            let mut result = None;
-            for attempt in 0..3u32 {
-                if attempt > 0 {
-                    tokio::time::sleep(std::time::Duration::from_secs(1 << attempt)).await;
-                }
-
-                let resp = match http
-                    .post(&url)
-                    .json(&body)
-                    .timeout(std::time::Duration::from_secs(self.timeout_secs))
-                    .send()
-                    .await
-                {
-                    Ok(r) => r,
-                    Err(e) => {
-                        tracing::warn!(
-                            attempt = attempt + 1,
-                            error = %e,
-                            "LLM request failed, retrying"
-                        );
-                        last_err = Some(TransformPipelineError::ServiceUnavailable {
-                            endpoint: format!("{url}: {e}"),
-                        });
-                        continue;
-                    }
-                };
-
-                if !resp.status().is_success() {
-                    let status = resp.status();
-                    let text = resp.text().await.unwrap_or_default();
-                    tracing::warn!(
-                        attempt = attempt + 1,
-                        status = %status,
-                        "LLM request returned error, retrying"
-                    );
-                    last_err = Some(TransformPipelineError::ServiceError(format!(
-                        "ollama {status}: {text}"
-                    )));
-                    continue;
-                }
-
-                match resp.json::<serde_json::Value>().await {
-                    Ok(j) => {
-                        result = Some(j);
-                        break;
-                    }
-                    Err(e) => {
-                        last_err = Some(TransformPipelineError::ServiceError(format!("json: {e}")));
-                        continue;
-                    }
-                }
-            }
-            match result {
-                Some(j) => j,
-                None => {
-                    return Err(last_err.unwrap_or_else(|| {
-                        TransformPipelineError::ServiceError(
-                            "LLM request failed after 3 attempts".into(),
-                        )
-                    }));
-                }
-            }
-        };
-
-        let raw_text = json["message"]["content"]
-            .as_str()
-            .unwrap_or("")
-            .to_string();
+        let raw_text = self
+            .llm
+            .chat(
+                http,
+                &[
+                    ChatMessage::system(self.system_prompt.as_str()),
+                    ChatMessage::user(sanitized_input),
+                ],
+            )
+            .await?;
 
         let cleaned_text = match validate_llm_output(&raw_text, &transcription.text) {
             Ok(text) => text,
@@ -266,7 +142,7 @@ impl TransformExecutor for LlmExecutor {
             uuid: key.uuid.to_string(),
             text: cleaned_text,
             source_uuid: key.uuid.to_string(),
-            model: self.model.clone(),
+            model: self.llm.model().to_string(),
             timestamp: transcription.timestamp,
             confidence,
             t_device: transcription.t_device,
@@ -312,7 +188,7 @@ const INJECTION_PATTERNS: &[&str] = &[
     "jailbreak",
 ];
 
-fn sanitize_llm_input(input: &str, transform_id: &str) -> String {
+pub(crate) fn sanitize_llm_input(input: &str, transform_id: &str) -> String {
     let lower = input.to_lowercase();
     for pattern in INJECTION_PATTERNS {
         if lower.contains(pattern) {
@@ -0,0 +1,623 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lifelog_core::PrivacyLevel;
+use serde_json::json;
+
+use super::egress::EgressGuard;
+use super::TransformPipelineError;
+
+const DEFAULT_MODEL: &str = "gemma3:4b-it-qat";
+
+/// Which wire protocol a transform's `service_endpoint` speaks, from the `provider` param.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderKind {
+    /// Ollama's `/api/chat`.
+    Ollama,
+    /// `/v1/chat/completions` as served by OpenAI, llama.cpp, vLLM and friends.
+    OpenAi,
+    /// Deterministic in-process replies; never touches the network.
+    Mock,
+}
+
+impl ProviderKind {
+    pub fn from_params(params: &HashMap<String, String>) -> Self {
+        match params
+            .get("provider")
+            .map(|s| s.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("openai") | Some("openai-compatible") | Some("llama.cpp") | Some("vllm") => {
+                ProviderKind::OpenAi
+            }
+            Some("mock") => ProviderKind::Mock,
+            Some("ollama") | None => ProviderKind::Ollama,
+            Some(other) => {
+                tracing::warn!(provider = %other, "Unknown LLM provider; falling back to ollama");
+                ProviderKind::Ollama
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub role: &'static str,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: "system",
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: "user",
+            content: content.into(),
+        }
+    }
+}
+
+/// A provider's answer with the token counts it reported (0 when it reported none).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChatReply {
+    pub content: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// The endpoint requests go to, checked against the egress policy. `None` for providers
+    /// that never leave the process.
+    fn endpoint(&self) -> Option<&str>;
+
+    /// Sends `messages`. With a `schema`, the provider is asked to constrain its reply to JSON
+    /// matching it; callers still validate, since not every server enforces this.
+    async fn chat(
+        &self,
+        http: &reqwest::Client,
+        model: &str,
+        messages: &[ChatMessage],
+        schema: Option<&serde_json::Value>,
+        timeout: Duration,
+    ) -> Result<ChatReply, ProviderError>;
+}
+
+/// A failed provider call, split by whether trying again can help.
+#[derive(Debug)]
+pub enum ProviderError {
+    Retryable(TransformPipelineError),
+    Fatal(TransformPipelineError),
+}
+
+impl ProviderError {
+    fn into_inner(self) -> TransformPipelineError {
+        match self {
+            ProviderError::Retryable(e) | ProviderError::Fatal(e) => e,
+        }
+    }
+}
+
+fn messages_json(messages: &[ChatMessage]) -> Vec<serde_json::Value> {
+    messages
+        .iter()
+        .map(|m| json!({ "role": m.role, "content": m.content }))
+        .collect()
+}
+
+async fn post_json(
+    http: &reqwest::Client,
+    url: &str,
+    api_key: Option<&str>,
+    body: &serde_json::Value,
+    timeout: Duration,
+) -> Result<serde_json::Value, ProviderError> {
+    let mut request = http.post(url).json(body).timeout(timeout);
+    if let Some(key) = api_key {
+        request = request.bearer_auth(key);
+    }
+    let resp = request.send().await.map_err(|e| {
+        ProviderError::Retryable(TransformPipelineError::ServiceUnavailable {
+            endpoint: format!("{url}: {e}"),
+        })
+    })?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        let text = resp.text().await.unwrap_or_default();
+        let err = TransformPipelineError::ServiceError(format!("{url} {status}: {text}"));
+        return Err(
+            if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                ProviderError::Retryable(err)
+            } else {
+                ProviderError::Fatal(err)
+            },
+        );
+    }
+
+    resp.json::<serde_json::Value>().await.map_err(|e| {
+        ProviderError::Retryable(TransformPipelineError::ServiceError(format!("json: {e}")))
+    })
+}
+
+pub struct OllamaProvider {
+    endpoint: String,
+}
+
+impl OllamaProvider {
+    pub fn new(endpoint: String) -> Self {
+        Self { endpoint }
+    }
+}
+
+fn parse_ollama_reply(json: &serde_json::Value) -> ChatReply {
+    ChatReply {
+        content: json["message"]["content"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        prompt_tokens: json["prompt_eval_count"].as_u64().unwrap_or(0),
+        completion_tokens: json["eval_count"].as_u64().unwrap_or(0),
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn endpoint(&self) -> Option<&str> {
+        Some(&self.endpoint)
+    }
+
+    async fn chat(
+        &self,
+        http: &reqwest::Client,
+        model: &str,
+        messages: &[ChatMessage],
+        schema: Option<&serde_json::Value>,
+        timeout: Duration,
+    ) -> Result<ChatReply, ProviderError> {
+        let url = format!("{}/api/chat", self.endpoint.trim_end_matches('/'));
+        let mut body = json!({
+            "model": model,
+            "messages": messages_json(messages),
+            "stream": false
+        });
+        if let Some(schema) = schema {
+            body["format"] = schema.clone();
+        }
+        let json = post_json(http, &url, None, &body, timeout).await?;
+        Ok(parse_ollama_reply(&json))
+    }
+}
+
+pub struct OpenAiProvider {
+    endpoint: String,
+    api_key: Option<String>,
+}
+
+impl OpenAiProvider {
+    /// `endpoint` may be the server root or its `/v1` prefix.
+    pub fn new(endpoint: String, api_key: Option<String>) -> Self {
+        Self { endpoint, api_key }
+    }
+
+    fn url(&self) -> String {
+        let base = self.endpoint.trim_end_matches('/');
+        if base.ends_with("/v1") {
+            format!("{base}/chat/completions")
+        } else {
+            format!("{base}/v1/chat/completions")
+        }
+    }
+}
+
+fn parse_openai_reply(json: &serde_json::Value) -> ChatReply {
+    ChatReply {
+        content: json["choices"][0]["message"]["content"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        prompt_tokens: json["usage"]["prompt_tokens"].as_u64().unwrap_or(0),
+        completion_tokens: json["usage"]["completion_tokens"].as_u64().unwrap_or(0),
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn endpoint(&self) -> Option<&str> {
+        Some(&self.endpoint)
+    }
+
+    async fn chat(
+        &self,
+        http: &reqwest::Client,
+        model: &str,
+        messages: &[ChatMessage],
+        schema: Option<&serde_json::Value>,
+        timeout: Duration,
+    ) -> Result<ChatReply, ProviderError> {
+        let mut body = json!({
+            "model": model,
+            "messages": messages_json(messages),
+            "stream": false
+        });
+        if let Some(schema) = schema {
+            body["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": { "name": "response", "schema": schema }
+            });
+        }
+        let json = post_json(http, &self.url(), self.api_key.as_deref(), &body, timeout).await?;
+        Ok(parse_openai_reply(&json))
+    }
+}
+
+/// Replies with `mock_response` when configured, otherwise echoes the last user message.
+/// Token counts are whitespace-separated words, so accounting is testable too.
+pub struct MockProvider {
+    response: Option<String>,
+}
+
+impl MockProvider {
+    pub fn new(response: Option<String>) -> Self {
+        Self { response }
+    }
+
+    fn reply(&self, messages: &[ChatMessage]) -> ChatReply {
+        let content = self.response.clone().unwrap_or_else(|| {
+            messages
+                .iter()
+                .rev()
+                .find(|m| m.role == "user")
+                .map(|m| m.content.clone())
+                .unwrap_or_default()
+        });
+        let words = |s: &str| s.split_whitespace().count() as u64;
+        ChatReply {
+            prompt_tokens: messages.iter().map(|m| words(&m.content)).sum(),
+            completion_tokens: words(&content),
+            content,
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MockProvider {
+    fn endpoint(&self) -> Option<&str> {
+        None
+    }
+
+    async fn chat(
+        &self,
+        _http: &reqwest::Client,
+        _model: &str,
+        messages: &[ChatMessage],
+        _schema: Option<&serde_json::Value>,
+        _timeout: Duration,
+    ) -> Result<ChatReply, ProviderError> {
+        Ok(self.reply(messages))
+    }
+}
+
+/// Running totals for one client.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LlmUsage {
+    pub calls: u64,
+    pub failed_calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+}
+
+/// Sliding one-hour window of call timestamps.
+struct RateLimiter {
+    max_per_hour: Option<u32>,
+    calls: Mutex<Vec<DateTime<Utc>>>,
+}
+
+impl RateLimiter {
+    fn acquire(&self, now: DateTime<Utc>) -> Result<(), TransformPipelineError> {
+        let Some(limit) = self.max_per_hour else {
+            return Ok(());
+        };
+        let one_hour_ago = now - chrono::Duration::hours(1);
+        let mut calls = self.calls.lock().unwrap_or_else(|e| e.into_inner());
+        calls.retain(|ts| *ts > one_hour_ago);
+        if calls.len() >= limit as usize {
+            return Err(TransformPipelineError::ServiceError(format!(
+                "rate limit exceeded: {} calls in the last hour (limit: {})",
+                calls.len(),
+                limit
+            )));
+        }
+        calls.push(now);
+        Ok(())
+    }
+}
+
+/// An LLM provider plus the policy every LLM-backed transform shares: egress checks, an hourly
+/// call budget, retries with backoff and token/cost accounting.
+///
+/// Built from transform params:
+/// - `provider`: `ollama` (default), `openai` or `mock`
+/// - `model`, `timeout_secs` (default 60), `max_retries` (default 2)
+/// - `max_calls_per_hour`: unset means unlimited
+/// - `api_key`: bearer token for `openai` (config resolves `@file` references)
+/// - `cost_per_1k_prompt_tokens`, `cost_per_1k_completion_tokens`: USD, default 0
+/// - `mock_response`: fixed reply for `mock`
+pub struct LlmClient {
+    id: String,
+    provider: Box<dyn LlmProvider>,
+    model: String,
+    timeout: Duration,
+    max_retries: u32,
+    privacy_level: PrivacyLevel,
+    egress: EgressGuard,
+    rate_limiter: RateLimiter,
+    prompt_cost_per_1k: f64,
+    completion_cost_per_1k: f64,
+    usage: Mutex<LlmUsage>,
+}
+
+impl LlmClient {
+    pub fn from_params(
+        id: &str,
+        endpoint: String,
+        params: &HashMap<String, String>,
+        egress: EgressGuard,
+    ) -> Self {
+        let provider: Box<dyn LlmProvider> = match ProviderKind::from_params(params) {
+            ProviderKind::Ollama => Box::new(OllamaProvider::new(endpoint)),
+            ProviderKind::OpenAi => {
+                let api_key = params.get("api_key").filter(|k| !k.is_empty()).cloned();
+                Box::new(OpenAiProvider::new(endpoint, api_key))
+            }
+            ProviderKind::Mock => Box::new(MockProvider::new(params.get("mock_response").cloned())),
+        };
+        let parse_f64 = |key: &str| {
+            params
+                .get(key)
+                .and_then(|v| v.parse::<f64>().ok())
+                .unwrap_or(0.0)
+        };
+
+        let max_calls_per_hour = params
+            .get("max_calls_per_hour")
+            .and_then(|v| v.parse().ok());
+        if let Some(limit) = max_calls_per_hour {
+            tracing::info!(
+                transform_id = %id,
+                max_calls_per_hour = limit,
+                "LLM transform rate limit configured"
+            );
+        }
+
+        Self {
+            id: id.to_string(),
+            provider,
+            model: params
+                .get("model")
+                .cloned()
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            timeout: Duration::from_secs(
+                params
+                    .get("timeout_secs")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(60),
+            ),
+            max_retries: params
+                .get("max_retries")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(2),
+            privacy_level: PrivacyLevel::from_params(params),
+            egress,
+            rate_limiter: RateLimiter {
+                max_per_hour: max_calls_per_hour,
+                calls: Mutex::new(Vec::new()),
+            },
+            prompt_cost_per_1k: parse_f64("cost_per_1k_prompt_tokens"),
+            completion_cost_per_1k: parse_f64("cost_per_1k_completion_tokens"),
+            usage: Mutex::new(LlmUsage::default()),
+        }
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn privacy_level(&self) -> PrivacyLevel {
+        self.privacy_level
+    }
+
+    pub fn usage(&self) -> LlmUsage {
+        *self.usage.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Sends `messages` and returns the reply text, trimmed.
+    pub async fn chat(
+        &self,
+        http: &reqwest::Client,
+        messages: &[ChatMessage],
+    ) -> Result<String, TransformPipelineError> {
+        self.complete(http, messages, None).await
+    }
+
+    /// Like [`LlmClient::chat`], asking the provider for a JSON reply matching `schema`.
+    pub async fn chat_json(
+        &self,
+        http: &reqwest::Client,
+        messages: &[ChatMessage],
+        schema: &serde_json::Value,
+    ) -> Result<String, TransformPipelineError> {
+        self.complete(http, messages, Some(schema)).await
+    }
+
+    async fn complete(
+        &self,
+        http: &reqwest::Client,
+        messages: &[ChatMessage],
+        schema: Option<&serde_json::Value>,
+    ) -> Result<String, TransformPipelineError> {
+        if let Some(endpoint) = self.provider.endpoint() {
+            self.egress
+                .check(endpoint, self.privacy_level)
+                .map_err(|e| TransformPipelineError::EgressDenied(e.to_string()))?;
+        }
+        self.rate_limiter.acquire(Utc::now())?;
+
+        let mut attempt = 0u32;
+        let reply = loop {
+            if attempt > 0 {
+                tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+            }
+            match self
+                .provider
+                .chat(http, &self.model, messages, schema, self.timeout)
+                .await
+            {
+                Ok(reply) => break reply,
+                Err(ProviderError::Retryable(e)) if attempt < self.max_retries => {
+                    tracing::warn!(
+                        transform_id = %self.id,
+                        attempt = attempt + 1,
+                        error = %e,
+                        "LLM request failed, retrying"
+                    );
+                    attempt += 1;
+                }
+                Err(e) => {
+                    self.usage
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .failed_calls += 1;
+                    return Err(e.into_inner());
+                }
+            }
+        };
+
+        let usage = self.record(&reply);
+        tracing::debug!(
+            transform_id = %self.id,
+            model = %self.model,
+            prompt_tokens = reply.prompt_tokens,
+            completion_tokens = reply.completion_tokens,
+            total_cost_usd = usage.cost_usd,
+            "LLM call completed"
+        );
+        Ok(reply.content.trim().to_string())
+    }
+
+    fn record(&self, reply: &ChatReply) -> LlmUsage {
+        let cost = reply.prompt_tokens as f64 / 1000.0 * self.prompt_cost_per_1k
+            + reply.completion_tokens as f64 / 1000.0 * self.completion_cost_per_1k;
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        usage.calls += 1;
+        usage.prompt_tokens += reply.prompt_tokens;
+        usage.completion_tokens += reply.completion_tokens;
+        usage.cost_usd += cost;
+        *usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_provider_replies() {
+        let ollama = json!({
+            "message": { "role": "assistant", "content": "coding" },
+            "prompt_eval_count": 12,
+            "eval_count": 3
+        });
+        assert_eq!(
+            parse_ollama_reply(&ollama),
+            ChatReply {
+                content: "coding".to_string(),
+                prompt_tokens: 12,
+                completion_tokens: 3
+            }
+        );
+
+        let openai = json!({
+            "choices": [{ "message": { "role": "assistant", "content": "email" } }],
+            "usage": { "prompt_tokens": 20, "completion_tokens": 1 }
+        });
+        assert_eq!(parse_openai_reply(&openai).content, "email");
+        assert_eq!(parse_openai_reply(&openai).prompt_tokens, 20);
+        assert_eq!(parse_openai_reply(&json!({})), ChatReply::default());
+    }
+
+    #[test]
+    fn openai_url_accepts_root_or_v1() {
+        let root = OpenAiProvider::new("http://localhost:8080/".to_string(), None);
+        assert_eq!(root.url(), "http://localhost:8080/v1/chat/completions");
+        let v1 = OpenAiProvider::new("http://localhost:8000/v1".to_string(), None);
+        assert_eq!(v1.url(), "http://localhost:8000/v1/chat/completions");
+    }
+
+    #[test]
+    fn mock_echoes_last_user_message_and_counts_words() {
+        let mock = MockProvider::new(None);
+        let reply = mock.reply(&[
+            ChatMessage::system("be brief"),
+            ChatMessage::user("hello there world"),
+        ]);
+        assert_eq!(reply.content, "hello there world");
+        assert_eq!(reply.prompt_tokens, 5);
+        assert_eq!(reply.completion_tokens, 3);
+
+        let fixed = MockProvider::new(Some("other".to_string()));
+        assert_eq!(fixed.reply(&[ChatMessage::user("x")]).content, "other");
+    }
+
+    #[test]
+    fn usage_accumulates_cost() {
+        let client = LlmClient::from_params(
+            "t",
+            String::new(),
+            &params(&[
+                ("provider", "mock"),
+                ("cost_per_1k_prompt_tokens", "0.5"),
+                ("cost_per_1k_completion_tokens", "2"),
+            ]),
+            EgressGuard::new(vec![]),
+        );
+        client.record(&ChatReply {
+            content: String::new(),
+            prompt_tokens: 2000,
+            completion_tokens: 500,
+        });
+        let usage = client.record(&ChatReply::default());
+        assert_eq!(usage.calls, 2);
+        assert_eq!(usage.prompt_tokens, 2000);
+        assert!((usage.cost_usd - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rate_limiter_enforces_hourly_window() {
+        let limiter = RateLimiter {
+            max_per_hour: Some(2),
+            calls: Mutex::new(Vec::new()),
+        };
+        let now = Utc::now();
+        assert!(limiter.acquire(now).is_ok());
+        assert!(limiter.acquire(now).is_ok());
+        assert!(limiter.acquire(now).is_err());
+        assert!(limiter.acquire(now + chrono::Duration::minutes(61)).is_ok());
+    }
+}
@@ -1,45 +1,531 @@
-use chrono::Utc;
-use serde_json::json;
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use config::NamedPlace;
+use serde_json::{json, Value as JsonValue};
 use uuid::Uuid;
 
 use crate::frames::FrameRow;
 use crate::postgres::PostgresPool;
 use lifelog_core::LifelogError;
 
-/// Records location context based on collector_id heuristics.
-/// Maps collector names to approximate locations (e.g., "laptop" → "mobile", "server" → "home").
-pub async fn record_location_context(
-    pool: &PostgresPool,
-    collector_id: &str,
-) -> Result<(), LifelogError> {
-    let location = match collector_id {
-        id if id.to_lowercase().contains("laptop") => "mobile",
-        id if id.to_lowercase().contains("server") || id.to_lowercase().contains("desktop") => {
-            "home"
-        }
-        _ => "unknown",
+/// How far back each run re-scans signal frames.
+const LOOKBACK: Duration = Duration::hours(6);
+/// Consecutive observations of the same place closer than this form one visit.
+const MERGE_GAP: Duration = Duration::minutes(10);
+/// Geofence radius used when a place gives coordinates without `radiusM`.
+const DEFAULT_RADIUS_M: f64 = 100.0;
+
+const LOCATION_STREAM_ID: &str = "location";
+
+/// Modalities of the frames visits are inferred from: the collector's `wifi` scans and `geo`
+/// fixes uploaded by phones.
+pub const SIGNAL_MODALITIES: &[&str] = &["Wifi", "Geo"];
+
+#[derive(Debug, Default, Clone)]
+pub struct LocationRunSummary {
+    /// Signal frames found in the lookback window.
+    pub signal_frames: u64,
+    pub visits_written: u64,
+    pub visits_removed: u64,
+}
+
+/// A single location signal taken from a `Wifi` or `Geo` frame.
+///
+/// WiFi frames carry `ssid`, `bssid` and the local `ip`; GPS fixes carry
+/// `latitude`/`longitude`/`accuracy_m`. Missing fields are simply not matched.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LocationObservation {
+    pub t: DateTime<Utc>,
+    pub ssid: Option<String>,
+    pub bssid: Option<String>,
+    pub ip: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub accuracy_m: Option<f64>,
+}
+
+impl LocationObservation {
+    fn from_payload(t: DateTime<Utc>, payload: &JsonValue) -> Self {
+        let text = |key: &str| {
+            payload[key]
+                .as_str()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+        };
+        // Scanners often report numbers as text, e.g. parsed from `nmcli` or `gpspipe` output.
+        let number = |key: &str| {
+            payload[key]
+                .as_f64()
+                .or_else(|| text(key).and_then(|s| s.parse().ok()))
+        };
+        Self {
+            t,
+            ssid: text("ssid"),
+            // `nmcli -t` escapes the colons of a BSSID.
+            bssid: text("bssid").map(|b| b.replace("\\:", ":")),
+            ip: text("ip").or_else(|| text("public_ip")),
+            latitude: number("latitude"),
+            longitude: number("longitude"),
+            accuracy_m: number("accuracy_m"),
+        }
+    }
+}
+
+/// The place an observation resolved to and which signal matched it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaceMatch {
+    pub place: String,
+    pub matched_by: &'static str,
+    pub confidence: f32,
+}
+
+/// A contiguous stay at one place.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaceVisit {
+    pub place: String,
+    pub matched_by: &'static str,
+    pub confidence: f32,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub observations: usize,
+}
+
+/// A `Location` frame written by an earlier run.
+#[derive(Debug, Clone)]
+struct StoredVisit {
+    id: Uuid,
+    place: String,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+}
+
+/// Great-circle distance in metres.
+pub fn haversine_m(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    const EARTH_RADIUS_M: f64 = 6_371_000.0;
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let d_phi = (lat2 - lat1).to_radians();
+    let d_lambda = (lon2 - lon1).to_radians();
+    let a = (d_phi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (d_lambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+}
+
+/// Resolves an observation against the configured places.
+///
+/// Signals are tried from most to least specific: BSSID, GPS geofence, SSID, then IP prefix.
+pub fn match_place(places: &[NamedPlace], obs: &LocationObservation) -> Option<PlaceMatch> {
+    let found = |place: &NamedPlace, matched_by, confidence| PlaceMatch {
+        place: place.name.clone(),
+        matched_by,
+        confidence,
     };
 
-    let now = Utc::now();
-    let frame = FrameRow {
-        id: Uuid::new_v4(),
-        collector_id: collector_id.to_string(),
-        stream_id: "location".to_string(),
-        modality: "Location".to_string(),
-        t_device: None,
-        t_ingest: now,
-        t_canonical: now,
-        t_end: None,
-        time_quality: "inferred".to_string(),
-        blob_hash: None,
-        blob_size: None,
-        indexed: false,
-        source_frame_id: None,
-        payload: json!({
-            "location": location,
-            "source": "collector_id_heuristic"
-        }),
+    if let Some(bssid) = &obs.bssid {
+        if let Some(p) = places
+            .iter()
+            .find(|p| p.bssids.iter().any(|b| b.eq_ignore_ascii_case(bssid)))
+        {
+            return Some(found(p, "bssid", 0.95));
+        }
+    }
+
+    if let (Some(lat), Some(lon)) = (obs.latitude, obs.longitude) {
+        let accuracy = obs.accuracy_m.unwrap_or(0.0).max(0.0);
+        let nearest = places
+            .iter()
+            .filter_map(|p| {
+                let distance = haversine_m(lat, lon, p.latitude?, p.longitude?);
+                let radius = p.radius_m.unwrap_or(DEFAULT_RADIUS_M);
+                (distance <= radius + accuracy).then_some((p, distance, radius))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((p, _, radius)) = nearest {
+            // Poor fixes relative to the fence size lower confidence.
+            let confidence = (0.9 - 0.4 * (accuracy / (radius + accuracy)).min(1.0)) as f32;
+            return Some(found(p, "gps", confidence));
+        }
+    }
+
+    if let Some(ssid) = &obs.ssid {
+        if let Some(p) = places.iter().find(|p| p.ssids.iter().any(|s| s == ssid)) {
+            return Some(found(p, "ssid", 0.8));
+        }
+    }
+
+    if let Some(ip) = &obs.ip {
+        if let Some(p) = places
+            .iter()
+            .find(|p| p.ip_prefixes.iter().any(|prefix| ip.starts_with(prefix)))
+        {
+            return Some(found(p, "ip", 0.6));
+        }
+    }
+
+    None
+}
+
+/// Stable frame id for a visit that no earlier run recorded, from where it starts.
+pub fn location_frame_id(collector_id: &str, place: &str, start: DateTime<Utc>) -> Uuid {
+    crate::frames::derived_frame_id(&format!(
+        "location:{collector_id}:{place}:{}",
+        start.timestamp_micros()
+    ))
+}
+
+/// Takes the earlier run's frame for `visit` out of `existing`: the one at the same place that
+/// started at the same time or overlaps it within the merge gap. Each frame is taken at most
+/// once, so separate visits never share one.
+fn claim_stored(existing: &mut Vec<StoredVisit>, visit: &PlaceVisit) -> Option<StoredVisit> {
+    let index = existing.iter().position(|stored| {
+        stored.place == visit.place
+            && (stored.start == visit.start
+                || (stored.start <= visit.end + MERGE_GAP && stored.end + MERGE_GAP >= visit.start))
+    })?;
+    Some(existing.swap_remove(index))
+}
+
+/// Turns time-ordered observations into visits.
+///
+/// Observations that resolve to the same place within `gap` of each other are merged. An
+/// observation at a different place, or an unmatched one, ends the current visit.
+pub fn build_visits(
+    places: &[NamedPlace],
+    observations: &[LocationObservation],
+    gap: Duration,
+) -> Vec<PlaceVisit> {
+    let mut visits: Vec<PlaceVisit> = Vec::new();
+    let mut open = false;
+    for obs in observations {
+        let Some(m) = match_place(places, obs) else {
+            open = false;
+            continue;
+        };
+        if let Some(last) = visits.last_mut() {
+            if open && last.place == m.place && obs.t <= last.end + gap {
+                last.end = last.end.max(obs.t);
+                last.observations += 1;
+                if m.confidence > last.confidence {
+                    last.confidence = m.confidence;
+                    last.matched_by = m.matched_by;
+                }
+                continue;
+            }
+        }
+        visits.push(PlaceVisit {
+            place: m.place,
+            matched_by: m.matched_by,
+            confidence: m.confidence,
+            start: obs.t,
+            end: obs.t,
+            observations: 1,
+        });
+        open = true;
+    }
+    visits
+}
+
+/// Infers where each collector was over the recent lookback window and writes `Location`
+/// interval frames, one per visit.
+///
+/// Visits are matched against `places` from WiFi, network and GPS signal frames. The resulting
+/// frames carry `place` in their payload, so they can drive queries such as
+/// `DURING(Location, place = "office")`. Re-running extends existing visits instead of
+/// duplicating them, and removes visits that started inside the window but no longer match.
+pub async fn infer_locations(
+    pool: &PostgresPool,
+    places: &[NamedPlace],
+    now: DateTime<Utc>,
+) -> Result<LocationRunSummary, LifelogError> {
+    if places.is_empty() {
+        return Ok(LocationRunSummary::default());
+    }
+
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+
+    let window_start = now - LOOKBACK;
+    let rows = client
+        .query(
+            "SELECT id, collector_id, modality, t_canonical, t_end, payload FROM frames
+             WHERE (modality = ANY($3) OR modality = 'Location')
+             AND time_range && tstzrange($1, $2, '[]')
+             ORDER BY t_canonical ASC",
+            &[&window_start, &now, &SIGNAL_MODALITIES],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("location query: {e}")))?;
+
+    let mut observations: HashMap<String, Vec<LocationObservation>> = HashMap::new();
+    let mut existing: HashMap<String, Vec<StoredVisit>> = HashMap::new();
+    for row in &rows {
+        let collector_id: String = row.get("collector_id");
+        let modality: String = row.get("modality");
+        let t: DateTime<Utc> = row.get("t_canonical");
+        let payload: JsonValue = row.get("payload");
+        if modality == "Location" {
+            let t_end: Option<DateTime<Utc>> = row.get("t_end");
+            let place = payload["place"].as_str().unwrap_or_default().to_string();
+            existing.entry(collector_id).or_default().push(StoredVisit {
+                id: row.get("id"),
+                place,
+                start: t,
+                end: t_end.unwrap_or(t),
+            });
+        } else {
+            observations
+                .entry(collector_id)
+                .or_default()
+                .push(LocationObservation::from_payload(t, &payload));
+        }
+    }
+
+    let mut summary = LocationRunSummary {
+        signal_frames: observations.values().map(|obs| obs.len() as u64).sum(),
+        ..Default::default()
     };
+    for (collector_id, obs) in observations {
+        let previous = existing.entry(collector_id.clone()).or_default();
+        for visit in build_visits(places, &obs, MERGE_GAP) {
+            // A visit at the edge of the window may have lost its first signals to it, so it
+            // keeps the earlier start its frame was written with.
+            let truncated = visit.start <= window_start + MERGE_GAP;
+            let (id, start) = match claim_stored(previous, &visit) {
+                Some(stored) if truncated => (stored.id, stored.start.min(visit.start)),
+                Some(stored) => (stored.id, visit.start),
+                None => (
+                    location_frame_id(&collector_id, &visit.place, visit.start),
+                    visit.start,
+                ),
+            };
+
+            let frame = FrameRow {
+                id,
+                collector_id: collector_id.clone(),
+                stream_id: LOCATION_STREAM_ID.to_string(),
+                modality: "Location".to_string(),
+                t_device: None,
+                t_ingest: now,
+                t_canonical: start,
+                t_end: Some(visit.end),
+                time_quality: "inferred".to_string(),
+                blob_hash: None,
+                blob_size: None,
+                indexed: true,
+                source_frame_id: None,
+                payload: json!({
+                    "place": visit.place,
+                    "matched_by": visit.matched_by,
+                    "confidence": visit.confidence,
+                    "observations": visit.observations,
+                    "source": "signal_inference",
+                }),
+            };
+
+            crate::frames::upsert(pool, &frame).await?;
+            summary.visits_written += 1;
+        }
+    }
+
+    // Frames no visit claimed and that started inside the window, where all their signals
+    // were re-read, no longer describe a visit.
+    let stale: Vec<Uuid> = existing
+        .values()
+        .flatten()
+        .filter(|stored| stored.start > window_start)
+        .map(|stored| stored.id)
+        .collect();
+    if !stale.is_empty() {
+        summary.visits_removed = client
+            .execute("DELETE FROM frames WHERE id = ANY($1)", &[&stale])
+            .await
+            .map_err(|e| LifelogError::Database(format!("location cleanup: {e}")))?;
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(mins: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + mins * 60, 0).unwrap_or_default()
+    }
+
+    fn places() -> Vec<NamedPlace> {
+        vec![
+            NamedPlace {
+                name: "home".to_string(),
+                ssids: vec!["HomeNet".to_string()],
+                ip_prefixes: vec!["192.168.1.".to_string()],
+                ..Default::default()
+            },
+            NamedPlace {
+                name: "office".to_string(),
+                bssids: vec!["aa:bb:cc:dd:ee:ff".to_string()],
+                latitude: Some(52.5200),
+                longitude: Some(13.4050),
+                radius_m: Some(200.0),
+                ..Default::default()
+            },
+        ]
+    }
+
+    fn wifi(mins: i64, ssid: &str) -> LocationObservation {
+        LocationObservation {
+            t: at(mins),
+            ssid: Some(ssid.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matches_most_specific_signal_first() {
+        let places = places();
+        let obs = LocationObservation {
+            t: at(0),
+            ssid: Some("HomeNet".to_string()),
+            bssid: Some("AA:BB:CC:DD:EE:FF".to_string()),
+            ..Default::default()
+        };
+        let m = match_place(&places, &obs);
+        assert_eq!(
+            m.map(|m| (m.place, m.matched_by)),
+            Some(("office".to_string(), "bssid"))
+        );
+
+        let ip_only = LocationObservation {
+            t: at(0),
+            ip: Some("192.168.1.42".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            match_place(&places, &ip_only).map(|m| m.place),
+            Some("home".to_string())
+        );
+        assert_eq!(match_place(&places, &wifi(0, "CafeNet")), None);
+    }
+
+    #[test]
+    fn matches_gps_geofence() {
+        let places = places();
+        let near = LocationObservation {
+            t: at(0),
+            latitude: Some(52.5205),
+            longitude: Some(13.4050),
+            accuracy_m: Some(10.0),
+            ..Default::default()
+        };
+        let far = LocationObservation {
+            latitude: Some(52.5300),
+            ..near.clone()
+        };
+        assert_eq!(
+            match_place(&places, &near).map(|m| m.matched_by),
+            Some("gps")
+        );
+        assert_eq!(match_place(&places, &far), None);
+    }
+
+    #[test]
+    fn matches_scanner_payloads() {
+        let places = places();
+        let frames = [
+            (
+                0,
+                json!({"ssid": "HomeNet", "bssid": "11\\:22\\:33\\:44\\:55\\:66", "signal": "72"}),
+            ),
+            (
+                4,
+                json!({"public_ip": "192.168.1.17", "interface": "wlan0"}),
+            ),
+            (
+                30,
+                json!({"ssid": "eduroam", "bssid": "AA\\:BB\\:CC\\:DD\\:EE\\:FF", "signal": "58"}),
+            ),
+            (
+                36,
+                json!({"latitude": "52.52010", "longitude": "13.40490", "accuracy_m": 25.0}),
+            ),
+            (90, json!({"ssid": "", "bssid": ""})),
+        ];
+        let obs: Vec<_> = frames
+            .iter()
+            .map(|(mins, payload)| LocationObservation::from_payload(at(*mins), payload))
+            .collect();
+        assert_eq!(obs[2].bssid.as_deref(), Some("AA:BB:CC:DD:EE:FF"));
+        assert_eq!(obs[3].latitude, Some(52.5201));
+        assert_eq!(
+            obs[4],
+            LocationObservation {
+                t: at(90),
+                ..Default::default()
+            }
+        );
+
+        let visits = build_visits(&places, &obs, MERGE_GAP);
+        let summary: Vec<_> = visits
+            .iter()
+            .map(|v| (v.place.as_str(), v.matched_by, v.start, v.end))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("home", "ssid", at(0), at(4)),
+                ("office", "bssid", at(30), at(36)),
+            ]
+        );
+    }
+
+    #[test]
+    fn builds_visits_split_by_gap_and_place_changes() {
+        let places = places();
+        let obs = vec![
+            wifi(0, "HomeNet"),
+            wifi(5, "HomeNet"),
+            wifi(10, "CafeNet"),
+            wifi(12, "HomeNet"),
+            wifi(60, "HomeNet"),
+        ];
+        let visits = build_visits(&places, &obs, Duration::minutes(10));
+        assert_eq!(visits.len(), 3);
+        assert_eq!((visits[0].start, visits[0].end), (at(0), at(5)));
+        assert_eq!(visits[0].observations, 2);
+        assert_eq!(visits[1].start, at(12));
+        assert_eq!(visits[2].start, at(60));
+    }
+
+    #[test]
+    fn separate_visits_to_one_place_keep_separate_frames() {
+        let places = places();
+        let obs = vec![wifi(0, "HomeNet"), wifi(5, "HomeNet"), wifi(30, "HomeNet")];
+        let visits = build_visits(&places, &obs, MERGE_GAP);
+        assert_eq!(visits.len(), 2);
+
+        // One frame from an earlier run that spans both visits.
+        let stored = StoredVisit {
+            id: Uuid::new_v4(),
+            place: "home".to_string(),
+            start: at(0),
+            end: at(30),
+        };
+        let mut existing = vec![stored.clone()];
+        assert_eq!(
+            claim_stored(&mut existing, &visits[0]).map(|v| v.id),
+            Some(stored.id)
+        );
+        assert!(claim_stored(&mut existing, &visits[1]).is_none());
 
-    crate::frames::upsert(pool, &frame).await
+        assert_ne!(
+            location_frame_id("laptop", "home", at(0)),
+            location_frame_id("laptop", "home", at(30))
+        );
+        assert_eq!(
+            location_frame_id("laptop", "home", at(30)),
+            location_frame_id("laptop", "home", at(30))
+        );
+    }
 }
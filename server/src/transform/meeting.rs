@@ -1,73 +1,641 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value as JsonValue};
+use uuid::Uuid;
+
+use crate::frames::FrameRow;
 use crate::postgres::PostgresPool;
-use chrono::Utc;
 use lifelog_core::LifelogError;
-use serde_json::json;
-use uuid::Uuid;
 
-pub async fn detect_meetings(pool: &PostgresPool) -> Result<(), LifelogError> {
+/// How far back each run re-scans. Runs overlap so sessions that straddle a run boundary are
+/// extended in place instead of being split.
+const LOOKBACK: Duration = Duration::hours(6);
+/// Two meeting-window observations closer than this belong to the same session.
+const MERGE_GAP: Duration = Duration::minutes(2);
+/// Sessions shorter than this are dropped unless the microphone was active during them.
+const MIN_DURATION: Duration = Duration::minutes(1);
+/// Audio/transcript frames this close to a session boundary are linked to it.
+const LINK_PAD: Duration = Duration::seconds(30);
+
+const MEETING_STREAM_ID: &str = "meeting";
+
+#[derive(Debug, Default, Clone)]
+pub struct MeetingRunSummary {
+    pub sessions_written: u64,
+    pub sessions_removed: u64,
+}
+
+/// A single observation of a meeting application being on screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeetingSpan {
+    pub app: &'static str,
+    pub title: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// A contiguous meeting interval built from merged spans.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeetingSession {
+    pub app: &'static str,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub titles: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct LinkedFrame {
+    id: Uuid,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    text: Option<String>,
+    /// Whether the frame's payload was sealed; see [`crate::payload_crypto`].
+    sealed: bool,
+}
+
+#[derive(Debug, Clone)]
+struct CalendarEvent {
+    title: String,
+    attendees: Vec<String>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+}
+
+/// A `Meeting` frame written by an earlier run.
+#[derive(Debug, Clone)]
+struct StoredMeeting {
+    id: Uuid,
+    app: &'static str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    /// Audio and transcript frames it linked.
+    linked: HashSet<Uuid>,
+}
+
+#[derive(Debug, Default)]
+struct CollectorSignals {
+    spans: Vec<MeetingSpan>,
+    audio: Vec<LinkedFrame>,
+    transcripts: Vec<LinkedFrame>,
+    calendar: Vec<CalendarEvent>,
+    existing: Vec<StoredMeeting>,
+}
+
+/// Classifies a window as belonging to a meeting application.
+///
+/// Matches on the application class first and falls back to well-known title shapes for
+/// browser-hosted meetings. Returns a stable app label.
+pub fn classify_meeting_window(application: &str, title: &str) -> Option<&'static str> {
+    let app = application.to_lowercase();
+    let title_lower = title.to_lowercase();
+
+    // Desktop clients stay open between calls, so the app class alone is not enough.
+    if (app.contains("zoom") || title_lower.contains("zoom")) && title_lower.contains("meeting") {
+        return Some("zoom");
+    }
+    if (app.contains("webex") || title_lower.contains("webex")) && title_lower.contains("meeting") {
+        return Some("webex");
+    }
+    if title_lower.contains("meet.google.com") || title_lower.starts_with("meet - ") {
+        return Some("google-meet");
+    }
+    if title_lower.contains("meet.jit.si") || app.contains("jitsi") {
+        return Some("jitsi");
+    }
+    if (app.contains("teams") || title_lower.contains("microsoft teams"))
+        && (title_lower.contains("meeting") || title_lower.contains("call"))
+    {
+        return Some("teams");
+    }
+    if (app.contains("slack") || title_lower.contains("slack")) && title_lower.contains("huddle") {
+        return Some("slack-huddle");
+    }
+    if (app.contains("discord") || title_lower.contains("discord")) && title_lower.contains("voice")
+    {
+        return Some("discord");
+    }
+    None
+}
+
+/// Merges spans of the same app into sessions, joining spans separated by at most `gap`.
+pub fn merge_spans(mut spans: Vec<MeetingSpan>, gap: Duration) -> Vec<MeetingSession> {
+    spans.sort_by(|a, b| a.app.cmp(b.app).then(a.start.cmp(&b.start)));
+
+    let mut sessions: Vec<MeetingSession> = Vec::new();
+    for span in spans {
+        if let Some(last) = sessions.last_mut() {
+            if last.app == span.app && span.start <= last.end + gap {
+                last.end = last.end.max(span.end);
+                if !span.title.is_empty() && !last.titles.contains(&span.title) {
+                    last.titles.push(span.title);
+                }
+                continue;
+            }
+        }
+        sessions.push(MeetingSession {
+            app: span.app,
+            start: span.start,
+            end: span.end,
+            titles: if span.title.is_empty() {
+                Vec::new()
+            } else {
+                vec![span.title]
+            },
+        });
+    }
+    sessions
+}
+
+/// Extracts participant names from a meeting window title.
+///
+/// Handles "Meeting with Alice, Bob and Carol" style titles and Teams call windows shaped like
+/// "Alice Smith | Microsoft Teams".
+pub fn participants_from_title(title: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let lower = title.to_lowercase();
+
+    if let Some(idx) = lower.find(" with ") {
+        let rest = &title[idx + " with ".len()..];
+        let rest = rest.split(['|', '-', '(']).next().unwrap_or_default();
+        for part in rest.split([',', '&']).flat_map(|p| p.split(" and ")) {
+            if let Some(name) = clean_name(part) {
+                out.push(name);
+            }
+        }
+        return out;
+    }
+
+    if let Some((left, right)) = title.split_once('|') {
+        let right = right.to_lowercase();
+        if right.contains("microsoft teams") {
+            let left = left.trim();
+            let generic = ["chat", "calendar", "activity", "teams", "meeting", "call"];
+            if !generic.iter().any(|g| left.eq_ignore_ascii_case(g)) {
+                if let Some(name) = clean_name(left) {
+                    out.push(name);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Extracts speaker labels ("Alice: ...") from a diarized transcript.
+pub fn participants_from_transcript(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| {
+            let (label, rest) = line.split_once(':')?;
+            if rest.trim().is_empty() {
+                return None;
+            }
+            let label = label.trim();
+            let words: Vec<&str> = label.split_whitespace().collect();
+            if words.is_empty() || words.len() > 3 {
+                return None;
+            }
+            if label.to_lowercase().starts_with("speaker") {
+                return None;
+            }
+            clean_name(label)
+        })
+        .collect()
+}
+
+fn clean_name(raw: &str) -> Option<String> {
+    let name = raw.trim().trim_matches(|c: char| !c.is_alphanumeric());
+    let first = name.chars().next()?;
+    if !first.is_uppercase() || name.len() > 60 {
+        return None;
+    }
+    Some(name.to_string())
+}
+
+fn payload_str<'a>(payload: &'a JsonValue, key: &str) -> &'a str {
+    payload[key].as_str().unwrap_or_default()
+}
+
+fn span_end(
+    start: DateTime<Utc>,
+    t_end: Option<DateTime<Utc>>,
+    payload: &JsonValue,
+) -> DateTime<Utc> {
+    let by_duration = payload["duration_secs"]
+        .as_f64()
+        .filter(|d| d.is_finite() && *d > 0.0)
+        .map(|d| start + Duration::milliseconds((d * 1000.0) as i64));
+    t_end
+        .into_iter()
+        .chain(by_duration)
+        .fold(start, |acc, t| acc.max(t))
+}
+
+fn hyprland_spans(t: DateTime<Utc>, payload: &JsonValue) -> Vec<MeetingSpan> {
+    let mut spans = Vec::new();
+    if let Some(clients) = payload["clients"].as_array() {
+        for client in clients {
+            let class = payload_str(client, "class");
+            let title = payload_str(client, "title");
+            if let Some(app) = classify_meeting_window(class, title) {
+                spans.push(MeetingSpan {
+                    app,
+                    title: title.to_string(),
+                    start: t,
+                    end: t,
+                });
+            }
+        }
+    }
+    if spans.is_empty() {
+        let title = payload["active_workspace"]["last_window_title"]
+            .as_str()
+            .unwrap_or_default();
+        if let Some(app) = classify_meeting_window("", title) {
+            spans.push(MeetingSpan {
+                app,
+                title: title.to_string(),
+                start: t,
+                end: t,
+            });
+        }
+    }
+    spans
+}
+
+/// Stable frame id for a session that no earlier run recorded, from where it starts.
+pub fn meeting_frame_id(collector_id: &str, app: &str, start: DateTime<Utc>) -> Uuid {
+    crate::frames::derived_frame_id(&format!(
+        "meeting:{collector_id}:{app}:{}",
+        start.timestamp_micros()
+    ))
+}
+
+/// Takes the earlier run's frame for `session` out of `existing`: the one of the same app that
+/// started at the same time or linked one of the same audio or transcript frames. A session
+/// starting at the edge of the lookback window may have lost its first spans to it, so it also
+/// takes over a frame it overlaps that started earlier. Each frame is taken at most once, so
+/// separate sessions never share one.
+fn claim_stored(
+    existing: &mut Vec<StoredMeeting>,
+    session: &MeetingSession,
+    linked: &HashSet<Uuid>,
+    window_start: DateTime<Utc>,
+) -> Option<StoredMeeting> {
+    let truncated = session.start <= window_start + MERGE_GAP;
+    let index = existing.iter().position(|stored| {
+        stored.app == session.app
+            && (stored.start == session.start
+                || !stored.linked.is_disjoint(linked)
+                || (truncated
+                    && stored.start <= session.start
+                    && stored.end + MERGE_GAP >= session.start))
+    })?;
+    Some(existing.swap_remove(index))
+}
+
+fn overlapping(
+    frames: &[LinkedFrame],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> impl Iterator<Item = &LinkedFrame> {
+    frames
+        .iter()
+        .filter(move |f| f.start <= end + LINK_PAD && f.end >= start - LINK_PAD)
+}
+
+/// Detects meeting sessions over the recent lookback window and writes one `Meeting` interval
+/// frame per session.
+///
+/// Sessions are built from WindowActivity/Hyprland observations of meeting apps, kept only when
+/// long enough or backed by microphone audio, and enriched with participants, overlapping
+/// calendar events and links to the audio and transcript frames recorded during them. Re-running
+/// over the same data updates the existing frames instead of inserting duplicates.
+pub async fn detect_meetings(
+    pool: &PostgresPool,
+    now: DateTime<Utc>,
+) -> Result<MeetingRunSummary, LifelogError> {
     let client = pool
         .get()
         .await
         .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
 
+    let window_start = now - LOOKBACK;
     let rows = client
         .query(
-            "SELECT COUNT(*) as meeting_count FROM frames
-             WHERE modality IN ('Hyprland', 'WindowActivity')
-             AND t_canonical > NOW() - INTERVAL '1 hour'
-             AND (
-               payload::text ILIKE '%zoom%'
-               OR payload::text ILIKE '%teams%'
-               OR payload::text ILIKE '%meet%'
-               OR payload::text ILIKE '%slack%'
-               OR payload::text ILIKE '%discord%'
-             )",
-            &[],
+            "SELECT id, collector_id, modality, t_canonical, t_end, payload FROM frames
+             WHERE modality IN ('WindowActivity', 'Hyprland', 'Audio', 'Transcription', 'Calendar', 'Meeting')
+             AND time_range && tstzrange($1, $2, '[]')
+             ORDER BY t_canonical ASC",
+            &[&window_start, &now],
         )
         .await
         .map_err(|e| LifelogError::Database(format!("meeting query: {e}")))?;
 
-    if rows.is_empty() {
-        return Ok(());
-    }
-
-    let meeting_indicators: i64 = rows[0].get(0);
-
-    if meeting_indicators > 0 {
-        let uuid = Uuid::new_v4();
-        let now = Utc::now();
-        let one_hour_ago = now - chrono::Duration::hours(1);
-
-        client
-            .execute(
-                "INSERT INTO frames
-                 (id, collector_id, stream_id, modality, time_range, t_canonical, time_quality, payload)
-                 VALUES ($1, $2, $3, $4, tstzrange($5, $6, '[)'), $7, $8, $9)
-                 ON CONFLICT DO NOTHING",
-                &[
-                    &uuid,
-                    &"system",
-                    &"meeting-detector",
-                    &"Meeting",
-                    &one_hour_ago,
-                    &now,
-                    &now,
-                    &"inferred",
-                    &json!({
-                        "detected": true,
-                        "indicators": meeting_indicators,
-                        "source": "window_activity_detection"
-                    }),
-                ],
-            )
+    let mut by_collector: HashMap<String, CollectorSignals> = HashMap::new();
+    for row in &rows {
+        let id: Uuid = row.get("id");
+        let collector_id: String = row.get("collector_id");
+        let modality: String = row.get("modality");
+        let start: DateTime<Utc> = row.get("t_canonical");
+        let t_end: Option<DateTime<Utc>> = row.get("t_end");
+        let mut payload: JsonValue = row.get("payload");
+        let sealed = payload.get(crate::payload_crypto::SEALED_FIELD).is_some();
+        if let Err(e) = crate::payload_crypto::open_payload(id, &mut payload) {
+            tracing::warn!(frame_id = %id, error = %e, "Meeting signal payload could not be opened");
+        }
+        let end = span_end(start, t_end, &payload);
+
+        let signals = by_collector.entry(collector_id).or_default();
+        match modality.as_str() {
+            "WindowActivity" => {
+                let application = payload_str(&payload, "application");
+                let title = payload_str(&payload, "window_title");
+                if let Some(app) = classify_meeting_window(application, title) {
+                    signals.spans.push(MeetingSpan {
+                        app,
+                        title: title.to_string(),
+                        start,
+                        end,
+                    });
+                }
+            }
+            "Hyprland" => signals.spans.extend(hyprland_spans(start, &payload)),
+            "Audio" => signals.audio.push(LinkedFrame {
+                id,
+                start,
+                end,
+                text: None,
+                sealed,
+            }),
+            "Transcription" => signals.transcripts.push(LinkedFrame {
+                id,
+                start,
+                end,
+                text: Some(payload_str(&payload, "text").to_string()),
+                sealed,
+            }),
+            "Calendar" => signals.calendar.push(CalendarEvent {
+                title: payload_str(&payload, "title").to_string(),
+                attendees: payload["attendees"]
+                    .as_array()
+                    .map(|a| {
+                        a.iter()
+                            .filter_map(|v| v.as_str().map(str::to_string))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                start,
+                end,
+            }),
+            "Meeting" => {
+                let app = payload_str(&payload, "app");
+                if let Some(app) = KNOWN_APPS.iter().find(|a| **a == app) {
+                    let linked = ["audio_frame_ids", "transcript_frame_ids"]
+                        .iter()
+                        .filter_map(|key| payload[*key].as_array())
+                        .flatten()
+                        .filter_map(|v| v.as_str().and_then(|s| Uuid::parse_str(s).ok()))
+                        .collect();
+                    // The stored end, not one recomputed from `duration_secs`.
+                    let end = t_end.unwrap_or(start);
+                    signals.existing.push(StoredMeeting {
+                        id,
+                        app,
+                        start,
+                        end,
+                        linked,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut summary = MeetingRunSummary::default();
+    let mut stale = Vec::new();
+    for (collector_id, mut signals) in by_collector {
+        for session in merge_spans(signals.spans, MERGE_GAP) {
+            let audio: Vec<&LinkedFrame> =
+                overlapping(&signals.audio, session.start, session.end).collect();
+            if session.end - session.start < MIN_DURATION && audio.is_empty() {
+                continue;
+            }
+            let transcripts: Vec<&LinkedFrame> =
+                overlapping(&signals.transcripts, session.start, session.end).collect();
+            let calendar = signals
+                .calendar
+                .iter()
+                .find(|e| e.start <= session.end && e.end >= session.start);
+
+            let mut participants = BTreeSet::new();
+            for title in &session.titles {
+                participants.extend(participants_from_title(title));
+            }
+            for t in &transcripts {
+                if let Some(text) = &t.text {
+                    participants.extend(participants_from_transcript(text));
+                }
+            }
+            if let Some(event) = calendar {
+                participants.extend(event.attendees.iter().cloned());
+            }
+
+            // Repeated runs extend the frame an earlier run wrote for the same session.
+            let linked: HashSet<Uuid> = audio.iter().chain(&transcripts).map(|f| f.id).collect();
+            let (id, start) =
+                match claim_stored(&mut signals.existing, &session, &linked, window_start) {
+                    Some(stored) => (stored.id, stored.start.min(session.start)),
+                    None => (
+                        meeting_frame_id(&collector_id, session.app, session.start),
+                        session.start,
+                    ),
+                };
+
+            let title = calendar
+                .map(|e| e.title.clone())
+                .filter(|t| !t.is_empty())
+                .or_else(|| session.titles.first().cloned())
+                .unwrap_or_default();
+
+            let frame = FrameRow {
+                id,
+                collector_id: collector_id.clone(),
+                stream_id: MEETING_STREAM_ID.to_string(),
+                modality: "Meeting".to_string(),
+                t_device: None,
+                t_ingest: now,
+                t_canonical: start,
+                t_end: Some(session.end),
+                time_quality: "inferred".to_string(),
+                blob_hash: None,
+                blob_size: None,
+                indexed: true,
+                source_frame_id: None,
+                payload: json!({
+                    "app": session.app,
+                    "title": title,
+                    "window_titles": session.titles,
+                    "participants": participants.into_iter().collect::<Vec<_>>(),
+                    "mic_active": !audio.is_empty(),
+                    "audio_frame_ids": audio.iter().map(|f| f.id.to_string()).collect::<Vec<_>>(),
+                    "transcript_frame_ids": transcripts.iter().map(|f| f.id.to_string()).collect::<Vec<_>>(),
+                    "calendar_title": calendar.map(|e| e.title.clone()),
+                    "duration_secs": (session.end - start).num_seconds(),
+                    "source": "interval_detection",
+                }),
+            };
+
+            // Participants taken from sealed transcripts are sealed with the meeting.
+            let sensitive_source = transcripts.iter().any(|t| t.sealed);
+            crate::frames::upsert_derived(pool, &frame, sensitive_source).await?;
+            summary.sessions_written += 1;
+            tracing::debug!(
+                collector_id = %collector_id,
+                app = session.app,
+                start = %start,
+                end = %session.end,
+                "Meeting session recorded"
+            );
+        }
+
+        // Frames no session claimed and that started inside the window, where all their
+        // signals were re-read, no longer describe a meeting.
+        stale.extend(
+            signals
+                .existing
+                .iter()
+                .filter(|stored| stored.start > window_start)
+                .map(|stored| stored.id),
+        );
+    }
+
+    if !stale.is_empty() {
+        summary.sessions_removed = client
+            .execute("DELETE FROM frames WHERE id = ANY($1)", &[&stale])
             .await
-            .map_err(|e| LifelogError::Database(format!("meeting insert: {e}")))?;
+            .map_err(|e| LifelogError::Database(format!("meeting cleanup: {e}")))?;
+    }
+
+    Ok(summary)
+}
+
+const KNOWN_APPS: &[&str] = &[
+    "zoom",
+    "webex",
+    "google-meet",
+    "jitsi",
+    "teams",
+    "slack-huddle",
+    "discord",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap_or_default()
+    }
 
-        tracing::debug!(
-            meeting_count = meeting_indicators,
-            "Meeting detected from window activity"
+    #[test]
+    fn classifies_meeting_windows_without_false_positives() {
+        assert_eq!(
+            classify_meeting_window("zoom", "Zoom Meeting"),
+            Some("zoom")
+        );
+        assert_eq!(
+            classify_meeting_window("firefox", "Meet - Weekly sync"),
+            Some("google-meet")
+        );
+        assert_eq!(
+            classify_meeting_window("Slack", "Huddle with Alice - Slack"),
+            Some("slack-huddle")
         );
+        assert_eq!(
+            classify_meeting_window("firefox", "Meetup: Rust Berlin"),
+            None
+        );
+        assert_eq!(classify_meeting_window("Slack", "general - Slack"), None);
+        assert_eq!(classify_meeting_window("zoom", "Zoom Workplace"), None);
     }
 
-    Ok(())
+    #[test]
+    fn merges_spans_within_gap_per_app() {
+        let span = |app, s, e| MeetingSpan {
+            app,
+            title: "Zoom Meeting".to_string(),
+            start: at(s),
+            end: at(e),
+        };
+        let sessions = merge_spans(
+            vec![
+                span("zoom", 0, 30),
+                span("zoom", 60, 90),
+                span("zoom", 1000, 1010),
+                span("teams", 10, 20),
+            ],
+            Duration::minutes(2),
+        );
+        assert_eq!(sessions.len(), 3);
+        let zoom: Vec<_> = sessions.iter().filter(|s| s.app == "zoom").collect();
+        assert_eq!(zoom[0].start, at(0));
+        assert_eq!(zoom[0].end, at(90));
+        assert_eq!(zoom[0].titles, vec!["Zoom Meeting".to_string()]);
+        assert_eq!(zoom[1].start, at(1000));
+    }
+
+    #[test]
+    fn extracts_participants_from_titles_and_transcripts() {
+        assert_eq!(
+            participants_from_title("Meeting with Alice, Bob and Carol | Microsoft Teams"),
+            vec!["Alice", "Bob", "Carol"]
+        );
+        assert_eq!(
+            participants_from_title("Dana Scully | Microsoft Teams"),
+            vec!["Dana Scully"]
+        );
+        assert!(participants_from_title("Chat | Microsoft Teams").is_empty());
+        assert_eq!(
+            participants_from_transcript("Alice: hi\nSpeaker 1: hello\nbob: lower\nBob Jones: ok"),
+            vec!["Alice", "Bob Jones"]
+        );
+    }
+
+    #[test]
+    fn separate_sessions_of_one_app_keep_separate_ids() {
+        let session = |s, e| MeetingSession {
+            app: "zoom",
+            start: at(s),
+            end: at(e),
+            titles: Vec::new(),
+        };
+        let stored = StoredMeeting {
+            id: Uuid::new_v4(),
+            app: "zoom",
+            start: at(0),
+            end: at(3600),
+            linked: HashSet::new(),
+        };
+        let mut existing = vec![stored.clone()];
+        let window_start = at(-3600);
+        let none = HashSet::new();
+
+        let first = claim_stored(&mut existing, &session(0, 1200), &none, window_start);
+        assert_eq!(first.map(|m| m.id), Some(stored.id));
+        let second = claim_stored(&mut existing, &session(1800, 3600), &none, window_start);
+        assert!(second.is_none());
+        assert_ne!(
+            meeting_frame_id("c", "zoom", at(0)),
+            meeting_frame_id("c", "zoom", at(1800))
+        );
+        assert_eq!(
+            meeting_frame_id("c", "zoom", at(1800)),
+            meeting_frame_id("c", "zoom", at(1800))
+        );
+
+        // A session cut off by the lookback window keeps the frame that started before it.
+        let mut existing = vec![stored.clone()];
+        let cut = claim_stored(&mut existing, &session(600, 1200), &none, at(600));
+        assert_eq!(cut.map(|m| m.id), Some(stored.id));
+    }
 }
@@ -1,14 +1,24 @@
 pub mod activity;
 pub mod browser_topic;
+pub mod cache;
 pub mod dag;
+pub mod dead_letter;
 pub mod egress;
+pub mod entities;
+pub mod episode;
 pub mod llm;
+pub mod llm_provider;
 pub mod location;
 pub mod meeting;
 pub mod ocr;
+pub mod process;
+pub mod secrets;
 pub mod sound;
+pub mod state;
+pub mod structured;
 pub mod stt;
 pub mod summary;
+pub mod translate;
 pub mod watermark;
 pub mod worker;
 pub mod writer;
@@ -29,6 +39,8 @@ pub enum TransformPipelineError {
     UnsupportedModality { transform: String, modality: String },
     #[error("cycle detected in transform DAG: {0}")]
     CycleDetected(String),
+    #[error("egress denied: {0}")]
+    EgressDenied(String),
 }
 
 pub enum TransformOutput {
@@ -36,6 +48,11 @@ pub enum TransformOutput {
     Transcription(lifelog_types::TranscriptionFrame),
     Embedding(lifelog_types::EmbeddingFrame),
     Generic(GenericTransformOutput),
+    PrivacyScan(secrets::PrivacyScanOutput),
+    Entities(entities::EntityOutput),
+    Structured(structured::StructuredOutput),
+    /// Nothing to write for this frame; the transform's watermark still moves past it.
+    Skipped,
 }
 
 pub struct GenericTransformOutput {
@@ -57,6 +74,21 @@ pub trait TransformExecutor: Send + Sync {
     fn privacy_level(&self) -> PrivacyLevel {
         PrivacyLevel::Standard
     }
+    /// True for transforms that update the source frame instead of writing a derived one.
+    /// Their input is not limited to non-derived frames.
+    fn annotates_in_place(&self) -> bool {
+        false
+    }
+    /// True for transforms that must run on a frame before its text is searchable. Frames they
+    /// match are stored with [`secrets::SCAN_PENDING_FIELD`] set until then.
+    fn gates_search(&self) -> bool {
+        false
+    }
+    /// Identifies this transform's configuration for the result cache; see [`cache::fingerprint`].
+    /// `None` disables caching.
+    fn cache_fingerprint(&self) -> Option<&str> {
+        None
+    }
 
     async fn execute(
         &self,
@@ -7,15 +7,26 @@ use lifelog_types::LifelogData;
 
 use super::{TransformExecutor, TransformOutput, TransformPipelineError};
 
+/// Version of what an OCR result carries, part of the cache fingerprint. Bump it when
+/// `CachedOutput::Ocr` gains fields so entries cached without them are recomputed; 2 added word
+/// and line boxes.
+const OUTPUT_VERSION: &str = "2";
+
 pub struct OcrExecutor {
     inner: OcrTransform,
     id: String,
     privacy_level: PrivacyLevel,
+    cache_fingerprint: Option<String>,
 }
 
 impl OcrExecutor {
     pub fn new(source: DataOrigin, config: OcrConfig) -> Self {
+        let params = std::collections::HashMap::from([
+            ("language".to_string(), config.language.clone()),
+            ("output_version".to_string(), OUTPUT_VERSION.to_string()),
+        ]);
         Self {
+            cache_fingerprint: super::cache::fingerprint("ocr", "", &params),
             inner: OcrTransform::new(source, config),
             id: "ocr".to_string(),
             privacy_level: PrivacyLevel::LocalOnly,
@@ -54,6 +65,10 @@ impl TransformExecutor for OcrExecutor {
         self.privacy_level
     }
 
+    fn cache_fingerprint(&self) -> Option<&str> {
+        self.cache_fingerprint.as_deref()
+    }
+
     fn matches_origin(&self, key_origin: &DataOrigin) -> bool {
         let src = self.inner.source();
         if src.modality_name != key_origin.modality_name {
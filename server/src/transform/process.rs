@@ -0,0 +1,797 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use base64::Engine;
+use lifelog_core::{DataOrigin, DataOriginType, LifelogFrameKey, PrivacyLevel};
+use lifelog_types::LifelogData;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+use utils::cas::sha256_hex;
+
+use super::cache::CachedOutput;
+use super::writer::extract_source_timestamps;
+use super::{GenericTransformOutput, TransformExecutor, TransformOutput, TransformPipelineError};
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const MAX_RESTART_BACKOFF_SECS: u64 = 300;
+
+/// Payload fields holding binary content. They are written to a file and passed as
+/// `blob_path` instead of being inlined as base64.
+const BLOB_FIELDS: &[&str] = &["imageBytes", "audioBytes", "binaryData"];
+
+/// Environment variables kept when `env_clear` is on.
+const INHERITED_ENV: &[&str] = &["PATH", "LANG", "LC_ALL", "TZ"];
+
+/// System paths bound read-only inside the `bwrap` sandbox, so interpreters and dynamically
+/// linked programs find their libraries. Missing ones are skipped.
+const SANDBOX_SYSTEM_PATHS: &[&str] = &[
+    "/usr",
+    "/bin",
+    "/sbin",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/etc/ld.so.cache",
+    "/etc/alternatives",
+    "/etc/localtime",
+];
+/// Also bound when the sandbox keeps network access.
+const SANDBOX_NETWORK_PATHS: &[&str] = &["/etc/resolv.conf", "/etc/hosts", "/etc/ssl"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Sandbox {
+    None,
+    /// Runs the command under bubblewrap with only system libraries, the program, its file
+    /// arguments, the working directory and the scratch directory visible, all read-only, plus a
+    /// private /tmp. No namespaces are shared with the server except (optionally) the network.
+    Bwrap {
+        allow_network: bool,
+    },
+}
+
+/// How to launch the external command.
+///
+/// Params:
+/// - `command`: program to run (required)
+/// - `args`: JSON array of strings, or whitespace-separated arguments
+/// - `working_dir`: working directory for the process
+/// - `env`: extra environment, `KEY=VALUE` pairs separated by `;`
+/// - `env_clear`: start from an empty environment except `PATH`/`LANG`/`LC_ALL`/`TZ`
+///   (default `"true"`)
+/// - `sandbox`: `none` (default) or `bwrap`
+/// - `allow_network`: keep network access inside the `bwrap` sandbox (default `"false"`)
+/// - `timeout_secs`: per-frame response timeout (default 30)
+#[derive(Debug, Clone, PartialEq)]
+struct ProcessConfig {
+    program: String,
+    args: Vec<String>,
+    /// The program and the arguments naming existing files (scripts, models), as absolute
+    /// paths.
+    files: Vec<PathBuf>,
+    working_dir: Option<PathBuf>,
+    env: Vec<(String, String)>,
+    env_clear: bool,
+    sandbox: Sandbox,
+    timeout: Duration,
+}
+
+impl ProcessConfig {
+    fn from_params(params: &HashMap<String, String>) -> Result<Self, String> {
+        let program = params
+            .get("command")
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty())
+            .ok_or_else(|| "missing required param `command`".to_string())?;
+        let args = match params.get("args") {
+            Some(raw) => parse_args(raw)?,
+            None => Vec::new(),
+        };
+        let env = params
+            .get("env")
+            .map(|raw| parse_env(raw))
+            .transpose()?
+            .unwrap_or_default();
+        let sandbox = match params.get("sandbox").map(String::as_str) {
+            None | Some("none") => Sandbox::None,
+            Some("bwrap") => Sandbox::Bwrap {
+                allow_network: params.get("allow_network").is_some_and(|v| v == "true"),
+            },
+            Some(other) => return Err(format!("unknown sandbox {other:?}")),
+        };
+        let working_dir = params.get("working_dir").map(PathBuf::from);
+        Ok(Self {
+            files: program_files(&program, &args, working_dir.as_deref()),
+            program,
+            args,
+            working_dir,
+            env,
+            env_clear: params.get("env_clear").is_none_or(|v| v != "false"),
+            sandbox,
+            timeout: Duration::from_secs(
+                params
+                    .get("timeout_secs")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(DEFAULT_TIMEOUT_SECS),
+            ),
+        })
+    }
+
+    /// The command line actually executed, including the sandbox wrapper. `scratch` is the
+    /// directory blob files are written to and must stay visible inside the sandbox.
+    fn argv(&self, scratch: &Path) -> Vec<String> {
+        let mut argv = Vec::new();
+        if let Sandbox::Bwrap { allow_network } = self.sandbox {
+            argv.extend(
+                [
+                    "bwrap",
+                    "--dev",
+                    "/dev",
+                    "--proc",
+                    "/proc",
+                    "--tmpfs",
+                    "/tmp",
+                    "--unshare-all",
+                    "--die-with-parent",
+                    "--new-session",
+                ]
+                .map(str::to_string),
+            );
+            if allow_network {
+                argv.push("--share-net".to_string());
+            }
+            let network_paths = if allow_network {
+                SANDBOX_NETWORK_PATHS
+            } else {
+                &[]
+            };
+            for path in SANDBOX_SYSTEM_PATHS.iter().chain(network_paths) {
+                argv.extend(["--ro-bind-try", path, path].map(str::to_string));
+            }
+            let visible = self
+                .files
+                .iter()
+                .map(PathBuf::as_path)
+                .filter(|path| !SANDBOX_SYSTEM_PATHS.iter().any(|dir| path.starts_with(dir)))
+                .chain(self.working_dir.as_deref())
+                .chain([scratch]);
+            for path in visible {
+                let path = path.to_string_lossy().to_string();
+                argv.extend(["--ro-bind".to_string(), path.clone(), path]);
+            }
+            if let Some(dir) = &self.working_dir {
+                let dir = dir.to_string_lossy().to_string();
+                argv.extend(["--chdir".to_string(), dir]);
+            }
+            argv.push("--".to_string());
+        }
+        argv.push(self.program.clone());
+        argv.extend(self.args.iter().cloned());
+        argv
+    }
+}
+
+/// The program, looked up on `PATH` unless it names a path, and the arguments that name existing
+/// files, relative ones resolved against `working_dir`.
+fn program_files(program: &str, args: &[String], working_dir: Option<&Path>) -> Vec<PathBuf> {
+    let absolute = |name: &str| {
+        let path = Path::new(name);
+        match working_dir {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+        }
+    };
+    let program = if program.contains('/') {
+        Some(absolute(program))
+    } else {
+        std::env::var_os("PATH").and_then(|paths| {
+            std::env::split_paths(&paths)
+                .map(|dir| dir.join(program))
+                .find(|path| path.is_file())
+        })
+    };
+    let mut files: Vec<PathBuf> = program.into_iter().collect();
+    for arg in args {
+        let path = absolute(arg);
+        if path.is_file() && !files.contains(&path) {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Hash of the contents of `files`, so editing the program or a script it runs invalidates the
+/// results cached for it. Unreadable files hash as empty.
+fn files_digest(files: &[PathBuf]) -> String {
+    let mut material = String::new();
+    for path in files {
+        let mut hasher = Sha256::new();
+        let content = std::fs::File::open(path)
+            .and_then(|mut file| std::io::copy(&mut file, &mut hasher))
+            .map(|_| format!("{:x}", hasher.finalize()))
+            .unwrap_or_default();
+        material.push_str(&format!("{}={content}\n", path.display()));
+    }
+    sha256_hex(material.as_bytes())
+}
+
+fn parse_args(raw: &str) -> Result<Vec<String>, String> {
+    if raw.trim_start().starts_with('[') {
+        serde_json::from_str(raw).map_err(|e| format!("args is not a JSON string array: {e}"))
+    } else {
+        Ok(raw.split_whitespace().map(str::to_string).collect())
+    }
+}
+
+fn parse_env(raw: &str) -> Result<Vec<(String, String)>, String> {
+    raw.split(';')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((k, v)) if !k.trim().is_empty() => Ok((k.trim().to_string(), v.to_string())),
+            _ => Err(format!("env entry {pair:?} is not KEY=VALUE")),
+        })
+        .collect()
+}
+
+/// One line on the process's stdin.
+#[derive(Debug, Serialize)]
+struct ProcessRequest {
+    id: String,
+    origin: String,
+    modality: String,
+    /// The source frame with snake_case field names and binary fields removed.
+    payload: Map<String, Value>,
+    /// File holding the frame's binary content (image or audio), if it has any.
+    blob_path: Option<String>,
+}
+
+/// One line on the process's stdout: either an `output` shaped like a cached transform result
+/// (`{"kind": "ocr" | "transcription" | "structured" | "generic", ...}`) or an `error` for
+/// this frame.
+#[derive(Debug, Deserialize)]
+struct ProcessResponse {
+    id: String,
+    #[serde(default)]
+    output: Option<CachedOutput>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// A frame's JSON payload and its binary content, if any.
+type SplitFrame = (Map<String, Value>, Option<Vec<u8>>);
+
+/// Splits a frame into its JSON payload and binary content.
+fn split_frame(data: &LifelogData) -> Result<SplitFrame, String> {
+    let frame = match serde_json::to_value(data) {
+        Ok(Value::Object(outer)) => outer.into_iter().next().map(|(_, frame)| frame),
+        Ok(_) => None,
+        Err(e) => return Err(format!("serialize frame: {e}")),
+    };
+    let Some(Value::Object(mut fields)) = frame else {
+        return Err("missing payload".to_string());
+    };
+
+    let mut blob = None;
+    for name in BLOB_FIELDS {
+        if let Some(Value::String(encoded)) = fields.remove(*name) {
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .map_err(|e| format!("decode {name}: {e}"))?;
+            blob = Some(bytes);
+        }
+    }
+    let payload = fields
+        .into_iter()
+        .map(|(k, v)| (super::structured::snake_case(&k), v))
+        .collect();
+    Ok((payload, blob))
+}
+
+fn parse_response(line: &str, expected_id: &str) -> Result<Result<CachedOutput, String>, String> {
+    let response: ProcessResponse =
+        serde_json::from_str(line).map_err(|e| format!("invalid response line: {e}"))?;
+    if response.id != expected_id {
+        return Err(format!(
+            "response for {} while waiting for {expected_id}",
+            response.id
+        ));
+    }
+    match (response.output, response.error) {
+        (Some(output), _) => Ok(Ok(output)),
+        (None, Some(error)) => Ok(Err(error)),
+        (None, None) => Err("response has neither output nor error".to_string()),
+    }
+}
+
+struct Running {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+}
+
+#[derive(Default)]
+struct ProcessState {
+    running: Option<Running>,
+    consecutive_failures: u32,
+    restart_at: Option<Instant>,
+}
+
+/// Delay before restarting after `failures` consecutive crashes or timeouts: 1s, 2s, 4s, ...
+/// capped at five minutes.
+fn restart_backoff(failures: u32) -> Duration {
+    let secs = 1u64 << failures.saturating_sub(1).min(16);
+    Duration::from_secs(secs.min(MAX_RESTART_BACKOFF_SECS))
+}
+
+/// Runs a user-configured command as a transform. The process is long-lived: it reads one JSON
+/// request per line on stdin and answers each with one JSON line on stdout. Its stderr is
+/// logged. A crash, hang past `timeout_secs` or protocol violation kills it; it is restarted on
+/// a later frame with exponential backoff.
+pub struct ProcessExecutor {
+    id: String,
+    source: DataOrigin,
+    destination_modality: String,
+    priority: u8,
+    privacy_level: PrivacyLevel,
+    config: ProcessConfig,
+    scratch: PathBuf,
+    cache_fingerprint: Option<String>,
+    state: Mutex<ProcessState>,
+}
+
+impl ProcessExecutor {
+    pub fn new(
+        id: String,
+        source: DataOrigin,
+        destination_modality: String,
+        priority: u8,
+        params: &HashMap<String, String>,
+    ) -> Result<Self, String> {
+        let config = ProcessConfig::from_params(params)?;
+        let scratch = std::env::temp_dir().join(format!("lifelog-process-{id}"));
+        let program = format!("{}\n{}", config.program, files_digest(&config.files));
+        Ok(Self {
+            cache_fingerprint: super::cache::fingerprint("process", &program, params),
+            privacy_level: PrivacyLevel::from_params(params),
+            id,
+            source,
+            destination_modality,
+            priority,
+            config,
+            scratch,
+            state: Mutex::new(ProcessState::default()),
+        })
+    }
+
+    fn prepare_scratch(&self) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.scratch)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&self.scratch, std::fs::Permissions::from_mode(0o700))?;
+        }
+        Ok(())
+    }
+
+    fn spawn(&self) -> Result<Running, String> {
+        self.prepare_scratch()
+            .map_err(|e| format!("create scratch dir {}: {e}", self.scratch.display()))?;
+        let argv = self.config.argv(&self.scratch);
+        let mut command = Command::new(&argv[0]);
+        command
+            .args(&argv[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if self.config.env_clear {
+            command.env_clear();
+            for name in INHERITED_ENV {
+                if let Ok(value) = std::env::var(name) {
+                    command.env(name, value);
+                }
+            }
+        }
+        command.envs(self.config.env.iter().map(|(k, v)| (k, v)));
+        if let (Some(dir), Sandbox::None) = (&self.config.working_dir, &self.config.sandbox) {
+            command.current_dir(dir);
+        }
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("spawn {}: {e}", argv[0]))?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err("child has no stdio pipes".to_string());
+        };
+        if let Some(stderr) = child.stderr.take() {
+            let id = self.id.clone();
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    tracing::info!(transform_id = %id, "{line}");
+                }
+            });
+        }
+        tracing::info!(transform_id = %self.id, command = %argv.join(" "), "Started transform process");
+        Ok(Running {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout).lines(),
+        })
+    }
+
+    /// Sends one request and waits for its response line.
+    async fn exchange(
+        running: &mut Running,
+        request: &str,
+        timeout: Duration,
+    ) -> Result<String, String> {
+        let io = async {
+            running.stdin.write_all(request.as_bytes()).await?;
+            running.stdin.write_all(b"\n").await?;
+            running.stdin.flush().await?;
+            running.stdout.next_line().await
+        };
+        match tokio::time::timeout(timeout, io).await {
+            Ok(Ok(Some(line))) => Ok(line),
+            Ok(Ok(None)) => Err("process exited".to_string()),
+            Ok(Err(e)) => Err(format!("process i/o: {e}")),
+            Err(_) => Err(format!("no response within {}s", timeout.as_secs())),
+        }
+    }
+
+    async fn write_blob(&self, key: &LifelogFrameKey, blob: &[u8]) -> Result<PathBuf, String> {
+        self.prepare_scratch()
+            .map_err(|e| format!("create scratch dir {}: {e}", self.scratch.display()))?;
+        let path = self.scratch.join(key.uuid.to_string());
+        tokio::fs::write(&path, blob)
+            .await
+            .map_err(|e| format!("write blob {}: {e}", path.display()))?;
+        Ok(path)
+    }
+
+    async fn run(
+        &self,
+        state: &mut ProcessState,
+        request: &str,
+        request_id: &str,
+    ) -> Result<Result<CachedOutput, String>, String> {
+        if state.running.is_none() {
+            if state.restart_at.is_some_and(|at| Instant::now() < at) {
+                return Err("process is backing off after a failure".to_string());
+            }
+            state.running = Some(self.spawn()?);
+        }
+        let Some(running) = state.running.as_mut() else {
+            return Err("process not running".to_string());
+        };
+        let line = Self::exchange(running, request, self.config.timeout).await?;
+        parse_response(&line, request_id)
+    }
+}
+
+#[async_trait]
+impl TransformExecutor for ProcessExecutor {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn source_modality(&self) -> &str {
+        &self.source.modality_name
+    }
+
+    fn destination_modality(&self) -> &str {
+        &self.destination_modality
+    }
+
+    fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    fn is_async(&self) -> bool {
+        true
+    }
+
+    fn privacy_level(&self) -> PrivacyLevel {
+        self.privacy_level
+    }
+
+    fn cache_fingerprint(&self) -> Option<&str> {
+        self.cache_fingerprint.as_deref()
+    }
+
+    fn matches_origin(&self, key_origin: &DataOrigin) -> bool {
+        let src = self.source();
+        if src.modality_name != key_origin.modality_name {
+            return false;
+        }
+        match &src.origin {
+            DataOriginType::DeviceId(id) if id == "*" => true,
+            _ => src == *key_origin,
+        }
+    }
+
+    fn source(&self) -> DataOrigin {
+        self.source.clone()
+    }
+
+    fn destination(&self) -> DataOrigin {
+        DataOrigin::new(
+            DataOriginType::DataOrigin(Box::new(self.source.clone())),
+            self.destination_modality.clone(),
+        )
+    }
+
+    async fn execute(
+        &self,
+        _http: &reqwest::Client,
+        data: &LifelogData,
+        key: &LifelogFrameKey,
+    ) -> Result<TransformOutput, TransformPipelineError> {
+        let (payload, blob) = split_frame(data).map_err(TransformPipelineError::DataError)?;
+        let blob_path = match &blob {
+            Some(bytes) => Some(
+                self.write_blob(key, bytes)
+                    .await
+                    .map_err(TransformPipelineError::ServiceError)?,
+            ),
+            None => None,
+        };
+
+        let request_id = key.uuid.to_string();
+        let request = ProcessRequest {
+            id: request_id.clone(),
+            origin: key.origin.to_string(),
+            modality: key.origin.modality_name.clone(),
+            payload,
+            blob_path: blob_path.as_ref().map(|p| p.to_string_lossy().to_string()),
+        };
+        let request = serde_json::to_string(&request)
+            .map_err(|e| TransformPipelineError::DataError(format!("encode request: {e}")))?;
+
+        let mut state = self.state.lock().await;
+        let result = self.run(&mut state, &request, &request_id).await;
+        if let Some(path) = &blob_path {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+
+        let output = match result {
+            Ok(output) => {
+                state.consecutive_failures = 0;
+                state.restart_at = None;
+                output
+            }
+            Err(reason) => {
+                if let Some(mut running) = state.running.take() {
+                    let _ = running.child.start_kill();
+                }
+                state.consecutive_failures += 1;
+                let delay = restart_backoff(state.consecutive_failures);
+                state.restart_at = Some(Instant::now() + delay);
+                tracing::warn!(
+                    transform_id = %self.id,
+                    failures = state.consecutive_failures,
+                    restart_in_secs = delay.as_secs(),
+                    "Transform process failed: {reason}"
+                );
+                return Err(TransformPipelineError::ServiceError(reason));
+            }
+        };
+        drop(state);
+
+        let output = output.map_err(|e| {
+            TransformPipelineError::DataError(format!("process rejected frame: {e}"))
+        })?;
+        Ok(
+            match output.into_output(key, &extract_source_timestamps(data)) {
+                TransformOutput::Generic(g) if g.modality.is_empty() => {
+                    TransformOutput::Generic(GenericTransformOutput {
+                        modality: self.destination_modality.clone(),
+                        ..g
+                    })
+                }
+                other => other,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn config_parses_args_env_and_sandbox() {
+        let config = ProcessConfig::from_params(&params(&[
+            ("command", "/usr/bin/python3"),
+            ("args", r#"["-u", "classify.py", "--model dir"]"#),
+            ("env", "MODEL=small; THREADS=2"),
+            ("sandbox", "bwrap"),
+        ]))
+        .ok();
+        let config = config.as_ref();
+        assert_eq!(
+            config.map(|c| c.args.clone()).unwrap_or_default(),
+            vec!["-u", "classify.py", "--model dir"]
+        );
+        assert_eq!(
+            config.map(|c| c.env.clone()).unwrap_or_default(),
+            vec![
+                ("MODEL".to_string(), "small".to_string()),
+                ("THREADS".to_string(), "2".to_string())
+            ]
+        );
+        assert_eq!(
+            config.map(|c| c.sandbox.clone()),
+            Some(Sandbox::Bwrap {
+                allow_network: false
+            })
+        );
+        assert_eq!(config.map(|c| c.env_clear), Some(true));
+
+        let argv = config
+            .map(|c| c.argv(Path::new("/tmp/lifelog-process-x")))
+            .unwrap_or_default();
+        assert_eq!(argv.first().map(String::as_str), Some("bwrap"));
+        assert!(!argv.contains(&"--share-net".to_string()));
+        assert!(!argv.windows(2).any(|w| w[0] == "/" && w[1] == "/"));
+        assert!(argv.windows(3).any(|w| w
+            == [
+                "--ro-bind",
+                "/tmp/lifelog-process-x",
+                "/tmp/lifelog-process-x"
+            ]));
+        assert_eq!(
+            &argv[argv.len() - 4..],
+            ["/usr/bin/python3", "-u", "classify.py", "--model dir"]
+        );
+
+        assert!(ProcessConfig::from_params(&params(&[("args", "x")])).is_err());
+        assert!(ProcessConfig::from_params(&params(&[("command", "x"), ("env", "NOPE")])).is_err());
+        assert!(
+            ProcessConfig::from_params(&params(&[("command", "x"), ("sandbox", "docker")]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn fingerprint_changes_with_the_script() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let script = dir.path().join("classify.py");
+        let source = DataOrigin::new(DataOriginType::DeviceId("*".to_string()), "Screen".into());
+        let params = params(&[
+            ("command", "python3"),
+            ("args", "-u classify.py"),
+            ("working_dir", &dir.path().to_string_lossy()),
+        ]);
+        let fingerprint = || {
+            ProcessExecutor::new(
+                "p".to_string(),
+                source.clone(),
+                "Ocr".to_string(),
+                3,
+                &params,
+            )
+            .map(|e| e.cache_fingerprint)
+            .map_err(anyhow::Error::msg)
+        };
+
+        std::fs::write(&script, "print('v1')")?;
+        let (first, again) = (fingerprint()?, fingerprint()?);
+        std::fs::write(&script, "print('v2')")?;
+        let edited = fingerprint()?;
+        assert!(first.is_some());
+        assert_eq!(first, again);
+        assert_ne!(first, edited);
+        Ok(())
+    }
+
+    #[test]
+    fn split_frame_moves_bytes_to_blob() {
+        let data = LifelogData {
+            payload: Some(lifelog_types::lifelog_data::Payload::Screenframe(
+                lifelog_types::ScreenFrame {
+                    uuid: "u1".to_string(),
+                    width: 640,
+                    image_bytes: vec![1, 2, 3],
+                    mime_type: "image/png".to_string(),
+                    ..Default::default()
+                },
+            )),
+        };
+        let (payload, blob) = split_frame(&data).unwrap_or_default();
+        assert_eq!(blob, Some(vec![1, 2, 3]));
+        assert_eq!(payload["width"], 640);
+        assert_eq!(payload["mime_type"], "image/png");
+        assert!(!payload.contains_key("image_bytes") && !payload.contains_key("imageBytes"));
+    }
+
+    #[test]
+    fn parses_outputs_errors_and_protocol_violations() {
+        let ok = parse_response(
+            r#"{"id": "a", "output": {"kind": "transcription", "text": "hi", "model": "m", "confidence": 0.5}}"#,
+            "a",
+        );
+        assert!(matches!(
+            ok,
+            Ok(Ok(CachedOutput::Transcription { ref text, .. })) if text == "hi"
+        ));
+
+        let rejected = parse_response(r#"{"id": "a", "error": "too dark"}"#, "a");
+        assert!(matches!(rejected, Ok(Err(ref e)) if e == "too dark"));
+
+        assert!(parse_response(r#"{"id": "b", "error": "x"}"#, "a").is_err());
+        assert!(parse_response(r#"{"id": "a"}"#, "a").is_err());
+        assert!(parse_response("not json", "a").is_err());
+    }
+
+    #[tokio::test]
+    async fn round_trips_frames_and_backs_off_after_a_hang() {
+        let source = DataOrigin::new(DataOriginType::DeviceId("*".to_string()), "Screen".into());
+        let script = r#"while IFS= read -r line; do
+            case "$line" in *'"width":1'*) sleep 5 ;; esac
+            id=$(printf '%s' "$line" | sed 's/^{"id":"\([^"]*\)".*/\1/')
+            printf '{"id":"%s","output":{"kind":"ocr","text":"seen"}}\n' "$id"
+        done"#;
+        let executor = ProcessExecutor::new(
+            "process-test".to_string(),
+            source.clone(),
+            "Ocr".to_string(),
+            3,
+            &params(&[
+                ("command", "sh"),
+                ("args", &serde_json::json!(["-c", script]).to_string()),
+                ("timeout_secs", "1"),
+            ]),
+        );
+        assert!(executor.is_ok());
+        let Ok(executor) = executor else {
+            return;
+        };
+        let http = reqwest::Client::new();
+        let frame = |width: u32| LifelogData {
+            payload: Some(lifelog_types::lifelog_data::Payload::Screenframe(
+                lifelog_types::ScreenFrame {
+                    width,
+                    image_bytes: vec![7; 16],
+                    ..Default::default()
+                },
+            )),
+        };
+        let key = LifelogFrameKey::new(uuid::Uuid::new_v4(), source);
+
+        let output = executor.execute(&http, &frame(640), &key).await;
+        assert!(matches!(output, Ok(TransformOutput::Ocr(ref f)) if f.text == "seen"));
+
+        assert!(executor.execute(&http, &frame(1), &key).await.is_err());
+        let backing_off = executor.execute(&http, &frame(640), &key).await;
+        assert!(matches!(
+            backing_off,
+            Err(TransformPipelineError::ServiceError(ref e)) if e.contains("backing off")
+        ));
+    }
+
+    #[test]
+    fn restart_backoff_doubles_and_caps() {
+        assert_eq!(restart_backoff(1), Duration::from_secs(1));
+        assert_eq!(restart_backoff(3), Duration::from_secs(4));
+        assert_eq!(
+            restart_backoff(30),
+            Duration::from_secs(MAX_RESTART_BACKOFF_SECS)
+        );
+    }
+}
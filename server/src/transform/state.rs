@@ -0,0 +1,387 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lifelog_core::LifelogError;
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::postgres::PostgresPool;
+
+use super::cache::{self, CachedOutput};
+use super::dead_letter::{self, DeadLetter};
+use super::entities::{self, EntityOutput};
+
+/// Findings of a privacy scan, ready to apply to the scanned frame.
+#[derive(Debug, Clone)]
+pub struct PrivacyFindings {
+    pub frame_id: Uuid,
+    pub transform_id: String,
+    /// Merged into the payload: redacted field values, `pii_findings` and `pii_redacted`.
+    pub patch: Map<String, Value>,
+    /// Payload fields removed along with the patch.
+    pub dropped: Vec<&'static str>,
+    /// `redacted` or `flagged`, as recorded in the audit log.
+    pub action: &'static str,
+    /// Distinct finding kinds, sorted.
+    pub kinds: Vec<String>,
+    pub count: i32,
+}
+
+/// What the transform worker keeps besides frames and watermarks: the result cache, dead
+/// letters, and the annotations privacy scans and entity extraction write for existing frames.
+#[async_trait]
+pub trait TransformStateStore: Send + Sync + std::fmt::Debug {
+    /// Returns the cached output for `input_hash`, counting the hit.
+    async fn cache_lookup(
+        &self,
+        transform_id: &str,
+        fingerprint: &str,
+        input_hash: &str,
+    ) -> Result<Option<CachedOutput>, LifelogError>;
+
+    async fn cache_store(
+        &self,
+        transform_id: &str,
+        fingerprint: &str,
+        input_hash: &str,
+        output: &CachedOutput,
+    ) -> Result<(), LifelogError>;
+
+    /// Adds one batch's hits and misses to the transform's running totals.
+    async fn record_cache_stats(
+        &self,
+        transform_id: &str,
+        hits: u64,
+        misses: u64,
+    ) -> Result<(), LifelogError>;
+
+    /// Drops a transform's entries cached under any other fingerprint.
+    async fn invalidate_stale_cache(
+        &self,
+        transform_id: &str,
+        fingerprint: &str,
+    ) -> Result<u64, LifelogError>;
+
+    /// Drops entries neither written nor hit since `before`.
+    async fn prune_cache(&self, before: DateTime<Utc>) -> Result<u64, LifelogError>;
+
+    /// Records a failed attempt and returns the entry's attempt count.
+    async fn record_failure(
+        &self,
+        transform_id: &str,
+        frame_id: Uuid,
+        origin: &str,
+        error: &str,
+        now: DateTime<Utc>,
+    ) -> Result<i32, LifelogError>;
+
+    /// Removes the entry after a successful retry.
+    async fn resolve_dead_letter(
+        &self,
+        transform_id: &str,
+        frame_id: Uuid,
+    ) -> Result<(), LifelogError>;
+
+    /// Entries of `transform_id` whose backoff has elapsed, oldest first.
+    async fn due_dead_letters(
+        &self,
+        transform_id: &str,
+        now: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<DeadLetter>, LifelogError>;
+
+    /// Lists entries, optionally for one transform, most recently failed first.
+    async fn list_dead_letters(
+        &self,
+        transform_id: Option<&str>,
+        include_exhausted: bool,
+        limit: usize,
+    ) -> Result<Vec<DeadLetter>, LifelogError>;
+
+    /// Makes entries due immediately and resets their attempt budget. An empty `frame_ids`
+    /// selects every entry of the transform.
+    async fn retry_dead_letters(
+        &self,
+        transform_id: &str,
+        frame_ids: &[Uuid],
+        now: DateTime<Utc>,
+    ) -> Result<u64, LifelogError>;
+
+    /// Drops entries without retrying them. An empty `frame_ids` selects every entry of the
+    /// transform.
+    async fn discard_dead_letters(
+        &self,
+        transform_id: &str,
+        frame_ids: &[Uuid],
+    ) -> Result<u64, LifelogError>;
+
+    /// Patches the scanned frame, resealing it if needed, and records an audit entry.
+    async fn record_privacy_findings(&self, findings: PrivacyFindings) -> Result<(), LifelogError>;
+
+    /// Makes a frame the secret scan found nothing in searchable; see
+    /// [`super::secrets::SCAN_PENDING_FIELD`].
+    async fn clear_scan_pending(&self, frame_id: Uuid) -> Result<(), LifelogError>;
+
+    /// Replaces the frame's mentions from this transform and upserts the entities they refer to.
+    async fn write_entity_mentions(
+        &self,
+        frame_id: Uuid,
+        output: &EntityOutput,
+        seen_at: DateTime<Utc>,
+    ) -> Result<(), LifelogError>;
+
+    /// Drops mentions of deleted frames and entities left without mentions. Returns the number
+    /// of mentions dropped.
+    async fn prune_entities(&self) -> Result<u64, LifelogError>;
+}
+
+#[derive(Debug)]
+pub struct PostgresTransformState {
+    pool: PostgresPool,
+}
+
+impl PostgresTransformState {
+    pub fn new(pool: PostgresPool) -> Self {
+        Self { pool }
+    }
+
+    async fn client(&self) -> Result<deadpool_postgres::Object, LifelogError> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| LifelogError::Database(format!("pool: {e}")))
+    }
+}
+
+#[async_trait]
+impl TransformStateStore for PostgresTransformState {
+    async fn cache_lookup(
+        &self,
+        transform_id: &str,
+        fingerprint: &str,
+        input_hash: &str,
+    ) -> Result<Option<CachedOutput>, LifelogError> {
+        cache::lookup(&self.pool, transform_id, fingerprint, input_hash).await
+    }
+
+    async fn cache_store(
+        &self,
+        transform_id: &str,
+        fingerprint: &str,
+        input_hash: &str,
+        output: &CachedOutput,
+    ) -> Result<(), LifelogError> {
+        cache::store(&self.pool, transform_id, fingerprint, input_hash, output).await
+    }
+
+    async fn record_cache_stats(
+        &self,
+        transform_id: &str,
+        hits: u64,
+        misses: u64,
+    ) -> Result<(), LifelogError> {
+        cache::record_stats(&self.pool, transform_id, hits, misses).await
+    }
+
+    async fn invalidate_stale_cache(
+        &self,
+        transform_id: &str,
+        fingerprint: &str,
+    ) -> Result<u64, LifelogError> {
+        cache::invalidate_stale(&self.pool, transform_id, fingerprint).await
+    }
+
+    async fn prune_cache(&self, before: DateTime<Utc>) -> Result<u64, LifelogError> {
+        cache::prune_unused(&self.pool, before).await
+    }
+
+    async fn record_failure(
+        &self,
+        transform_id: &str,
+        frame_id: Uuid,
+        origin: &str,
+        error: &str,
+        now: DateTime<Utc>,
+    ) -> Result<i32, LifelogError> {
+        dead_letter::record_failure(&self.pool, transform_id, frame_id, origin, error, now).await
+    }
+
+    async fn resolve_dead_letter(
+        &self,
+        transform_id: &str,
+        frame_id: Uuid,
+    ) -> Result<(), LifelogError> {
+        dead_letter::resolve(&self.pool, transform_id, frame_id).await
+    }
+
+    async fn due_dead_letters(
+        &self,
+        transform_id: &str,
+        now: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<DeadLetter>, LifelogError> {
+        dead_letter::due(&self.pool, transform_id, now, limit).await
+    }
+
+    async fn list_dead_letters(
+        &self,
+        transform_id: Option<&str>,
+        include_exhausted: bool,
+        limit: usize,
+    ) -> Result<Vec<DeadLetter>, LifelogError> {
+        dead_letter::list(&self.pool, transform_id, include_exhausted, limit).await
+    }
+
+    async fn retry_dead_letters(
+        &self,
+        transform_id: &str,
+        frame_ids: &[Uuid],
+        now: DateTime<Utc>,
+    ) -> Result<u64, LifelogError> {
+        dead_letter::retry_now(&self.pool, transform_id, frame_ids, now).await
+    }
+
+    async fn discard_dead_letters(
+        &self,
+        transform_id: &str,
+        frame_ids: &[Uuid],
+    ) -> Result<u64, LifelogError> {
+        dead_letter::discard(&self.pool, transform_id, frame_ids).await
+    }
+
+    async fn record_privacy_findings(&self, findings: PrivacyFindings) -> Result<(), LifelogError> {
+        let PrivacyFindings {
+            frame_id,
+            transform_id,
+            patch,
+            dropped,
+            action,
+            kinds,
+            count,
+        } = findings;
+        let mut client = self.client().await?;
+        let tx = client
+            .transaction()
+            .await
+            .map_err(|e| LifelogError::Database(format!("privacy scan tx begin: {e}")))?;
+        let sealed = tx
+            .query_opt(
+                "SELECT payload FROM frames WHERE id = $1 AND payload ? 'sealed' FOR UPDATE",
+                &[&frame_id],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("privacy scan select: {e}")))?;
+        match sealed {
+            Some(row) => {
+                let payload = crate::payload_crypto::patch_sealed(
+                    frame_id,
+                    row.get("payload"),
+                    patch,
+                    &dropped,
+                )
+                .map_err(|e| LifelogError::Database(format!("privacy scan reseal: {e}")))?;
+                tx.execute(
+                    "UPDATE frames SET payload = $2 WHERE id = $1",
+                    &[&frame_id, &payload],
+                )
+                .await
+            }
+            None => {
+                tx.execute(
+                    "UPDATE frames SET payload = (payload - $3::text[]) || $2::jsonb WHERE id = $1",
+                    &[&frame_id, &Value::Object(patch), &dropped],
+                )
+                .await
+            }
+        }
+        .map_err(|e| LifelogError::Database(format!("privacy scan update: {e}")))?;
+        tx.execute(
+            "INSERT INTO privacy_audit (frame_id, transform_id, action, finding_kinds, finding_count)
+             VALUES ($1, $2, $3, $4, $5)",
+            &[&frame_id, &transform_id, &action, &kinds, &count],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("privacy audit insert: {e}")))?;
+        tx.commit()
+            .await
+            .map_err(|e| LifelogError::Database(format!("privacy scan tx commit: {e}")))
+    }
+
+    async fn clear_scan_pending(&self, frame_id: Uuid) -> Result<(), LifelogError> {
+        // The marker sits beside the sealed fields, so sealed payloads need no reseal.
+        self.client()
+            .await?
+            .execute(
+                "UPDATE frames SET payload = payload - $2::text
+                 WHERE id = $1 AND payload ? $2",
+                &[&frame_id, &super::secrets::SCAN_PENDING_FIELD],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("scan pending clear: {e}")))?;
+        Ok(())
+    }
+
+    async fn write_entity_mentions(
+        &self,
+        frame_id: Uuid,
+        output: &EntityOutput,
+        seen_at: DateTime<Utc>,
+    ) -> Result<(), LifelogError> {
+        let mut client = self.client().await?;
+        let tx = client
+            .transaction()
+            .await
+            .map_err(|e| LifelogError::Database(format!("entity tx begin: {e}")))?;
+        tx.execute(
+            "DELETE FROM entity_mentions WHERE frame_id = $1 AND transform_id = $2",
+            &[&frame_id, &output.transform_id],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("entity mention clear: {e}")))?;
+
+        for mention in &output.mentions {
+            let row = tx
+                .query_one(
+                    "INSERT INTO entities (kind, value, display, first_seen, last_seen)
+                     VALUES ($1, $2, $3, $4, $4)
+                     ON CONFLICT (kind, value) DO UPDATE SET
+                         first_seen = LEAST(entities.first_seen, EXCLUDED.first_seen),
+                         last_seen = GREATEST(entities.last_seen, EXCLUDED.last_seen)
+                     RETURNING id",
+                    &[
+                        &mention.kind.as_str(),
+                        &mention.value,
+                        &mention.display,
+                        &seen_at,
+                    ],
+                )
+                .await
+                .map_err(|e| LifelogError::Database(format!("entity upsert: {e}")))?;
+            let entity_id: i64 = row.get(0);
+            tx.execute(
+                "INSERT INTO entity_mentions
+                     (entity_id, frame_id, transform_id, field, start_offset, end_offset, t_canonical)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)
+                 ON CONFLICT DO NOTHING",
+                &[
+                    &entity_id,
+                    &frame_id,
+                    &output.transform_id,
+                    &mention.field,
+                    &(mention.start as i32),
+                    &(mention.end as i32),
+                    &seen_at,
+                ],
+            )
+            .await
+            .map_err(|e| LifelogError::Database(format!("entity mention insert: {e}")))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| LifelogError::Database(format!("entity tx commit: {e}")))
+    }
+
+    async fn prune_entities(&self) -> Result<u64, LifelogError> {
+        entities::prune_orphans(&self.pool).await
+    }
+}
@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Map, Value};
+
+use super::llm_provider::{ChatMessage, LlmClient};
+use super::TransformPipelineError;
+
+/// A classifier's structured result: the frame written for search plus the typed fields merged
+/// into its payload, so `category = "coding"` matches with `Eq`.
+pub struct StructuredOutput {
+    pub frame: lifelog_types::TranscriptionFrame,
+    pub fields: Map<String, Value>,
+}
+
+/// Payload fields of the frame a structured result is written as, and those the server adds to
+/// payloads later. A response schema declaring one of them is rejected, since its value would
+/// collide with the server's.
+pub(crate) const RESERVED_FIELDS: &[&str] = &[
+    "text",
+    "model",
+    "confidence",
+    "sealed",
+    "blind_index",
+    "pii_findings",
+    "pii_redacted",
+    "pii_scan_pending",
+    "duplicate_of",
+    "phash",
+];
+
+/// Prompt and response contract for an LLM classifier.
+///
+/// Params:
+/// - `system_prompt`: instructions; the response schema is appended automatically
+/// - `prompt_template`: user message with `{{field}}` placeholders for source payload fields
+///   (snake_case, e.g. `{{url}}`, `{{title}}`, `{{visit_count}}`)
+/// - `response_schema`: JSON Schema (object with `properties`/`required`) for the reply
+pub struct StructuredPrompt {
+    system_prompt: String,
+    template: String,
+    schema: Value,
+}
+
+impl StructuredPrompt {
+    pub fn from_params(
+        transform_id: &str,
+        params: &HashMap<String, String>,
+        default_system_prompt: &str,
+        default_template: &str,
+        default_schema: Value,
+    ) -> Self {
+        let schema = match params.get("response_schema") {
+            Some(raw) => match serde_json::from_str::<Value>(raw) {
+                Ok(v) if v["properties"].is_object() => match reserved_properties(&v) {
+                    reserved if reserved.is_empty() => v,
+                    reserved => {
+                        tracing::warn!(
+                            transform_id = %transform_id,
+                            fields = %reserved.join(", "),
+                            "response_schema declares fields the server writes itself; using default"
+                        );
+                        default_schema
+                    }
+                },
+                Ok(_) | Err(_) => {
+                    tracing::warn!(
+                        transform_id = %transform_id,
+                        "response_schema is not a JSON object schema; using default"
+                    );
+                    default_schema
+                }
+            },
+            None => default_schema,
+        };
+        Self {
+            system_prompt: params
+                .get("system_prompt")
+                .cloned()
+                .unwrap_or_else(|| default_system_prompt.to_string()),
+            template: params
+                .get("prompt_template")
+                .cloned()
+                .unwrap_or_else(|| default_template.to_string()),
+            schema,
+        }
+    }
+
+    pub fn messages(&self, fields: &Map<String, Value>) -> Vec<ChatMessage> {
+        vec![
+            ChatMessage::system(format!(
+                "{}\nRespond with only a JSON object matching this schema:\n{}",
+                self.system_prompt, self.schema
+            )),
+            ChatMessage::user(render_template(&self.template, fields)),
+        ]
+    }
+
+    /// Asks the model, validates the reply against the schema and, if it cannot be repaired
+    /// locally, asks once more with the validation error.
+    pub async fn run(
+        &self,
+        llm: &LlmClient,
+        http: &reqwest::Client,
+        fields: &Map<String, Value>,
+    ) -> Result<Map<String, Value>, TransformPipelineError> {
+        let mut messages = self.messages(fields);
+        let raw = llm.chat_json(http, &messages, &self.schema).await?;
+        let reason = match conform(&raw, &self.schema) {
+            Ok(fields) => return Ok(fields),
+            Err(reason) => reason,
+        };
+
+        messages.push(ChatMessage {
+            role: "assistant",
+            content: raw,
+        });
+        messages.push(ChatMessage::user(format!(
+            "That reply was invalid ({reason}). Reply again with only the JSON object."
+        )));
+        let raw = llm.chat_json(http, &messages, &self.schema).await?;
+        conform(&raw, &self.schema).map_err(|reason| {
+            TransformPipelineError::DataError(format!(
+                "model output does not match schema: {reason}"
+            ))
+        })
+    }
+}
+
+/// Properties of `schema` named in [`RESERVED_FIELDS`].
+fn reserved_properties(schema: &Value) -> Vec<&str> {
+    schema["properties"]
+        .as_object()
+        .map(|properties| {
+            properties
+                .keys()
+                .map(String::as_str)
+                .filter(|name| RESERVED_FIELDS.contains(name))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Flattens a protobuf frame into snake_case fields for prompt templates.
+pub fn frame_fields<T: serde::Serialize>(frame: &T) -> Map<String, Value> {
+    match serde_json::to_value(frame) {
+        Ok(Value::Object(map)) => map.into_iter().map(|(k, v)| (snake_case(&k), v)).collect(),
+        _ => Map::new(),
+    }
+}
+
+pub(crate) fn snake_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len() + 4);
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            out.push('_');
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Replaces `{{field}}` with the field's value. Unknown fields render empty.
+pub fn render_template(template: &str, fields: &Map<String, Value>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let name = rest[start + 2..start + 2 + len].trim();
+        match fields.get(name) {
+            Some(Value::String(s)) => out.push_str(s),
+            Some(Value::Null) | None => {}
+            Some(other) => out.push_str(&other.to_string()),
+        }
+        rest = &rest[start + 2 + len + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Pulls the JSON object out of a reply that may be wrapped in prose or code fences.
+fn extract_json_object(raw: &str) -> Option<Map<String, Value>> {
+    let start = raw.find('{')?;
+    let end = raw.rfind('}')?;
+    if end < start {
+        return None;
+    }
+    match serde_json::from_str::<Value>(&raw[start..=end]) {
+        Ok(Value::Object(map)) => Some(map),
+        _ => None,
+    }
+}
+
+/// Validates a model reply against `schema`, repairing what can be repaired: string/number/bool
+/// coercions, comma-separated strings for string arrays, case-insensitive enum matches (falling
+/// back to `"other"` when the enum has it) and clamping to `minimum`/`maximum`. Properties the
+/// schema does not declare are dropped.
+pub fn conform(raw: &str, schema: &Value) -> Result<Map<String, Value>, String> {
+    let object = extract_json_object(raw).ok_or_else(|| "no JSON object in reply".to_string())?;
+    let properties = schema["properties"]
+        .as_object()
+        .ok_or_else(|| "schema has no properties".to_string())?;
+
+    let mut out = Map::new();
+    for (name, spec) in properties {
+        let Some(value) = object.get(name).filter(|v| !v.is_null()) else {
+            continue;
+        };
+        out.insert(
+            name.clone(),
+            coerce(value, spec).map_err(|e| format!("{name}: {e}"))?,
+        );
+    }
+
+    if let Some(required) = schema["required"].as_array() {
+        for name in required.iter().filter_map(Value::as_str) {
+            if !out.contains_key(name) {
+                return Err(format!("missing required field {name}"));
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn coerce(value: &Value, spec: &Value) -> Result<Value, String> {
+    match spec["type"].as_str().unwrap_or("string") {
+        "string" => {
+            let s = match value {
+                Value::String(s) => s.trim().to_string(),
+                Value::Number(_) | Value::Bool(_) => value.to_string(),
+                _ => return Err("expected a string".to_string()),
+            };
+            match spec["enum"].as_array() {
+                Some(allowed) => match_enum(&s, allowed),
+                None => Ok(Value::String(s)),
+            }
+        }
+        kind @ ("number" | "integer") => {
+            let n = match value {
+                Value::Number(n) => n.as_f64(),
+                Value::String(s) => s.trim().parse::<f64>().ok(),
+                _ => None,
+            }
+            .ok_or_else(|| format!("expected a {kind}"))?;
+            let min = spec["minimum"].as_f64().unwrap_or(f64::MIN);
+            let max = spec["maximum"].as_f64().unwrap_or(f64::MAX);
+            let n = n.clamp(min, max);
+            Ok(if kind == "integer" {
+                json!(n.round() as i64)
+            } else {
+                json!(n)
+            })
+        }
+        "boolean" => match value {
+            Value::Bool(b) => Ok(Value::Bool(*b)),
+            Value::String(s) if s.eq_ignore_ascii_case("true") => Ok(Value::Bool(true)),
+            Value::String(s) if s.eq_ignore_ascii_case("false") => Ok(Value::Bool(false)),
+            _ => Err("expected a boolean".to_string()),
+        },
+        "array" => {
+            let items: Vec<Value> = match value {
+                Value::Array(items) => items.clone(),
+                Value::String(s) => s
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(|s| Value::String(s.to_string()))
+                    .collect(),
+                _ => return Err("expected an array".to_string()),
+            };
+            items
+                .iter()
+                .map(|item| coerce(item, &spec["items"]))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Array)
+        }
+        other => Err(format!("unsupported schema type {other}")),
+    }
+}
+
+fn match_enum(s: &str, allowed: &[Value]) -> Result<Value, String> {
+    let wanted = s.to_lowercase();
+    let options: Vec<&str> = allowed.iter().filter_map(Value::as_str).collect();
+    if let Some(hit) = options.iter().find(|o| o.to_lowercase() == wanted) {
+        return Ok(Value::String(hit.to_string()));
+    }
+    if options.contains(&"other") {
+        return Ok(Value::String("other".to_string()));
+    }
+    Err(format!("{s:?} is not one of {}", options.join(", ")))
+}
+
+/// Search text for a structured result: `"category: description"`, matching the free-text
+/// format these transforms used to store.
+pub fn summary_text(fields: &Map<String, Value>, label: &str, detail: &str) -> String {
+    let text_of = |v: &Value| match v {
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items
+            .iter()
+            .map(|i| {
+                i.as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| i.to_string())
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        other => other.to_string(),
+    };
+    let label = fields.get(label).map(text_of).unwrap_or_default();
+    match fields.get(detail).map(text_of).filter(|d| !d.is_empty()) {
+        Some(detail) => format!("{label}: {detail}"),
+        None => label,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "category": { "type": "string", "enum": ["coding", "email", "other"] },
+                "keywords": { "type": "array", "items": { "type": "string" } },
+                "confidence": { "type": "number", "minimum": 0, "maximum": 1 }
+            },
+            "required": ["category"]
+        })
+    }
+
+    #[test]
+    fn renders_snake_case_frame_fields() {
+        let frame = lifelog_types::BrowserFrame {
+            url: "https://docs.rs".to_string(),
+            title: "tokio".to_string(),
+            visit_count: 3,
+            ..Default::default()
+        };
+        let fields = frame_fields(&frame);
+        assert_eq!(
+            render_template(
+                "{{ url }} | {{title}} | {{visit_count}} | {{missing}}",
+                &fields
+            ),
+            "https://docs.rs | tokio | 3 | "
+        );
+    }
+
+    #[test]
+    fn schemas_declaring_reserved_fields_fall_back_to_default() {
+        let prompt = |custom: &Value| {
+            let params = HashMap::from([("response_schema".to_string(), custom.to_string())]);
+            StructuredPrompt::from_params("t", &params, "", "", json!({ "properties": {} })).schema
+        };
+        assert_eq!(prompt(&schema())["properties"], json!({}));
+
+        let mut allowed = schema();
+        if let Some(properties) = allowed["properties"].as_object_mut() {
+            properties.remove("confidence");
+        }
+        assert_eq!(prompt(&allowed), allowed);
+    }
+
+    #[test]
+    fn conform_repairs_fenced_and_loose_output() {
+        let raw = "Sure!\n```json\n{\"category\": \"Coding\", \"keywords\": \"rust, async\", \"confidence\": \"1.7\", \"extra\": 1}\n```";
+        let fields = conform(raw, &schema()).unwrap_or_default();
+        assert_eq!(fields["category"], "coding");
+        assert_eq!(fields["keywords"], json!(["rust", "async"]));
+        assert_eq!(fields["confidence"], json!(1.0));
+        assert!(!fields.contains_key("extra"));
+
+        let unknown = conform(r#"{"category": "gaming"}"#, &schema()).unwrap_or_default();
+        assert_eq!(unknown["category"], "other");
+    }
+
+    #[test]
+    fn conform_rejects_missing_required_and_non_json() {
+        assert!(conform(r#"{"keywords": ["a"]}"#, &schema()).is_err());
+        assert!(conform("coding: editing rust", &schema()).is_err());
+    }
+
+    #[test]
+    fn summary_text_matches_legacy_format() {
+        let fields = conform(
+            r#"{"category": "coding", "keywords": ["rust", "tokio"]}"#,
+            &schema(),
+        )
+        .unwrap_or_default();
+        assert_eq!(
+            summary_text(&fields, "category", "keywords"),
+            "coding: rust, tokio"
+        );
+        assert_eq!(summary_text(&fields, "category", "description"), "coding");
+    }
+}
@@ -1,114 +1,369 @@
+use std::collections::BTreeSet;
+
+use crate::frames::FrameRow;
 use crate::postgres::PostgresPool;
-use chrono::Utc;
+use crate::transform::llm_provider::{ChatMessage, LlmClient};
+use chrono::{DateTime, Datelike, Duration, DurationRound, Utc};
 use lifelog_core::LifelogError;
-use serde_json::json;
-use utils::cas::FsCas;
+use serde_json::{json, Value as JsonValue};
+use utils::cas::sha256_hex;
 use uuid::Uuid;
 
-pub async fn generate_daily_summary(
+/// Hourly summaries are regenerated for hours that ended within this window, so late uploads
+/// still get folded in.
+const HOURLY_LOOKBACK: Duration = Duration::hours(24);
+/// Cap on raw frames handed to the model for a single hour.
+const MAX_HOURLY_INPUTS: usize = 40;
+const MAX_SNIPPET_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryLevel {
+    Hourly,
+    Daily,
+    Weekly,
+}
+
+impl SummaryLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SummaryLevel::Hourly => "hourly",
+            SummaryLevel::Daily => "daily",
+            SummaryLevel::Weekly => "weekly",
+        }
+    }
+
+    pub fn stream_id(&self) -> String {
+        format!("summary-{}", self.as_str())
+    }
+
+    /// Start of the period containing `t`. Days and weeks are UTC; weeks start on Monday.
+    pub fn period_start(&self, t: DateTime<Utc>) -> DateTime<Utc> {
+        let day_start = t
+            .date_naive()
+            .and_hms_opt(0, 0, 0)
+            .map(|d| d.and_utc())
+            .unwrap_or(t);
+        match self {
+            SummaryLevel::Hourly => t.duration_trunc(Duration::hours(1)).unwrap_or(t),
+            SummaryLevel::Daily => day_start,
+            SummaryLevel::Weekly => {
+                day_start - Duration::days(i64::from(t.weekday().num_days_from_monday()))
+            }
+        }
+    }
+
+    pub fn period_len(&self) -> Duration {
+        match self {
+            SummaryLevel::Hourly => Duration::hours(1),
+            SummaryLevel::Daily => Duration::days(1),
+            SummaryLevel::Weekly => Duration::weeks(1),
+        }
+    }
+
+    /// The level this one is rolled up from, if any.
+    fn child(&self) -> Option<SummaryLevel> {
+        match self {
+            SummaryLevel::Hourly => None,
+            SummaryLevel::Daily => Some(SummaryLevel::Hourly),
+            SummaryLevel::Weekly => Some(SummaryLevel::Daily),
+        }
+    }
+}
+
+/// Stable frame id for the summary of one period, so regeneration overwrites in place.
+pub fn summary_frame_id(level: SummaryLevel, period_start: DateTime<Utc>) -> Uuid {
+    crate::frames::derived_frame_id(&format!(
+        "summary:{}:{}",
+        level.as_str(),
+        period_start.timestamp()
+    ))
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SummaryRunSummary {
+    pub written: u64,
+    pub unchanged: u64,
+}
+
+/// One numbered item shown to the model. `cites` are the frame ids it stands for, and
+/// `fingerprint` changes whenever the content it shows does.
+#[derive(Debug, Clone)]
+struct SummaryInput {
+    id: Uuid,
+    text: String,
+    cites: Vec<String>,
+    fingerprint: String,
+}
+
+/// Extracts the `[n]` / `[n, m]` item references from model output, as zero-based indices
+/// below `count`.
+pub fn parse_citations(text: &str, count: usize) -> Vec<usize> {
+    let mut out = BTreeSet::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('[') {
+        let after = &rest[open + 1..];
+        let Some(close) = after.find(']') else {
+            break;
+        };
+        for part in after[..close].split(',') {
+            if let Ok(n) = part.trim().parse::<usize>() {
+                if n >= 1 && n <= count {
+                    out.insert(n - 1);
+                }
+            }
+        }
+        rest = &after[close..];
+    }
+    out.into_iter().collect()
+}
+
+fn input_fingerprint(inputs: &[SummaryInput]) -> String {
+    let mut parts: Vec<String> = inputs
+        .iter()
+        .map(|i| format!("{}:{}", i.id, i.fingerprint))
+        .collect();
+    parts.sort();
+    sha256_hex(parts.join("\n").as_bytes())
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.chars().count() > max_chars {
+        format!(
+            "{}...",
+            collapsed.chars().take(max_chars).collect::<String>()
+        )
+    } else {
+        collapsed
+    }
+}
+
+async fn load_raw_inputs(
     pool: &PostgresPool,
-    _cas: &FsCas,
-    http: &reqwest::Client,
-    endpoint: &str,
-    model: &str,
-) -> Result<(), LifelogError> {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<Vec<SummaryInput>, LifelogError> {
     let client = pool
         .get()
         .await
         .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
-
     let rows = client
         .query(
-            "SELECT modality, substring(payload::text, 1, 300) as snippet FROM frames
-             WHERE t_canonical > NOW() - INTERVAL '24 hours'
-             ORDER BY t_canonical DESC LIMIT 100",
-            &[],
+            "SELECT id, modality, t_canonical, COALESCE(
+                 NULLIF(payload->>'text', ''),
+                 NULLIF(concat_ws(' - ', payload->>'application', payload->>'window_title'), ''),
+                 NULLIF(concat_ws(' - ', payload->>'title', payload->>'url'), '')
+             ) AS snippet
+             FROM frames
+             WHERE modality IN ('Ocr', 'Transcription', 'WindowActivity', 'Browser')
+             AND t_canonical >= $1 AND t_canonical < $2
+             ORDER BY t_canonical ASC",
+            &[&start, &end],
         )
         .await
         .map_err(|e| LifelogError::Database(format!("summary query: {e}")))?;
 
-    let mut data_snippets = Vec::new();
-
+    let mut seen = BTreeSet::new();
+    let mut inputs = Vec::new();
     for row in rows {
-        let modality: String = row.get(0);
-        let snippet: String = row.get(1);
-
-        let truncated = if snippet.len() > 200 {
-            format!("{}...", &snippet[..200])
-        } else {
-            snippet
+        let snippet: Option<String> = row.get("snippet");
+        let Some(snippet) = snippet.map(|s| truncate(&s, MAX_SNIPPET_CHARS)) else {
+            continue;
         };
-
-        data_snippets.push(format!("[{}] {}", modality, truncated));
+        // Consecutive OCR/window frames repeat a lot; one copy is enough for the prompt.
+        if snippet.is_empty() || !seen.insert(snippet.clone()) {
+            continue;
+        }
+        let id: Uuid = row.get("id");
+        let modality: String = row.get("modality");
+        let t: DateTime<Utc> = row.get("t_canonical");
+        inputs.push(SummaryInput {
+            id,
+            text: format!("{} {}: {}", t.format("%H:%M"), modality, snippet),
+            cites: vec![id.to_string()],
+            // Edits and redactions change the snippet, so the summary is written again.
+            fingerprint: sha256_hex(snippet.as_bytes()),
+        });
     }
 
-    if data_snippets.is_empty() {
-        tracing::debug!("no data found for daily summary");
-        return Ok(());
+    // Spread the budget evenly over the hour rather than keeping only its first minutes.
+    if inputs.len() > MAX_HOURLY_INPUTS {
+        let step = inputs.len() as f64 / MAX_HOURLY_INPUTS as f64;
+        inputs = (0..MAX_HOURLY_INPUTS)
+            .filter_map(|i| inputs.get((i as f64 * step) as usize).cloned())
+            .collect();
     }
+    Ok(inputs)
+}
+
+async fn load_child_summaries(
+    pool: &PostgresPool,
+    level: SummaryLevel,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<Vec<SummaryInput>, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let rows = client
+        .query(
+            "SELECT id, t_canonical, payload FROM frames
+             WHERE modality = 'Summary' AND stream_id = $1
+             AND t_canonical >= $2 AND t_canonical < $3
+             ORDER BY t_canonical ASC",
+            &[&level.stream_id(), &start, &end],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("summary query: {e}")))?;
 
-    let data_str = data_snippets[..data_snippets.len().min(20)].join("\n");
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let payload: JsonValue = row.get("payload");
+            let t: DateTime<Utc> = row.get("t_canonical");
+            let label = match level {
+                SummaryLevel::Hourly => t.format("%H:00").to_string(),
+                _ => t.format("%a %Y-%m-%d").to_string(),
+            };
+            SummaryInput {
+                id: row.get("id"),
+                text: format!("{label}: {}", payload["text"].as_str().unwrap_or_default()),
+                cites: payload["cited_frame_ids"]
+                    .as_array()
+                    .map(|ids| {
+                        ids.iter()
+                            .filter_map(|v| v.as_str().map(str::to_string))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                fingerprint: payload["input_fingerprint"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            }
+        })
+        .collect())
+}
 
-    let prompt = format!(
-        "Summarize this person's day based on their computer activity:\n\n{}",
-        data_str
-    );
+async fn existing_fingerprint(
+    pool: &PostgresPool,
+    id: Uuid,
+) -> Result<Option<String>, LifelogError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| LifelogError::Database(format!("pool: {e}")))?;
+    let row = client
+        .query_opt(
+            "SELECT payload->>'input_fingerprint' FROM frames WHERE id = $1",
+            &[&id],
+        )
+        .await
+        .map_err(|e| LifelogError::Database(format!("summary lookup: {e}")))?;
+    Ok(row.and_then(|r| r.get::<_, Option<String>>(0)))
+}
 
-    let url = format!("{}/api/chat", endpoint.trim_end_matches('/'));
+fn build_prompt(level: SummaryLevel, inputs: &[SummaryInput]) -> String {
+    let items = inputs
+        .iter()
+        .enumerate()
+        .map(|(i, input)| format!("[{}] {}", i + 1, input.text))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let subject = match level {
+        SummaryLevel::Hourly => "this hour of a person's computer activity",
+        SummaryLevel::Daily => "this person's day from their hourly summaries",
+        SummaryLevel::Weekly => "this person's week from their daily summaries",
+    };
+    format!(
+        "Summarize {subject} in a short paragraph. After each statement, cite the numbered \
+         items it is based on, like [2] or [3, 5]. Only cite numbers from the list.\n\n{items}"
+    )
+}
 
-    let body = json!({
-        "model": model,
-        "messages": [
-            { "role": "user", "content": prompt }
-        ],
-        "stream": false
-    });
+/// Summarizes one period and stores it as a `Summary` frame.
+///
+/// Hourly summaries read OCR, transcripts, window activity and browser frames; daily and weekly
+/// summaries roll up the level below. The stored payload lists `cited_frame_ids` (raw frames the
+/// summary relies on) and `child_summary_ids`. Unless `force` is set, nothing is regenerated
+/// while the inputs' fingerprint matches the stored one. Returns whether a frame was written.
+pub async fn summarize_period(
+    pool: &PostgresPool,
+    http: &reqwest::Client,
+    llm: &LlmClient,
+    level: SummaryLevel,
+    period_start: DateTime<Utc>,
+    force: bool,
+) -> Result<bool, LifelogError> {
+    let start = level.period_start(period_start);
+    let end = start + level.period_len();
 
-    let resp = http
-        .post(&url)
-        .json(&body)
-        .timeout(std::time::Duration::from_secs(120))
-        .send()
-        .await
-        .map_err(|e| LifelogError::Database(format!("summary request failed: {e}")))?;
+    let inputs = match level.child() {
+        None => load_raw_inputs(pool, start, end).await?,
+        Some(child) => load_child_summaries(pool, child, start, end).await?,
+    };
+    if inputs.is_empty() {
+        return Ok(false);
+    }
 
-    if !resp.status().is_success() {
-        let status = resp.status();
-        let text: String = resp.text().await.unwrap_or_default();
-        return Err(LifelogError::Database(format!(
-            "ollama summary {status}: {text}"
-        )));
+    let id = summary_frame_id(level, start);
+    let fingerprint = input_fingerprint(&inputs);
+    if !force && existing_fingerprint(pool, id).await?.as_deref() == Some(fingerprint.as_str()) {
+        return Ok(false);
     }
 
-    let json: serde_json::Value = resp
-        .json()
+    let text = llm
+        .chat(http, &[ChatMessage::user(build_prompt(level, &inputs))])
         .await
-        .map_err(|e| LifelogError::Database(format!("summary json parse: {e}")))?;
-
-    let summary_text = json["message"]["content"]
-        .as_str()
-        .unwrap_or("unable to generate summary")
-        .to_string();
+        .map_err(|e| LifelogError::Database(format!("summary request failed: {e}")))?;
+    if text.is_empty() {
+        return Err(LifelogError::Database(
+            "summary response had no content".to_string(),
+        ));
+    }
 
-    let today = Utc::now().format("%Y-%m-%d").to_string();
+    // Without usable citations, attribute the summary to everything it was shown.
+    let mut cited = parse_citations(&text, inputs.len());
+    if cited.is_empty() {
+        cited = (0..inputs.len()).collect();
+    }
+    let cited_inputs: Vec<&SummaryInput> = cited.iter().filter_map(|&i| inputs.get(i)).collect();
+    let cited_frame_ids: BTreeSet<&str> = cited_inputs
+        .iter()
+        .flat_map(|i| i.cites.iter().map(String::as_str))
+        .collect();
+    let child_summary_ids: Vec<String> = if level.child().is_some() {
+        cited_inputs.iter().map(|i| i.id.to_string()).collect()
+    } else {
+        Vec::new()
+    };
 
-    let frame_row = crate::frames::FrameRow {
-        id: Uuid::new_v4(),
+    let frame_row = FrameRow {
+        id,
         collector_id: "system".to_string(),
-        stream_id: "daily-summary".to_string(),
+        stream_id: level.stream_id(),
         modality: "Summary".to_string(),
         t_device: None,
         t_ingest: Utc::now(),
-        t_canonical: Utc::now(),
-        t_end: None,
+        t_canonical: start,
+        t_end: Some(end),
         time_quality: "system".to_string(),
         blob_hash: None,
         blob_size: None,
         indexed: true,
         source_frame_id: None,
         payload: json!({
-            "text": summary_text,
-            "date": today,
+            "text": text,
+            "level": level.as_str(),
+            "date": start.format("%Y-%m-%d").to_string(),
+            "period_start": start.to_rfc3339(),
+            "period_end": end.to_rfc3339(),
+            "cited_frame_ids": cited_frame_ids,
+            "child_summary_ids": child_summary_ids,
+            "input_count": inputs.len(),
+            "input_fingerprint": fingerprint,
+            "model": llm.model(),
         }),
     };
 
@@ -116,7 +371,91 @@ pub async fn generate_daily_summary(
         .await
         .map_err(|e| LifelogError::Database(format!("summary upsert failed: {e}")))?;
 
-    tracing::info!("daily summary generated and stored");
+    Ok(true)
+}
+
+/// Brings the summary hierarchy up to date as of `now`.
+///
+/// Covers every hour that ended in the last day, the last two complete days and the last
+/// complete week. Periods whose inputs have not changed are left alone, so this is cheap to run
+/// often.
+pub async fn generate_summaries(
+    pool: &PostgresPool,
+    http: &reqwest::Client,
+    llm: &LlmClient,
+    now: DateTime<Utc>,
+) -> Result<SummaryRunSummary, LifelogError> {
+    let mut periods = Vec::new();
+    let current_hour = SummaryLevel::Hourly.period_start(now);
+    let mut hour = SummaryLevel::Hourly.period_start(now - HOURLY_LOOKBACK);
+    while hour < current_hour {
+        periods.push((SummaryLevel::Hourly, hour));
+        hour += SummaryLevel::Hourly.period_len();
+    }
+    let today = SummaryLevel::Daily.period_start(now);
+    periods.push((SummaryLevel::Daily, today - Duration::days(2)));
+    periods.push((SummaryLevel::Daily, today - Duration::days(1)));
+    let this_week = SummaryLevel::Weekly.period_start(now);
+    periods.push((SummaryLevel::Weekly, this_week - Duration::weeks(1)));
+
+    let mut summary = SummaryRunSummary::default();
+    for (level, start) in periods {
+        if summarize_period(pool, http, llm, level, start, false).await? {
+            summary.written += 1;
+            tracing::debug!(level = level.as_str(), period_start = %start, "summary written");
+        } else {
+            summary.unchanged += 1;
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn utc(y: i32, mo: u32, d: u32, h: u32, mi: u32, sec: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, sec)
+            .single()
+            .unwrap_or_default()
+    }
 
-    Ok(())
+    #[test]
+    fn period_starts_align_to_hour_day_and_monday() {
+        // Thursday 2024-03-14 15:42:10 UTC
+        let t = utc(2024, 3, 14, 15, 42, 10);
+        assert_eq!(
+            SummaryLevel::Hourly.period_start(t),
+            utc(2024, 3, 14, 15, 0, 0)
+        );
+        assert_eq!(
+            SummaryLevel::Daily.period_start(t),
+            utc(2024, 3, 14, 0, 0, 0)
+        );
+        assert_eq!(
+            SummaryLevel::Weekly.period_start(t),
+            utc(2024, 3, 11, 0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn summary_ids_are_stable_per_period() {
+        let t = utc(2024, 3, 14, 15, 0, 0);
+        assert_eq!(
+            summary_frame_id(SummaryLevel::Hourly, t),
+            summary_frame_id(SummaryLevel::Hourly, t)
+        );
+        assert_ne!(
+            summary_frame_id(SummaryLevel::Hourly, t),
+            summary_frame_id(SummaryLevel::Daily, t)
+        );
+    }
+
+    #[test]
+    fn parses_citations_within_range() {
+        let text = "Wrote code [1]. Reviewed PRs [2, 4] and chatted [9]. See [x].";
+        assert_eq!(parse_citations(text, 4), vec![0, 1, 3]);
+        assert!(parse_citations("no refs", 3).is_empty());
+    }
 }
@@ -0,0 +1,225 @@
+use async_trait::async_trait;
+use lifelog_core::{DataOrigin, DataOriginType, LifelogFrameKey, PrivacyLevel};
+use lifelog_types::LifelogData;
+use whatlang::Lang;
+
+use super::egress::EgressGuard;
+use super::llm::sanitize_llm_input;
+use super::llm_provider::{ChatMessage, LlmClient};
+use super::{GenericTransformOutput, TransformExecutor, TransformOutput, TransformPipelineError};
+use crate::search_language;
+
+/// Modality of the derived frames holding translated text.
+pub const TRANSLATION_MODALITY: &str = "Translation";
+
+const DEFAULT_SYSTEM_PROMPT: &str = "Translate the user's text into {{language}}. Keep line breaks, names, numbers, code and URLs as they are. Output only the translation.";
+
+/// Translates OCR and transcript text into the user's language with a local model.
+///
+/// Frames are only sent when their text is reliably detected as another language than the
+/// target; everything else is skipped. The model endpoint must be local regardless of the
+/// configured `privacy_level`. Params: `target_language` (ISO 639-1 or 639-3 code, default the
+/// search default language), `system_prompt` (`{{language}}` is replaced by the target's
+/// English name), plus the usual LLM provider params.
+pub struct TranslateExecutor {
+    id: String,
+    source: DataOrigin,
+    target: Lang,
+    system_prompt: String,
+    llm: LlmClient,
+    cache_fingerprint: Option<String>,
+}
+
+impl TranslateExecutor {
+    pub fn new(
+        id: String,
+        source: DataOrigin,
+        endpoint: String,
+        params: &std::collections::HashMap<String, String>,
+        egress: EgressGuard,
+    ) -> Self {
+        let configured = params.get("target_language").map(String::as_str);
+        let target = configured
+            .or_else(|| search_language::configured().default_language())
+            .and_then(search_language::parse_lang)
+            .unwrap_or_else(|| {
+                tracing::warn!(
+                    transform_id = %id,
+                    target_language = %configured.unwrap_or(""),
+                    "Unknown translation target language; using English"
+                );
+                Lang::Eng
+            });
+
+        let mut params = params.clone();
+        params.insert(
+            "privacy_level".to_string(),
+            PrivacyLevel::LocalOnly.to_string(),
+        );
+        let system_prompt = params
+            .get("system_prompt")
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_SYSTEM_PROMPT)
+            .replace("{{language}}", target.eng_name());
+
+        Self {
+            cache_fingerprint: super::cache::fingerprint("translate", &endpoint, &params),
+            llm: LlmClient::from_params(&id, endpoint, &params, egress),
+            id,
+            source,
+            target,
+            system_prompt,
+        }
+    }
+
+    /// Language of `text` when it should be translated: reliably detected and not the target.
+    fn needs_translation(&self, text: &str) -> Option<Lang> {
+        search_language::detect_lang(text).filter(|lang| *lang != self.target)
+    }
+}
+
+#[async_trait]
+impl TransformExecutor for TranslateExecutor {
+    fn id(&self) -> &str {
+        &self.id
+    }
+    fn source_modality(&self) -> &str {
+        &self.source.modality_name
+    }
+    fn destination_modality(&self) -> &str {
+        TRANSLATION_MODALITY
+    }
+    fn priority(&self) -> u8 {
+        3
+    }
+    fn is_async(&self) -> bool {
+        true
+    }
+
+    fn privacy_level(&self) -> PrivacyLevel {
+        PrivacyLevel::LocalOnly
+    }
+
+    fn cache_fingerprint(&self) -> Option<&str> {
+        self.cache_fingerprint.as_deref()
+    }
+
+    fn matches_origin(&self, key_origin: &DataOrigin) -> bool {
+        let src = self.source();
+        if src.modality_name != key_origin.modality_name {
+            return false;
+        }
+        match &src.origin {
+            DataOriginType::DeviceId(id) if id == "*" => true,
+            _ => src == *key_origin,
+        }
+    }
+
+    fn source(&self) -> DataOrigin {
+        self.source.clone()
+    }
+
+    fn destination(&self) -> DataOrigin {
+        DataOrigin::new(
+            DataOriginType::DataOrigin(Box::new(self.source.clone())),
+            TRANSLATION_MODALITY.to_string(),
+        )
+    }
+
+    async fn execute(
+        &self,
+        http: &reqwest::Client,
+        data: &LifelogData,
+        key: &LifelogFrameKey,
+    ) -> Result<TransformOutput, TransformPipelineError> {
+        use lifelog_types::lifelog_data::Payload;
+
+        let payload = data
+            .payload
+            .as_ref()
+            .ok_or_else(|| TransformPipelineError::DataError("missing payload".to_string()))?;
+
+        let (text, source_modality) = match payload {
+            Payload::Ocrframe(f) => (&f.text, "Ocr"),
+            Payload::Transcriptionframe(f) => (&f.text, "Transcription"),
+            _ => {
+                return Err(TransformPipelineError::UnsupportedModality {
+                    transform: self.id.clone(),
+                    modality: format!("{:?}", payload),
+                });
+            }
+        };
+
+        let Some(source_language) = self.needs_translation(text) else {
+            return Ok(TransformOutput::Skipped);
+        };
+
+        let translated = self
+            .llm
+            .chat(
+                http,
+                &[
+                    ChatMessage::system(self.system_prompt.as_str()),
+                    ChatMessage::user(sanitize_llm_input(text, &self.id)),
+                ],
+            )
+            .await?;
+        if translated.trim().is_empty() {
+            return Err(TransformPipelineError::DataError(
+                "model returned an empty translation".to_string(),
+            ));
+        }
+
+        Ok(TransformOutput::Generic(GenericTransformOutput {
+            source_uuid: key.uuid.to_string(),
+            modality: TRANSLATION_MODALITY.to_string(),
+            payload: serde_json::json!({
+                "text": translated.trim(),
+                "source_language": search_language::lang_code(source_language),
+                "target_language": search_language::lang_code(self.target),
+                "source_modality": source_modality,
+                "model": self.llm.model(),
+            }),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn executor(params: &[(&str, &str)]) -> TranslateExecutor {
+        let mut params: HashMap<String, String> = params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        params.insert("provider".to_string(), "mock".to_string());
+        TranslateExecutor::new(
+            "translate".to_string(),
+            DataOrigin::new(DataOriginType::DeviceId("*".to_string()), "Ocr".to_string()),
+            "http://localhost:11434".to_string(),
+            &params,
+            EgressGuard::new(Vec::new()),
+        )
+    }
+
+    #[test]
+    fn only_foreign_text_is_translated() {
+        let translate = executor(&[("target_language", "en"), ("privacy_level", "standard")]);
+        let german = "Die Besprechung wurde auf Donnerstag verschoben, weil der Kunde noch \
+                      keine Rückmeldung zu den Unterlagen gegeben hat.";
+        let english = "The meeting was moved to Thursday because the customer has not yet \
+                       replied about the documents.";
+        assert_eq!(translate.needs_translation(german), Some(Lang::Deu));
+        assert_eq!(translate.needs_translation(english), None);
+        assert_eq!(translate.needs_translation("OK"), None);
+        assert_eq!(translate.privacy_level(), PrivacyLevel::LocalOnly);
+        assert_eq!(translate.llm.privacy_level(), PrivacyLevel::LocalOnly);
+        assert!(translate.system_prompt.contains("into English."));
+
+        let to_german = executor(&[("target_language", "deu")]);
+        assert_eq!(to_german.needs_translation(german), None);
+        assert_eq!(to_german.needs_translation(english), Some(Lang::Eng));
+    }
+}
@@ -5,7 +5,7 @@ use lifelog_core::LifelogError;
 use crate::postgres::PostgresPool;
 
 #[async_trait]
-pub trait WatermarkStore: Send + Sync {
+pub trait WatermarkStore: Send + Sync + std::fmt::Debug {
     async fn get(&self, transform_id: &str, origin: &str) -> Result<DateTime<Utc>, LifelogError>;
     async fn set(
         &self,
@@ -15,6 +15,7 @@ pub trait WatermarkStore: Send + Sync {
     ) -> Result<(), LifelogError>;
 }
 
+#[derive(Debug)]
 pub struct PostgresWatermarkStore {
     pool: PostgresPool,
 }
@@ -8,12 +8,15 @@ use tokio::sync::Mutex;
 use tokio::task::JoinSet;
 use utils::cas::FsCas;
 
-use crate::postgres::PostgresPool;
+use crate::storage::FrameStore;
 
+use super::cache::{self, CachedOutput};
 use super::dag::TransformDag;
+use super::dead_letter;
+use super::state::TransformStateStore;
 use super::watermark::WatermarkStore;
-use super::writer::{extract_source_timestamps, write_transform_output};
-use super::TransformExecutor;
+use super::writer::{extract_source_timestamps, write_transform_output, SourceTimestamps};
+use super::{TransformExecutor, TransformOutput, TransformPipelineError};
 
 const CIRCUIT_BREAKER_THRESHOLD: u32 = 5;
 const CIRCUIT_BREAKER_COOLDOWN_SECS: i64 = 300;
@@ -41,7 +44,8 @@ pub struct TransformStatus {
 pub struct PipelineWorker {
     dag: Arc<TransformDag>,
     watermarks: Arc<dyn WatermarkStore>,
-    postgres_pool: PostgresPool,
+    store: Arc<dyn FrameStore>,
+    state: Arc<dyn TransformStateStore>,
     cas: FsCas,
     http_client: reqwest::Client,
     batch_size: usize,
@@ -53,7 +57,8 @@ impl PipelineWorker {
     pub fn new(
         dag: Arc<TransformDag>,
         watermarks: Arc<dyn WatermarkStore>,
-        postgres_pool: PostgresPool,
+        store: Arc<dyn FrameStore>,
+        state: Arc<dyn TransformStateStore>,
         cas: FsCas,
         http_client: reqwest::Client,
         batch_size: usize,
@@ -61,7 +66,8 @@ impl PipelineWorker {
         Self {
             dag,
             watermarks,
-            postgres_pool,
+            store,
+            state,
             cas,
             http_client,
             batch_size,
@@ -177,7 +183,7 @@ impl PipelineWorker {
             return Ok(());
         }
 
-        let available_origins = crate::frames::get_origins(&self.postgres_pool).await?;
+        let available_origins = self.store.origins().await?;
 
         let mut join_set = JoinSet::new();
 
@@ -211,9 +217,9 @@ impl PipelineWorker {
             for downstream in self.dag.transforms_for_modality(modality) {
                 let worker = Arc::clone(self);
                 let downstream = Arc::clone(downstream);
-                let pool = self.postgres_pool.clone();
+                let store = Arc::clone(&self.store);
                 downstream_set.spawn(async move {
-                    let origins = match crate::frames::get_origins(&pool).await {
+                    let origins = match store.origins().await {
                         Ok(o) => o,
                         Err(e) => {
                             tracing::error!(error = %e, "Failed to get origins for downstream");
@@ -268,9 +274,7 @@ impl PipelineWorker {
         let targets = resolve_targets(&transform.source(), available_origins);
 
         for target_origin in &targets {
-            match crate::frames::count_keys_after(&self.postgres_pool, target_origin, watermark)
-                .await
-            {
+            match self.store.count_keys_after(target_origin, watermark).await {
                 Ok(backlog) => {
                     if backlog > 10_000 {
                         tracing::error!(
@@ -305,19 +309,18 @@ impl PipelineWorker {
             }
         }
 
+        self.retry_dead_letters(transform).await;
+
         let mut any_work = false;
 
-        let same_modality = transform.source_modality() == transform.destination_modality();
+        let same_modality = !transform.annotates_in_place()
+            && transform.source_modality() == transform.destination_modality();
 
         for target_origin in targets {
-            let keys = match crate::frames::get_keys_after_filtered(
-                &self.postgres_pool,
-                &target_origin,
-                watermark,
-                self.batch_size,
-                same_modality,
-            )
-            .await
+            let keys = match self
+                .store
+                .get_keys_after(&target_origin, watermark, self.batch_size, same_modality)
+                .await
             {
                 Ok(k) => k,
                 Err(e) => {
@@ -372,88 +375,311 @@ impl PipelineWorker {
     ) -> Option<DateTime<Utc>> {
         let mut last_ts: Option<DateTime<Utc>> = None;
         let mut skip_count: u64 = 0;
+        let (mut cache_hits, mut cache_misses) = (0u64, 0u64);
 
         for key in keys {
-            let data = match crate::frames::get_by_id(
-                &self.postgres_pool,
-                &self.cas,
-                uuid::Uuid::from_bytes(key.uuid.into_bytes()),
-            )
-            .await
-            {
-                Ok(d) => d,
-                Err(e) => {
-                    tracing::error!(uuid = %key.uuid, error = %e, "Failed to load data for transform; skipping frame");
+            let outcome = self.process_frame(transform, key).await;
+            if let Some(ts) = outcome.source_ts {
+                last_ts = Some(last_ts.map_or(ts, |prev| prev.max(ts)));
+            }
+            match outcome.cache_hit {
+                Some(true) => cache_hits += 1,
+                Some(false) => cache_misses += 1,
+                None => {}
+            }
+            match outcome.result {
+                Ok(Some(ts)) => {
+                    last_ts = Some(last_ts.map_or(ts, |prev| prev.max(ts)));
+                }
+                Ok(None) => {}
+                Err(error) => {
                     skip_count += 1;
-                    continue;
+                    self.dead_letter(transform, key, &error).await;
                 }
-            };
+            }
+        }
 
-            if !transform.matches_origin(&key.origin) {
-                continue;
+        if skip_count > 0 {
+            tracing::warn!(
+                transform = %transform.id(),
+                skipped_frames = skip_count,
+                "Batch completed with skipped frames"
+            );
+        }
+
+        if cache_hits + cache_misses > 0 {
+            tracing::debug!(
+                transform = %transform.id(),
+                cache_hits,
+                cache_misses,
+                hit_rate = cache_hits as f64 / (cache_hits + cache_misses) as f64,
+                "Transform cache usage"
+            );
+            if let Err(e) = self
+                .state
+                .record_cache_stats(transform.id(), cache_hits, cache_misses)
+                .await
+            {
+                tracing::warn!(transform = %transform.id(), error = %e, "Failed to record cache stats");
             }
+        }
 
-            let source_timestamps = extract_source_timestamps(&data);
-            if let Some(ref t) = source_timestamps.t_canonical {
-                let t_utc = DateTime::from_timestamp(t.seconds, t.nanos as u32).unwrap_or_default();
-                last_ts = Some(last_ts.map_or(t_utc, |prev| prev.max(t_utc)));
+        last_ts
+    }
+
+    /// Re-runs dead-lettered frames whose backoff has elapsed. Watermarks are not touched.
+    async fn retry_dead_letters(&self, transform: &Arc<dyn TransformExecutor>) {
+        let id = transform.id();
+        let due = match self
+            .state
+            .due_dead_letters(id, Utc::now(), self.batch_size)
+            .await
+        {
+            Ok(d) => d,
+            Err(e) => {
+                tracing::error!(transform_id = %id, error = %e, "Failed to load dead letters");
+                return;
             }
+        };
 
-            let output = match transform.execute(&self.http_client, &data, key).await {
+        for entry in due {
+            let origin = match DataOrigin::tryfrom_string(entry.origin.clone()) {
                 Ok(o) => o,
                 Err(e) => {
-                    tracing::error!(
-                        uuid = %key.uuid,
-                        transform = %transform.id(),
-                        error = %e,
-                        "Transform execution failed; skipping frame"
-                    );
-                    skip_count += 1;
+                    tracing::warn!(transform_id = %id, origin = %entry.origin, error = %e, "Dead letter has invalid origin");
                     continue;
                 }
             };
+            let key = LifelogFrameKey {
+                uuid: lifelog_core::uuid::Uuid::from_bytes(entry.frame_id.into_bytes()),
+                origin,
+            };
+            match self.process_frame(transform, &key).await.result {
+                Ok(_) => {
+                    tracing::info!(transform_id = %id, uuid = %key.uuid, attempts = entry.attempts, "Dead-lettered frame succeeded on retry");
+                    if let Err(e) = self.state.resolve_dead_letter(id, entry.frame_id).await {
+                        tracing::error!(transform_id = %id, error = %e, "Failed to resolve dead letter");
+                    }
+                }
+                Err(error) => self.dead_letter(transform, &key, &error).await,
+            }
+        }
+    }
 
-            let destination = transform.destination();
-
-            match write_transform_output(
-                &self.postgres_pool,
-                output,
-                &destination,
-                &source_timestamps,
+    async fn dead_letter(
+        &self,
+        transform: &Arc<dyn TransformExecutor>,
+        key: &LifelogFrameKey,
+        error: &str,
+    ) {
+        let frame_id = uuid::Uuid::from_bytes(key.uuid.into_bytes());
+        match self
+            .state
+            .record_failure(
+                transform.id(),
+                frame_id,
+                &key.origin.to_string(),
+                error,
+                Utc::now(),
             )
             .await
+        {
+            Ok(attempts) if attempts >= dead_letter::MAX_ATTEMPTS => {
+                tracing::warn!(
+                    uuid = %key.uuid,
+                    transform = %transform.id(),
+                    attempts = attempts,
+                    "Frame exhausted automatic retries; left in dead-letter queue"
+                );
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!(uuid = %key.uuid, transform = %transform.id(), error = %e, "Failed to record dead letter");
+            }
+        }
+    }
+
+    /// Whether the frame is stored sealed, or is sealed once the backlog reaches it.
+    async fn is_sealed_source(
+        &self,
+        key: &LifelogFrameKey,
+        frame_id: uuid::Uuid,
+    ) -> Result<bool, LifelogError> {
+        if crate::payload_crypto::configured().is_none() {
+            return Ok(false);
+        }
+        if crate::payload_crypto::is_sensitive(&key.origin.modality_name) {
+            return Ok(true);
+        }
+        self.store.is_sealed(frame_id).await
+    }
+
+    /// Runs the transform, or replays its cached result for identical input content.
+    /// Cache errors are logged and fall through to a normal execution. Results from sealed
+    /// sources are neither looked up nor stored, since the cache keeps them in the clear.
+    async fn execute_cached(
+        &self,
+        transform: &Arc<dyn TransformExecutor>,
+        data: &lifelog_types::LifelogData,
+        key: &LifelogFrameKey,
+        source_timestamps: &SourceTimestamps,
+        sealed: bool,
+    ) -> Result<(TransformOutput, Option<bool>), TransformPipelineError> {
+        let cache_key = transform
+            .cache_fingerprint()
+            .filter(|_| !sealed)
+            .zip(cache::input_hash(data));
+        let Some((fingerprint, input_hash)) = cache_key else {
+            let output = transform.execute(&self.http_client, data, key).await?;
+            return Ok((output, None));
+        };
+
+        match self
+            .state
+            .cache_lookup(transform.id(), fingerprint, &input_hash)
+            .await
+        {
+            Ok(Some(cached)) => {
+                return Ok((cached.into_output(key, source_timestamps), Some(true)));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::warn!(transform = %transform.id(), error = %e, "Transform cache lookup failed");
+            }
+        }
+
+        let output = transform.execute(&self.http_client, data, key).await?;
+        if let Some(cached) = CachedOutput::from_output(&output) {
+            if let Err(e) = self
+                .state
+                .cache_store(transform.id(), fingerprint, &input_hash, &cached)
+                .await
             {
-                Ok(Some(ts)) => {
-                    last_ts = Some(last_ts.map_or(ts, |prev| prev.max(ts)));
+                tracing::warn!(transform = %transform.id(), error = %e, "Transform cache store failed");
+            }
+        }
+        Ok((output, Some(false)))
+    }
+
+    async fn process_frame(
+        &self,
+        transform: &Arc<dyn TransformExecutor>,
+        key: &LifelogFrameKey,
+    ) -> FrameOutcome {
+        let frame_id = uuid::Uuid::from_bytes(key.uuid.into_bytes());
+        let data = match self.store.get_by_ids(&self.cas, &[frame_id]).await {
+            Ok(mut rows) if !rows.is_empty() => rows.swap_remove(0),
+            Ok(_) => {
+                tracing::error!(uuid = %key.uuid, "Frame not found for transform; skipping frame");
+                return FrameOutcome::failed(
+                    None,
+                    format!("load failed: frame not found: {frame_id}"),
+                );
+            }
+            Err(e) => {
+                tracing::error!(uuid = %key.uuid, error = %e, "Failed to load data for transform; skipping frame");
+                return FrameOutcome::failed(None, format!("load failed: {e}"));
+            }
+        };
+
+        if !transform.matches_origin(&key.origin) {
+            return FrameOutcome {
+                source_ts: None,
+                cache_hit: None,
+                result: Ok(None),
+            };
+        }
+
+        let source_timestamps = extract_source_timestamps(&data);
+        let source_ts = source_timestamps
+            .t_canonical
+            .as_ref()
+            .map(|t| DateTime::from_timestamp(t.seconds, t.nanos as u32).unwrap_or_default());
+
+        let sealed = match self.is_sealed_source(key, frame_id).await {
+            Ok(sealed) => sealed,
+            Err(e) => {
+                tracing::error!(uuid = %key.uuid, error = %e, "Failed to check whether frame is sealed; skipping frame");
+                return FrameOutcome::failed(source_ts, format!("load failed: {e}"));
+            }
+        };
+
+        let (output, cache_hit) = match self
+            .execute_cached(transform, &data, key, &source_timestamps, sealed)
+            .await
+        {
+            // The entity index stores the extracted values in the clear.
+            Ok((TransformOutput::Entities(_), cache_hit)) if sealed => {
+                (TransformOutput::Skipped, cache_hit)
+            }
+            Ok(o) => o,
+            Err(e) => {
+                tracing::error!(
+                    uuid = %key.uuid,
+                    transform = %transform.id(),
+                    error = %e,
+                    "Transform execution failed; skipping frame"
+                );
+                return FrameOutcome::failed(source_ts, e.to_string());
+            }
+        };
+
+        let destination = transform.destination();
+        let scan_pending = self.dag.search_gated(&destination);
+
+        match write_transform_output(
+            self.store.as_ref(),
+            self.state.as_ref(),
+            output,
+            &destination,
+            scan_pending,
+            &source_timestamps,
+        )
+        .await
+        {
+            Ok(ts) => {
+                if ts.is_some() {
                     tracing::debug!(
                         uuid = %key.uuid,
                         transform = %transform.id(),
                         "Transform output written"
                     );
                 }
-                Ok(None) => {}
-                Err(e) => {
-                    tracing::error!(
-                        uuid = %key.uuid,
-                        transform = %transform.id(),
-                        error = %e,
-                        "Failed to write transform output; skipping frame"
-                    );
-                    skip_count += 1;
-                    continue;
+                FrameOutcome {
+                    source_ts,
+                    cache_hit,
+                    result: Ok(ts),
                 }
             }
+            Err(e) => {
+                tracing::error!(
+                    uuid = %key.uuid,
+                    transform = %transform.id(),
+                    error = %e,
+                    "Failed to write transform output; skipping frame"
+                );
+                FrameOutcome::failed(source_ts, format!("write failed: {e}"))
+            }
         }
+    }
+}
 
-        if skip_count > 0 {
-            tracing::warn!(
-                transform = %transform.id(),
-                skipped_frames = skip_count,
-                "Batch completed with skipped frames"
-            );
-        }
+/// Result of running a transform on one frame. `source_ts` is set once the frame was loaded,
+/// so the watermark can move past frames that then fail.
+/// `cache_hit` is `None` when the transform does not use the result cache.
+struct FrameOutcome {
+    source_ts: Option<DateTime<Utc>>,
+    cache_hit: Option<bool>,
+    result: Result<Option<DateTime<Utc>>, String>,
+}
 
-        last_ts
+impl FrameOutcome {
+    fn failed(source_ts: Option<DateTime<Utc>>, error: String) -> Self {
+        Self {
+            source_ts,
+            cache_hit: None,
+            result: Err(error),
+        }
     }
 }
 
@@ -2,8 +2,10 @@ use chrono::{DateTime, Utc};
 use lifelog_core::{DataOrigin, LifelogError};
 
 use crate::frames;
-use crate::postgres::PostgresPool;
+use crate::storage::FrameStore;
 
+use super::secrets::{PrivacyScanOutput, SCAN_PENDING_FIELD};
+use super::state::{PrivacyFindings, TransformStateStore};
 use super::{GenericTransformOutput, TransformOutput};
 
 pub struct SourceTimestamps {
@@ -63,6 +65,21 @@ pub fn extract_source_timestamps(data: &lifelog_types::LifelogData) -> SourceTim
             t_end: f.t_end.or(f.t_canonical).or(f.timestamp),
             time_quality: tq_str(f.time_quality),
         },
+        Payload::Clipboardframe(f) => SourceTimestamps {
+            t_canonical: f.t_canonical.or(f.timestamp),
+            t_end: f.t_end.or(f.t_canonical).or(f.timestamp),
+            time_quality: tq_str(f.time_quality),
+        },
+        Payload::Shellhistoryframe(f) => SourceTimestamps {
+            t_canonical: f.t_canonical.or(f.timestamp),
+            t_end: f.t_end.or(f.t_canonical).or(f.timestamp),
+            time_quality: tq_str(f.time_quality),
+        },
+        Payload::Windowactivityframe(f) => SourceTimestamps {
+            t_canonical: f.t_canonical.or(f.timestamp),
+            t_end: f.t_end.or(f.t_canonical).or(f.timestamp),
+            time_quality: tq_str(f.time_quality),
+        },
         _ => SourceTimestamps {
             t_canonical: None,
             t_end: None,
@@ -79,9 +96,11 @@ fn pb_to_dt(ts: Option<pbjson_types::Timestamp>) -> DateTime<Utc> {
 }
 
 pub async fn write_transform_output(
-    pool: &PostgresPool,
+    store: &dyn FrameStore,
+    state: &dyn TransformStateStore,
     output: TransformOutput,
     destination: &DataOrigin,
+    scan_pending: bool,
     source_timestamps: &SourceTimestamps,
 ) -> Result<Option<DateTime<Utc>>, LifelogError> {
     let collector_id = destination.collector_id().unwrap_or("unknown").to_string();
@@ -99,8 +118,9 @@ pub async fn write_transform_output(
             row.t_end = Some(t_end);
             row.time_quality = source_timestamps.time_quality.clone();
             row.t_ingest = Utc::now();
+            mark_scan_pending(&mut row, scan_pending);
 
-            frames::insert_transform_output(pool, &row).await?;
+            store.insert_transform_output(&row).await?;
 
             extract_timestamp(source_timestamps.t_canonical)
         }
@@ -113,8 +133,39 @@ pub async fn write_transform_output(
             row.t_end = Some(pb_to_dt(frame.t_end.or(ts)));
             row.time_quality = source_timestamps.time_quality.clone();
             row.t_ingest = Utc::now();
+            mark_scan_pending(&mut row, scan_pending);
 
-            frames::insert_transform_output(pool, &row).await?;
+            store.insert_transform_output(&row).await?;
+
+            extract_timestamp(ts)
+        }
+        TransformOutput::Structured(structured) => {
+            let frame = structured.frame;
+            let ts = frame.t_canonical.or(frame.timestamp);
+
+            let mut row = frames::from_transcription(&collector_id, &stream_id, &frame);
+            row.id = uuid::Uuid::new_v4();
+            row.t_canonical = pb_to_dt(ts);
+            row.t_end = Some(pb_to_dt(frame.t_end.or(ts)));
+            row.time_quality = source_timestamps.time_quality.clone();
+            row.t_ingest = Utc::now();
+            if let serde_json::Value::Object(payload) = &mut row.payload {
+                for (field, value) in structured.fields {
+                    // Response schemas may not declare these; see `structured::RESERVED_FIELDS`.
+                    if payload.contains_key(&field) {
+                        tracing::warn!(
+                            field = %field,
+                            modality = %destination.modality_name,
+                            "Structured field collides with a payload field; dropped"
+                        );
+                        continue;
+                    }
+                    payload.insert(field, value);
+                }
+            }
+            mark_scan_pending(&mut row, scan_pending);
+
+            store.insert_transform_output(&row).await?;
 
             extract_timestamp(ts)
         }
@@ -123,23 +174,113 @@ pub async fn write_transform_output(
             Ok(None)
         }
         TransformOutput::Generic(generic) => {
-            write_generic_output(pool, generic, &collector_id, &stream_id, source_timestamps).await
+            write_generic_output(
+                store,
+                generic,
+                &collector_id,
+                &stream_id,
+                scan_pending,
+                source_timestamps,
+            )
+            .await
+        }
+        TransformOutput::PrivacyScan(scan) => {
+            write_privacy_scan(state, scan).await?;
+            extract_timestamp(source_timestamps.t_canonical)
         }
+        TransformOutput::Entities(entities) => {
+            let frame_id = uuid::Uuid::parse_str(&entities.source_uuid)
+                .map_err(|e| LifelogError::Database(format!("invalid entity frame id: {e}")))?;
+            state
+                .write_entity_mentions(frame_id, &entities, pb_to_dt(source_timestamps.t_canonical))
+                .await?;
+            extract_timestamp(source_timestamps.t_canonical)
+        }
+        TransformOutput::Skipped => extract_timestamp(source_timestamps.t_canonical),
+    }
+}
+
+/// Payload fields that repeat scanned text piecewise (OCR word and line boxes). They are dropped
+/// rather than redacted when a frame is redacted, since a finding can span several boxes.
+const DERIVED_TEXT_FIELDS: &[&str] = &["words", "lines"];
+
+/// Tags the scanned frame with its findings and records an audit entry.
+///
+/// Unredacted frames with findings stay out of `search_doc`; redacted ones become searchable
+/// since the matched text is gone. Frames without findings only lose their
+/// [`SCAN_PENDING_FIELD`] marker.
+async fn write_privacy_scan(
+    state: &dyn TransformStateStore,
+    scan: PrivacyScanOutput,
+) -> Result<(), LifelogError> {
+    let frame_id = uuid::Uuid::parse_str(&scan.source_uuid)
+        .map_err(|e| LifelogError::Database(format!("invalid scanned frame id: {e}")))?;
+    if scan.findings.is_empty() {
+        return state.clear_scan_pending(frame_id).await;
+    }
+
+    let redacted = scan.redacted_fields.is_some();
+    let mut patch = serde_json::Map::new();
+    for (field, value) in scan.redacted_fields.unwrap_or_default() {
+        patch.insert(field, serde_json::Value::String(value));
+    }
+    patch.insert(
+        "pii_findings".to_string(),
+        serde_json::to_value(&scan.findings)?,
+    );
+    patch.insert(
+        "pii_redacted".to_string(),
+        serde_json::Value::Bool(redacted),
+    );
+
+    let mut kinds: Vec<String> = scan
+        .findings
+        .iter()
+        .map(|f| f.kind.as_str().to_string())
+        .collect();
+    kinds.sort_unstable();
+    kinds.dedup();
+    let action = if redacted { "redacted" } else { "flagged" };
+    let mut dropped: Vec<&'static str> = vec![SCAN_PENDING_FIELD];
+    if redacted {
+        dropped.extend(DERIVED_TEXT_FIELDS);
     }
+
+    state
+        .record_privacy_findings(PrivacyFindings {
+            frame_id,
+            transform_id: scan.transform_id.clone(),
+            patch,
+            dropped,
+            action,
+            kinds,
+            count: scan.findings.len() as i32,
+        })
+        .await?;
+
+    tracing::info!(
+        frame_id = %frame_id,
+        transform_id = %scan.transform_id,
+        findings = scan.findings.len(),
+        action,
+        "Privacy scan findings recorded"
+    );
+    Ok(())
 }
 
 async fn write_generic_output(
-    pool: &PostgresPool,
+    store: &dyn FrameStore,
     generic: GenericTransformOutput,
     collector_id: &str,
     stream_id: &str,
+    scan_pending: bool,
     source_timestamps: &SourceTimestamps,
 ) -> Result<Option<DateTime<Utc>>, LifelogError> {
     let t_canonical = pb_to_dt(source_timestamps.t_canonical);
     let t_end = pb_to_dt(source_timestamps.t_end);
     let source_uuid = uuid::Uuid::parse_str(&generic.source_uuid).ok();
 
-    let row = frames::FrameRow {
+    let mut row = frames::FrameRow {
         id: uuid::Uuid::new_v4(),
         collector_id: collector_id.to_string(),
         stream_id: stream_id.to_string(),
@@ -155,12 +296,23 @@ async fn write_generic_output(
         source_frame_id: source_uuid,
         payload: generic.payload,
     };
+    mark_scan_pending(&mut row, scan_pending);
 
-    frames::insert_transform_output(pool, &row).await?;
+    store.insert_transform_output(&row).await?;
 
     extract_timestamp(source_timestamps.t_canonical)
 }
 
+/// Holds a new frame out of full-text search until a secret scan has run on it.
+fn mark_scan_pending(row: &mut frames::FrameRow, pending: bool) {
+    if let (true, serde_json::Value::Object(payload)) = (pending, &mut row.payload) {
+        payload.insert(
+            SCAN_PENDING_FIELD.to_string(),
+            serde_json::Value::Bool(true),
+        );
+    }
+}
+
 fn extract_timestamp(
     ts: Option<::pbjson_types::Timestamp>,
 ) -> Result<Option<DateTime<Utc>>, LifelogError> {
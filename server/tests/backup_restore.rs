@@ -0,0 +1,101 @@
+#![allow(clippy::expect_used, clippy::panic, clippy::unwrap_used)]
+
+mod harness;
+
+use chrono::Utc;
+use harness::device_client::DeviceClient;
+use harness::TestContext;
+use lifelog_server::backup::{backup, restore, BackupRepo};
+use lifelog_types::{Chunk, StreamIdentity, WeatherFrame};
+use prost::Message;
+
+fn weather_chunk(stream: &StreamIdentity, offset: u64) -> Chunk {
+    let ts = lifelog_types::to_pb_ts(Utc::now());
+    let frame = WeatherFrame {
+        uuid: lifelog_core::Uuid::new_v4().to_string(),
+        timestamp: ts,
+        temperature: 21.5,
+        conditions: "clear".to_string(),
+        t_device: ts,
+        t_canonical: ts,
+        t_end: ts,
+        ..Default::default()
+    };
+    let data = frame.encode_to_vec();
+    Chunk {
+        stream: Some(stream.clone()),
+        offset,
+        hash: utils::cas::sha256_hex(&data),
+        data,
+    }
+}
+
+#[tokio::test]
+#[ignore = "integration test: requires PostgreSQL"]
+async fn restored_server_lets_a_collector_resume() {
+    let source = TestContext::new().await;
+    let target = TestContext::new().await;
+    let stream = StreamIdentity {
+        collector_id: "backup-device".to_string(),
+        stream_id: "weather".to_string(),
+        session_id: 7,
+    };
+
+    let mut device = DeviceClient::new(stream.collector_id.clone(), source.client());
+    let first = weather_chunk(&stream, 0);
+    let resume_at = first.data.len() as u64;
+    let chunk_hash = first.hash.clone();
+    let ack = device.upload_chunks(vec![first]).await.expect("upload");
+    assert_eq!(ack.acked_offset, resume_at);
+
+    let repo_dir = tempfile::tempdir().expect("repo dir");
+    let repo = BackupRepo::open(repo_dir.path(), None).expect("open repo");
+    let source_pool = lifelog_server::postgres::connect_pool(&source.pg_url, 2)
+        .await
+        .expect("source pool");
+    let summary = backup(&source_pool, &source.cas(), &repo, Utc::now())
+        .await
+        .expect("backup");
+    let chunks = summary
+        .snapshot
+        .tables
+        .iter()
+        .find(|dump| dump.table == "upload_chunks")
+        .expect("upload_chunks dump");
+    assert_eq!(chunks.rows, 1);
+
+    let target_pool = lifelog_server::postgres::connect_pool(&target.pg_url, 2)
+        .await
+        .expect("target pool");
+    restore(&target_pool, &target.cas(), &repo, &summary.snapshot)
+        .await
+        .expect("restore");
+    assert!(
+        target.cas().get(&chunk_hash).is_ok(),
+        "the chunk's blob is restored with its row"
+    );
+
+    let mut device = DeviceClient::new(stream.collector_id.clone(), target.client());
+    assert_eq!(
+        device
+            .get_offset(&stream.stream_id, stream.session_id)
+            .await
+            .expect("offset"),
+        resume_at,
+        "a collector resumes after what the snapshot received"
+    );
+    let next = weather_chunk(&stream, resume_at);
+    let next_end = resume_at + next.data.len() as u64;
+    let ack = device
+        .upload_chunks(vec![next])
+        .await
+        .expect("resume upload");
+    assert_eq!(ack.acked_offset, next_end);
+    assert_eq!(
+        device
+            .get_offset(&stream.stream_id, stream.session_id)
+            .await
+            .expect("offset"),
+        next_end
+    );
+}
@@ -3,16 +3,26 @@
 mod harness;
 
 use chrono::{Duration, Utc};
-use harness::TestContext;
+use harness::{Backend, TestContext};
+use lifelog_server::frames::FrameRow;
 use lifelog_types::{AudioFrame, BrowserFrame, Query, QueryRequest};
 use prost::Message;
 
 #[tokio::test]
 #[ignore = "integration test: requires PostgreSQL"]
 async fn test_llql_canonical_example_audio_during_youtube_and_3b1b() {
+    llql_canonical_example_audio_during_youtube_and_3b1b(Backend::Postgres).await;
+}
+
+#[tokio::test]
+async fn test_llql_canonical_example_audio_during_youtube_and_3b1b_sqlite() {
+    llql_canonical_example_audio_during_youtube_and_3b1b(Backend::Sqlite).await;
+}
+
+async fn llql_canonical_example_audio_during_youtube_and_3b1b(backend: Backend) {
     let _ = tracing_subscriber::fmt::try_init();
 
-    let ctx = TestContext::new().await;
+    let ctx = TestContext::on(backend).await;
     let mut client = ctx.client();
 
     let collector_id = "test-collector";
@@ -101,37 +111,36 @@ async fn test_llql_canonical_example_audio_during_youtube_and_3b1b() {
         .expect("Ingest browser failed");
 
     // Seed an OcrRecord directly into the frames table so LLQL can resolve modality "Ocr".
-    let (pg_client, pg_conn) = tokio_postgres::connect(&ctx.pg_url, tokio_postgres::NoTls)
-        .await
-        .expect("connect to test postgres");
-    tokio::spawn(pg_conn);
-
-    let ocr_uuid = lifelog_core::Uuid::new_v4().to_string();
+    let ocr_uuid = lifelog_core::Uuid::new_v4();
     let ocr_ts = base + Duration::seconds(8);
 
     let payload = serde_json::json!({
-        "uuid": ocr_uuid,
+        "uuid": ocr_uuid.to_string(),
         "text": "Watching 3Blue1Brown",
         "timestamp": ocr_ts.to_rfc3339(),
     });
 
-    pg_client
-        .execute(
-            "INSERT INTO frames (id, collector_id, stream_id, modality, t_canonical, t_end, t_ingest, payload, indexed, search_doc)
-             VALUES ($1::uuid, $2, 'ocr', 'Ocr', $3, $3, now(), $4, true,
-                     to_tsvector('english', $5))",
-            &[
-                &lifelog_core::Uuid::new_v4(),
-                &collector_id,
-                &ocr_ts,
-                &payload,
-                &"Watching 3Blue1Brown".to_string(),
-            ],
-        )
+    ctx.store()
+        .upsert(&FrameRow {
+            id: ocr_uuid,
+            collector_id: collector_id.to_string(),
+            stream_id: "ocr".to_string(),
+            modality: "Ocr".to_string(),
+            t_device: None,
+            t_ingest: Utc::now(),
+            t_canonical: ocr_ts,
+            t_end: Some(ocr_ts),
+            time_quality: "unknown".to_string(),
+            blob_hash: None,
+            blob_size: None,
+            indexed: true,
+            source_frame_id: None,
+            payload,
+        })
         .await
         .expect("insert ocr frame failed");
 
-    // Give Postgres a moment to update indexes.
+    // Give the database a moment to update indexes.
     tokio::time::sleep(std::time::Duration::from_millis(250)).await;
 
     // Execute the canonical cross-modal query via LLQL.
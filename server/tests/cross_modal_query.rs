@@ -3,7 +3,7 @@
 
 mod harness;
 
-use harness::TestContext;
+use harness::{Backend, TestContext};
 use lifelog_core::Utc;
 use lifelog_types::{BrowserFrame, GetDataRequest, Query, QueryRequest, ScreenFrame};
 use prost::Message;
@@ -11,8 +11,17 @@ use prost::Message;
 #[tokio::test]
 #[ignore = "integration test: requires PostgreSQL"]
 async fn test_cross_modal_query() {
+    cross_modal_query(Backend::Postgres).await;
+}
+
+#[tokio::test]
+async fn test_cross_modal_query_sqlite() {
+    cross_modal_query(Backend::Sqlite).await;
+}
+
+async fn cross_modal_query(backend: Backend) {
     let _ = tracing_subscriber::fmt::try_init();
-    let ctx = TestContext::new().await;
+    let ctx = TestContext::on(backend).await;
     let mut client = ctx.client();
 
     let collector_id = "test-collector";
@@ -96,25 +105,6 @@ async fn test_cross_modal_query() {
     // Wait for indexing
     tokio::time::sleep(std::time::Duration::from_secs(1)).await;
 
-    // DEBUG: check if record exists directly in Postgres
-    let (pg_client, pg_conn) = tokio_postgres::connect(&ctx.pg_url, tokio_postgres::NoTls)
-        .await
-        .expect("connect to test postgres");
-    tokio::spawn(pg_conn);
-
-    let rows = pg_client
-        .query(
-            "SELECT id, modality, payload FROM frames WHERE collector_id = $1 AND modality = 'Browser'",
-            &[&collector_id],
-        )
-        .await
-        .expect("Debug query failed");
-    println!(
-        "DEBUG: Browser frames for {}: {} rows",
-        collector_id,
-        rows.len()
-    );
-
     // 3. Perform Unified Search via Query and GetData
 
     // Search for "Rust"
@@ -3,18 +3,27 @@
 mod harness;
 
 use chrono::{Duration, Utc};
-use harness::TestContext;
+use harness::{Backend, TestContext};
 use lifelog_core::{DataOrigin, DataOriginType};
-use lifelog_server::query::{ast, executor, planner};
+use lifelog_server::query::{ast, planner};
 use lifelog_types::{AudioFrame, ScreenFrame};
 use prost::Message;
 
 #[tokio::test]
 #[ignore = "integration test: requires PostgreSQL"]
 async fn test_during_returns_target_records_inside_source_intervals() {
+    during_returns_target_records_inside_source_intervals(Backend::Postgres).await;
+}
+
+#[tokio::test]
+async fn test_during_returns_target_records_inside_source_intervals_sqlite() {
+    during_returns_target_records_inside_source_intervals(Backend::Sqlite).await;
+}
+
+async fn during_returns_target_records_inside_source_intervals(backend: Backend) {
     let _ = tracing_subscriber::fmt::try_init();
 
-    let ctx = TestContext::new().await;
+    let ctx = TestContext::on(backend).await;
     let mut client = ctx.client();
 
     let collector_id = "test-collector";
@@ -164,10 +173,6 @@ async fn test_during_returns_target_records_inside_source_intervals() {
 
     tokio::time::sleep(std::time::Duration::from_millis(250)).await;
 
-    let pg_pool = lifelog_server::postgres::connect_pool(&ctx.pg_url, 2)
-        .await
-        .expect("connect to test postgres pool");
-
     let screen_origin = DataOrigin::new(
         DataOriginType::DeviceId(collector_id.to_string()),
         "Screen".to_string(),
@@ -190,7 +195,9 @@ async fn test_during_returns_target_records_inside_source_intervals() {
     };
 
     let plan = planner::Planner::plan(&query, &[screen_origin.clone(), audio_origin.clone()]);
-    let keys = executor::execute_postgres(&pg_pool, plan)
+    let keys = ctx
+        .store()
+        .execute(plan)
         .await
         .expect("DURING query execution failed");
 
@@ -209,9 +216,18 @@ async fn test_during_returns_target_records_inside_source_intervals() {
 #[tokio::test]
 #[ignore = "integration test: requires PostgreSQL"]
 async fn test_during_conjunction_intersects_intervals() {
+    during_conjunction_intersects_intervals(Backend::Postgres).await;
+}
+
+#[tokio::test]
+async fn test_during_conjunction_intersects_intervals_sqlite() {
+    during_conjunction_intersects_intervals(Backend::Sqlite).await;
+}
+
+async fn during_conjunction_intersects_intervals(backend: Backend) {
     let _ = tracing_subscriber::fmt::try_init();
 
-    let ctx = TestContext::new().await;
+    let ctx = TestContext::on(backend).await;
     let mut client = ctx.client();
 
     let collector_id = "test-collector";
@@ -364,10 +380,6 @@ async fn test_during_conjunction_intersects_intervals() {
 
     tokio::time::sleep(std::time::Duration::from_millis(250)).await;
 
-    let pg_pool = lifelog_server::postgres::connect_pool(&ctx.pg_url, 2)
-        .await
-        .expect("connect to test postgres pool");
-
     let screen_origin = DataOrigin::new(
         DataOriginType::DeviceId(collector_id.to_string()),
         "Screen".to_string(),
@@ -402,7 +414,9 @@ async fn test_during_conjunction_intersects_intervals() {
     };
 
     let plan = planner::Planner::plan(&query, &[screen_origin.clone(), audio_origin.clone()]);
-    let keys = executor::execute_postgres(&pg_pool, plan)
+    let keys = ctx
+        .store()
+        .execute(plan)
         .await
         .expect("DURING conjunction query execution failed");
 
@@ -421,9 +435,18 @@ async fn test_during_conjunction_intersects_intervals() {
 #[tokio::test]
 #[ignore = "integration test: requires PostgreSQL"]
 async fn test_during_interval_target_overlaps_point_sources() {
+    during_interval_target_overlaps_point_sources(Backend::Postgres).await;
+}
+
+#[tokio::test]
+async fn test_during_interval_target_overlaps_point_sources_sqlite() {
+    during_interval_target_overlaps_point_sources(Backend::Sqlite).await;
+}
+
+async fn during_interval_target_overlaps_point_sources(backend: Backend) {
     let _ = tracing_subscriber::fmt::try_init();
 
-    let ctx = TestContext::new().await;
+    let ctx = TestContext::on(backend).await;
     let mut client = ctx.client();
 
     let collector_id = "test-collector";
@@ -508,10 +531,6 @@ async fn test_during_interval_target_overlaps_point_sources() {
 
     tokio::time::sleep(std::time::Duration::from_millis(250)).await;
 
-    let pg_pool = lifelog_server::postgres::connect_pool(&ctx.pg_url, 2)
-        .await
-        .expect("connect to test postgres pool");
-
     let screen_origin = DataOrigin::new(
         DataOriginType::DeviceId(collector_id.to_string()),
         "Screen".to_string(),
@@ -534,7 +553,9 @@ async fn test_during_interval_target_overlaps_point_sources() {
     };
 
     let plan = planner::Planner::plan(&query, &[screen_origin, audio_origin.clone()]);
-    let keys = executor::execute_postgres(&pg_pool, plan)
+    let keys = ctx
+        .store()
+        .execute(plan)
         .await
         .expect("DURING interval-target query execution failed");
 
@@ -0,0 +1,41 @@
+#![allow(clippy::expect_used, clippy::panic, clippy::unwrap_used)]
+
+mod harness;
+
+use harness::TestContext;
+use lifelog_core::Uuid;
+
+#[tokio::test]
+#[ignore = "integration test: requires PostgreSQL"]
+async fn resegmenting_removes_episodes_of_collectors_without_activity() {
+    let ctx = TestContext::new().await;
+    let pool = lifelog_server::postgres::connect_pool(&ctx.pg_url, 2)
+        .await
+        .expect("connect to test postgres");
+    let client = pool.get().await.expect("pool client");
+
+    // An episode left behind after its collector's activity was forgotten.
+    let stale = Uuid::new_v4();
+    client
+        .execute(
+            "INSERT INTO frames (id, collector_id, stream_id, modality, time_range, t_canonical,
+                                 payload)
+             VALUES ($1, 'episodes-test', 'episodes', 'Episode',
+                     tstzrange(NOW() - INTERVAL '2 minutes', NOW() - INTERVAL '1 minute', '[]'),
+                     NOW() - INTERVAL '2 minutes', '{\"title\": \"gone\"}')",
+            &[&stale],
+        )
+        .await
+        .expect("insert episode");
+
+    let summary = lifelog_server::transform::episode::segment_episodes(&pool, chrono::Utc::now())
+        .await
+        .expect("segment episodes");
+    assert!(summary.episodes_removed >= 1);
+
+    let left = client
+        .query_opt("SELECT 1 FROM frames WHERE id = $1", &[&stale])
+        .await
+        .expect("look up episode");
+    assert!(left.is_none(), "stale episode must be removed");
+}
@@ -5,10 +5,11 @@ pub mod event_gen;
 pub mod fault_layer;
 pub mod simulated_modalities;
 
-use config::ServerConfig;
+use config::{ServerConfig, ServerDeployConfig};
 use fault_layer::{FaultController, FaultInjectionLayer};
 use lifelog_server::grpc_service::GRPCServerLifelogServerService;
 use lifelog_server::server::{Server, ServerHandle};
+use lifelog_server::storage::FrameStore;
 use lifelog_types::lifelog_server_service_client::LifelogServerServiceClient;
 use lifelog_types::lifelog_server_service_server::LifelogServerServiceServer;
 use std::path::PathBuf;
@@ -116,6 +117,14 @@ impl Interceptor for ServerAuthInterceptor {
 pub type TestClient =
     LifelogServerServiceClient<InterceptedService<Channel, ClientAuthInterceptor>>;
 
+/// The database a test server runs on.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Postgres,
+    Sqlite,
+}
+
 #[allow(dead_code)]
 pub struct TestContext {
     pub server_addr: String,
@@ -128,7 +137,9 @@ pub struct TestContext {
     pub cas_path: PathBuf,
     tls_ca_path: PathBuf,
     server_port: u16,
+    /// Empty when running on SQLite.
     pub pg_url: String,
+    store: Arc<dyn FrameStore>,
     _pg_container: Option<ContainerAsync<Postgres>>,
 }
 
@@ -214,7 +225,17 @@ impl TestContext {
         Self::new_with_faults(FaultController::new()).await
     }
 
+    #[allow(dead_code)]
+    pub async fn on(backend: Backend) -> Self {
+        Self::new_with(FaultController::new(), backend).await
+    }
+
+    #[allow(dead_code)]
     pub async fn new_with_faults(fault_controller: FaultController) -> Self {
+        Self::new_with(fault_controller, Backend::Postgres).await
+    }
+
+    pub async fn new_with(fault_controller: FaultController, backend: Backend) -> Self {
         let _ = rustls::crypto::ring::default_provider().install_default();
         let temp_dir = TempDir::new().expect("Failed to create temp dir");
         let server_port = portpicker::pick_unused_port().expect("No ports available");
@@ -228,25 +249,30 @@ impl TestContext {
         std::env::set_var("LIFELOG_ENROLLMENT_TOKEN", "test-enrollment-token");
         std::env::set_var("LIFELOG_TLS_CA_CERT_PATH", &tls_cert_path);
 
-        let test_db_name = std::env::var("LIFELOG_TEST_DB")
-            .unwrap_or_else(|_| format!("lifelog_test_{}", server_port));
-        let pg_setup = provision_postgres().await;
-        {
-            let (pg_client, pg_conn) =
-                tokio_postgres::connect(&pg_setup.admin_url, tokio_postgres::NoTls)
+        let (pg_test_url, pg_container) = if backend == Backend::Postgres {
+            let test_db_name = std::env::var("LIFELOG_TEST_DB")
+                .unwrap_or_else(|_| format!("lifelog_test_{}", server_port));
+            let pg_setup = provision_postgres().await;
+            {
+                let (pg_client, pg_conn) =
+                    tokio_postgres::connect(&pg_setup.admin_url, tokio_postgres::NoTls)
+                        .await
+                        .expect("Failed to connect to postgres for test DB setup");
+                tokio::spawn(pg_conn);
+                let _ = pg_client
+                    .execute(&format!("DROP DATABASE IF EXISTS \"{test_db_name}\""), &[])
+                    .await;
+                pg_client
+                    .execute(&format!("CREATE DATABASE \"{test_db_name}\""), &[])
                     .await
-                    .expect("Failed to connect to postgres for test DB setup");
-            tokio::spawn(pg_conn);
-            let _ = pg_client
-                .execute(&format!("DROP DATABASE IF EXISTS \"{test_db_name}\""), &[])
-                .await;
-            pg_client
-                .execute(&format!("CREATE DATABASE \"{test_db_name}\""), &[])
-                .await
-                .expect("Failed to create test database");
-        }
-        let pg_test_url = (pg_setup.test_db_url_fn)(&test_db_name);
-        std::env::set_var("LIFELOG_POSTGRES_INGEST_URL", &pg_test_url);
+                    .expect("Failed to create test database");
+            }
+            let pg_test_url = (pg_setup.test_db_url_fn)(&test_db_name);
+            std::env::set_var("LIFELOG_POSTGRES_INGEST_URL", &pg_test_url);
+            (pg_test_url, pg_setup.container)
+        } else {
+            (String::new(), None)
+        };
 
         let mut transforms = Vec::new();
         if let Ok(v) = std::env::var("LIFELOG_TRANSFORMS_JSON") {
@@ -267,10 +293,23 @@ impl TestContext {
             transforms,
         };
 
-        let server = timeout(Duration::from_secs(30), Server::new(&config))
+        let new_server = async {
+            match backend {
+                Backend::Postgres => Server::new(&config).await,
+                Backend::Sqlite => {
+                    let deploy = ServerDeployConfig {
+                        sqlite_path: Some(temp_dir.path().join("lifelog.db").display().to_string()),
+                        ..Default::default()
+                    };
+                    Server::new_with_deploy(&config, &deploy).await
+                }
+            }
+        };
+        let server = timeout(Duration::from_secs(30), new_server)
             .await
             .expect("Timed out creating server (check DB connectivity / schema init)")
             .expect("Failed to create server");
+        let store = server.store.clone();
         let server_handle = Arc::new(RwLock::new(server));
         let handle_clone = ServerHandle::new(server_handle.clone());
         let grpc_service = GRPCServerLifelogServerService {
@@ -339,7 +378,8 @@ impl TestContext {
             tls_ca_path: tls_cert_path,
             server_port,
             pg_url: pg_test_url,
-            _pg_container: pg_setup.container,
+            store,
+            _pg_container: pg_container,
         }
     }
 
@@ -370,6 +410,12 @@ impl TestContext {
         FsCas::new(&self.cas_path)
     }
 
+    /// The server's frame store, for seeding frames and running plans directly.
+    #[allow(dead_code)]
+    pub fn store(&self) -> Arc<dyn FrameStore> {
+        self.store.clone()
+    }
+
     #[allow(dead_code)]
     pub fn server_port(&self) -> u16 {
         self.server_port
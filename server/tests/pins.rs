@@ -0,0 +1,100 @@
+#![allow(clippy::expect_used, clippy::panic, clippy::unwrap_used)]
+
+mod harness;
+
+use harness::TestContext;
+use lifelog_core::Uuid;
+
+#[tokio::test]
+#[ignore = "integration test: requires PostgreSQL"]
+async fn frame_pinned_follows_lineage_to_any_depth() {
+    let ctx = TestContext::new().await;
+    let (pg_client, pg_conn) = tokio_postgres::connect(&ctx.pg_url, tokio_postgres::NoTls)
+        .await
+        .expect("connect to test postgres");
+    tokio::spawn(pg_conn);
+
+    // screenshot -> OCR -> translation, plus an unrelated screenshot.
+    let (screen, ocr, translation, other) = (
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+    );
+    for (id, source, modality) in [
+        (screen, None, "Screen"),
+        (ocr, Some(screen), "Ocr"),
+        (translation, Some(ocr), "Translation"),
+        (other, None, "Screen"),
+    ] {
+        pg_client
+            .execute(
+                "INSERT INTO frames (id, collector_id, stream_id, modality, time_range,
+                                     t_canonical, source_frame_id)
+                 VALUES ($1, 'pins-test', lower($3), $3,
+                         tstzrange(NOW() - INTERVAL '1 day', NOW() - INTERVAL '1 day', '[]'),
+                         NOW() - INTERVAL '1 day', $2)",
+                &[&id, &source, &modality],
+            )
+            .await
+            .expect("insert frame");
+    }
+
+    let pinned = |id: Uuid| {
+        let pg_client = &pg_client;
+        async move {
+            pg_client
+                .query_one(
+                    "SELECT frame_pinned(id, source_frame_id, time_range) FROM frames
+                     WHERE id = $1",
+                    &[&id],
+                )
+                .await
+                .expect("frame_pinned")
+                .get::<_, bool>(0)
+        }
+    };
+    // Retention's per-run snapshot must agree with the per-row check.
+    let agree = || {
+        let pg_client = &pg_client;
+        async move {
+            let set: Vec<Uuid> = pg_client
+                .query("SELECT * FROM pinned_frame_ids()", &[])
+                .await
+                .expect("pinned_frame_ids")
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+            for id in [screen, ocr, translation, other] {
+                assert_eq!(set.contains(&id), pinned(id).await, "{id}");
+            }
+        }
+    };
+    assert!(!pinned(screen).await);
+    agree().await;
+
+    pg_client
+        .execute("INSERT INTO pins (frame_id) VALUES ($1)", &[&translation])
+        .await
+        .expect("pin translation");
+    assert!(pinned(translation).await);
+    assert!(pinned(ocr).await);
+    assert!(
+        pinned(screen).await,
+        "pins must reach ancestors two levels up"
+    );
+    assert!(!pinned(other).await);
+    agree().await;
+
+    pg_client
+        .execute("DELETE FROM pins", &[])
+        .await
+        .expect("clear pins");
+    pg_client
+        .execute("INSERT INTO pins (frame_id) VALUES ($1)", &[&screen])
+        .await
+        .expect("pin screenshot");
+    assert!(pinned(translation).await, "pins must reach descendants");
+    assert!(!pinned(other).await);
+    agree().await;
+}
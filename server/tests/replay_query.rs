@@ -3,16 +3,25 @@
 mod harness;
 
 use chrono::{Duration, Utc};
-use harness::TestContext;
+use harness::{Backend, TestContext};
 use lifelog_types::{BrowserFrame, ReplayRequest, ScreenFrame};
 use prost::Message;
 
 #[tokio::test]
 #[ignore = "integration test: requires PostgreSQL"]
 async fn test_replay_query_returns_ordered_steps_with_context() {
+    replay_query_returns_ordered_steps_with_context(Backend::Postgres).await;
+}
+
+#[tokio::test]
+async fn test_replay_query_returns_ordered_steps_with_context_sqlite() {
+    replay_query_returns_ordered_steps_with_context(Backend::Sqlite).await;
+}
+
+async fn replay_query_returns_ordered_steps_with_context(backend: Backend) {
     let _ = tracing_subscriber::fmt::try_init();
 
-    let ctx = TestContext::new().await;
+    let ctx = TestContext::on(backend).await;
     let mut client = ctx.client();
 
     let collector_id = "test-collector";
@@ -0,0 +1,158 @@
+#![allow(clippy::expect_used, clippy::panic, clippy::unwrap_used)]
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use lifelog_core::{DataOrigin, DataOriginType, LifelogFrameKey, PrivacyLevel};
+use lifelog_server::storage::{
+    FrameStore, SqliteStore, SqliteTransformState, SqliteWatermarkStore,
+};
+use lifelog_server::transform::dag::TransformDag;
+use lifelog_server::transform::entities::EntityExtractExecutor;
+use lifelog_server::transform::worker::PipelineWorker;
+use lifelog_server::transform::{TransformExecutor, TransformOutput, TransformPipelineError};
+use lifelog_types::lifelog_data::Payload;
+use lifelog_types::{ClipboardFrame, LifelogData, TranscriptionFrame};
+use utils::cas::FsCas;
+
+const SECRET: &str = "alice@example.com";
+
+/// Copies a clipboard entry's text into a transcript, with the result cache on.
+struct EchoTransform;
+
+#[async_trait]
+impl TransformExecutor for EchoTransform {
+    fn id(&self) -> &str {
+        "echo"
+    }
+    fn source_modality(&self) -> &str {
+        "Clipboard"
+    }
+    fn destination_modality(&self) -> &str {
+        "Transcription"
+    }
+    fn priority(&self) -> u8 {
+        1
+    }
+    fn is_async(&self) -> bool {
+        false
+    }
+    fn matches_origin(&self, key_origin: &DataOrigin) -> bool {
+        key_origin.modality_name == "Clipboard"
+    }
+    fn source(&self) -> DataOrigin {
+        DataOrigin::new(
+            DataOriginType::DeviceId("*".to_string()),
+            "Clipboard".to_string(),
+        )
+    }
+    fn destination(&self) -> DataOrigin {
+        DataOrigin::new(
+            DataOriginType::DeviceId("laptop".to_string()),
+            "Transcription".to_string(),
+        )
+    }
+    fn privacy_level(&self) -> PrivacyLevel {
+        PrivacyLevel::LocalOnly
+    }
+    fn cache_fingerprint(&self) -> Option<&str> {
+        Some("echo-v1")
+    }
+
+    async fn execute(
+        &self,
+        _http: &reqwest::Client,
+        data: &LifelogData,
+        key: &LifelogFrameKey,
+    ) -> Result<TransformOutput, TransformPipelineError> {
+        let Some(Payload::Clipboardframe(clip)) = &data.payload else {
+            return Ok(TransformOutput::Skipped);
+        };
+        Ok(TransformOutput::Transcription(TranscriptionFrame {
+            uuid: lifelog_core::Uuid::new_v4().to_string(),
+            timestamp: clip.timestamp,
+            t_canonical: clip.t_canonical,
+            text: clip.text.clone(),
+            source_uuid: key.uuid.to_string(),
+            model: "echo".to_string(),
+            ..Default::default()
+        }))
+    }
+}
+
+#[tokio::test]
+async fn sealed_frames_leave_no_plaintext_in_the_entity_index_or_cache() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let cas_path = dir.path().join("cas");
+    std::env::set_var("LIFELOG_CONFIG_PATH", dir.path().join("missing.toml"));
+    std::env::set_var("LIFELOG_PAYLOAD_KEY", "passphrase:sealed-side-tables");
+    lifelog_server::payload_crypto::init(&cas_path.display().to_string())
+        .expect("payload key")
+        .expect("payload encryption on");
+
+    let db_path = dir.path().join("lifelog.db");
+    let sqlite = SqliteStore::open(&db_path).await.expect("open sqlite");
+    let store: Arc<dyn FrameStore> = Arc::new(sqlite.clone());
+    let cas = FsCas::new(&cas_path);
+
+    let ts = lifelog_types::to_pb_ts(Utc::now() - Duration::minutes(1));
+    let clip = LifelogData {
+        payload: Some(Payload::Clipboardframe(ClipboardFrame {
+            uuid: lifelog_core::Uuid::new_v4().to_string(),
+            timestamp: ts,
+            t_device: ts,
+            t_canonical: ts,
+            t_end: ts,
+            text: format!("mail {SECRET} about it"),
+            mime_type: "text/plain".to_string(),
+            ..Default::default()
+        })),
+    };
+    let mut row = lifelog_server::frames::from_lifelog_data("laptop", "clipboard", &clip, &cas)
+        .expect("clipboard row");
+    store.insert_ingested(&mut row).await.expect("ingest");
+
+    let transforms: Vec<Arc<dyn TransformExecutor>> = vec![
+        Arc::new(EchoTransform),
+        Arc::new(EntityExtractExecutor::new(
+            "entity-extract-clipboard".to_string(),
+            DataOrigin::new(
+                DataOriginType::DeviceId("*".to_string()),
+                "Clipboard".to_string(),
+            ),
+            &HashMap::new(),
+        )),
+    ];
+    let worker = Arc::new(PipelineWorker::new(
+        Arc::new(TransformDag::new(transforms).expect("dag")),
+        Arc::new(SqliteWatermarkStore::new(sqlite.clone())),
+        store.clone(),
+        Arc::new(SqliteTransformState::new(sqlite.clone())),
+        cas,
+        reqwest::Client::new(),
+        16,
+    ));
+    worker.poll_once().await.expect("transform pass");
+
+    let conn = rusqlite::Connection::open(&db_path).expect("open db");
+    let count = |sql: &str| -> i64 {
+        conn.query_row(sql, [], |r| r.get(0))
+            .unwrap_or_else(|e| panic!("{sql}: {e}"))
+    };
+    // The transforms did run: the echo's transcript was written, sealed.
+    assert_eq!(
+        count("SELECT COUNT(*) FROM frames WHERE modality = 'Transcription'"),
+        1
+    );
+    assert_eq!(
+        count(&format!(
+            "SELECT COUNT(*) FROM frames WHERE payload LIKE '%{SECRET}%'"
+        )),
+        0
+    );
+    assert_eq!(count("SELECT COUNT(*) FROM entity_mentions"), 0);
+    assert_eq!(count("SELECT COUNT(*) FROM entities"), 0);
+    assert_eq!(count("SELECT COUNT(*) FROM transform_cache"), 0);
+}
@@ -3,18 +3,27 @@
 mod harness;
 
 use chrono::{Duration, Utc};
-use harness::TestContext;
+use harness::{Backend, TestContext};
 use lifelog_core::{DataOrigin, DataOriginType};
-use lifelog_server::query::{ast, executor, planner};
+use lifelog_server::query::{ast, planner};
 use lifelog_types::{BrowserFrame, ScreenFrame};
 use prost::Message;
 
 #[tokio::test]
 #[ignore = "integration test: requires PostgreSQL"]
 async fn test_within_returns_target_records_near_source_matches() {
+    within_returns_target_records_near_source_matches(Backend::Postgres).await;
+}
+
+#[tokio::test]
+async fn test_within_returns_target_records_near_source_matches_sqlite() {
+    within_returns_target_records_near_source_matches(Backend::Sqlite).await;
+}
+
+async fn within_returns_target_records_near_source_matches(backend: Backend) {
     let _ = tracing_subscriber::fmt::try_init();
 
-    let ctx = TestContext::new().await;
+    let ctx = TestContext::on(backend).await;
     let mut client = ctx.client();
 
     let collector_id = "test-collector";
@@ -133,10 +142,6 @@ async fn test_within_returns_target_records_near_source_matches() {
 
     tokio::time::sleep(std::time::Duration::from_millis(250)).await;
 
-    let pg_pool = lifelog_server::postgres::connect_pool(&ctx.pg_url, 2)
-        .await
-        .expect("connect to test postgres pool");
-
     let screen_origin = DataOrigin::new(
         DataOriginType::DeviceId(collector_id.to_string()),
         "Screen".to_string(),
@@ -159,7 +164,9 @@ async fn test_within_returns_target_records_near_source_matches() {
     };
 
     let plan = planner::Planner::plan(&query, &[screen_origin.clone(), browser_origin.clone()]);
-    let keys = executor::execute_postgres(&pg_pool, plan)
+    let keys = ctx
+        .store()
+        .execute(plan)
         .await
         .expect("WITHIN query execution failed");
 
@@ -178,9 +185,18 @@ async fn test_within_returns_target_records_near_source_matches() {
 #[tokio::test]
 #[ignore = "integration test: requires PostgreSQL"]
 async fn test_within_multiple_terms_intersects_windows() {
+    within_multiple_terms_intersects_windows(Backend::Postgres).await;
+}
+
+#[tokio::test]
+async fn test_within_multiple_terms_intersects_windows_sqlite() {
+    within_multiple_terms_intersects_windows(Backend::Sqlite).await;
+}
+
+async fn within_multiple_terms_intersects_windows(backend: Backend) {
     let _ = tracing_subscriber::fmt::try_init();
 
-    let ctx = TestContext::new().await;
+    let ctx = TestContext::on(backend).await;
     let mut client = ctx.client();
 
     let collector_id = "test-collector";
@@ -324,10 +340,6 @@ async fn test_within_multiple_terms_intersects_windows() {
 
     tokio::time::sleep(std::time::Duration::from_millis(250)).await;
 
-    let pg_pool = lifelog_server::postgres::connect_pool(&ctx.pg_url, 2)
-        .await
-        .expect("connect to test postgres pool");
-
     let screen_origin = DataOrigin::new(
         DataOriginType::DeviceId(collector_id.to_string()),
         "Screen".to_string(),
@@ -360,7 +372,9 @@ async fn test_within_multiple_terms_intersects_windows() {
     };
 
     let plan = planner::Planner::plan(&query, &[screen_origin.clone(), browser_origin.clone()]);
-    let keys = executor::execute_postgres(&pg_pool, plan)
+    let keys = ctx
+        .store()
+        .execute(plan)
         .await
         .expect("multi-WITHIN query execution failed");
 